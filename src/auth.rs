@@ -0,0 +1,108 @@
+//! Shared-secret authentication of daemon connections.
+//!
+//! Off by default: the Unix socket is only accessible to its owner (0600),
+//! which is enough locally. A daemon started with `--require-token` (which
+//! `--listen` on a non-loopback address needs) reads the token from
+//! `~/.claude-sessions/token`, creating it if missing, and answers every
+//! connection whose first request isn't a matching `Request::Authenticate`
+//! with an `unauthenticated` error before closing it.
+//!
+//! Clients send the token whenever they have one: `CLAUDE_SESSIONS_TOKEN`,
+//! or else the token file (copy it to the machine you connect from).
+
+use anyhow::{Context, Result};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+/// Where the daemon's token lives: `~/.claude-sessions/token`
+pub fn token_path() -> Result<PathBuf> {
+    Ok(crate::daemon::Daemon::socket_path()?.with_file_name("token"))
+}
+
+/// Read the token at `path`; `None` if there is no token file
+pub fn read_token(path: &Path) -> Result<Option<String>> {
+    match std::fs::read_to_string(path) {
+        Ok(contents) => {
+            let token = contents.trim();
+            if token.is_empty() {
+                anyhow::bail!("Token file {:?} is empty", path);
+            }
+            Ok(Some(token.to_string()))
+        }
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e).with_context(|| format!("Failed to read token file {:?}", path)),
+    }
+}
+
+/// The token at `path`, generating a random one (readable only by its
+/// owner) if there is none yet
+pub fn load_or_create_token(path: &Path) -> Result<String> {
+    if let Some(token) = read_token(path)? {
+        return Ok(token);
+    }
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+
+    let token = format!("{}{}", uuid::Uuid::new_v4().simple(), uuid::Uuid::new_v4().simple());
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    let mut file = options
+        .open(path)
+        .with_context(|| format!("Failed to create token file {:?}", path))?;
+    writeln!(file, "{}", token)?;
+    file.sync_all()?;
+    println!("🔑 Created auth token {:?}", path);
+    Ok(token)
+}
+
+/// The token a client presents: `CLAUDE_SESSIONS_TOKEN`, or the token file
+pub fn client_token() -> Option<String> {
+    if let Ok(token) = std::env::var("CLAUDE_SESSIONS_TOKEN") {
+        if !token.trim().is_empty() {
+            return Some(token.trim().to_string());
+        }
+    }
+    read_token(&token_path().ok()?).ok().flatten()
+}
+
+/// Compare a presented token with the expected one without bailing out at
+/// the first differing byte
+pub fn tokens_match(presented: &str, expected: &str) -> bool {
+    presented.len() == expected.len()
+        && presented
+            .bytes()
+            .zip(expected.bytes())
+            .fold(0u8, |diff, (a, b)| diff | (a ^ b))
+            == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_token_is_created_once_and_private() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let path = temp_dir.path().join("token");
+        assert_eq!(read_token(&path).unwrap(), None);
+
+        let token = load_or_create_token(&path).unwrap();
+        assert_eq!(token.len(), 64);
+        assert_eq!(load_or_create_token(&path).unwrap(), token);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            assert_eq!(std::fs::metadata(&path).unwrap().permissions().mode() & 0o777, 0o600);
+        }
+
+        assert!(tokens_match(&token, &token));
+        assert!(!tokens_match(&token[1..], &token));
+        assert!(!tokens_match(&token.replace(&token[..1], "-"), &token));
+    }
+}
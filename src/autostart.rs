@@ -0,0 +1,182 @@
+//! Starting the daemon on demand, so commands work without running
+//! `claude-sessions daemon` first.
+//!
+//! On by default; turn it off with `--autostart=false` or
+//! `CLAUDE_SESSIONS_AUTOSTART=0`. Concurrent CLIs may all spawn a daemon;
+//! the daemon lock file (and `Daemon::bind` after it) makes sure only one
+//! of them keeps running and the rest exit as "already running".
+
+use crate::daemon::{Daemon, Probe};
+use anyhow::{Context, Result};
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+/// How long to wait for a spawned daemon to answer
+const STARTUP_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// First delay between probes of a starting daemon; doubles up to `MAX_BACKOFF`
+const FIRST_BACKOFF: Duration = Duration::from_millis(25);
+const MAX_BACKOFF: Duration = Duration::from_millis(400);
+
+/// Whether autostart is on when `--autostart` isn't given
+pub fn enabled_from_env() -> bool {
+    match std::env::var("CLAUDE_SESSIONS_AUTOSTART") {
+        Ok(value) => !matches!(value.trim().to_ascii_lowercase().as_str(), "0" | "false" | "no" | "off"),
+        Err(_) => true,
+    }
+}
+
+/// Launches a daemon process
+pub trait Spawner {
+    fn spawn(&self) -> Result<()>;
+}
+
+/// Runs this binary's `daemon --foreground` detached in the background,
+/// with its output going to `Daemon::log_path`.
+///
+/// This re-executes the binary instead of forking: forking a process that
+/// already runs a multi-threaded tokio runtime isn't safe.
+#[derive(Debug, Default)]
+pub struct BackgroundDaemon {
+    /// Extra `daemon` arguments (e.g. retention flags)
+    pub args: Vec<String>,
+}
+
+impl Spawner for BackgroundDaemon {
+    fn spawn(&self) -> Result<()> {
+        let exe = std::env::current_exe().context("Cannot find the claude-sessions binary")?;
+        let log_path = Daemon::log_path()?;
+        if let Some(parent) = log_path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        let log = std::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&log_path)
+            .with_context(|| format!("Failed to open {}", log_path.display()))?;
+
+        let mut command = std::process::Command::new(exe);
+        command
+            .args(["daemon", "--foreground"])
+            .args(&self.args)
+            .stdin(std::process::Stdio::null())
+            .stdout(log.try_clone()?)
+            .stderr(log);
+        #[cfg(unix)]
+        {
+            use std::os::unix::process::CommandExt;
+            // Own session: the daemon outlives this CLI and its terminal
+            unsafe {
+                command.pre_exec(|| {
+                    libc::setsid();
+                    Ok(())
+                });
+            }
+        }
+        command.spawn().context("Failed to spawn the daemon")?;
+        Ok(())
+    }
+}
+
+/// Make sure a daemon answers on `socket_path`, starting one with
+/// `spawner` if none does.
+///
+/// Returns whether a daemon had to be started.
+pub async fn ensure_daemon(socket_path: &Path, spawner: &dyn Spawner) -> Result<bool> {
+    if Daemon::probe(socket_path).await == Probe::Alive {
+        return Ok(false);
+    }
+    spawner.spawn()?;
+
+    let deadline = Instant::now() + STARTUP_TIMEOUT;
+    let mut backoff = FIRST_BACKOFF;
+    while Daemon::probe(socket_path).await != Probe::Alive {
+        if Instant::now() >= deadline {
+            anyhow::bail!("Daemon didn't start within {:?}", STARTUP_TIMEOUT);
+        }
+        tokio::time::sleep(backoff).await;
+        backoff = (backoff * 2).min(MAX_BACKOFF);
+    }
+    Ok(true)
+}
+
+/// Show the full explanation only the first time a daemon is autostarted
+///
+/// Remembered with a marker file next to the socket.
+pub fn first_time(socket_path: &Path) -> bool {
+    let marker = socket_path.with_file_name("autostart-notice");
+    if marker.exists() {
+        return false;
+    }
+    let _ = std::fs::write(&marker, "");
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::client::Client;
+    use crate::ipc::{Request, Response};
+    use std::path::PathBuf;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+    use tokio::net::UnixListener;
+
+    /// Starts a fake daemon on `socket_path`, a little late like a real one
+    struct FakeDaemon {
+        socket_path: PathBuf,
+        spawned: AtomicUsize,
+    }
+
+    impl Spawner for FakeDaemon {
+        fn spawn(&self) -> Result<()> {
+            self.spawned.fetch_add(1, Ordering::SeqCst);
+            let socket_path = self.socket_path.clone();
+            tokio::spawn(async move {
+                tokio::time::sleep(Duration::from_millis(100)).await;
+                let listener = UnixListener::bind(&socket_path).unwrap();
+                loop {
+                    let (stream, _) = listener.accept().await.unwrap();
+                    tokio::spawn(async move {
+                        let (reader, mut writer) = stream.into_split();
+                        let mut lines = BufReader::new(reader).lines();
+                        while let Ok(Some(line)) = lines.next_line().await {
+                            let response = match serde_json::from_str(&line).unwrap() {
+                                Request::ListSessions { .. } => Response::SessionList { sessions: vec![] },
+                                Request::Hello { protocol_version, .. } => {
+                                    Response::Hello { protocol_version, version: "test".to_string() }
+                                }
+                                _ => Response::Pong { nonce: None, active_connections: None, max_connections: None },
+                            };
+                            let json = serde_json::to_string(&response).unwrap();
+                            writer.write_all(format!("{}\n", json).as_bytes()).await.unwrap();
+                        }
+                    });
+                }
+            });
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_list_autostarts_missing_daemon() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let socket_path = temp_dir.path().join("daemon.sock");
+        let spawner = FakeDaemon { socket_path: socket_path.clone(), spawned: AtomicUsize::new(0) };
+
+        assert!(ensure_daemon(&socket_path, &spawner).await.unwrap());
+        let client = Client::with_socket_path(socket_path.clone());
+        let listed = client
+            .send_request(Request::ListSessions { fields: None, idle_threshold_secs: None })
+            .await
+            .unwrap();
+        assert!(matches!(listed, Response::SessionList { .. }));
+
+        // Already running: nothing more is spawned
+        assert!(!ensure_daemon(&socket_path, &spawner).await.unwrap());
+        assert_eq!(spawner.spawned.load(Ordering::SeqCst), 1);
+
+        assert!(first_time(&socket_path));
+        assert!(!first_time(&socket_path));
+    }
+}
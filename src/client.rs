@@ -1,19 +1,142 @@
-use crate::ipc::{Request, Response};
+use crate::daemon::Probe;
+use crate::ipc::{
+    protocol_compatible, read_line_limited, ErrorCode, Request, Response, MAX_RESPONSE_BYTES, MIN_PROTOCOL_VERSION,
+    PROTOCOL_VERSION,
+};
 use anyhow::{Context, Result};
 use std::path::PathBuf;
-use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
-use tokio::net::UnixStream;
+use tokio::io::{AsyncRead, AsyncWrite, AsyncWriteExt, BufReader};
+use tokio::net::{TcpStream, UnixStream};
 
 /// IPC Client for communicating with the daemon
+#[derive(Clone)]
 pub struct Client {
     socket_path: PathBuf,
+    /// `host:port` of a daemon listening on TCP (`daemon --listen`), used
+    /// instead of the Unix socket
+    remote: Option<String>,
+    /// Sent first on every connection (see `auth`)
+    token: Option<String>,
+}
+
+/// A single daemon connection reused across several requests.
+///
+/// Compound operations (e.g. resolving a session via `list` and then
+/// stopping it) should open one of these with `Client::session()` and
+/// send all their requests over it, instead of reconnecting per request.
+pub struct ClientSession {
+    reader: BufReader<Box<dyn AsyncRead + Unpin + Send>>,
+    writer: Box<dyn AsyncWrite + Unpin + Send>,
+    /// Where to reconnect when the daemon hangs up between requests
+    client: Option<Client>,
+    /// Whether the daemon has answered a request on this connection
+    answered: bool,
+}
+
+impl ClientSession {
+    /// Wrap an already-connected stream
+    pub fn from_halves<R, W>(reader: R, writer: W) -> Self
+    where
+        R: AsyncRead + Unpin + Send + 'static,
+        W: AsyncWrite + Unpin + Send + 'static,
+    {
+        ClientSession {
+            reader: BufReader::new(Box::new(reader)),
+            writer: Box::new(writer),
+            client: None,
+            answered: false,
+        }
+    }
+
+    /// Send a request over this connection and wait for its response.
+    ///
+    /// A daemon that answers one request per connection closes it after
+    /// the first response, so later requests can't be delivered on it and
+    /// go over a new connection instead (if this one came from
+    /// `Client::session`). A request the daemon received is never sent
+    /// twice.
+    pub async fn send_request(&mut self, request: Request) -> Result<Response> {
+        self.try_request(request).await?.context("Daemon closed the connection")
+    }
+
+    /// `send_request`, but `None` if the daemon closed the connection
+    /// without answering
+    async fn try_request(&mut self, request: Request) -> Result<Option<Response>> {
+        let request_json = serde_json::to_string(&request)?;
+        let mut sent = self.write_request(&request_json).await?;
+        if let (false, true, Some(client)) = (sent, self.answered, &self.client) {
+            *self = client.connect().await?;
+            sent = self.write_request(&request_json).await?;
+        }
+        if !sent {
+            return Ok(None);
+        }
+        self.next_response().await
+    }
+
+    /// Read the next response from a streaming connection (e.g. the
+    /// `LogChunk`s after `AttachStarted`); `None` once the daemon closes it
+    pub async fn next_response(&mut self) -> Result<Option<Response>> {
+        let mut line = String::new();
+        match read_line_limited(&mut self.reader, &mut line, MAX_RESPONSE_BYTES).await {
+            Ok(0) => return Ok(None),
+            Ok(_) => {}
+            Err(e) if is_hang_up(&e) => return Ok(None),
+            Err(e) => return Err(e).context("Failed to read daemon response"),
+        }
+        self.answered = true;
+
+        let response: Response = serde_json::from_str(&line)
+            .context("Failed to parse daemon response")?;
+
+        Ok(Some(response))
+    }
+
+    /// Send one request line; `false` if the daemon had already closed
+    /// the connection
+    async fn write_request(&mut self, request_json: &str) -> Result<bool> {
+        // Send request (JSON + newline)
+        let sent = async {
+            self.writer.write_all(request_json.as_bytes()).await?;
+            self.writer.write_all(b"\n").await?;
+            self.writer.flush().await
+        }
+        .await;
+        match sent {
+            Ok(()) => Ok(true),
+            Err(e) if is_hang_up(&e) => Ok(false),
+            Err(e) => Err(e.into()),
+        }
+    }
+}
+
+/// Whether an I/O error means the other end closed the connection
+fn is_hang_up(error: &std::io::Error) -> bool {
+    matches!(error.kind(), std::io::ErrorKind::BrokenPipe | std::io::ErrorKind::ConnectionReset)
 }
 
 impl Client {
     /// Create a new client
     pub fn new() -> Result<Self> {
         let socket_path = Self::socket_path()?;
-        Ok(Client { socket_path })
+        Ok(Client { socket_path, remote: None, token: crate::auth::client_token() })
+    }
+
+    /// Create a client for an explicit socket path (sending no token)
+    pub fn with_socket_path(socket_path: PathBuf) -> Self {
+        Client { socket_path, remote: None, token: None }
+    }
+
+    /// Connect to a daemon's TCP listener at `addr` (`host:port`) instead
+    /// of the local socket
+    pub fn connect_to(mut self, addr: impl Into<String>) -> Self {
+        self.remote = Some(addr.into());
+        self
+    }
+
+    /// The TCP address this client connects to, if not the local socket
+    pub fn remote(&self) -> Option<&str> {
+        self.remote.as_deref()
     }
 
     /// Get the Unix socket path
@@ -26,41 +149,149 @@ impl Client {
             .join("daemon.sock"))
     }
 
-    /// Send a request to the daemon and get a response
-    pub async fn send_request(&self, request: Request) -> Result<Response> {
-        // Connect to daemon
+    /// Open a connection to the daemon that can carry several requests,
+    /// authenticated if the client has a token and checked to speak a
+    /// protocol version this client understands.
+    ///
+    /// Daemons from before authentication and negotiation (protocol 0)
+    /// hang up on requests they don't know; they get a fresh connection
+    /// without the handshake.
+    pub async fn session(&self) -> Result<ClientSession> {
+        let mut session = self.connect().await?;
+        if let Some(token) = &self.token {
+            let Some(response) = session.try_request(Request::Authenticate { token: token.clone() }).await? else {
+                return self.connect().await;
+            };
+            match response {
+                // Daemons from before authentication don't know the request
+                // (and don't need it)
+                Response::Ok | Response::Error { code: ErrorCode::Unimplemented | ErrorCode::UnknownRequest, .. } => {}
+                Response::Error { message, .. } => anyhow::bail!("Daemon rejected the auth token: {}", message),
+                other => anyhow::bail!("Unexpected response to authentication: {:?}", other),
+            }
+        }
+
+        let hello = Request::Hello {
+            protocol_version: PROTOCOL_VERSION,
+            version: Some(env!("CARGO_PKG_VERSION").to_string()),
+        };
+        let Some(response) = session.try_request(hello).await? else {
+            return self.connect().await;
+        };
+        match response {
+            Response::Hello { protocol_version, version } if !protocol_compatible(protocol_version) => anyhow::bail!(
+                "Client/daemon version mismatch: the daemon (version {}) speaks protocol {}, this client (version {}) \
+                 needs at least {}; please upgrade and restart the daemon",
+                version,
+                protocol_version,
+                env!("CARGO_PKG_VERSION"),
+                MIN_PROTOCOL_VERSION
+            ),
+            // Daemons from before negotiation speak protocol 1
+            Response::Hello { .. }
+            | Response::Error { code: ErrorCode::Unimplemented | ErrorCode::UnknownRequest, .. } => Ok(session),
+            Response::Error { message, code: ErrorCode::VersionMismatch } => anyhow::bail!(message),
+            Response::Error { message, .. } => anyhow::bail!("Daemon rejected the connection: {}", message),
+            other => anyhow::bail!("Unexpected response to hello: {:?}", other),
+        }
+    }
+
+    /// Connect to the daemon's socket, or its TCP listener with `connect_to`
+    async fn connect(&self) -> Result<ClientSession> {
+        if let Some(addr) = &self.remote {
+            let stream = TcpStream::connect(addr.as_str())
+                .await
+                .with_context(|| format!("Failed to connect to daemon at {}. Is it listening?", addr))?;
+            stream.set_nodelay(true)?;
+            let (reader, writer) = stream.into_split();
+            return Ok(ClientSession {
+                client: Some(self.clone()),
+                ..ClientSession::from_halves(reader, writer)
+            });
+        }
+
         let stream = UnixStream::connect(&self.socket_path)
             .await
             .context("Failed to connect to daemon. Is it running?")?;
 
-        let (reader, mut writer) = stream.into_split();
-        let mut reader = BufReader::new(reader);
+        let (reader, writer) = stream.into_split();
+        Ok(ClientSession {
+            client: Some(self.clone()),
+            ..ClientSession::from_halves(reader, writer)
+        })
+    }
 
-        // Send request (JSON + newline)
-        let request_json = serde_json::to_string(&request)?;
-        writer.write_all(request_json.as_bytes()).await?;
-        writer.write_all(b"\n").await?;
-        writer.flush().await?;
+    /// Send a single request to the daemon and get a response
+    pub async fn send_request(&self, request: Request) -> Result<Response> {
+        self.session().await?.send_request(request).await
+    }
 
-        // Read response
-        let mut line = String::new();
-        reader.read_line(&mut line).await?;
+    /// The daemon socket this client connects to
+    pub fn socket(&self) -> &std::path::Path {
+        &self.socket_path
+    }
 
-        let response: Response = serde_json::from_str(&line)
-            .context("Failed to parse daemon response")?;
+    /// Check whether a daemon is answering on the socket.
+    ///
+    /// Only a refused connection makes the socket stale. A daemon that
+    /// accepts but doesn't answer within a second, or a socket we can't
+    /// connect to for another reason (e.g. permissions), counts as alive:
+    /// better to refuse to start than to steal a busy daemon's socket.
+    ///
+    /// A remote daemon is alive if it answers and absent otherwise.
+    pub async fn probe(&self) -> Probe {
+        if self.remote.is_some() {
+            // Unauthenticated: any answer, even an error, means it's there
+            let ping = async { self.connect().await?.send_request(Request::Ping { nonce: None }).await };
+            return match tokio::time::timeout(std::time::Duration::from_secs(5), ping).await {
+                Ok(Ok(_)) => Probe::Alive,
+                Ok(Err(_)) | Err(_) => Probe::Absent,
+            };
+        }
+        if !self.socket_path.exists() {
+            return Probe::Absent;
+        }
 
-        Ok(response)
+        let stream = match UnixStream::connect(&self.socket_path).await {
+            Ok(stream) => stream,
+            Err(e) if e.kind() == std::io::ErrorKind::ConnectionRefused => return Probe::Stale,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Probe::Absent,
+            Err(_) => return Probe::Alive,
+        };
+        let (reader, writer) = stream.into_split();
+        let mut session = ClientSession::from_halves(reader, writer);
+        let ping = session.send_request(Request::Ping { nonce: None });
+        match tokio::time::timeout(std::time::Duration::from_secs(1), ping).await {
+            Ok(Err(_)) => Probe::Stale,
+            Ok(Ok(_)) | Err(_) => Probe::Alive,
+        }
     }
 
-    /// Check if daemon is running
-    pub fn is_daemon_running(&self) -> bool {
-        self.socket_path.exists()
+    /// Check if daemon is running.
+    ///
+    /// Pings it rather than trusting the socket file, which a crashed
+    /// daemon leaves behind. A leftover socket that refuses connections
+    /// is removed.
+    pub async fn is_daemon_running(&self) -> bool {
+        match self.probe().await {
+            Probe::Alive => true,
+            Probe::Absent => false,
+            Probe::Stale => {
+                let refused = std::os::unix::net::UnixStream::connect(&self.socket_path)
+                    .is_err_and(|e| e.kind() == std::io::ErrorKind::ConnectionRefused);
+                if refused {
+                    let _ = std::fs::remove_file(&self.socket_path);
+                }
+                false
+            }
+        }
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use tokio::io::AsyncBufReadExt;
 
     #[test]
     fn test_client_creation() {
@@ -73,4 +304,156 @@ mod tests {
         let path = Client::socket_path().unwrap();
         assert!(path.to_str().unwrap().contains(".claude-sessions"));
     }
+
+    #[tokio::test]
+    async fn test_dangling_socket_is_not_running() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let socket_path = temp_dir.path().join("daemon.sock");
+        // A daemon that died without cleaning up
+        drop(std::os::unix::net::UnixListener::bind(&socket_path).unwrap());
+        assert!(socket_path.exists());
+
+        let client = Client::with_socket_path(socket_path.clone());
+        assert!(!client.is_daemon_running().await);
+        assert!(!socket_path.exists(), "stale socket should be cleaned up");
+        assert!(!client.is_daemon_running().await);
+    }
+
+    #[tokio::test]
+    async fn test_session_reuses_one_connection() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+        use std::sync::Arc;
+        use tokio::net::UnixListener;
+
+        let temp_dir = tempfile::tempdir().unwrap();
+        let socket_path = temp_dir.path().join("daemon.sock");
+        let listener = UnixListener::bind(&socket_path).unwrap();
+
+        // Fake daemon: count connections, answer every request line
+        let connections = Arc::new(AtomicUsize::new(0));
+        let counter = Arc::clone(&connections);
+        tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                counter.fetch_add(1, Ordering::SeqCst);
+                tokio::spawn(async move {
+                    let (reader, mut writer) = stream.into_split();
+                    let mut lines = BufReader::new(reader).lines();
+                    while let Ok(Some(line)) = lines.next_line().await {
+                        let response = match serde_json::from_str::<Request>(&line).unwrap() {
+                            Request::ListSessions { .. } => Response::SessionList { sessions: vec![] },
+                            Request::StopSession { session_id, .. } => {
+                                Response::SessionStopped { session_id }
+                            }
+                            Request::Hello { protocol_version, .. } => Response::Hello {
+                                protocol_version,
+                                version: "test".to_string(),
+                            },
+                            _ => Response::Ok,
+                        };
+                        let json = serde_json::to_string(&response).unwrap();
+                        writer.write_all(json.as_bytes()).await.unwrap();
+                        writer.write_all(b"\n").await.unwrap();
+                    }
+                });
+            }
+        });
+
+        let client = Client::with_socket_path(socket_path);
+        let mut session = client.session().await.unwrap();
+
+        let listed = session.send_request(Request::ListSessions { fields: None, idle_threshold_secs: None }).await.unwrap();
+        assert!(matches!(listed, Response::SessionList { .. }));
+
+        let stopped = session
+            .send_request(Request::StopSession {
+                session_id: "abc".to_string(),
+                checkpoint: false,
+                export_path: None,
+                force_after_secs: None,
+            })
+            .await
+            .unwrap();
+        assert!(matches!(stopped, Response::SessionStopped { .. }));
+
+        assert_eq!(connections.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn test_session_reconnects_to_one_request_daemon() {
+        use tokio::net::UnixListener;
+
+        let temp_dir = tempfile::tempdir().unwrap();
+        let socket_path = temp_dir.path().join("daemon.sock");
+        let listener = UnixListener::bind(&socket_path).unwrap();
+
+        // Fake daemon from before negotiation: answer one request, then
+        // hang up
+        tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                let (reader, mut writer) = stream.into_split();
+                let mut line = String::new();
+                BufReader::new(reader).read_line(&mut line).await.unwrap();
+                let response = match serde_json::from_str(&line).unwrap() {
+                    Request::Hello { .. } => Response::Error {
+                        message: "Unsupported request: hello".to_string(),
+                        code: ErrorCode::Unimplemented,
+                    },
+                    _ => Response::Ok,
+                };
+                let json = serde_json::to_string(&response).unwrap();
+                writer.write_all(json.as_bytes()).await.unwrap();
+                writer.write_all(b"\n").await.unwrap();
+            }
+        });
+
+        let client = Client::with_socket_path(socket_path);
+        let mut session = client.session().await.unwrap();
+        for text in ["one", "two"] {
+            let request = Request::SendInput { session_id: "abc".to_string(), text: text.to_string() };
+            assert!(matches!(session.send_request(request).await.unwrap(), Response::Ok));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_session_skips_handshake_with_daemon_that_hangs_up() {
+        use tokio::net::UnixListener;
+
+        let temp_dir = tempfile::tempdir().unwrap();
+        let socket_path = temp_dir.path().join("daemon.sock");
+        let listener = UnixListener::bind(&socket_path).unwrap();
+
+        // Fake protocol-0 daemon: closes the connection on request types
+        // it doesn't know, answers the rest
+        tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                tokio::spawn(async move {
+                    let (reader, mut writer) = stream.into_split();
+                    let mut lines = BufReader::new(reader).lines();
+                    while let Ok(Some(line)) = lines.next_line().await {
+                        if !line.contains(r#""type":"ping""#) {
+                            return;
+                        }
+                        let json = serde_json::to_string(&Response::Pong {
+                            nonce: None,
+                            active_connections: None,
+                            max_connections: None,
+                        })
+                        .unwrap();
+                        writer.write_all(json.as_bytes()).await.unwrap();
+                        writer.write_all(b"\n").await.unwrap();
+                    }
+                });
+            }
+        });
+
+        let plain = Client::with_socket_path(socket_path.clone());
+        let with_token = Client { token: Some("secret".to_string()), ..plain.clone() };
+        for client in [plain, with_token] {
+            let mut session = client.session().await.unwrap();
+            for _ in 0..2 {
+                let response = session.send_request(Request::Ping { nonce: None }).await.unwrap();
+                assert!(matches!(response, Response::Pong { .. }), "{:?}", response);
+            }
+        }
+    }
 }
@@ -0,0 +1,226 @@
+//! Daemon settings from `~/.claude-sessions/config.toml`.
+//!
+//! Read once when the daemon starts. Every setting has a default, a
+//! missing file means all defaults, and an environment variable beats the
+//! file for the settings that have one:
+//!
+//! ```toml
+//! claude_bin = "/opt/claude/bin/claude"   # CLAUDE_SESSIONS_BIN
+//! idle_timeout_secs = 3600                # CLAUDE_SESSIONS_IDLE_TIMEOUT_SECS
+//! max_sessions = 32                       # CLAUDE_SESSIONS_MAX_SESSIONS (0 = no limit)
+//! max_log_bytes = 52428800                # CLAUDE_SESSIONS_MAX_LOG_BYTES (0 = never rotate)
+//! keep_logs_days = 0                      # CLAUDE_SESSIONS_KEEP_LOGS_DAYS (0 = forever)
+//! prune_records = false
+//! reattach = false
+//! ```
+//!
+//! `daemon` flags (`--idle-timeout`, `--keep-logs-days`, `--prune-records`,
+//! `--reattach`) beat both.
+
+use crate::logging::DEFAULT_MAX_LOG_BYTES;
+use crate::manager::DEFAULT_MAX_SESSIONS;
+use crate::pty::ClaudeBinary;
+use crate::retention::RetentionPolicy;
+use anyhow::{Context, Result};
+use serde::Deserialize;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+/// Everything the daemon reads from the config file
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// Program sessions run (see `ClaudeBinary`)
+    pub claude_bin: String,
+    /// Shut the daemon down after this long without sessions or clients
+    /// (`None` or 0: never)
+    pub idle_timeout_secs: Option<u64>,
+    /// Running sessions allowed at once (0: no limit)
+    pub max_sessions: usize,
+    /// Size at which session logs are rotated (0: never)
+    pub max_log_bytes: u64,
+    /// Days to keep logs of finished sessions (0: forever)
+    pub keep_logs_days: u32,
+    /// With `keep_logs_days`, also forget expired sessions
+    pub prune_records: bool,
+    /// Keep running sessions' terminals open across a restart and take
+    /// them back (see `crate::reattach`)
+    pub reattach: bool,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            claude_bin: ClaudeBinary::default().program,
+            idle_timeout_secs: None,
+            max_sessions: DEFAULT_MAX_SESSIONS,
+            max_log_bytes: DEFAULT_MAX_LOG_BYTES,
+            keep_logs_days: 0,
+            prune_records: false,
+            reattach: false,
+        }
+    }
+}
+
+impl Config {
+    /// Where the config file lives: `~/.claude-sessions/config.toml`
+    pub fn path() -> Result<PathBuf> {
+        Ok(crate::daemon::Daemon::socket_path()?.with_file_name("config.toml"))
+    }
+
+    /// The config file plus the environment
+    pub fn load() -> Result<Self> {
+        Self::load_with(&Self::path()?, |name| std::env::var(name).ok())
+    }
+
+    /// Defaults plus the environment, without reading a file
+    pub fn from_env() -> Self {
+        Config::default().with_env(|name| std::env::var(name).ok())
+    }
+
+    /// The file at `path` (all defaults if there is none), then whatever
+    /// `env` overrides
+    pub fn load_with(path: &Path, env: impl Fn(&str) -> Option<String>) -> Result<Self> {
+        let config = match std::fs::read_to_string(path) {
+            Ok(contents) => Self::parse(&contents).with_context(|| format!("Invalid config file {:?}", path))?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Config::default(),
+            Err(e) => return Err(e).with_context(|| format!("Failed to read config file {:?}", path)),
+        };
+        Ok(config.with_env(env))
+    }
+
+    /// Parse a config file, reporting where it went wrong
+    pub fn parse(contents: &str) -> Result<Self> {
+        toml::from_str(contents).map_err(|e| {
+            let message = e.message().trim_end();
+            match e.span() {
+                Some(span) => {
+                    let (line, column) = line_column(contents, span.start);
+                    anyhow::anyhow!("line {}, column {}: {}", line, column, message)
+                }
+                None => anyhow::anyhow!("{}", message),
+            }
+        })
+    }
+
+    /// Override settings from `env`; unset, empty or unparsable
+    /// variables leave them alone
+    pub fn with_env(mut self, env: impl Fn(&str) -> Option<String>) -> Self {
+        let var = |name: &str| env(name).map(|value| value.trim().to_string()).filter(|value| !value.is_empty());
+        if let Some(program) = var("CLAUDE_SESSIONS_BIN") {
+            self.claude_bin = program;
+        }
+        if let Some(secs) = var("CLAUDE_SESSIONS_IDLE_TIMEOUT_SECS").and_then(|v| v.parse().ok()) {
+            self.idle_timeout_secs = Some(secs);
+        }
+        if let Some(max) = var("CLAUDE_SESSIONS_MAX_SESSIONS").and_then(|v| v.parse().ok()) {
+            self.max_sessions = max;
+        }
+        if let Some(max) = var("CLAUDE_SESSIONS_MAX_LOG_BYTES").and_then(|v| v.parse().ok()) {
+            self.max_log_bytes = max;
+        }
+        if let Some(days) = var("CLAUDE_SESSIONS_KEEP_LOGS_DAYS").and_then(|v| v.parse().ok()) {
+            self.keep_logs_days = days;
+        }
+        self
+    }
+
+    /// The program sessions run, without arguments
+    pub fn claude_binary(&self) -> ClaudeBinary {
+        ClaudeBinary { program: self.claude_bin.clone(), args: Vec::new() }
+    }
+
+    pub fn idle_timeout(&self) -> Option<Duration> {
+        self.idle_timeout_secs.filter(|&secs| secs > 0).map(Duration::from_secs)
+    }
+
+    /// Limit on running sessions (`None`: unlimited)
+    pub fn max_sessions(&self) -> Option<usize> {
+        (self.max_sessions > 0).then_some(self.max_sessions)
+    }
+
+    /// Log rotation threshold (`None`: never rotate)
+    pub fn max_log_bytes(&self) -> Option<u64> {
+        (self.max_log_bytes > 0).then_some(self.max_log_bytes)
+    }
+
+    pub fn retention(&self) -> RetentionPolicy {
+        RetentionPolicy { keep_logs_days: self.keep_logs_days, prune_records: self.prune_records }
+    }
+}
+
+/// 1-based line and column (in characters) of byte `offset` in `text`
+fn line_column(text: &str, offset: usize) -> (usize, usize) {
+    let before = text.get(..offset).unwrap_or(text);
+    let line_start = before.rfind('\n').map_or(0, |i| i + 1);
+    (before.matches('\n').count() + 1, before[line_start..].chars().count() + 1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn no_env(_: &str) -> Option<String> {
+        None
+    }
+
+    #[test]
+    fn test_missing_file_gives_defaults() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let config = Config::load_with(&temp_dir.path().join("config.toml"), no_env).unwrap();
+        assert_eq!(config, Config::default());
+        assert_eq!(config.claude_binary(), ClaudeBinary::default());
+        assert_eq!(config.max_sessions(), Some(DEFAULT_MAX_SESSIONS));
+        assert_eq!(config.max_log_bytes(), Some(DEFAULT_MAX_LOG_BYTES));
+        assert_eq!(config.idle_timeout(), None);
+        assert!(!config.retention().is_enabled());
+
+        // An empty file is all defaults too
+        std::fs::write(temp_dir.path().join("config.toml"), "").unwrap();
+        assert_eq!(Config::load_with(&temp_dir.path().join("config.toml"), no_env).unwrap(), Config::default());
+    }
+
+    #[test]
+    fn test_env_beats_file_beats_default() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let path = temp_dir.path().join("config.toml");
+        std::fs::write(&path, "claude_bin = \"/opt/claude\"\nmax_sessions = 4\nkeep_logs_days = 7\n").unwrap();
+
+        let config = Config::load_with(&path, no_env).unwrap();
+        assert_eq!(config.claude_bin, "/opt/claude");
+        assert_eq!(config.max_sessions(), Some(4));
+        assert_eq!(config.retention().keep_logs_days, 7);
+        assert_eq!(config.max_log_bytes, DEFAULT_MAX_LOG_BYTES);
+
+        let env = |name: &str| match name {
+            "CLAUDE_SESSIONS_MAX_SESSIONS" => Some("0".to_string()),
+            "CLAUDE_SESSIONS_IDLE_TIMEOUT_SECS" => Some(" 90 ".to_string()),
+            // Ignored: the file's value stays
+            "CLAUDE_SESSIONS_KEEP_LOGS_DAYS" => Some("a week".to_string()),
+            "CLAUDE_SESSIONS_BIN" => Some(String::new()),
+            _ => None,
+        };
+        let config = Config::load_with(&path, env).unwrap();
+        assert_eq!(config.max_sessions(), None);
+        assert_eq!(config.idle_timeout(), Some(Duration::from_secs(90)));
+        assert_eq!(config.keep_logs_days, 7);
+        assert_eq!(config.claude_bin, "/opt/claude");
+    }
+
+    #[test]
+    fn test_parse_errors_say_where() {
+        let err = Config::parse("max_sessions = 4\nmax_log_bytes = \"big\"\n").unwrap_err();
+        assert!(err.to_string().starts_with("line 2, column 17:"), "{}", err);
+
+        let err = Config::parse("max_sesions = 4\n").unwrap_err();
+        assert!(err.to_string().starts_with("line 1, column 1:"), "{}", err);
+        assert!(err.to_string().contains("max_sesions"), "{}", err);
+
+        let temp_dir = tempfile::tempdir().unwrap();
+        let path = temp_dir.path().join("config.toml");
+        std::fs::write(&path, "max_sessions = [\n").unwrap();
+        let err = Config::load_with(&path, no_env).unwrap_err();
+        assert!(format!("{:#}", err).contains("config.toml"), "{:#}", err);
+        assert!(format!("{:#}", err).contains("line "), "{:#}", err);
+    }
+}
@@ -1,36 +1,325 @@
-use crate::ipc::{Request, Response, SessionInfo};
-use crate::manager::SessionManager;
+use crate::config::Config;
+use crate::ipc::{
+    protocol_compatible, read_line_limited, DaemonInfo, ErrorCode, Request, Response, SessionDetail, SessionInfo,
+    StopFailure, MAX_REQUEST_BYTES, MIN_PROTOCOL_VERSION, PROTOCOL_VERSION, REQUEST_TYPES,
+};
+use crate::manager::{PruneOptions, SessionEvent, SessionManager, ShutdownMode, StartOptions, ACTIVITY_SAVE_INTERVAL};
+use crate::retention::{RetentionPolicy, SWEEP_INTERVAL};
+use crate::session::{LogNaming, Session, SessionStatus};
+use crate::logging::{last_seq, LogEntry, LogFollower};
+use crate::tail;
 use anyhow::{Context, Result};
 use std::path::PathBuf;
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::Arc;
-use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
-use tokio::net::{UnixListener, UnixStream};
-use tokio::sync::Mutex;
+use std::time::{Duration, Instant};
+use base64::{engine::general_purpose, Engine as _};
+use std::path::Path;
+use tokio::io::{AsyncBufRead, AsyncRead, AsyncWrite, AsyncWriteExt, BufReader};
+use tokio::net::{TcpListener, UnixListener};
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
 use uuid::Uuid;
 
+/// What is behind the daemon socket path
+#[derive(Debug, PartialEq, Eq)]
+pub enum Probe {
+    /// A daemon answered
+    Alive,
+    /// The socket file exists but nothing is listening
+    Stale,
+    /// No socket file
+    Absent,
+}
+
+/// Set `path`'s permission bits to `mode` (a no-op off Unix)
+pub(crate) fn restrict_permissions(path: &Path, mode: u32) -> Result<()> {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        std::fs::set_permissions(path, std::fs::Permissions::from_mode(mode))
+            .with_context(|| format!("Failed to restrict permissions of {:?}", path))?;
+    }
+    #[cfg(not(unix))]
+    let _ = (path, mode);
+    Ok(())
+}
+
+/// Exclusive advisory lock (`flock`) on the daemon lock file, held for
+/// as long as a daemon runs.
+///
+/// Unlike the socket, the lock is taken before session state is recovered
+/// and can't be raced: the kernel hands it to exactly one process and
+/// releases it when that process exits, however it exits.
+#[derive(Debug)]
+pub struct DaemonLock {
+    _file: std::fs::File,
+}
+
+/// `DaemonLock::acquire` found the lock held by another daemon.
+///
+/// Returned inside `anyhow::Error`; find it with `downcast_ref`.
+#[derive(Debug)]
+pub struct LockHeld(pub PathBuf);
+
+impl std::fmt::Display for LockHeld {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Another daemon is already running (lock {:?} is held)", self.0)
+    }
+}
+
+impl std::error::Error for LockHeld {}
+
+impl DaemonLock {
+    /// Take the lock at `path`, failing at once (with `LockHeld`) if another
+    /// process holds it
+    pub fn acquire(path: &Path) -> Result<Self> {
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        let file = std::fs::OpenOptions::new()
+            .create(true)
+            .truncate(false)
+            .write(true)
+            .open(path)
+            .with_context(|| format!("Failed to open lock file {:?}", path))?;
+        #[cfg(unix)]
+        {
+            use std::os::unix::io::AsRawFd;
+            if unsafe { libc::flock(file.as_raw_fd(), libc::LOCK_EX | libc::LOCK_NB) } != 0 {
+                let err = std::io::Error::last_os_error();
+                if err.kind() == std::io::ErrorKind::WouldBlock {
+                    return Err(LockHeld(path.to_path_buf()).into());
+                }
+                return Err(err).with_context(|| format!("Failed to lock {:?}", path));
+            }
+        }
+        Ok(DaemonLock { _file: file })
+    }
+}
+
+/// How `Daemon::run` ended
+#[derive(Debug, PartialEq, Eq)]
+pub enum RunOutcome {
+    /// Served until shut down
+    Stopped,
+    /// Another daemon won the race for the socket
+    AlreadyRunning,
+}
+
+/// How many client connections the daemon serves at once.
+///
+/// Guards against a misbehaving client (e.g. a UI stuck in a reconnect
+/// loop) exhausting file descriptors and tasks.
+#[derive(Debug, Clone)]
+pub struct ConnectionLimit {
+    pub max: usize,
+    /// Hold further connections until a slot frees up instead of
+    /// answering them with a busy error
+    pub queue: bool,
+}
+
+impl Default for ConnectionLimit {
+    fn default() -> Self {
+        ConnectionLimit { max: 64, queue: false }
+    }
+}
+
+impl ConnectionLimit {
+    /// Build a limit from defaults plus environment overrides
+    /// (`CLAUDE_SESSIONS_MAX_CONNECTIONS`, `CLAUDE_SESSIONS_QUEUE_CONNECTIONS=1`)
+    pub fn from_env() -> Self {
+        let mut limit = Self::default();
+        if let Some(max) = std::env::var("CLAUDE_SESSIONS_MAX_CONNECTIONS").ok().and_then(|v| v.parse().ok()) {
+            limit.max = max;
+        }
+        if let Ok(queue) = std::env::var("CLAUDE_SESSIONS_QUEUE_CONNECTIONS") {
+            limit.queue = matches!(queue.as_str(), "1" | "true" | "yes");
+        }
+        limit
+    }
+}
+
+/// How long a connection that must authenticate has to send its token
+const AUTH_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Connection slots handed out against a `ConnectionLimit`
+pub struct Connections {
+    limit: ConnectionLimit,
+    permits: Arc<Semaphore>,
+    /// Connections served since the daemon started
+    accepted: AtomicU64,
+    /// Token every connection must present first, if required (see `auth`)
+    token: Option<String>,
+    /// When the daemon started serving, for `DaemonInfo`
+    started_at: std::time::SystemTime,
+}
+
+impl Connections {
+    pub fn new(limit: ConnectionLimit) -> Self {
+        let max = limit.max.max(1);
+        Connections {
+            limit: ConnectionLimit { max, ..limit },
+            permits: Arc::new(Semaphore::new(max)),
+            accepted: AtomicU64::new(0),
+            token: None,
+            started_at: std::time::SystemTime::now(),
+        }
+    }
+
+    /// Require connections to authenticate with `token` (off: `None`)
+    pub fn with_token(mut self, token: Option<String>) -> Self {
+        self.token = token;
+        self
+    }
+
+    /// Connections served since the daemon started
+    pub fn accepted(&self) -> u64 {
+        self.accepted.load(Ordering::Relaxed)
+    }
+
+    /// Connections currently being served
+    pub fn active(&self) -> usize {
+        self.limit.max - self.permits.available_permits()
+    }
+
+    pub fn max(&self) -> usize {
+        self.limit.max
+    }
+}
+
+/// Decides when an idle daemon should shut itself down.
+///
+/// The daemon is busy while a session is running or a connection is open
+/// (a streaming attach holds one), and any connection since the last
+/// check counts as activity too, so a session started and stopped in
+/// between still resets the timer.
+#[derive(Debug)]
+pub struct IdleTimer {
+    timeout: Duration,
+    idle_since: Option<Instant>,
+    last_accepted: u64,
+}
+
+impl IdleTimer {
+    pub fn new(timeout: Duration) -> Self {
+        IdleTimer { timeout, idle_since: None, last_accepted: 0 }
+    }
+
+    /// How often to look
+    pub fn check_interval(&self) -> Duration {
+        (self.timeout / 10).clamp(Duration::from_millis(100), Duration::from_secs(5))
+    }
+
+    /// Record what the daemon is doing at `now`; returns whether it has
+    /// been idle for the whole timeout
+    pub fn observe(&mut self, busy: bool, accepted: u64, now: Instant) -> bool {
+        let active = busy || accepted != self.last_accepted;
+        self.last_accepted = accepted;
+        if active {
+            self.idle_since = None;
+            return false;
+        }
+        now.duration_since(*self.idle_since.get_or_insert(now)) >= self.timeout
+    }
+}
+
+/// Where the daemon accepts connections: its Unix socket, or a TCP
+/// address given with `daemon --listen`. Both speak the same protocol.
+pub enum Listener {
+    Unix(UnixListener),
+    Tcp(TcpListener),
+}
+
+/// A client connection accepted from a `Listener`
+trait Connection: AsyncRead + AsyncWrite + Unpin + Send {}
+
+impl<T: AsyncRead + AsyncWrite + Unpin + Send> Connection for T {}
+
+impl Listener {
+    async fn accept(&self) -> std::io::Result<Box<dyn Connection>> {
+        Ok(match self {
+            Listener::Unix(listener) => Box::new(listener.accept().await?.0),
+            Listener::Tcp(listener) => {
+                let (stream, _addr) = listener.accept().await?;
+                stream.set_nodelay(true)?;
+                Box::new(stream)
+            }
+        })
+    }
+}
+
+/// Refuse to listen where other machines can connect unless connections
+/// must authenticate: without a token only loopback addresses are allowed
+/// (`0.0.0.0` and `::` included in the refusal)
+pub fn check_listen_addr(addr: std::net::SocketAddr, authenticated: bool) -> Result<()> {
+    if !addr.ip().is_loopback() && !authenticated {
+        anyhow::bail!(
+            "Refusing to listen on {}: without an auth token (--require-token) only loopback addresses are allowed",
+            addr
+        );
+    }
+    Ok(())
+}
+
 /// Daemon manages a long-running session manager and IPC server
 pub struct Daemon {
     manager: Arc<SessionManager>,
     socket_path: PathBuf,
-    shutdown_tx: tokio::sync::broadcast::Sender<()>,
+    shutdown_tx: tokio::sync::broadcast::Sender<ShutdownMode>,
+    retention: RetentionPolicy,
+    connections: Arc<Connections>,
+    /// Shut down after this long without sessions or clients
+    idle_timeout: Option<Duration>,
+    /// Also accept connections on this TCP address
+    listen: Option<std::net::SocketAddr>,
+    /// Held from before recovery until shutdown
+    lock: Option<DaemonLock>,
+    /// Hand sessions' terminals over across restarts (see
+    /// `crate::reattach`)
+    reattach: bool,
 }
 
 impl Daemon {
-    /// Create a new daemon instance
-    pub async fn new() -> Result<Self> {
+    /// Create a new daemon instance configured by `config`
+    ///
+    /// Fails with `LockHeld` if another daemon holds the lock file; nothing
+    /// (in particular no persisted state) is touched in that case.
+    pub async fn new(config: Config) -> Result<Self> {
         let socket_path = Self::socket_path()?;
-        let manager = Arc::new(SessionManager::with_recovery().await);
+        let lock = DaemonLock::acquire(&Self::lock_path()?)?;
+        let manager = Arc::new(SessionManager::with_recovery(&config).await);
         let (shutdown_tx, _) = tokio::sync::broadcast::channel(1);
 
         Ok(Daemon {
             manager,
             socket_path,
             shutdown_tx,
+            retention: config.retention(),
+            connections: Arc::new(Connections::new(ConnectionLimit::from_env())),
+            idle_timeout: config.idle_timeout(),
+            listen: None,
+            lock: Some(lock),
+            reattach: config.reattach,
         })
     }
 
+    /// Accept connections on `addr` over TCP as well as on the Unix socket
+    /// (see `check_listen_addr`)
+    pub fn with_listen(mut self, addr: Option<std::net::SocketAddr>) -> Self {
+        self.listen = addr;
+        self
+    }
+
+    /// Require every connection, on the socket and over TCP, to present
+    /// `token` first (off by default)
+    pub fn with_token(mut self, token: Option<String>) -> Self {
+        let limit = self.connections.limit.clone();
+        self.connections = Arc::new(Connections::new(limit).with_token(token));
+        self
+    }
+
     /// Get the Unix socket path for IPC
-    fn socket_path() -> Result<PathBuf> {
+    pub fn socket_path() -> Result<PathBuf> {
         let home = std::env::var("HOME")
             .or_else(|_| std::env::var("USERPROFILE"))
             .context("Cannot determine home directory")?;
@@ -39,90 +328,650 @@ impl Daemon {
             .join("daemon.sock"))
     }
 
-    /// Check if daemon is already running
-    pub fn is_running() -> bool {
-        if let Ok(socket_path) = Self::socket_path() {
-            socket_path.exists()
-        } else {
-            false
+    /// Where a backgrounded daemon's stdout/stderr go
+    pub fn log_path() -> Result<PathBuf> {
+        Ok(Self::socket_path()?.with_file_name("daemon.log"))
+    }
+
+    /// The lock file that keeps a second daemon from starting
+    pub fn lock_path() -> Result<PathBuf> {
+        Ok(Self::socket_path()?.with_file_name("daemon.lock"))
+    }
+
+    /// Where the running daemon records its process ID
+    pub fn pid_path() -> Result<PathBuf> {
+        Ok(Self::socket_path()?.with_file_name("daemon.pid"))
+    }
+
+    /// PID of the running daemon, from the PID file at `pid_path`.
+    ///
+    /// A PID file naming a process that is gone (the daemon crashed) is
+    /// stale: it is removed and `None` returned.
+    pub fn read_pid(pid_path: &Path) -> Option<u32> {
+        let pid = std::fs::read_to_string(pid_path).ok()?.trim().parse().ok();
+        match pid {
+            Some(pid) if crate::persistence::is_process_alive(pid) => Some(pid),
+            _ => {
+                let _ = std::fs::remove_file(pid_path);
+                None
+            }
         }
     }
 
-    /// Start the daemon (blocking)
-    pub async fn run(&mut self) -> Result<()> {
-        // Ensure socket directory exists
-        if let Some(parent) = self.socket_path.parent() {
+    /// Check whether a daemon is answering on `socket_path` (see
+    /// `Client::probe`)
+    pub async fn probe(socket_path: &Path) -> Probe {
+        crate::client::Client::with_socket_path(socket_path.to_path_buf()).probe().await
+    }
+
+    /// Bind the daemon socket, cleaning up a stale one first.
+    ///
+    /// Returns `None` if another daemon is alive on it. Losing a bind race
+    /// (`EADDRINUSE` after the probe) re-probes instead of failing, so
+    /// concurrent starts end with exactly one daemon.
+    ///
+    /// Only the owner may connect: the directory is made `0700` before
+    /// binding (closing the window before the socket's own mode is set)
+    /// and the socket `0600` before it is returned for accepting.
+    pub async fn bind(socket_path: &Path) -> Result<Option<UnixListener>> {
+        if let Some(parent) = socket_path.parent() {
             std::fs::create_dir_all(parent)?;
+            restrict_permissions(parent, 0o700)?;
         }
 
-        // Remove stale socket if it exists
-        if self.socket_path.exists() {
-            std::fs::remove_file(&self.socket_path)?;
+        for _ in 0..3 {
+            match Self::probe(socket_path).await {
+                Probe::Alive => return Ok(None),
+                Probe::Stale => match std::fs::remove_file(socket_path) {
+                    Ok(()) => {}
+                    Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+                    Err(e) => return Err(e).context("Failed to remove stale socket"),
+                },
+                Probe::Absent => {}
+            }
+
+            match UnixListener::bind(socket_path) {
+                Ok(listener) => {
+                    restrict_permissions(socket_path, 0o600)?;
+                    return Ok(Some(listener));
+                }
+                Err(e) if e.kind() == std::io::ErrorKind::AddrInUse => continue,
+                Err(e) => return Err(e).context("Failed to bind Unix socket"),
+            }
         }
 
-        // Bind Unix socket
-        let listener = UnixListener::bind(&self.socket_path)
-            .context("Failed to bind Unix socket")?;
+        anyhow::bail!("Could not bind {:?}: it keeps being taken", socket_path)
+    }
+
+    /// Start the daemon (blocking)
+    pub async fn run(&mut self) -> Result<RunOutcome> {
+        let Some(listener) = Self::bind(&self.socket_path).await? else {
+            return Ok(RunOutcome::AlreadyRunning);
+        };
+        let tcp_listener = match self.listen {
+            Some(addr) => {
+                check_listen_addr(addr, self.connections.token.is_some())?;
+                let listener = TcpListener::bind(addr)
+                    .await
+                    .with_context(|| format!("Failed to listen on {}", addr))?;
+                Some(listener)
+            }
+            None => None,
+        };
+
+        let pid_path = self.socket_path.with_file_name("daemon.pid");
+        if let Err(e) = std::fs::write(&pid_path, format!("{}\n", std::process::id())) {
+            eprintln!("⚠️  Failed to write PID file {:?}: {}", pid_path, e);
+        }
 
         println!("✅ Daemon started. Socket: {:?}", self.socket_path);
+        if let Some(listener) = &tcp_listener {
+            println!("🌐 Listening on {}", listener.local_addr()?);
+        }
 
-        // Accept connections in a loop
+        #[cfg(unix)]
+        if self.reattach {
+            self.take_terminals().await;
+        }
+        if self.retention.is_enabled() {
+            self.spawn_retention_sweeper();
+        }
+        self.spawn_activity_saver();
+        if let Some(timeout) = self.idle_timeout {
+            self.spawn_idle_watchdog(timeout);
+        }
         let mut shutdown_rx = self.shutdown_tx.subscribe();
+
+        let serve_tcp = async {
+            if let Some(listener) = tcp_listener {
+                let manager = Arc::clone(&self.manager);
+                Self::serve(Listener::Tcp(listener), manager, self.shutdown_tx.clone(), Arc::clone(&self.connections))
+                    .await;
+            }
+        };
+        tokio::join!(
+            Self::serve(
+                Listener::Unix(listener),
+                Arc::clone(&self.manager),
+                self.shutdown_tx.clone(),
+                Arc::clone(&self.connections),
+            ),
+            serve_tcp,
+        );
+        println!("Daemon shutting down...");
+
+        let mode = shutdown_rx.try_recv().unwrap_or_default();
+        let running = Self::shutdown(&self.socket_path, self.manager.shutdown_all(mode)).await;
+        #[cfg(unix)]
+        let kept = if mode == ShutdownMode::Leave && self.reattach { self.hand_off_terminals().await } else { 0 };
+        #[cfg(not(unix))]
+        let kept = 0;
+        let _ = std::fs::remove_file(&pid_path);
+        // Sessions are saved; another daemon may start now
+        self.lock.take();
+        match mode {
+            ShutdownMode::Kill => println!("✅ Daemon stopped ({} session(s) stopped)", running),
+            ShutdownMode::Leave if self.reattach => println!(
+                "✅ Daemon stopped ({} of {} session(s) kept for the next daemon to reattach)",
+                kept, running
+            ),
+            ShutdownMode::Leave => println!(
+                "✅ Daemon stopped ({} session(s) not signalled; they get SIGHUP as their terminals close)",
+                running
+            ),
+        }
+
+        Ok(RunOutcome::Stopped)
+    }
+
+    /// Take back the terminals a keeper holds for us and reattach the
+    /// orphaned sessions they belong to (see `crate::reattach`)
+    #[cfg(unix)]
+    async fn take_terminals(&self) {
+        let socket = crate::reattach::keeper_socket_path(&self.socket_path);
+        let taken = tokio::task::spawn_blocking(move || crate::reattach::take_terminals(&socket)).await;
+        match taken {
+            Ok(Ok(terminals)) if terminals.is_empty() => {}
+            Ok(Ok(terminals)) => {
+                let offered = terminals.len();
+                let reattached = self.manager.reattach(terminals).await;
+                println!("🔗 Reattached {} of {} session(s) kept by the previous daemon", reattached, offered);
+            }
+            Ok(Err(e)) => eprintln!("⚠️  Failed to take terminals from the keeper: {:#}", e),
+            Err(e) => eprintln!("⚠️  Failed to take terminals from the keeper: {}", e),
+        }
+    }
+
+    /// Hand the running sessions' terminals to a keeper, so they stay open
+    /// after this daemon exits; returns how many
+    #[cfg(unix)]
+    async fn hand_off_terminals(&self) -> usize {
+        let terminals = self.manager.release_terminals().await;
+        if terminals.is_empty() {
+            return 0;
+        }
+        let socket = crate::reattach::keeper_socket_path(&self.socket_path);
+        match crate::reattach::spawn_keeper(&socket, &terminals) {
+            Ok(()) => terminals.len(),
+            Err(e) => {
+                eprintln!("⚠️  Failed to keep the sessions' terminals: {:#}", e);
+                0
+            }
+        }
+    }
+
+    /// Tear down once the accept loop has ended (no new connections are
+    /// accepted from then on), in this order:
+    ///
+    /// 1. Remove the socket, so probes see the daemon as gone and new
+    ///    connects fail fast instead of hanging on a daemon that is exiting
+    /// 2. Run `stop_sessions` (`SessionManager::shutdown_all`: sync the
+    ///    logs, stop or leave the processes, save the state), which may
+    ///    take a while
+    async fn shutdown<F, T>(socket_path: &Path, stop_sessions: F) -> T
+    where
+        F: std::future::Future<Output = T>,
+    {
+        let _ = std::fs::remove_file(socket_path);
+        stop_sessions.await
+    }
+
+    /// Accept connections until shutdown, within the connection limit.
+    ///
+    /// Over the limit, a new connection either waits for a free slot
+    /// (`queue`) or gets a busy error and is closed.
+    async fn serve(
+        listener: Listener,
+        manager: Arc<SessionManager>,
+        shutdown_tx: tokio::sync::broadcast::Sender<ShutdownMode>,
+        connections: Arc<Connections>,
+    ) {
+        let mut shutdown_rx = shutdown_tx.subscribe();
         loop {
+            // When queueing, wait for a slot before accepting so waiting
+            // clients sit in the listen backlog rather than holding a task
+            let queued = if connections.limit.queue {
+                tokio::select! {
+                    permit = Arc::clone(&connections.permits).acquire_owned() => permit.ok(),
+                    _ = shutdown_rx.recv() => break,
+                }
+            } else {
+                None
+            };
+
             tokio::select! {
                 accept_result = listener.accept() => {
                     match accept_result {
-                        Ok((stream, _addr)) => {
-                            let manager = Arc::clone(&self.manager);
-                            let shutdown_tx = self.shutdown_tx.clone();
-                            // Handle connection sequentially (no need to spawn for local IPC)
-                            if let Err(e) = Self::handle_connection(stream, manager, shutdown_tx).await {
-                                eprintln!("Connection error: {}", e);
-                            }
+                        Ok(stream) => {
+                            let permit = queued
+                                .or_else(|| Arc::clone(&connections.permits).try_acquire_owned().ok());
+                            let Some(permit) = permit else {
+                                tokio::spawn(Self::reject_busy(stream, connections.max()));
+                                continue;
+                            };
+                            connections.accepted.fetch_add(1, Ordering::Relaxed);
+                            let manager = Arc::clone(&manager);
+                            let shutdown_tx = shutdown_tx.clone();
+                            let connections = Arc::clone(&connections);
+                            // Connections may carry several requests, so each one gets
+                            // its own task to avoid a held connection blocking others
+                            tokio::spawn(async move {
+                                let _permit: OwnedSemaphorePermit = permit;
+                                if let Err(e) = Self::handle_connection(stream, manager, shutdown_tx, connections).await {
+                                    eprintln!("Connection error: {}", e);
+                                }
+                            });
                         }
                         Err(e) => {
                             eprintln!("Failed to accept connection: {}", e);
                         }
                     }
                 }
-                _ = shutdown_rx.recv() => {
-                    println!("Daemon shutting down...");
-                    break;
-                }
+                _ = shutdown_rx.recv() => break,
             }
         }
+    }
 
-        // Cleanup socket on shutdown
-        let _ = std::fs::remove_file(&self.socket_path);
-        println!("✅ Daemon stopped");
+    /// Answer a connection over the limit with a busy error.
+    ///
+    /// The client's first request is read (briefly) before answering, so
+    /// it doesn't hit a closed socket while still writing it.
+    async fn reject_busy<S: AsyncRead + AsyncWrite + Send>(stream: S, max: usize) {
+        let (reader, mut writer) = tokio::io::split(stream);
+        let mut reader = BufReader::new(reader);
+        let mut line = String::new();
+        let read = read_line_limited(&mut reader, &mut line, MAX_REQUEST_BYTES);
+        let _ = tokio::time::timeout(std::time::Duration::from_secs(1), read).await;
 
-        Ok(())
+        let response = Response::Error {
+            message: format!("Daemon busy: all {} connection slots are in use, try again", max),
+            code: ErrorCode::ResourceExhausted,
+        };
+        let _ = Self::write_response(&mut writer, &response).await;
+    }
+
+    /// Run the retention sweep now and then every `SWEEP_INTERVAL`
+    fn spawn_retention_sweeper(&self) {
+        let manager = Arc::clone(&self.manager);
+        let policy = self.retention.clone();
+        let mut shutdown_rx = self.shutdown_tx.subscribe();
+
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(SWEEP_INTERVAL);
+            loop {
+                tokio::select! {
+                    _ = interval.tick() => {}
+                    _ = shutdown_rx.recv() => return,
+                }
+                let log_dir = Session::default_log_dir();
+                match manager.sweep_retention(&policy, &log_dir, std::time::SystemTime::now()).await {
+                    Ok(report) => {
+                        for path in &report.logs_removed {
+                            println!("🧹 Retention: removed log {:?}", path);
+                        }
+                        for id in &report.records_removed {
+                            println!("🧹 Retention: forgot session {}", id);
+                        }
+                    }
+                    Err(e) => eprintln!("⚠️  Retention sweep failed: {}", e),
+                }
+            }
+        });
+    }
+
+    /// Save running sessions' last activity every `ACTIVITY_SAVE_INTERVAL`
+    fn spawn_activity_saver(&self) {
+        let manager = Arc::clone(&self.manager);
+        let mut shutdown_rx = self.shutdown_tx.subscribe();
+
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(ACTIVITY_SAVE_INTERVAL);
+            loop {
+                tokio::select! {
+                    _ = interval.tick() => {}
+                    _ = shutdown_rx.recv() => return,
+                }
+                if let Err(e) = manager.save_activity().await {
+                    eprintln!("⚠️  Failed to save session activity: {}", e);
+                }
+            }
+        });
+    }
+
+    /// Trigger the `Request::Shutdown` path once the daemon has been idle
+    /// for `timeout` (see `IdleTimer`)
+    fn spawn_idle_watchdog(&self, timeout: Duration) {
+        let manager = Arc::clone(&self.manager);
+        let connections = Arc::clone(&self.connections);
+        let shutdown_tx = self.shutdown_tx.clone();
+        let mut shutdown_rx = self.shutdown_tx.subscribe();
+
+        tokio::spawn(async move {
+            let mut timer = IdleTimer::new(timeout);
+            let mut interval = tokio::time::interval(timer.check_interval());
+            loop {
+                tokio::select! {
+                    _ = interval.tick() => {}
+                    _ = shutdown_rx.recv() => return,
+                }
+                let busy = manager.running_count().await > 0 || connections.active() > 0;
+                if timer.observe(busy, connections.accepted(), Instant::now()) {
+                    println!("💤 Idle for {:?}, shutting down", timeout);
+                    let _ = shutdown_tx.send(ShutdownMode::Leave);
+                    return;
+                }
+            }
+        });
     }
 
     /// Handle a single client connection
-    async fn handle_connection(
-        stream: UnixStream,
+    ///
+    /// Requests are newline-delimited JSON; each gets exactly one response
+    /// line. The connection stays open until the client closes it or asks
+    /// for `Shutdown`.
+    async fn handle_connection<S: AsyncRead + AsyncWrite + Send>(
+        stream: S,
         manager: Arc<SessionManager>,
-        shutdown_tx: tokio::sync::broadcast::Sender<()>,
+        shutdown_tx: tokio::sync::broadcast::Sender<ShutdownMode>,
+        connections: Arc<Connections>,
     ) -> Result<()> {
-        let (reader, mut writer) = stream.into_split();
+        let (reader, mut writer) = tokio::io::split(stream);
         let mut reader = BufReader::new(reader);
+        if let Some(token) = &connections.token {
+            if !Self::authenticate(&mut reader, &mut writer, token).await? {
+                return Ok(());
+            }
+        }
+        let mut line = String::new();
+
+        loop {
+            line.clear();
+            match read_line_limited(&mut reader, &mut line, MAX_REQUEST_BYTES).await {
+                // Clean EOF between requests: client closed the connection
+                Ok(0) => return Ok(()),
+                Ok(_) => {}
+                Err(e) if e.kind() == std::io::ErrorKind::InvalidData => {
+                    // Over the limit (or not text): the rest of the line is
+                    // still unread, so there's no next request to find
+                    let response = Response::Error {
+                        message: format!("Invalid request: {}", e),
+                        code: ErrorCode::InvalidArgument,
+                    };
+                    let _ = Self::write_response(&mut writer, &response).await;
+                    return Ok(());
+                }
+                Err(e) => return Err(e.into()),
+            }
+
+            if !line.ends_with('\n') {
+                // Peer closed mid-request, so the buffer may be truncated JSON.
+                // Report a framing error instead of a confusing parse error.
+                let response = Response::Error {
+                    message: "Incomplete request: connection closed before end of line".to_string(),
+                    code: ErrorCode::InvalidArgument,
+                };
+                let _ = Self::write_response(&mut writer, &response).await;
+                return Ok(());
+            }
+
+            let request: Request = match serde_json::from_str(&line) {
+                Ok(request) => request,
+                Err(e) => {
+                    let response = match unsupported_request_type(&line) {
+                        // A newer client asking for something this daemon lacks
+                        Some(kind) => Response::Error {
+                            message: format!("Unknown request type: {}", kind),
+                            code: ErrorCode::UnknownRequest,
+                        },
+                        // The line was read whole, so the next one is still
+                        // a request boundary: report it and carry on
+                        None => Response::Error {
+                            message: format!("Invalid request: {}", e),
+                            code: ErrorCode::InvalidArgument,
+                        },
+                    };
+                    Self::write_response(&mut writer, &response).await?;
+                    continue;
+                }
+            };
+            let attach = match &request {
+                Request::AttachSession { session_id, since_seq } => Some((session_id.clone(), *since_seq)),
+                _ => None,
+            };
+            let shutdown = matches!(request, Request::Shutdown { .. });
+            let subscribe = matches!(request, Request::Subscribe);
+            // Subscribe before replying, so no event slips in between (an
+            // attach watches for its session ending)
+            let events = (subscribe || attach.is_some()).then(|| manager.subscribe());
+
+            let response = Self::handle_request(request, &manager, shutdown_tx.clone(), &connections).await;
+            Self::write_response(&mut writer, &response).await?;
+            if shutdown {
+                return Ok(());
+            }
+            match (events, attach, &response) {
+                (Some(events), _, _) if subscribe => {
+                    return Self::stream_events(&mut reader, &mut writer, events).await;
+                }
+                // A successful attach turns the connection into a stream
+                (Some(events), Some((session_id, since_seq)), Response::AttachStarted { .. }) => {
+                    let uuid = Uuid::parse_str(&session_id)?;
+                    let log_path = manager.log_path(uuid).await?;
+                    return Self::stream_log(&mut reader, &mut writer, &manager, uuid, &log_path, since_seq, events)
+                        .await;
+                }
+                _ => {}
+            }
+        }
+    }
+
+    /// Check that a connection opens with a matching `Authenticate`.
+    ///
+    /// Anything else (a wrong token, another request, or nothing within
+    /// `AUTH_TIMEOUT`) gets a single `unauthenticated` error, and the
+    /// caller closes the connection.
+    async fn authenticate<R, W>(reader: &mut R, writer: &mut W, expected: &str) -> Result<bool>
+    where
+        R: AsyncBufRead + Unpin,
+        W: AsyncWriteExt + Unpin,
+    {
         let mut line = String::new();
+        let read = tokio::time::timeout(AUTH_TIMEOUT, read_line_limited(reader, &mut line, MAX_REQUEST_BYTES)).await;
+        if matches!(read, Ok(Ok(0))) {
+            // Closed without a word
+            return Ok(false);
+        }
+
+        let authenticated = match serde_json::from_str(&line) {
+            Ok(Request::Authenticate { token }) => crate::auth::tokens_match(&token, expected),
+            _ => false,
+        };
+        let response = if authenticated {
+            Response::Ok
+        } else {
+            Response::Error {
+                message: "Authentication required: this daemon needs its token (see ~/.claude-sessions/token)"
+                    .to_string(),
+                code: ErrorCode::Unauthenticated,
+            }
+        };
+        Self::write_response(writer, &response).await?;
+        Ok(authenticated)
+    }
+
+    /// Stream a session log as `LogChunk`s until the session stops or
+    /// exits, which is sent on as `SessionStopped`/`SessionExited` to end
+    /// the stream, or the client disconnects.
+    ///
+    /// Chunks carry their entry's `seq`. With `since_seq`, entries after it
+    /// are replayed first, a log file at a time; without it only new output
+    /// is sent. If `since_seq` points past the end of the log, the log was
+    /// replaced, so a `Resync` notice is sent and everything is replayed.
+    async fn stream_log<R, W>(
+        reader: &mut R,
+        writer: &mut W,
+        manager: &SessionManager,
+        session_id: Uuid,
+        log_path: &Path,
+        since_seq: Option<u64>,
+        mut events: tokio::sync::broadcast::Receiver<SessionEvent>,
+    ) -> Result<()>
+    where
+        R: AsyncBufRead + Unpin,
+        W: AsyncWriteExt + Unpin,
+    {
+        use tokio::sync::broadcast::error::RecvError;
+
+        let id = session_id.to_string();
+        let mut log = LogFollower::at_end(log_path);
+        if let Some(since_seq) = since_seq {
+            let resync = since_seq > last_seq(log_path);
+            log = LogFollower::from_start(log_path);
+            let mut entries = log.read_next_entries()?;
+            if resync {
+                let from_seq = entries.first().map_or(1, |entry| entry.seq);
+                Self::write_response(writer, &Response::Resync { from_seq }).await?;
+            }
+            let after = if resync { 0 } else { since_seq };
+            while !entries.is_empty() {
+                for entry in entries.into_iter().filter(|entry| entry.seq > after) {
+                    Self::write_chunk(writer, &id, entry).await?;
+                }
+                entries = log.read_next_entries()?;
+            }
+        }
+
+        let mut discard = String::new();
+        loop {
+            let ended = tokio::select! {
+                read = read_line_limited(reader, &mut discard, MAX_REQUEST_BYTES) => {
+                    // The client has nothing more to say on a stream; EOF
+                    // (or an error) means it went away
+                    if read.unwrap_or(0) == 0 {
+                        return Ok(());
+                    }
+                    discard.clear();
+                    None
+                }
+                event = events.recv() => match event {
+                    Ok(event @ (SessionEvent::Stopped { session_id: ended } | SessionEvent::Exited { session_id: ended, .. }))
+                        if ended == session_id =>
+                    {
+                        Some(Response::from(event))
+                    }
+                    Ok(_) => None,
+                    // Ours may have been among the missed events
+                    Err(RecvError::Lagged(_)) => Self::attach_ended(manager, session_id).await,
+                    Err(RecvError::Closed) => return Ok(()),
+                },
+                _ = tokio::time::sleep(tail::POLL_INTERVAL) => None,
+            };
+
+            for entry in log.read_new_entries()? {
+                Self::write_chunk(writer, &id, entry).await?;
+            }
+            if let Some(ended) = ended {
+                Self::write_response(writer, &ended).await?;
+                return Ok(());
+            }
+        }
+    }
+
+    /// The response that ends an attach stream, if its session has
+    /// stopped (or is gone) or exited
+    async fn attach_ended(manager: &SessionManager, session_id: Uuid) -> Option<Response> {
+        match manager.describe_session(session_id).await {
+            None => Some(Response::SessionStopped { session_id: session_id.to_string() }),
+            Some((info, _)) if !matches!(info.status, SessionStatus::Running | SessionStatus::Unresponsive) => {
+                Some(Response::SessionExited {
+                    session_id: info.id,
+                    status: info.status,
+                    exit_code: info.exit_code,
+                })
+            }
+            Some(_) => None,
+        }
+    }
 
-        // Read one request per connection (simple protocol)
-        reader.read_line(&mut line).await?;
-        
-        let request: Request = serde_json::from_str(&line)
-            .context("Failed to parse request")?;
+    /// Forward lifecycle events until the client disconnects.
+    ///
+    /// A subscriber that falls more than the channel's capacity behind is
+    /// told how many events it missed (`EventsMissed`) and carries on.
+    async fn stream_events<R, W>(
+        reader: &mut R,
+        writer: &mut W,
+        mut events: tokio::sync::broadcast::Receiver<SessionEvent>,
+    ) -> Result<()>
+    where
+        R: AsyncBufRead + Unpin,
+        W: AsyncWriteExt + Unpin,
+    {
+        use tokio::sync::broadcast::error::RecvError;
+
+        let mut discard = String::new();
+        loop {
+            tokio::select! {
+                read = read_line_limited(reader, &mut discard, MAX_REQUEST_BYTES) => {
+                    if read.unwrap_or(0) == 0 {
+                        return Ok(());
+                    }
+                    discard.clear();
+                }
+                event = events.recv() => match event {
+                    Ok(event) => Self::write_response(writer, &Response::from(event)).await?,
+                    Err(RecvError::Lagged(count)) => {
+                        Self::write_response(writer, &Response::EventsMissed { count }).await?
+                    }
+                    Err(RecvError::Closed) => return Ok(()),
+                },
+            }
+        }
+    }
 
-        let response = Self::handle_request(request, &manager, shutdown_tx).await;
+    /// Send one log entry as a `LogChunk` (output entries only)
+    async fn write_chunk<W: AsyncWriteExt + Unpin>(
+        writer: &mut W,
+        session_id: &str,
+        entry: LogEntry,
+    ) -> Result<()> {
+        let Some(entry) = entry.into_output() else {
+            return Ok(());
+        };
+        let chunk = Response::LogChunk {
+            session_id: session_id.to_string(),
+            seq: entry.seq,
+            data: general_purpose::STANDARD.encode(&entry.data),
+            timestamp: Some(entry.timestamp),
+        };
+        Self::write_response(writer, &chunk).await
+    }
 
-        // Send response
-        let response_json = serde_json::to_string(&response)?;
+    /// Write a single newline-terminated response
+    async fn write_response<W: AsyncWriteExt + Unpin>(writer: &mut W, response: &Response) -> Result<()> {
+        let response_json = serde_json::to_string(response)?;
         writer.write_all(response_json.as_bytes()).await?;
         writer.write_all(b"\n").await?;
         writer.flush().await?;
-
         Ok(())
     }
 
@@ -130,57 +979,161 @@ impl Daemon {
     async fn handle_request(
         request: Request,
         manager: &SessionManager,
-        shutdown_tx: tokio::sync::broadcast::Sender<()>,
+        shutdown_tx: tokio::sync::broadcast::Sender<ShutdownMode>,
+        connections: &Connections,
     ) -> Response {
         match request {
-            Request::StartSession { working_dir } => {
-                match manager.start_session(working_dir).await {
-                    Ok(session_id) => {
-                        let sessions = manager.list_sessions().await;
-                        let session = sessions.iter()
-                            .find(|s| s.id == session_id.to_string());
-                        
-                        if let Some(s) = session {
-                            Response::SessionStarted {
-                                session_id: s.id.clone(),
-                                log_path: s.log_path.clone(),
-                            }
-                        } else {
-                            Response::Error {
-                                message: "Session started but not found in list".to_string(),
-                            }
-                        }
-                    }
+            Request::StartSession {
+                working_dir,
+                restart_on_crash,
+                log_dir,
+                descriptive_log_name,
+                env,
+                claude_args,
+                name,
+                tags,
+                allow_duplicate,
+            } => {
+                let log_naming = if descriptive_log_name { LogNaming::Descriptive } else { LogNaming::Id };
+                let options = StartOptions {
+                    restart_on_crash,
+                    log_dir,
+                    log_naming,
+                    env,
+                    claude_args,
+                    name,
+                    tags,
+                    allow_duplicate,
+                };
+                match manager.start_session(working_dir, options).await {
+                    Ok(info) => Response::SessionStarted {
+                        session_id: info.id,
+                        log_path: info.log_path,
+                    },
                     Err(e) => Response::Error {
                         message: format!("Failed to start session: {}", e),
+                        code: ErrorCode::of(&e),
                     },
                 }
             }
-            Request::ListSessions => {
+            Request::ListSessions { fields, idle_threshold_secs } => {
+                let threshold = idle_threshold_secs.unwrap_or_else(crate::ipc::default_idle_threshold);
+                let now = std::time::SystemTime::now();
                 let sessions: Vec<SessionInfo> = manager
                     .list_sessions()
                     .await
                     .into_iter()
-                    .map(|s| SessionInfo {
-                        id: s.id,
-                        working_dir: s.working_dir,
-                        created_at: s.created_at,
-                        status: s.status,
-                        log_path: s.log_path,
+                    .map(|info| {
+                        let mut info = SessionInfo::from(info);
+                        info.assess_idle(threshold, now);
+                        info
                     })
                     .collect();
-                Response::SessionList { sessions }
+                match fields {
+                    Some(fields) => Response::SessionFields {
+                        sessions: sessions.iter().map(|s| crate::ipc::project(s, &fields)).collect(),
+                    },
+                    None => Response::SessionList { sessions },
+                }
+            }
+            Request::DaemonInfo => {
+                let started_at = connections.started_at;
+                Response::DaemonInfo {
+                    daemon: DaemonInfo {
+                        version: env!("CARGO_PKG_VERSION").to_string(),
+                        protocol_version: PROTOCOL_VERSION,
+                        pid: std::process::id(),
+                        started_at: chrono::DateTime::<chrono::Utc>::from(started_at).to_rfc3339(),
+                        uptime_seconds: started_at.elapsed().map(|uptime| uptime.as_secs()).unwrap_or(0),
+                        running_sessions: manager.running_count().await,
+                    },
+                }
+            }
+            Request::SessionInfo { session_id } => {
+                match Uuid::parse_str(&session_id) {
+                    Ok(uuid) => match manager.describe_session(uuid).await {
+                        Some((info, pid)) => Response::SessionDetail {
+                            session: SessionDetail::new(info, pid, std::time::SystemTime::now()),
+                        },
+                        None => Response::Error {
+                            message: format!("Session not found: {}", session_id),
+                            code: ErrorCode::NotFound,
+                        },
+                    },
+                    Err(_) => Response::Error {
+                        message: "Invalid session ID format".to_string(),
+                        code: ErrorCode::InvalidArgument,
+                    },
+                }
+            }
+            Request::StopSession { session_id, checkpoint, export_path, force_after_secs } => {
+                match Uuid::parse_str(&session_id) {
+                    Ok(uuid) => {
+                        if checkpoint {
+                            if let Err(e) = manager.checkpoint_session(uuid, export_path.as_deref()).await {
+                                return Response::Error {
+                                    message: format!("Failed to checkpoint session: {}", e),
+                                    code: ErrorCode::of(&e),
+                                };
+                            }
+                        }
+                        let force_after = force_after_secs.map(std::time::Duration::from_secs);
+                        match manager.stop_session_with(uuid, force_after).await {
+                            Ok(info) => Response::SessionStopped { session_id: info.id },
+                            Err(e) => Response::Error {
+                                message: format!("Failed to stop session: {}", e),
+                                code: ErrorCode::of(&e),
+                            },
+                        }
+                    }
+                    Err(_) => Response::Error {
+                        message: "Invalid session ID format".to_string(),
+                        code: ErrorCode::InvalidArgument,
+                    },
+                }
+            }
+            Request::StopAllSessions { include_orphaned } => {
+                let report = manager.stop_all_sessions(include_orphaned).await;
+                Response::StoppedAll {
+                    stopped: report.stopped.iter().map(Uuid::to_string).collect(),
+                    failed: report
+                        .failed
+                        .into_iter()
+                        .map(|(id, message)| StopFailure { session_id: id.to_string(), message })
+                        .collect(),
+                }
+            }
+            Request::RestartSession { session_id } => {
+                match Uuid::parse_str(&session_id) {
+                    Ok(uuid) => match manager.restart_session(uuid).await {
+                        Ok(info) => Response::SessionRestarted {
+                            previous_id: session_id,
+                            session_id: info.id,
+                            log_path: info.log_path,
+                        },
+                        Err(e) => Response::Error {
+                            message: format!("Failed to restart session: {:#}", e),
+                            code: ErrorCode::of(&e),
+                        },
+                    },
+                    Err(_) => Response::Error {
+                        message: "Invalid session ID format".to_string(),
+                        code: ErrorCode::InvalidArgument,
+                    },
+                }
             }
-            Request::StopSession { session_id } => {
+            Request::RenameSession { session_id, name } => {
                 match Uuid::parse_str(&session_id) {
-                    Ok(uuid) => match manager.stop_session(uuid).await {
-                        Ok(_) => Response::SessionStopped { session_id },
+                    Ok(uuid) => match manager.rename_session(uuid, &name).await {
+                        Ok(()) => Response::Ok,
                         Err(e) => Response::Error {
-                            message: format!("Failed to stop session: {}", e),
+                            message: format!("Failed to rename session: {}", e),
+                            code: ErrorCode::of(&e),
                         },
                     },
                     Err(_) => Response::Error {
                         message: "Invalid session ID format".to_string(),
+                        code: ErrorCode::InvalidArgument,
                     },
                 }
             }
@@ -190,31 +1143,164 @@ impl Daemon {
                         Ok(_) => Response::Ok,
                         Err(e) => Response::Error {
                             message: format!("Failed to send input: {}", e),
+                            code: ErrorCode::of(&e),
                         },
                     },
                     Err(_) => Response::Error {
                         message: "Invalid session ID format".to_string(),
+                        code: ErrorCode::InvalidArgument,
                     },
                 }
             }
-            Request::AttachSession { session_id: _ } => {
-                // TODO: Implement log streaming
-                Response::Error {
-                    message: "Attach not implemented yet".to_string(),
-                }
-            }
-            Request::Ping => Response::Pong,
-            Request::Shutdown => {
-                let _ = shutdown_tx.send(());
-                Response::Ok
-            }
-        }
+            Request::SendRaw { session_id, data } => {
+                match Uuid::parse_str(&session_id) {
+                    Ok(uuid) => match manager.send_raw(uuid, &data).await {
+                        Ok(_) => Response::Ok,
+                        Err(e) => Response::Error {
+                            message: format!("Failed to send input: {}", e),
+                            code: ErrorCode::of(&e),
+                        },
+                    },
+                    Err(_) => Response::Error {
+                        message: "Invalid session ID format".to_string(),
+                        code: ErrorCode::InvalidArgument,
+                    },
+                }
+            }
+            Request::Resize { session_id, rows, cols } => {
+                match Uuid::parse_str(&session_id) {
+                    Ok(uuid) => match manager.resize(uuid, rows, cols).await {
+                        Ok(_) => Response::Ok,
+                        Err(e) => Response::Error {
+                            message: format!("Failed to resize: {}", e),
+                            code: ErrorCode::of(&e),
+                        },
+                    },
+                    Err(_) => Response::Error {
+                        message: "Invalid session ID format".to_string(),
+                        code: ErrorCode::InvalidArgument,
+                    },
+                }
+            }
+            Request::AttachSession { session_id, .. } => {
+                // LogChunks follow in handle_connection
+                match Uuid::parse_str(&session_id) {
+                    Ok(uuid) => match manager.attach_info(uuid).await {
+                        Ok(info) => Response::AttachStarted {
+                            session_id,
+                            pty_rows: info.pty_rows,
+                            pty_cols: info.pty_cols,
+                            scrollback_available: info.scrollback_available,
+                        },
+                        Err(e) => Response::Error {
+                            message: format!("Failed to attach: {}", e),
+                            code: ErrorCode::of(&e),
+                        },
+                    },
+                    Err(_) => Response::Error {
+                        message: "Invalid session ID format".to_string(),
+                        code: ErrorCode::InvalidArgument,
+                    },
+                }
+            }
+            Request::PruneSessions { older_than_secs, dry_run, keep_logs } => {
+                let options = PruneOptions {
+                    older_than: Duration::from_secs(older_than_secs),
+                    dry_run,
+                    keep_logs,
+                };
+                let log_dir = Session::default_log_dir();
+                match manager.prune_sessions(&options, &log_dir, std::time::SystemTime::now()).await {
+                    Ok(report) => {
+                        if !dry_run && !report.is_empty() {
+                            println!(
+                                "🧹 Pruned {} session(s), {} log(s)",
+                                report.records_removed.len(),
+                                report.logs_removed.len()
+                            );
+                        }
+                        Response::Pruned {
+                            session_ids: report.records_removed.iter().map(Uuid::to_string).collect(),
+                            logs_removed: report.logs_removed.iter().map(|p| p.display().to_string()).collect(),
+                            dry_run,
+                        }
+                    }
+                    Err(e) => Response::Error {
+                        message: format!("Failed to prune sessions: {}", e),
+                        code: ErrorCode::of(&e),
+                    },
+                }
+            }
+            // The stream itself is set up by `handle_connection`
+            Request::Subscribe => Response::Subscribed,
+            Request::Flush { session_id } => {
+                let uuid = match session_id.as_deref().map(Uuid::parse_str).transpose() {
+                    Ok(uuid) => uuid,
+                    Err(_) => {
+                        return Response::Error {
+                            message: "Invalid session ID format".to_string(),
+                            code: ErrorCode::InvalidArgument,
+                        };
+                    }
+                };
+                match manager.flush(uuid).await {
+                    Ok(sessions) => Response::Flushed { sessions },
+                    Err(e) => Response::Error {
+                        message: format!("Failed to flush: {}", e),
+                        code: ErrorCode::of(&e),
+                    },
+                }
+            }
+            // Connections that need a token are checked before requests
+            // get here (see `authenticate`)
+            Request::Authenticate { .. } => Response::Ok,
+            Request::Hello { protocol_version, version } => {
+                if protocol_compatible(protocol_version) {
+                    Response::Hello {
+                        protocol_version: PROTOCOL_VERSION,
+                        version: env!("CARGO_PKG_VERSION").to_string(),
+                    }
+                } else {
+                    Response::Error {
+                        message: format!(
+                            "Client/daemon version mismatch: the client (version {}) speaks protocol {}, this daemon \
+                             (version {}) needs at least {}; please upgrade the client",
+                            version.as_deref().unwrap_or("unknown"),
+                            protocol_version,
+                            env!("CARGO_PKG_VERSION"),
+                            MIN_PROTOCOL_VERSION
+                        ),
+                        code: ErrorCode::VersionMismatch,
+                    }
+                }
+            }
+            Request::Ping { nonce } => Response::Pong {
+                nonce,
+                active_connections: Some(connections.active()),
+                max_connections: Some(connections.max()),
+            },
+            Request::Shutdown { kill_sessions } => {
+                let mode = if kill_sessions { ShutdownMode::Kill } else { ShutdownMode::Leave };
+                let _ = shutdown_tx.send(mode);
+                Response::Ok
+            }
+        }
     }
 }
 
+/// The `type` of a well-formed request this daemon doesn't know, if that's
+/// why `line` failed to parse
+fn unsupported_request_type(line: &str) -> Option<String> {
+    let value: serde_json::Value = serde_json::from_str(line).ok()?;
+    let kind = value.get("type")?.as_str()?;
+    (!REQUEST_TYPES.contains(&kind)).then(|| kind.to_string())
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use tokio::io::AsyncBufReadExt;
+    use tokio::net::UnixStream;
 
     #[test]
     fn test_socket_path() {
@@ -222,4 +1308,951 @@ mod tests {
         assert!(path.to_str().unwrap().contains(".claude-sessions"));
         assert!(path.to_str().unwrap().ends_with("daemon.sock"));
     }
+
+    #[tokio::test]
+    async fn test_bind_fresh_running_and_stale() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let socket_path = temp_dir.path().join("run").join("daemon.sock");
+
+        // Fresh start: nothing there yet
+        assert_eq!(Daemon::probe(&socket_path).await, Probe::Absent);
+        let listener = Daemon::bind(&socket_path).await.unwrap().unwrap();
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = |path: &Path| std::fs::metadata(path).unwrap().permissions().mode() & 0o777;
+            assert_eq!(mode(&socket_path), 0o600);
+            assert_eq!(mode(socket_path.parent().unwrap()), 0o700);
+        }
+
+        // Already running: a live daemon keeps its socket
+        let (shutdown_tx, _) = tokio::sync::broadcast::channel(1);
+        let persistence = crate::persistence::PersistenceManager::with_state_file(
+            temp_dir.path().join("sessions.json"),
+        )
+        .unwrap();
+        let manager = Arc::new(SessionManager::with_persistence(persistence));
+        let connections = Arc::new(Connections::new(ConnectionLimit::default()));
+        let server = tokio::spawn(Daemon::serve(Listener::Unix(listener), manager, shutdown_tx, connections));
+        assert_eq!(Daemon::probe(&socket_path).await, Probe::Alive);
+        assert!(Daemon::bind(&socket_path).await.unwrap().is_none());
+
+        // Stale: the daemon died without removing its socket
+        server.abort();
+        let _ = server.await;
+        assert!(socket_path.exists());
+        assert_eq!(Daemon::probe(&socket_path).await, Probe::Stale);
+        assert!(Daemon::bind(&socket_path).await.unwrap().is_some());
+    }
+
+    #[tokio::test]
+    async fn test_bind_replaces_refused_socket_but_not_a_silent_one() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let socket_path = temp_dir.path().join("daemon.sock");
+
+        // Leftover file nobody listens on: connecting is refused
+        std::fs::write(&socket_path, "").unwrap();
+        assert_eq!(Daemon::probe(&socket_path).await, Probe::Stale);
+        drop(Daemon::bind(&socket_path).await.unwrap().unwrap());
+        std::fs::remove_file(&socket_path).unwrap();
+
+        // A listener that accepts but never answers may be a busy daemon
+        let _silent = std::os::unix::net::UnixListener::bind(&socket_path).unwrap();
+        assert_eq!(Daemon::probe(&socket_path).await, Probe::Alive);
+        assert!(Daemon::bind(&socket_path).await.unwrap().is_none());
+        assert!(socket_path.exists());
+    }
+
+    #[test]
+    fn test_stale_pid_file_is_ignored_and_removed() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let pid_path = temp_dir.path().join("daemon.pid");
+
+        std::fs::write(&pid_path, format!("{}\n", std::process::id())).unwrap();
+        assert_eq!(Daemon::read_pid(&pid_path), Some(std::process::id()));
+
+        // A daemon that crashed left its PID behind
+        let mut dead = std::process::Command::new("true").spawn().unwrap();
+        dead.wait().unwrap();
+        std::fs::write(&pid_path, dead.id().to_string()).unwrap();
+        assert_eq!(Daemon::read_pid(&pid_path), None);
+        assert!(!pid_path.exists());
+    }
+
+    #[test]
+    fn test_second_daemon_lock_is_refused() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let lock_path = temp_dir.path().join("daemon.lock");
+
+        let held = DaemonLock::acquire(&lock_path).unwrap();
+        let err = DaemonLock::acquire(&lock_path).unwrap_err();
+        assert!(err.to_string().contains("already running"), "{}", err);
+        assert!(err.downcast_ref::<LockHeld>().is_some());
+
+        // Released when the holder goes away
+        drop(held);
+        assert!(DaemonLock::acquire(&lock_path).is_ok());
+    }
+
+    #[test]
+    fn test_idle_timer_resets_on_activity() {
+        let start = Instant::now();
+        let at = |secs| start + Duration::from_secs(secs);
+        let mut timer = IdleTimer::new(Duration::from_secs(60));
+
+        assert!(!timer.observe(false, 0, at(0)));
+        assert!(!timer.observe(false, 0, at(59)));
+        // A session (or an attached client) keeps the daemon up
+        assert!(!timer.observe(true, 0, at(61)));
+        assert!(!timer.observe(false, 0, at(62)));
+        assert!(!timer.observe(false, 0, at(100)));
+        // A connection since the last look re-arms the timer
+        assert!(!timer.observe(false, 1, at(121)));
+        assert!(!timer.observe(false, 1, at(122)));
+        assert!(timer.observe(false, 1, at(182)));
+    }
+
+    #[tokio::test]
+    async fn test_shutdown_removes_socket_before_stopping_sessions() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let socket_path = temp_dir.path().join("daemon.sock");
+        let listener = UnixListener::bind(&socket_path).unwrap();
+        drop(listener);
+        assert!(socket_path.exists());
+
+        // Stand-in for slow session teardown: look at the socket meanwhile
+        let probed = Daemon::shutdown(&socket_path, async {
+            tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+            (socket_path.exists(), Daemon::probe(&socket_path).await)
+        })
+        .await;
+        assert_eq!(probed, (false, Probe::Absent));
+    }
+
+    #[tokio::test]
+    async fn test_left_sessions_get_sighup_once_the_daemon_is_gone() {
+        use crate::persistence::{is_process_alive, PersistenceManager};
+        use crate::pty::test_support::cat_process;
+
+        let temp_dir = tempfile::tempdir().unwrap();
+        let persistence = PersistenceManager::with_state_file(temp_dir.path().join("sessions.json")).unwrap();
+        let manager = Arc::new(SessionManager::with_persistence(persistence));
+        let session = Session::with_log_dir(temp_dir.path().to_path_buf(), Some(temp_dir.path()));
+        let process = cat_process(session.id, &session.log_path);
+        let pid = process.pid().unwrap();
+        manager.insert_session(session).await;
+        manager.insert_process(process).await;
+
+        let (shutdown_tx, _) = tokio::sync::broadcast::channel(1);
+        let mut daemon = Daemon {
+            manager,
+            socket_path: temp_dir.path().join("daemon.sock"),
+            shutdown_tx: shutdown_tx.clone(),
+            retention: RetentionPolicy::default(),
+            connections: Arc::new(Connections::new(ConnectionLimit::default())),
+            idle_timeout: None,
+            listen: None,
+            lock: None,
+            reattach: false,
+        };
+        let run = tokio::spawn(async move {
+            let outcome = daemon.run().await;
+            (daemon, outcome)
+        });
+        while !temp_dir.path().join("daemon.sock").exists() {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        let _ = shutdown_tx.send(ShutdownMode::Leave);
+        let (daemon, outcome) = run.await.unwrap();
+        assert!(matches!(outcome.unwrap(), RunOutcome::Stopped));
+
+        // Not signalled by the daemon...
+        assert!(is_process_alive(pid));
+
+        // ...but its terminal closes with the daemon, and `cat` doesn't
+        // survive the SIGHUP
+        drop(daemon);
+        let deadline = std::time::Instant::now() + Duration::from_secs(5);
+        while is_process_alive(pid) && std::time::Instant::now() < deadline {
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+        assert!(!is_process_alive(pid), "left session outlived its terminal");
+    }
+
+    #[tokio::test]
+    async fn test_serves_the_same_protocol_over_tcp() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let persistence = crate::persistence::PersistenceManager::with_state_file(
+            temp_dir.path().join("sessions.json"),
+        )
+        .unwrap();
+        let manager = Arc::new(SessionManager::with_persistence(persistence));
+        let (shutdown_tx, _) = tokio::sync::broadcast::channel(1);
+        let connections = Arc::new(Connections::new(ConnectionLimit::default()));
+        let server = tokio::spawn(Daemon::serve(Listener::Tcp(listener), manager, shutdown_tx.clone(), connections));
+
+        // No Unix socket involved
+        let client = crate::client::Client::with_socket_path(temp_dir.path().join("daemon.sock"))
+            .connect_to(addr.to_string());
+        assert_eq!(client.probe().await, Probe::Alive);
+
+        let mut session = client.session().await.unwrap();
+        let pong = session.send_request(Request::Ping { nonce: Some("tcp".to_string()) }).await.unwrap();
+        assert!(matches!(pong, Response::Pong { nonce: Some(nonce), .. } if nonce == "tcp"));
+        let list = Request::ListSessions { fields: None, idle_threshold_secs: None };
+        let list = session.send_request(list).await.unwrap();
+        assert!(matches!(list, Response::SessionList { sessions } if sessions.is_empty()));
+
+        let _ = shutdown_tx.send(ShutdownMode::Leave);
+        server.await.unwrap();
+        assert_eq!(client.probe().await, Probe::Absent);
+    }
+
+    #[tokio::test]
+    async fn test_token_must_be_presented_first() {
+        use crate::client::ClientSession;
+
+        let temp_dir = tempfile::tempdir().unwrap();
+        let persistence = crate::persistence::PersistenceManager::with_state_file(
+            temp_dir.path().join("sessions.json"),
+        )
+        .unwrap();
+        let manager = Arc::new(SessionManager::with_persistence(persistence));
+        let (shutdown_tx, _) = tokio::sync::broadcast::channel(1);
+        let connections =
+            Arc::new(Connections::new(ConnectionLimit::default()).with_token(Some("secret".to_string())));
+        let connect = || {
+            let (client, server) = UnixStream::pair().unwrap();
+            let handler =
+                Daemon::handle_connection(server, Arc::clone(&manager), shutdown_tx.clone(), Arc::clone(&connections));
+            tokio::spawn(handler);
+            let (reader, writer) = client.into_split();
+            ClientSession::from_halves(reader, writer)
+        };
+
+        // The right token: requests go through on the same connection
+        let mut session = connect();
+        let response = session.send_request(Request::Authenticate { token: "secret".to_string() }).await.unwrap();
+        assert!(matches!(response, Response::Ok));
+        let response = session.send_request(Request::Ping { nonce: None }).await.unwrap();
+        assert!(matches!(response, Response::Pong { .. }));
+
+        // A wrong token, or none: one error and the connection is closed
+        let unauthenticated = [
+            Request::Authenticate { token: "guess".to_string() },
+            Request::ListSessions { fields: None, idle_threshold_secs: None },
+        ];
+        for first in unauthenticated {
+            let mut session = connect();
+            let response = session.send_request(first).await.unwrap();
+            assert!(matches!(response, Response::Error { code: ErrorCode::Unauthenticated, .. }), "{:?}", response);
+            assert!(session.next_response().await.unwrap().is_none());
+        }
+    }
+
+    #[test]
+    fn test_listen_addr_must_be_loopback_without_token() {
+        for allowed in ["127.0.0.1:7878", "[::1]:7878"] {
+            assert!(check_listen_addr(allowed.parse().unwrap(), false).is_ok(), "{}", allowed);
+        }
+        for refused in ["0.0.0.0:7878", "[::]:7878", "192.168.1.10:7878"] {
+            assert!(check_listen_addr(refused.parse().unwrap(), false).is_err(), "{}", refused);
+            assert!(check_listen_addr(refused.parse().unwrap(), true).is_ok(), "{}", refused);
+        }
+    }
+
+    /// Serve a fresh daemon on a temp socket with the given limit
+    fn spawn_server(temp_dir: &tempfile::TempDir, limit: ConnectionLimit) -> crate::client::Client {
+        let socket_path = temp_dir.path().join("daemon.sock");
+        let listener = UnixListener::bind(&socket_path).unwrap();
+        let persistence = crate::persistence::PersistenceManager::with_state_file(
+            temp_dir.path().join("sessions.json"),
+        )
+        .unwrap();
+        let manager = Arc::new(SessionManager::with_persistence(persistence));
+        let (shutdown_tx, _) = tokio::sync::broadcast::channel(1);
+        tokio::spawn(Daemon::serve(Listener::Unix(listener), manager, shutdown_tx, Arc::new(Connections::new(limit))));
+        crate::client::Client::with_socket_path(socket_path)
+    }
+
+    #[tokio::test]
+    async fn test_oversized_request_is_rejected() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let client = spawn_server(&temp_dir, ConnectionLimit::default());
+
+        // A "request" with no newline in sight, well past the limit
+        let (reader, mut writer) = UnixStream::connect(client.socket()).await.unwrap().into_split();
+        let flood = tokio::spawn(async move {
+            let chunk = vec![b'x'; 64 * 1024];
+            for _ in 0..MAX_REQUEST_BYTES / chunk.len() * 2 {
+                if writer.write_all(&chunk).await.is_err() {
+                    break;
+                }
+            }
+        });
+        let mut reader = BufReader::new(reader);
+        let mut line = String::new();
+        reader.read_line(&mut line).await.unwrap();
+        assert!(line.contains(r#""code":"invalid-argument""#), "{}", line);
+        match serde_json::from_str(&line).unwrap() {
+            Response::Error { code: ErrorCode::InvalidArgument, message } => {
+                assert!(message.contains("longer than"), "{}", message)
+            }
+            other => panic!("Expected an invalid-argument error, got {:?}", other),
+        }
+        // Then the daemon hangs up; with the flood still unread that can
+        // arrive as a reset rather than a clean EOF
+        line.clear();
+        assert!(!matches!(reader.read_line(&mut line).await, Ok(read) if read > 0), "{}", line);
+        flood.abort();
+
+        // The daemon carries on serving
+        let response = client.send_request(Request::Ping { nonce: None }).await.unwrap();
+        assert!(matches!(response, Response::Pong { .. }));
+    }
+
+    #[tokio::test]
+    async fn test_connection_limit_rejects_when_full() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let client = spawn_server(&temp_dir, ConnectionLimit { max: 1, queue: false });
+
+        // The first connection holds the only slot while it stays open,
+        // even before it sends its request
+        let held = UnixStream::connect(temp_dir.path().join("daemon.sock")).await.unwrap();
+
+        // Raw, so the refusal isn't taken for a failed handshake
+        let (reader, writer) = UnixStream::connect(client.socket()).await.unwrap().into_split();
+        let mut refused = crate::client::ClientSession::from_halves(reader, writer);
+        match refused.send_request(Request::Ping { nonce: None }).await.unwrap() {
+            Response::Error { message, code } => {
+                assert!(message.contains("busy"), "{}", message);
+                assert_eq!(code, ErrorCode::ResourceExhausted);
+            }
+            other => panic!("Expected busy error, got {:?}", other),
+        }
+
+        // Once it closes, new connections are served again
+        drop(held);
+        tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+        match client.send_request(Request::Ping { nonce: None }).await.unwrap() {
+            Response::Pong { active_connections, max_connections, .. } => {
+                assert_eq!((active_connections, max_connections), (Some(1), Some(1)));
+            }
+            other => panic!("Expected Pong, got {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_connection_limit_queues_when_full() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let client = spawn_server(&temp_dir, ConnectionLimit { max: 1, queue: true });
+
+        let held = UnixStream::connect(temp_dir.path().join("daemon.sock")).await.unwrap();
+
+        let waiting = tokio::spawn(async move { client.send_request(Request::Ping { nonce: None }).await });
+        tokio::time::sleep(std::time::Duration::from_millis(200)).await;
+        assert!(!waiting.is_finished());
+
+        drop(held);
+        let response = tokio::time::timeout(std::time::Duration::from_secs(2), waiting)
+            .await
+            .unwrap()
+            .unwrap()
+            .unwrap();
+        assert!(matches!(response, Response::Pong { .. }));
+    }
+
+    /// Run `handle_connection` on one end of a socket pair
+    fn spawn_handler(temp_dir: &tempfile::TempDir) -> (UnixStream, tokio::task::JoinHandle<Result<()>>) {
+        use crate::persistence::PersistenceManager;
+
+        let persistence =
+            PersistenceManager::with_state_file(temp_dir.path().join("sessions.json")).unwrap();
+        let manager = Arc::new(SessionManager::with_persistence(persistence));
+        let (shutdown_tx, _) = tokio::sync::broadcast::channel(1);
+        let (client, server) = UnixStream::pair().unwrap();
+        let connections = Arc::new(Connections::new(ConnectionLimit::default()));
+        let handle = tokio::spawn(Daemon::handle_connection(server, manager, shutdown_tx, connections));
+        (client, handle)
+    }
+
+    #[tokio::test]
+    async fn test_attach_reports_pty_size() {
+        use crate::persistence::PersistenceManager;
+        use crate::pty::test_support::cat_process;
+
+        let temp_dir = tempfile::tempdir().unwrap();
+        let persistence =
+            PersistenceManager::with_state_file(temp_dir.path().join("sessions.json")).unwrap();
+        let manager = SessionManager::with_persistence(persistence);
+
+        // `cat` in a PTY stands in for a running Claude
+        let session_id = Uuid::new_v4();
+        let process = cat_process(session_id, &temp_dir.path().join("session.jsonl"));
+        process.resize(40, 120).unwrap();
+        manager.insert_process(process).await;
+
+        let (shutdown_tx, _) = tokio::sync::broadcast::channel(1);
+        let request = Request::AttachSession { session_id: session_id.to_string(), since_seq: None };
+        let connections = Connections::new(ConnectionLimit::default());
+        match Daemon::handle_request(request, &manager, shutdown_tx, &connections).await {
+            Response::AttachStarted { session_id: id, pty_rows, pty_cols, .. } => {
+                assert_eq!(id, session_id.to_string());
+                assert_eq!((pty_rows, pty_cols), (40, 120));
+            }
+            other => panic!("Expected AttachStarted, got {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_send_input_reaches_pty() {
+        use crate::logging::{read_entries, Direction, EntryFilter};
+        use crate::persistence::PersistenceManager;
+        use crate::pty::test_support::cat_process;
+
+        let temp_dir = tempfile::tempdir().unwrap();
+        let persistence =
+            PersistenceManager::with_state_file(temp_dir.path().join("sessions.json")).unwrap();
+        let manager = SessionManager::with_persistence(persistence);
+
+        let session_id = Uuid::new_v4();
+        let log_path = temp_dir.path().join("session.jsonl");
+        manager.insert_process(cat_process(session_id, &log_path)).await;
+
+        let (shutdown_tx, _) = tokio::sync::broadcast::channel(1);
+        let connections = Connections::new(ConnectionLimit::default());
+        let send = |session_id: String| Request::SendInput { session_id, text: "hello".to_string() };
+
+        let response =
+            Daemon::handle_request(send(session_id.to_string()), &manager, shutdown_tx.clone(), &connections).await;
+        assert!(matches!(response, Response::Ok), "{:?}", response);
+        let inputs: Vec<_> = read_entries(&log_path, EntryFilter::direction(Direction::Input))
+            .unwrap()
+            .map(|entry| entry.unwrap().data)
+            .collect();
+        assert_eq!(inputs, vec![b"hello\n".to_vec()]);
+
+        match Daemon::handle_request(send("not-a-uuid".to_string()), &manager, shutdown_tx.clone(), &connections).await {
+            Response::Error { message, .. } => assert_eq!(message, "Invalid session ID format"),
+            other => panic!("Expected error, got {:?}", other),
+        }
+
+        // A session without a PTY (stale, crashed) reports it
+        match Daemon::handle_request(send(Uuid::new_v4().to_string()), &manager, shutdown_tx, &connections).await {
+            Response::Error { message, .. } => assert!(message.contains("not active"), "{}", message),
+            other => panic!("Expected error, got {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_stop_with_checkpoint_leaves_marker() {
+        use crate::logging::{read_entries, Direction, EntryFilter};
+        use crate::persistence::PersistenceManager;
+        use crate::pty::test_support::cat_process;
+
+        let temp_dir = tempfile::tempdir().unwrap();
+        let persistence =
+            PersistenceManager::with_state_file(temp_dir.path().join("sessions.json")).unwrap();
+        let manager = SessionManager::with_persistence(persistence);
+
+        let session = Session::with_log_dir(temp_dir.path().to_path_buf(), Some(temp_dir.path()));
+        let (session_id, log_path) = (session.id, session.log_path.clone());
+        manager.insert_session(session).await;
+        manager.insert_process(cat_process(session_id, &log_path)).await;
+
+        let (shutdown_tx, _) = tokio::sync::broadcast::channel(1);
+        let connections = Connections::new(ConnectionLimit::default());
+        let stop = Request::StopSession {
+            session_id: session_id.to_string(),
+            checkpoint: true,
+            export_path: None,
+            force_after_secs: None,
+        };
+        let response = Daemon::handle_request(stop, &manager, shutdown_tx, &connections).await;
+        assert!(matches!(response, Response::SessionStopped { .. }), "{:?}", response);
+
+        let markers: Vec<_> = read_entries(&log_path, EntryFilter::direction(Direction::Marker))
+            .unwrap()
+            .map(|entry| entry.unwrap().data)
+            .collect();
+        assert_eq!(markers, vec![b"checkpoint".to_vec()]);
+    }
+
+    #[tokio::test]
+    async fn test_resize_rejects_zero_and_unknown_sessions() {
+        use crate::persistence::PersistenceManager;
+        use crate::pty::test_support::cat_process;
+
+        let temp_dir = tempfile::tempdir().unwrap();
+        let persistence =
+            PersistenceManager::with_state_file(temp_dir.path().join("sessions.json")).unwrap();
+        let manager = SessionManager::with_persistence(persistence);
+
+        let session_id = Uuid::new_v4();
+        manager.insert_process(cat_process(session_id, &temp_dir.path().join("session.jsonl"))).await;
+
+        let (shutdown_tx, _) = tokio::sync::broadcast::channel(1);
+        let connections = Connections::new(ConnectionLimit::default());
+        let resize = |session_id: Uuid, rows: u16, cols: u16| Request::Resize {
+            session_id: session_id.to_string(),
+            rows,
+            cols,
+        };
+
+        let response =
+            Daemon::handle_request(resize(session_id, 40, 120), &manager, shutdown_tx.clone(), &connections).await;
+        assert!(matches!(response, Response::Ok), "{:?}", response);
+
+        match Daemon::handle_request(resize(session_id, 0, 120), &manager, shutdown_tx.clone(), &connections).await {
+            Response::Error { message, code } => {
+                assert!(message.contains("non-zero"), "{}", message);
+                assert_eq!(code, ErrorCode::InvalidArgument);
+            }
+            other => panic!("Expected error, got {:?}", other),
+        }
+
+        match Daemon::handle_request(resize(Uuid::new_v4(), 40, 120), &manager, shutdown_tx, &connections).await {
+            Response::Error { message, code } => {
+                assert!(message.contains("not found"), "{}", message);
+                assert_eq!(code, ErrorCode::NotFound);
+            }
+            other => panic!("Expected error, got {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_failures_carry_error_codes() {
+        use crate::persistence::PersistenceManager;
+
+        let temp_dir = tempfile::tempdir().unwrap();
+        let persistence =
+            PersistenceManager::with_state_file(temp_dir.path().join("sessions.json")).unwrap();
+        let manager = SessionManager::with_persistence(persistence);
+        let session = Session::with_log_dir(temp_dir.path().to_path_buf(), Some(temp_dir.path()));
+        let stale_id = session.id.to_string();
+        manager.insert_session(session).await;
+
+        let (shutdown_tx, _) = tokio::sync::broadcast::channel(1);
+        let connections = Connections::new(ConnectionLimit::default());
+        let cases = [
+            (
+                Request::StopSession {
+                    session_id: "not-a-uuid".to_string(),
+                    checkpoint: false,
+                    export_path: None,
+                    force_after_secs: None,
+                },
+                ErrorCode::InvalidArgument,
+            ),
+            (
+                Request::StopSession {
+                    session_id: Uuid::new_v4().to_string(),
+                    checkpoint: false,
+                    export_path: None,
+                    force_after_secs: None,
+                },
+                ErrorCode::NotFound,
+            ),
+            (
+                Request::RenameSession { session_id: stale_id.clone(), name: "  ".to_string() },
+                ErrorCode::InvalidArgument,
+            ),
+            // Known, but has no process to write to
+            (
+                Request::SendInput { session_id: stale_id, text: "hi".to_string() },
+                ErrorCode::NotFound,
+            ),
+            (
+                Request::StartSession {
+                    working_dir: temp_dir.path().join("missing"),
+                    restart_on_crash: false,
+                    log_dir: None,
+                    descriptive_log_name: false,
+                    env: vec![],
+                    claude_args: vec![],
+                    name: None,
+                    tags: Default::default(),
+                    allow_duplicate: false,
+                },
+                ErrorCode::InvalidArgument,
+            ),
+        ];
+
+        for (request, expected) in cases {
+            let label = format!("{:?}", request);
+            match Daemon::handle_request(request, &manager, shutdown_tx.clone(), &connections).await {
+                Response::Error { code, .. } => assert_eq!(code, expected, "{}", label),
+                other => panic!("Expected error for {}, got {:?}", label, other),
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_restart_keeps_session_it_cannot_restart() {
+        use crate::persistence::PersistenceManager;
+
+        let temp_dir = tempfile::tempdir().unwrap();
+        let persistence =
+            PersistenceManager::with_state_file(temp_dir.path().join("sessions.json")).unwrap();
+        let manager = SessionManager::with_persistence(persistence);
+        let session = Session::with_log_dir(temp_dir.path().join("gone"), Some(temp_dir.path()));
+        let session_id = session.id;
+        manager.insert_session(session).await;
+
+        let (shutdown_tx, _) = tokio::sync::broadcast::channel(1);
+        let connections = Connections::new(ConnectionLimit::default());
+        let restart = |session_id: Uuid| Request::RestartSession { session_id: session_id.to_string() };
+
+        match Daemon::handle_request(restart(session_id), &manager, shutdown_tx.clone(), &connections).await {
+            Response::Error { message, .. } => assert!(message.contains("does not exist"), "{}", message),
+            other => panic!("Expected error, got {:?}", other),
+        }
+        // Checked before stopping: the session is still there
+        assert_eq!(manager.list_sessions().await.len(), 1);
+
+        match Daemon::handle_request(restart(Uuid::new_v4()), &manager, shutdown_tx, &connections).await {
+            Response::Error { message, .. } => assert!(message.contains("not found"), "{}", message),
+            other => panic!("Expected error, got {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_daemon_info_reports_build_and_process() {
+        let manager = SessionManager::new();
+        let (shutdown_tx, _) = tokio::sync::broadcast::channel(1);
+        let connections = Connections::new(ConnectionLimit::default());
+
+        match Daemon::handle_request(Request::DaemonInfo, &manager, shutdown_tx, &connections).await {
+            Response::DaemonInfo { daemon } => {
+                assert_eq!(daemon.version, env!("CARGO_PKG_VERSION"));
+                assert_eq!(daemon.protocol_version, PROTOCOL_VERSION);
+                assert_eq!(daemon.pid, std::process::id());
+                assert_eq!(daemon.running_sessions, 0);
+                assert!(chrono::DateTime::parse_from_rfc3339(&daemon.started_at).is_ok());
+            }
+            other => panic!("Expected DaemonInfo, got {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_hello_rejects_an_older_client_protocol() {
+        let manager = SessionManager::new();
+        let (shutdown_tx, _) = tokio::sync::broadcast::channel(1);
+        let connections = Connections::new(ConnectionLimit::default());
+        let hello = |protocol_version| Request::Hello { protocol_version, version: Some("0.0.1".to_string()) };
+
+        match Daemon::handle_request(hello(PROTOCOL_VERSION), &manager, shutdown_tx.clone(), &connections).await {
+            Response::Hello { protocol_version, version } => {
+                assert_eq!(protocol_version, PROTOCOL_VERSION);
+                assert_eq!(version, env!("CARGO_PKG_VERSION"));
+            }
+            other => panic!("Expected Hello, got {:?}", other),
+        }
+
+        match Daemon::handle_request(hello(MIN_PROTOCOL_VERSION - 1), &manager, shutdown_tx, &connections).await {
+            Response::Error { message, code } => {
+                assert_eq!(code, ErrorCode::VersionMismatch);
+                assert_eq!(code.exit_status(), 9);
+                assert!(message.contains("version mismatch") && message.contains("upgrade"), "{}", message);
+            }
+            other => panic!("Expected a version mismatch, got {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_session_info_reports_log_size_and_not_found() {
+        use crate::logging::{Direction, SessionLogger};
+
+        let temp_dir = tempfile::tempdir().unwrap();
+        let manager = SessionManager::new();
+        let session = Session::with_log_dir(temp_dir.path().to_path_buf(), Some(temp_dir.path()));
+        let session_id = session.id;
+        let mut logger = SessionLogger::with_path(session_id, session.log_path.clone()).unwrap();
+        logger.log(Direction::Output, b"hello".to_vec()).unwrap();
+        let on_disk = std::fs::metadata(&session.log_path).unwrap().len();
+        manager.insert_session(session).await;
+
+        let (shutdown_tx, _) = tokio::sync::broadcast::channel(1);
+        let connections = Connections::new(ConnectionLimit::default());
+        let info = |session_id: Uuid| Request::SessionInfo { session_id: session_id.to_string() };
+
+        match Daemon::handle_request(info(session_id), &manager, shutdown_tx.clone(), &connections).await {
+            Response::SessionDetail { session } => {
+                assert_eq!(session.info.id, session_id.to_string());
+                assert_eq!(session.log_size_bytes, on_disk);
+                // Not running: no process, no uptime
+                assert_eq!(session.pid, None);
+                assert_eq!(session.uptime_seconds, None);
+            }
+            other => panic!("Expected session detail, got {:?}", other),
+        }
+
+        match Daemon::handle_request(info(Uuid::new_v4()), &manager, shutdown_tx, &connections).await {
+            Response::Error { code, .. } => assert_eq!(code, ErrorCode::NotFound),
+            other => panic!("Expected error, got {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_resume_since_seq_sends_only_newer_entries() {
+        use crate::logging::{Direction, SessionLogger};
+        use crate::persistence::PersistenceManager;
+
+        let temp_dir = tempfile::tempdir().unwrap();
+        let persistence =
+            PersistenceManager::with_state_file(temp_dir.path().join("sessions.json")).unwrap();
+        let manager = Arc::new(SessionManager::with_persistence(persistence));
+        let session = Session::with_log_dir(temp_dir.path().to_path_buf(), Some(temp_dir.path()));
+        let (session_id, log_path) = (session.id, session.log_path.clone());
+        manager.insert_session(session).await;
+        let mut logger = SessionLogger::with_path(session_id, log_path.clone()).unwrap();
+        // Numbered 1 to 4; the input takes a seq but isn't streamed
+        logger.log(Direction::Output, b"zero".to_vec()).unwrap();
+        logger.log(Direction::Input, b"go".to_vec()).unwrap();
+        logger.log(Direction::Output, b"one".to_vec()).unwrap();
+        logger.log(Direction::Output, b"two".to_vec()).unwrap();
+
+        let stream_from = |since_seq: Option<u64>| {
+            let (client, server) = tokio::io::duplex(64 * 1024);
+            let (server_read, mut server_write) = tokio::io::split(server);
+            let stream_path = log_path.clone();
+            let manager = Arc::clone(&manager);
+            let events = manager.subscribe();
+            let stream = tokio::spawn(async move {
+                let mut reader = BufReader::new(server_read);
+                Daemon::stream_log(&mut reader, &mut server_write, &manager, session_id, &stream_path, since_seq, events)
+                    .await
+            });
+            let (client_read, client_write) = tokio::io::split(client);
+            (stream, BufReader::new(client_read).lines(), client_write)
+        };
+
+        // Entries up to 3 were already seen
+        let (stream, mut lines, client_write) = stream_from(Some(3));
+        assert_eq!(next_chunk(&mut lines).await, (4, b"two".to_vec()));
+        logger.log(Direction::Output, b"three".to_vec()).unwrap();
+        assert_eq!(next_chunk(&mut lines).await, (5, b"three".to_vec()));
+        // Both halves, or the duplex stays open
+        drop((lines, client_write));
+        assert!(stream.await.unwrap().is_ok());
+
+        // Past the end: the log was replaced, so it all comes again
+        let (stream, mut lines, client_write) = stream_from(Some(99));
+        let line = lines.next_line().await.unwrap().unwrap();
+        assert!(matches!(serde_json::from_str(&line).unwrap(), Response::Resync { from_seq: 1 }));
+        assert_eq!(next_chunk(&mut lines).await, (1, b"zero".to_vec()));
+        assert_eq!(next_chunk(&mut lines).await, (3, b"one".to_vec()));
+        // Both halves, or the duplex stays open
+        drop((lines, client_write));
+        assert!(stream.await.unwrap().is_ok());
+
+        // Stopping the session ends the stream
+        let (stream, mut lines, _client_write) = stream_from(None);
+        manager.stop_session(session_id).await.unwrap();
+        let line = lines.next_line().await.unwrap().unwrap();
+        assert!(matches!(serde_json::from_str(&line).unwrap(), Response::SessionStopped { .. }), "{}", line);
+        assert!(stream.await.unwrap().is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_subscribers_receive_stop_events() {
+        use crate::persistence::PersistenceManager;
+
+        let temp_dir = tempfile::tempdir().unwrap();
+        let persistence =
+            PersistenceManager::with_state_file(temp_dir.path().join("sessions.json")).unwrap();
+        let manager = SessionManager::with_persistence(persistence);
+        let session = Session::with_log_dir(temp_dir.path().to_path_buf(), Some(temp_dir.path()));
+        let session_id = session.id;
+        manager.insert_session(session).await;
+
+        // Two subscribers, each on its own connection
+        let mut clients = Vec::new();
+        let mut streams = Vec::new();
+        for _ in 0..2 {
+            let (client, server) = tokio::io::duplex(64 * 1024);
+            let (server_read, mut server_write) = tokio::io::split(server);
+            let events = manager.subscribe();
+            streams.push(tokio::spawn(async move {
+                let mut reader = BufReader::new(server_read);
+                Daemon::stream_events(&mut reader, &mut server_write, events).await
+            }));
+            clients.push(tokio::io::split(client));
+        }
+
+        manager.stop_session(session_id).await.unwrap();
+
+        for (client_read, client_write) in clients {
+            let mut lines = BufReader::new(client_read).lines();
+            let line = tokio::time::timeout(std::time::Duration::from_secs(2), lines.next_line())
+                .await
+                .unwrap()
+                .unwrap()
+                .unwrap();
+            match serde_json::from_str::<Response>(&line).unwrap() {
+                Response::SessionStopped { session_id: stopped } => assert_eq!(stopped, session_id.to_string()),
+                other => panic!("Expected SessionStopped, got {:?}", other),
+            }
+            drop(client_write);
+        }
+        for stream in streams {
+            assert!(stream.await.unwrap().is_ok());
+        }
+    }
+
+    /// Read the next `LogChunk` as (seq, decoded data)
+    async fn next_chunk<R: AsyncBufRead + Unpin>(lines: &mut tokio::io::Lines<R>) -> (u64, Vec<u8>) {
+        let line = tokio::time::timeout(std::time::Duration::from_secs(2), lines.next_line())
+            .await
+            .unwrap()
+            .unwrap()
+            .unwrap();
+        match serde_json::from_str::<Response>(&line).unwrap() {
+            Response::LogChunk { seq, data, .. } => (seq, general_purpose::STANDARD.decode(data).unwrap()),
+            other => panic!("Expected LogChunk, got {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_ping_echoes_nonce() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let (client, handle) = spawn_handler(&temp_dir);
+
+        let (reader, writer) = client.into_split();
+        let mut session = crate::client::ClientSession::from_halves(reader, writer);
+        let pong = session
+            .send_request(Request::Ping { nonce: Some("abc-123".to_string()) })
+            .await
+            .unwrap();
+        match pong {
+            Response::Pong { nonce, active_connections, .. } => {
+                assert_eq!(nonce.as_deref(), Some("abc-123"));
+                assert_eq!(active_connections, Some(0));
+            }
+            other => panic!("Expected Pong, got {:?}", other),
+        }
+
+        drop(session);
+        assert!(handle.await.unwrap().is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_malformed_request_keeps_the_connection() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let (mut client, handle) = spawn_handler(&temp_dir);
+
+        // Not JSON, and a known request missing its fields
+        client.write_all(b"not json\n{\"type\":\"stop_session\"}\n").await.unwrap();
+        let (reader, writer) = client.into_split();
+        let mut session = crate::client::ClientSession::from_halves(reader, writer);
+        for _ in 0..2 {
+            match session.next_response().await.unwrap() {
+                Some(Response::Error { message, code }) => {
+                    assert_eq!(code, ErrorCode::InvalidArgument);
+                    assert!(message.starts_with("Invalid request"), "{}", message);
+                }
+                other => panic!("Expected InvalidArgument error, got {:?}", other),
+            }
+        }
+
+        let pong = session.send_request(Request::Ping { nonce: None }).await.unwrap();
+        assert!(matches!(pong, Response::Pong { .. }));
+
+        drop(session);
+        assert!(handle.await.unwrap().is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_unknown_request_is_reported_by_type() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let (mut client, handle) = spawn_handler(&temp_dir);
+
+        // A request type from some future client
+        client.write_all(b"{\"type\":\"frobnicate_session\",\"session_id\":\"x\"}\n").await.unwrap();
+        client.write_all(b"{\"type\":\"does_not_exist\"}\n").await.unwrap();
+        client.write_all(b"{\"type\":\"send_input\",\"session_id\":\"x\"}\n").await.unwrap();
+        let (reader, writer) = client.into_split();
+        let mut session = crate::client::ClientSession::from_halves(reader, writer);
+        for kind in ["frobnicate_session", "does_not_exist"] {
+            match session.next_response().await.unwrap() {
+                Some(Response::Error { message, code }) => {
+                    assert_eq!(code, ErrorCode::UnknownRequest);
+                    assert!(message.contains(kind), "{}", message);
+                }
+                other => panic!("Expected unknown-request error, got {:?}", other),
+            }
+        }
+
+        // A known type with bad fields is malformed, not unknown
+        match session.next_response().await.unwrap() {
+            Some(Response::Error { code, .. }) => assert_eq!(code, ErrorCode::InvalidArgument),
+            other => panic!("Expected invalid-argument error, got {:?}", other),
+        }
+
+        // The connection stays usable
+        let pong = session.send_request(Request::Ping { nonce: None }).await.unwrap();
+        assert!(matches!(pong, Response::Pong { .. }));
+
+        drop(session);
+        assert!(handle.await.unwrap().is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_requests_share_a_connection_until_shutdown() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let (client, handle) = spawn_handler(&temp_dir);
+        let (reader, writer) = client.into_split();
+        let mut session = crate::client::ClientSession::from_halves(reader, writer);
+
+        for nonce in ["one", "two"] {
+            match session.send_request(Request::Ping { nonce: Some(nonce.to_string()) }).await.unwrap() {
+                Response::Pong { nonce: echoed, .. } => assert_eq!(echoed.as_deref(), Some(nonce)),
+                other => panic!("Expected Pong, got {:?}", other),
+            }
+        }
+        let listed = session
+            .send_request(Request::ListSessions { fields: None, idle_threshold_secs: None })
+            .await
+            .unwrap();
+        assert!(matches!(listed, Response::SessionList { .. }));
+
+        // Shutdown is answered, then the daemon closes the connection
+        let response = session.send_request(Request::Shutdown { kill_sessions: false }).await.unwrap();
+        assert!(matches!(response, Response::Ok), "{:?}", response);
+        assert!(session.next_response().await.unwrap().is_none());
+        assert!(handle.await.unwrap().is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_clean_close_is_silent() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let (client, handle) = spawn_handler(&temp_dir);
+
+        drop(client);
+        assert!(handle.await.unwrap().is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_partial_line_gets_framing_error() {
+        use tokio::io::AsyncReadExt;
+
+        let temp_dir = tempfile::tempdir().unwrap();
+        let (mut client, handle) = spawn_handler(&temp_dir);
+
+        // Truncated request, then half-close our write side
+        client.write_all(br#"{"type":"pi"#).await.unwrap();
+        client.shutdown().await.unwrap();
+
+        let mut reply = String::new();
+        client.read_to_string(&mut reply).await.unwrap();
+        let response: Response = serde_json::from_str(reply.trim()).unwrap();
+        match response {
+            Response::Error { message, .. } => assert!(message.contains("Incomplete request")),
+            other => panic!("Expected framing error, got {:?}", other),
+        }
+
+        assert!(handle.await.unwrap().is_ok());
+    }
 }
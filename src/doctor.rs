@@ -0,0 +1,246 @@
+//! `claude-sessions doctor`: check that this machine can run sessions.
+//!
+//! Each check looks at one thing a first run commonly trips over (the
+//! config file, the `claude` binary, the data directory, the daemon
+//! socket, the persisted state) and reports it as passed, a warning, or failed. Only failures
+//! make the command exit non-zero.
+
+use crate::config::Config;
+use crate::daemon::{Daemon, Probe};
+use crate::persistence::Persistence;
+use crate::pty::ClaudeBinary;
+use serde::Serialize;
+use std::fmt;
+use std::path::Path;
+use std::time::Duration;
+
+/// How long `claude --version` may take before it's reported as hanging
+const VERSION_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// How a check came out
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Outcome {
+    Pass,
+    /// Worth knowing, but sessions can still run
+    Warn,
+    /// Sessions won't work until this is fixed
+    Fail,
+}
+
+/// The result of one check
+#[derive(Debug, Clone, Serialize)]
+pub struct Check {
+    pub name: &'static str,
+    pub outcome: Outcome,
+    pub detail: String,
+}
+
+impl Check {
+    fn new(name: &'static str, outcome: Outcome, detail: impl Into<String>) -> Self {
+        Check { name, outcome, detail: detail.into() }
+    }
+
+    /// The report line for people
+    pub fn human(&self) -> String {
+        let icon = match self.outcome {
+            Outcome::Pass => "✅",
+            Outcome::Warn => "⚠️ ",
+            Outcome::Fail => "❌",
+        };
+        format!("{} {}: {}", icon, self.name, self.detail)
+    }
+}
+
+impl fmt::Display for Check {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let outcome = match self.outcome {
+            Outcome::Pass => "pass",
+            Outcome::Warn => "warn",
+            Outcome::Fail => "fail",
+        };
+        write!(f, "{}\t{}\t{}", outcome, self.name, self.detail)
+    }
+}
+
+/// Run every check, in the order a new user would fix them
+pub async fn run() -> Vec<Check> {
+    let (config_check, config) = match Config::path() {
+        Ok(path) => check_config(&path),
+        Err(e) => (Check::new("config file", Outcome::Fail, format!("{:#}", e)), Config::from_env()),
+    };
+    let mut checks = vec![config_check];
+    checks.push(check_claude(&config.claude_binary(), std::env::var_os("PATH").as_deref()).await);
+    match Daemon::socket_path() {
+        Ok(socket_path) => {
+            if let Some(dir) = socket_path.parent() {
+                checks.push(check_data_dir(dir));
+            }
+            checks.push(check_socket(&socket_path, Daemon::probe(&socket_path).await));
+        }
+        Err(e) => checks.push(Check::new("data directory", Outcome::Fail, format!("{:#}", e))),
+    }
+    checks.push(match crate::persistence::from_env() {
+        Ok(backend) => check_state(backend.as_ref()),
+        Err(e) => Check::new("session state", Outcome::Fail, format!("{:#}", e)),
+    });
+    checks
+}
+
+/// The config file, if there is one, parses; also returns the config the
+/// daemon would run with (defaults plus the environment if it doesn't)
+pub fn check_config(path: &Path) -> (Check, Config) {
+    const NAME: &str = "config file";
+    match Config::load_with(path, |name| std::env::var(name).ok()) {
+        Ok(config) if path.exists() => (Check::new(NAME, Outcome::Pass, format!("{} loaded", path.display())), config),
+        Ok(config) => (Check::new(NAME, Outcome::Pass, format!("no {}; using defaults", path.display())), config),
+        Err(e) => (Check::new(NAME, Outcome::Fail, format!("{:#}", e)), Config::from_env()),
+    }
+}
+
+/// The binary sessions run can be found, and answers `--version`
+pub async fn check_claude(claude: &ClaudeBinary, search_path: Option<&std::ffi::OsStr>) -> Check {
+    const NAME: &str = "claude binary";
+    let program = match claude.resolve(search_path) {
+        Ok(program) => program,
+        Err(e) => return Check::new(NAME, Outcome::Fail, format!("{:#}", e)),
+    };
+
+    let version = tokio::process::Command::new(&program)
+        .arg("--version")
+        .stdin(std::process::Stdio::null())
+        .kill_on_drop(true)
+        .output();
+    match tokio::time::timeout(VERSION_TIMEOUT, version).await {
+        Ok(Ok(output)) if output.status.success() => {
+            let stdout = String::from_utf8_lossy(&output.stdout);
+            let version = stdout.lines().next().unwrap_or_default().trim();
+            Check::new(NAME, Outcome::Pass, format!("{} ({})", program.display(), version))
+        }
+        Ok(Ok(output)) => Check::new(
+            NAME,
+            Outcome::Warn,
+            format!("{} found, but `--version` exited with {}", program.display(), output.status),
+        ),
+        Ok(Err(e)) => Check::new(NAME, Outcome::Fail, format!("Can't run {}: {}", program.display(), e)),
+        Err(_) => Check::new(
+            NAME,
+            Outcome::Warn,
+            format!("{} found, but `--version` didn't answer within {:?}", program.display(), VERSION_TIMEOUT),
+        ),
+    }
+}
+
+/// `~/.claude-sessions` is a writable directory only its owner can read
+pub fn check_data_dir(dir: &Path) -> Check {
+    const NAME: &str = "data directory";
+    let metadata = match std::fs::metadata(dir) {
+        Ok(metadata) => metadata,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+            return Check::new(NAME, Outcome::Warn, format!("{} doesn't exist yet; the daemon creates it", dir.display()));
+        }
+        Err(e) => return Check::new(NAME, Outcome::Fail, format!("Can't read {}: {}", dir.display(), e)),
+    };
+    if !metadata.is_dir() {
+        return Check::new(NAME, Outcome::Fail, format!("{} is not a directory", dir.display()));
+    }
+
+    let probe = dir.join(format!(".doctor-{}", uuid::Uuid::new_v4().simple()));
+    if let Err(e) = std::fs::write(&probe, b"") {
+        return Check::new(NAME, Outcome::Fail, format!("{} is not writable: {}", dir.display(), e));
+    }
+    let _ = std::fs::remove_file(&probe);
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        let mode = metadata.permissions().mode() & 0o777;
+        if mode & 0o077 != 0 {
+            return Check::new(
+                NAME,
+                Outcome::Warn,
+                format!("{} is open to other users (mode {:o}); fix with: chmod 700 {}", dir.display(), mode, dir.display()),
+            );
+        }
+    }
+    Check::new(NAME, Outcome::Pass, format!("{} is writable", dir.display()))
+}
+
+/// Whether a daemon answers on the socket, or a dead one left it behind
+pub fn check_socket(socket_path: &Path, probe: Probe) -> Check {
+    const NAME: &str = "daemon socket";
+    match probe {
+        Probe::Alive => Check::new(NAME, Outcome::Pass, format!("a daemon is listening on {}", socket_path.display())),
+        Probe::Absent => Check::new(NAME, Outcome::Pass, "no daemon running (one starts when needed)"),
+        Probe::Stale => Check::new(
+            NAME,
+            Outcome::Warn,
+            format!("{} is left over from a daemon that died; the next daemon replaces it", socket_path.display()),
+        ),
+    }
+}
+
+/// The persisted sessions load
+pub fn check_state(backend: &dyn Persistence) -> Check {
+    const NAME: &str = "session state";
+    match backend.load_state() {
+        Ok(sessions) => Check::new(NAME, Outcome::Pass, format!("{} session(s) recorded", sessions.len())),
+        Err(e) => Check::new(
+            NAME,
+            Outcome::Fail,
+            format!("{:#} (see `claude-sessions repair`, or move the state file aside)", e),
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_data_dir_and_state_checks() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let dir = temp_dir.path().join("data");
+
+        assert_eq!(check_data_dir(&dir).outcome, Outcome::Warn);
+        std::fs::write(&dir, b"").unwrap();
+        assert_eq!(check_data_dir(&dir).outcome, Outcome::Fail);
+        std::fs::remove_file(&dir).unwrap();
+        std::fs::create_dir(&dir).unwrap();
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            std::fs::set_permissions(&dir, std::fs::Permissions::from_mode(0o755)).unwrap();
+            assert_eq!(check_data_dir(&dir).outcome, Outcome::Warn);
+            std::fs::set_permissions(&dir, std::fs::Permissions::from_mode(0o700)).unwrap();
+        }
+        assert_eq!(check_data_dir(&dir).outcome, Outcome::Pass);
+        assert!(std::fs::read_dir(&dir).unwrap().next().is_none(), "the write probe is cleaned up");
+
+        let state_file = dir.join("sessions.json");
+        let backend = crate::persistence::PersistenceManager::with_state_file(state_file.clone()).unwrap();
+        assert_eq!(check_state(&backend).outcome, Outcome::Pass);
+        std::fs::write(&state_file, b"{not json").unwrap();
+        assert_eq!(check_state(&backend).outcome, Outcome::Fail);
+    }
+
+    #[test]
+    fn test_malformed_config_fails() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let path = temp_dir.path().join("config.toml");
+        assert_eq!(check_config(&path).0.outcome, Outcome::Pass);
+        std::fs::write(&path, "max_sessions = \"many\"\n").unwrap();
+        let (check, _) = check_config(&path);
+        assert_eq!(check.outcome, Outcome::Fail);
+        assert!(check.detail.contains("line 1"), "{}", check.detail);
+    }
+
+    #[tokio::test]
+    async fn test_missing_claude_fails() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let claude = ClaudeBinary { program: "no-such-claude".to_string(), args: Vec::new() };
+        let check = check_claude(&claude, Some(temp_dir.path().as_os_str())).await;
+        assert_eq!(check.outcome, Outcome::Fail);
+        assert!(check.detail.contains("no-such-claude"), "{}", check.detail);
+    }
+}
@@ -0,0 +1,202 @@
+//! `claude-sessions exec`: run one prompt in a fresh session and print
+//! what Claude answered.
+//!
+//! An attempt starts a session, sends the prompt, captures output until it
+//! goes quiet and stops the session again. Attempts that fail in a way
+//! another try could fix (spawn failure, no output) are retried with
+//! `--retries`; others (e.g. a missing directory) fail right away.
+
+use crate::client::Client;
+use crate::ipc::{ErrorCode, Request, Response};
+use crate::logging::render::OutputFilter;
+use crate::logging::{Direction, LogFollower};
+use crate::tail;
+use std::future::Future;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+pub struct ExecOptions {
+    pub working_dir: PathBuf,
+    pub prompt: String,
+    /// An attempt with no output after this long has failed
+    pub timeout: Duration,
+    /// Output is complete once nothing new has arrived for this long
+    pub quiet: Duration,
+}
+
+/// Why an attempt failed
+#[derive(Debug)]
+pub enum AttemptError {
+    /// Another attempt may succeed (spawn failed, no output, session died)
+    Retryable(anyhow::Error),
+    /// Retrying won't help (e.g. the directory doesn't exist)
+    Fatal(anyhow::Error),
+}
+
+/// Run `attempt` until it succeeds, fails fatally, or has been retried
+/// `retries` times, waiting `backoff` between attempts.
+///
+/// `attempt` gets the 1-based attempt number. Returns the last result and
+/// how many attempts were made.
+pub async fn with_retries<T, F, Fut>(retries: u32, backoff: Duration, mut attempt: F) -> (anyhow::Result<T>, u32)
+where
+    F: FnMut(u32) -> Fut,
+    Fut: Future<Output = Result<T, AttemptError>>,
+{
+    let mut attempts = 0;
+    loop {
+        attempts += 1;
+        match attempt(attempts).await {
+            Ok(value) => return (Ok(value), attempts),
+            Err(AttemptError::Fatal(e)) => return (Err(e), attempts),
+            Err(AttemptError::Retryable(e)) if attempts > retries => return (Err(e), attempts),
+            Err(AttemptError::Retryable(_)) => tokio::time::sleep(backoff).await,
+        }
+    }
+}
+
+/// One start → send → capture → stop cycle, returning the captured output
+pub async fn exec_once(client: &Client, options: &ExecOptions) -> Result<Vec<u8>, AttemptError> {
+    let request = Request::StartSession {
+        working_dir: options.working_dir.clone(),
+        restart_on_crash: false,
+        log_dir: None,
+        descriptive_log_name: false,
+        env: vec![],
+        claude_args: vec![],
+        name: None,
+        tags: Default::default(),
+        // A throwaway session; it doesn't compete with one the user is in
+        allow_duplicate: true,
+    };
+    let (session_id, log_path) = match client.send_request(request).await.map_err(AttemptError::Retryable)? {
+        Response::SessionStarted { session_id, log_path } => (session_id, log_path),
+        Response::Error { message, code } if code == ErrorCode::InvalidArgument || is_fatal_start_error(&message) => {
+            return Err(AttemptError::Fatal(anyhow::anyhow!(message)))
+        }
+        Response::Error { message, .. } => return Err(AttemptError::Retryable(anyhow::anyhow!(message))),
+        _ => return Err(AttemptError::Retryable(anyhow::anyhow!("Unexpected response from daemon"))),
+    };
+
+    let result = capture(client, &session_id, Path::new(&log_path), options).await;
+
+    // The session is throwaway whatever happened
+    let stop = Request::StopSession { session_id, checkpoint: false, export_path: None, force_after_secs: None };
+    let _ = client.send_request(stop).await;
+
+    result
+}
+
+/// Send the prompt and collect output until it goes quiet
+async fn capture(
+    client: &Client,
+    session_id: &str,
+    log_path: &Path,
+    options: &ExecOptions,
+) -> Result<Vec<u8>, AttemptError> {
+    let mut log = LogFollower::at_end(log_path);
+
+    let send = Request::SendInput { session_id: session_id.to_string(), text: options.prompt.clone() };
+    match client.send_request(send).await.map_err(AttemptError::Retryable)? {
+        Response::Ok => {}
+        Response::Error { message, .. } => return Err(AttemptError::Retryable(anyhow::anyhow!(message))),
+        _ => return Err(AttemptError::Retryable(anyhow::anyhow!("Unexpected response from daemon"))),
+    }
+
+    let mut filter = OutputFilter::default();
+    filter.keep_colors = false;
+    let mut output = Vec::new();
+    let started = Instant::now();
+    let mut last_output: Option<Instant> = None;
+    loop {
+        let entries = log
+            .read_new_entries()
+            .map_err(|e| AttemptError::Retryable(e.into()))?;
+        for entry in entries {
+            if entry.direction == Direction::Output {
+                output.extend(filter.feed(&entry.data));
+                last_output = Some(Instant::now());
+            }
+        }
+
+        match last_output {
+            Some(at) if at.elapsed() >= options.quiet => return Ok(output),
+            Some(_) if started.elapsed() >= options.timeout => return Ok(output),
+            None if started.elapsed() >= options.timeout => {
+                return Err(AttemptError::Retryable(anyhow::anyhow!(
+                    "No output within {:?}",
+                    options.timeout
+                )))
+            }
+            _ => tokio::time::sleep(tail::POLL_INTERVAL).await,
+        }
+    }
+}
+
+/// Start errors caused by the request itself rather than the moment, as
+/// reported by daemons that predate `ErrorCode::InvalidArgument`
+fn is_fatal_start_error(message: &str) -> bool {
+    ["does not exist", "not valid UTF-8", "Invalid"]
+        .iter()
+        .any(|needle| message.contains(needle))
+}
+
+/// Parse a duration like `500ms`, `2s`, `5m` or `1h` (bare numbers are
+/// seconds)
+pub fn parse_duration(arg: &str) -> Result<Duration, String> {
+    let split = arg.find(|c: char| !c.is_ascii_digit()).unwrap_or(arg.len());
+    let (number, unit) = arg.split_at(split);
+    let number: u64 = number.parse().map_err(|_| format!("invalid duration: {:?}", arg))?;
+    match unit {
+        "ms" => Ok(Duration::from_millis(number)),
+        "" | "s" => Ok(Duration::from_secs(number)),
+        "m" => Ok(Duration::from_secs(number * 60)),
+        "h" => Ok(Duration::from_secs(number * 3600)),
+        _ => Err(format!("invalid duration unit in {:?} (use ms, s, m or h)", arg)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_exec_retries_after_failed_spawn() {
+        // Stand-in spawner: the first attempt fails to spawn, the second works
+        let (result, attempts) = with_retries(2, Duration::from_millis(1), |attempt| async move {
+            if attempt == 1 {
+                Err(AttemptError::Retryable(anyhow::anyhow!("Failed to spawn claude process")))
+            } else {
+                Ok("answer")
+            }
+        })
+        .await;
+        assert_eq!(result.unwrap(), "answer");
+        assert_eq!(attempts, 2);
+
+        // Fatal failures aren't retried; retryable ones stop at the budget
+        let (result, attempts) = with_retries(3, Duration::ZERO, |_| async {
+            Err::<(), _>(AttemptError::Fatal(anyhow::anyhow!("Working directory does not exist")))
+        })
+        .await;
+        assert!(result.is_err());
+        assert_eq!(attempts, 1);
+
+        let (result, attempts) = with_retries(2, Duration::ZERO, |_| async {
+            Err::<(), _>(AttemptError::Retryable(anyhow::anyhow!("No output")))
+        })
+        .await;
+        assert!(result.is_err());
+        assert_eq!(attempts, 3);
+    }
+
+    #[test]
+    fn test_parse_duration() {
+        assert_eq!(parse_duration("500ms"), Ok(Duration::from_millis(500)));
+        assert_eq!(parse_duration("2s"), Ok(Duration::from_secs(2)));
+        assert_eq!(parse_duration("3"), Ok(Duration::from_secs(3)));
+        assert_eq!(parse_duration("5m"), Ok(Duration::from_secs(300)));
+        assert!(parse_duration("5x").is_err());
+        assert!(parse_duration("s").is_err());
+    }
+}
@@ -1,5 +1,23 @@
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 use std::path::PathBuf;
+use crate::session::SessionStatus;
+
+/// Version of the request/response protocol. Bumped when a change would
+/// make an older peer misread messages; new optional fields and new
+/// variants (answered with `UnknownRequest`, or `Unimplemented` by older
+/// daemons) don't need it.
+pub const PROTOCOL_VERSION: u32 = 1;
+
+/// Oldest protocol version of a peer this build still talks to. Clients
+/// and daemons each check the other against their own minimum (see
+/// `Request::Hello`).
+pub const MIN_PROTOCOL_VERSION: u32 = 1;
+
+/// Whether a peer speaking `protocol_version` is one this build can talk to
+pub fn protocol_compatible(protocol_version: u32) -> bool {
+    protocol_version >= MIN_PROTOCOL_VERSION
+}
 
 /// IPC Request messages sent from CLI to Daemon
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -8,28 +26,213 @@ pub enum Request {
     /// Start a new Claude session
     StartSession {
         working_dir: PathBuf,
+        /// Restart the session automatically if Claude exits unexpectedly
+        #[serde(default)]
+        restart_on_crash: bool,
+        /// Write the session log here instead of the default log directory
+        #[serde(default)]
+        log_dir: Option<PathBuf>,
+        /// Name the log `<created>-<dir basename>-<short id>.jsonl` instead
+        /// of `<id>.jsonl`
+        #[serde(default)]
+        descriptive_log_name: bool,
+        /// Extra environment variables for Claude
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        env: Vec<(String, String)>,
+        /// Extra arguments for the Claude binary
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        claude_args: Vec<String>,
+        /// Label for the session; omitted: the directory's basename
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        name: Option<String>,
+        /// `key=value` tags for selecting the session later
+        #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+        tags: BTreeMap<String, String>,
+        /// Start even if another session is running in `working_dir`
+        #[serde(default)]
+        allow_duplicate: bool,
     },
     /// List all active sessions
-    ListSessions,
+    ListSessions {
+        /// Only return these `SessionInfo` fields (e.g. `["id", "status"]`),
+        /// answered with `SessionFields`; `name` is an alias of `label`,
+        /// and unknown names are ignored.
+        /// Omitted: the full `SessionList`.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        fields: Option<Vec<String>>,
+        /// Running sessions idle at least this long are reported unhealthy
+        /// (default: the daemon's `default_idle_threshold`)
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        idle_threshold_secs: Option<u64>,
+    },
+    /// Full detail of one session, answered with `SessionDetail`
+    SessionInfo {
+        session_id: String,
+    },
+    /// The daemon's version, protocol version, PID and uptime, answered
+    /// with `DaemonInfo`
+    DaemonInfo,
     /// Stop a running session
     StopSession {
         session_id: String,
+        /// Write a final checkpoint marker before stopping
+        #[serde(default)]
+        checkpoint: bool,
+        /// Also export a transcript here as part of the checkpoint
+        #[serde(default)]
+        export_path: Option<PathBuf>,
+        /// Seconds between SIGTERM and SIGKILL. Omitted: the daemon's
+        /// default (`STOP_GRACE`).
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        force_after_secs: Option<u64>,
+    },
+    /// Stop every running session (see `SessionManager::stop_all_sessions`)
+    StopAllSessions {
+        /// Also terminate orphaned sessions' processes by PID
+        #[serde(default)]
+        include_orphaned: bool,
+    },
+    /// Stop a session and start Claude again in its directory, as a new
+    /// session with a new log
+    RestartSession {
+        session_id: String,
+    },
+    /// Change a session's label
+    RenameSession {
+        session_id: String,
+        name: String,
     },
     /// Send input to a running session
     SendInput {
         session_id: String,
         text: String,
     },
+    /// Write raw bytes to a session's PTY (no newline is added)
+    SendRaw {
+        session_id: String,
+        #[serde(with = "crate::logging::base64_serde")]
+        data: Vec<u8>,
+    },
+    /// Resize a session's PTY
+    Resize {
+        session_id: String,
+        rows: u16,
+        cols: u16,
+    },
     /// Attach to session output stream (streaming logs)
+    ///
+    /// After `AttachStarted`, the connection carries `LogChunk`s until the
+    /// client closes it, or until the session stops or exits, which ends
+    /// the stream with `SessionStopped`/`SessionExited`.
     AttachSession {
         session_id: String,
+        /// Resume after this log entry seq (the last one the client saw)
+        /// instead of starting with live output
+        #[serde(default)]
+        since_seq: Option<u64>,
+    },
+    /// Forget finished sessions older than `older_than_secs` and delete
+    /// their logs (see `SessionManager::prune_sessions`)
+    PruneSessions {
+        older_than_secs: u64,
+        #[serde(default)]
+        dry_run: bool,
+        #[serde(default)]
+        keep_logs: bool,
+    },
+    /// Turn this connection into a stream of session lifecycle events
+    /// (`SessionStarted`, `SessionStopped`, `SessionExited`) after a
+    /// `Subscribed` reply, until the client disconnects
+    Subscribe,
+    /// Force logs and persisted state to disk (one session, or all)
+    Flush {
+        #[serde(default)]
+        session_id: Option<String>,
+    },
+    /// Present the shared-secret token (see `auth`); must be the first
+    /// request on a connection to a daemon that requires one. Answered
+    /// with `Ok`, also by daemons that don't require a token.
+    Authenticate { token: String },
+    /// Announce the client's `PROTOCOL_VERSION` before any commands.
+    /// Answered with `Hello`, or a `VersionMismatch` error if the daemon
+    /// no longer speaks it; daemons from before negotiation answer
+    /// `Unimplemented` and speak protocol 1.
+    Hello {
+        protocol_version: u32,
+        /// The client's crate version, for messages
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        version: Option<String>,
     },
     /// Ping the daemon (health check)
-    Ping,
-    /// Shutdown the daemon gracefully
-    Shutdown,
+    Ping {
+        /// Echoed back in the `Pong`, to correlate pings and time round trips
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        nonce: Option<String>,
+    },
+    /// Shutdown the daemon gracefully: stop accepting connections, sync
+    /// every session log and save the state before exiting
+    Shutdown {
+        /// Also stop the sessions' processes (SIGTERM, then SIGKILL);
+        /// otherwise they are left running
+        #[serde(default)]
+        kill_sessions: bool,
+    },
+}
+
+impl Request {
+    /// The `type` tag this request is sent with (for tests). The match
+    /// makes a new variant fail to build until it has a tag here, which the
+    /// tests then require in `REQUEST_TYPES`.
+    #[cfg(test)]
+    pub fn type_tag(&self) -> &'static str {
+        match self {
+            Request::StartSession { .. } => "start_session",
+            Request::ListSessions { .. } => "list_sessions",
+            Request::SessionInfo { .. } => "session_info",
+            Request::DaemonInfo => "daemon_info",
+            Request::StopSession { .. } => "stop_session",
+            Request::StopAllSessions { .. } => "stop_all_sessions",
+            Request::RestartSession { .. } => "restart_session",
+            Request::RenameSession { .. } => "rename_session",
+            Request::SendInput { .. } => "send_input",
+            Request::SendRaw { .. } => "send_raw",
+            Request::Resize { .. } => "resize",
+            Request::AttachSession { .. } => "attach_session",
+            Request::PruneSessions { .. } => "prune_sessions",
+            Request::Subscribe => "subscribe",
+            Request::Flush { .. } => "flush",
+            Request::Authenticate { .. } => "authenticate",
+            Request::Hello { .. } => "hello",
+            Request::Ping { .. } => "ping",
+            Request::Shutdown { .. } => "shutdown",
+        }
+    }
 }
 
+/// The `type_tag` of every `Request` variant, so the daemon can tell a
+/// request type it doesn't know from a malformed one it does
+pub const REQUEST_TYPES: &[&str] = &[
+    "start_session",
+    "list_sessions",
+    "session_info",
+    "daemon_info",
+    "stop_session",
+    "stop_all_sessions",
+    "restart_session",
+    "rename_session",
+    "send_input",
+    "send_raw",
+    "resize",
+    "attach_session",
+    "prune_sessions",
+    "subscribe",
+    "flush",
+    "authenticate",
+    "hello",
+    "ping",
+    "shutdown",
+];
+
 /// IPC Response messages sent from Daemon to CLI
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(tag = "type", rename_all = "snake_case")]
@@ -47,57 +250,522 @@ pub enum Response {
     SessionStopped {
         session_id: String,
     },
+    /// Claude exited on its own (event), or was given up on as
+    /// crash-looping (then without an exit code)
+    SessionExited {
+        session_id: String,
+        status: SessionStatus,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        exit_code: Option<u32>,
+    },
+    /// The connection is now an event stream
+    Subscribed,
+    /// Events were dropped because this subscriber fell behind; re-list
+    /// sessions to catch up
+    EventsMissed {
+        count: u64,
+    },
+    /// Result of `StopAllSessions`
+    StoppedAll {
+        stopped: Vec<String>,
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        failed: Vec<StopFailure>,
+    },
+    /// What `PruneSessions` removed (or, on a dry run, would remove)
+    Pruned {
+        session_ids: Vec<String>,
+        logs_removed: Vec<String>,
+        dry_run: bool,
+    },
+    /// Success response for restart: the session that replaced `previous_id`
+    SessionRestarted {
+        previous_id: String,
+        session_id: String,
+        log_path: String,
+    },
+    /// Opening handshake of an attach connection, sent once before any
+    /// `LogChunk`s so the client can size its renderer
+    AttachStarted {
+        session_id: String,
+        pty_rows: u16,
+        pty_cols: u16,
+        /// Whether the session has logged output that can be replayed
+        scrollback_available: bool,
+    },
     /// Streaming log chunk (for attach)
     LogChunk {
         session_id: String,
+        /// `seq` of the entry in the session log (see `LogEntry::seq`)
+        #[serde(default)]
+        seq: u64,
         data: String, // Base64 encoded
+        /// When the entry was logged (RFC 3339)
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        timestamp: Option<String>,
+    },
+    /// The requested `since_seq` is past the end of the log (it was
+    /// replaced or truncated); replay restarts from `from_seq`
+    Resync {
+        from_seq: u64,
+    },
+    /// Logs and state are durable on disk
+    Flushed {
+        /// Number of session logs synced
+        sessions: usize,
+    },
+    /// One session's detail, for `SessionInfo`
+    SessionDetail {
+        session: SessionDetail,
+    },
+    /// The daemon's build and state, for `DaemonInfo`
+    DaemonInfo {
+        daemon: DaemonInfo,
+    },
+    /// Projected session list: each session has only the requested fields
+    SessionFields {
+        sessions: Vec<serde_json::Map<String, serde_json::Value>>,
     },
     /// Pong response
-    Pong,
+    Pong {
+        /// The nonce from the `Ping`, if any
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        nonce: Option<String>,
+        /// Connections the daemon is serving, including this one
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        active_connections: Option<usize>,
+        /// The daemon's connection limit
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        max_connections: Option<usize>,
+    },
+    /// The daemon's side of `Hello`
+    Hello {
+        protocol_version: u32,
+        /// The daemon's crate version
+        version: String,
+    },
     /// Generic success
     Ok,
     /// Error response
     Error {
         message: String,
+        /// What kind of failure this is; older daemons omit it
+        #[serde(default)]
+        code: ErrorCode,
     },
+    /// A response type from a newer daemon that this client doesn't know;
+    /// handled like any other unexpected response
+    #[serde(other)]
+    Unknown,
+}
+
+/// Machine-readable class of an `Error` response.
+///
+/// The wire names (kebab-case, e.g. `not-found`) are stable; codes a
+/// client doesn't know read as `Internal`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ErrorCode {
+    /// The daemon doesn't support this request (yet); clients should fall
+    /// back rather than report a failure
+    Unimplemented,
+    /// The request's `type` isn't one the daemon knows (the message names
+    /// it), e.g. from a newer client; the connection stays usable. Older
+    /// daemons answer these with `Unimplemented`.
+    UnknownRequest,
+    /// The request named a session the daemon doesn't know, or one that
+    /// isn't running for a request that needs a live process
+    NotFound,
+    /// The request was malformed or its arguments were rejected (bad
+    /// session ID, missing directory, zero terminal size, ...)
+    InvalidArgument,
+    /// The request would create something that already exists
+    AlreadyExists,
+    /// A daemon limit was reached (e.g. the maximum of running sessions);
+    /// retrying after freeing something up can succeed
+    ResourceExhausted,
+    /// The connection didn't present the daemon's token first; the daemon
+    /// closes it after this error
+    Unauthenticated,
+    /// Client and daemon don't speak a common protocol version (see
+    /// `Request::Hello`); one of them needs upgrading
+    VersionMismatch,
+    /// The request was understood but failed. Last, since serde only
+    /// allows the catch-all for unknown codes on the last variant.
+    #[default]
+    #[serde(other)]
+    Internal,
+}
+
+impl ErrorCode {
+    /// Classify a failure: `SessionError`s by kind, anything else is
+    /// `Internal`
+    pub fn of(error: &anyhow::Error) -> Self {
+        use crate::manager::SessionError;
+        match error.downcast_ref::<SessionError>() {
+            Some(SessionError::NotFound(_) | SessionError::NotRunning(_)) => ErrorCode::NotFound,
+            Some(SessionError::InvalidArgument(_) | SessionError::InvalidWorkingDir { .. }) => {
+                ErrorCode::InvalidArgument
+            }
+            Some(SessionError::LimitReached { .. }) => ErrorCode::ResourceExhausted,
+            Some(SessionError::DuplicateWorkingDir { .. }) => ErrorCode::AlreadyExists,
+            None => ErrorCode::Internal,
+        }
+    }
+
+    /// CLI exit status for a failed request of this class (1 for
+    /// internal errors, 2 is left to usage errors)
+    pub fn exit_status(self) -> i32 {
+        match self {
+            ErrorCode::Internal => 1,
+            ErrorCode::InvalidArgument => 3,
+            ErrorCode::NotFound => 4,
+            ErrorCode::AlreadyExists => 5,
+            ErrorCode::Unimplemented | ErrorCode::UnknownRequest => 6,
+            ErrorCode::ResourceExhausted => 7,
+            ErrorCode::Unauthenticated => 8,
+            ErrorCode::VersionMismatch => 9,
+        }
+    }
 }
 
 /// Session info for list responses
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct SessionInfo {
     pub id: String,
+    /// Human-readable name (empty from daemons that predate labels)
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub label: String,
+    /// Tags given at start (empty from daemons that predate tags)
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub tags: BTreeMap<String, String>,
     pub working_dir: String,
     pub created_at: String,
-    pub status: String,
+    pub status: SessionStatus,
     pub log_path: String,
+    /// Last error (e.g. why a crash-looping session was given up on)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_error: Option<String>,
+    /// How Claude exited, for "exited" and "crashed" sessions
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub exit_code: Option<u32>,
+    /// When input was last sent or output last logged (RFC 3339); absent
+    /// before the first and from older daemons
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_activity: Option<String>,
+    /// Seconds since a running session last logged anything
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub idle_seconds: Option<u64>,
+    /// Whether a running session's `idle_seconds` is under the list's
+    /// idle threshold
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub healthy: Option<bool>,
+}
+
+/// A session `StopAllSessions` couldn't stop
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StopFailure {
+    pub session_id: String,
+    pub message: String,
+}
+
+/// Everything the daemon knows about one session
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SessionDetail {
+    #[serde(flatten)]
+    pub info: SessionInfo,
+    /// Claude's process ID, while it is running (or orphaned)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pid: Option<u32>,
+    /// Seconds since `created_at`, for a running session
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub uptime_seconds: Option<u64>,
+    /// Size of the session log on disk, rotated segments included
+    pub log_size_bytes: u64,
+}
+
+/// What the daemon reports about itself
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DaemonInfo {
+    /// The daemon's crate version
+    pub version: String,
+    /// The daemon's `PROTOCOL_VERSION`
+    pub protocol_version: u32,
+    pub pid: u32,
+    /// When the daemon started (RFC 3339)
+    pub started_at: String,
+    pub uptime_seconds: u64,
+    /// Sessions with a live process
+    pub running_sessions: usize,
+}
+
+/// Idle threshold used when `ListSessions` doesn't give one
+pub const DEFAULT_IDLE_THRESHOLD_SECS: u64 = 600;
+
+/// Daemon-wide idle threshold: `CLAUDE_SESSIONS_IDLE_THRESHOLD_SECS`, or
+/// `DEFAULT_IDLE_THRESHOLD_SECS`
+pub fn default_idle_threshold() -> u64 {
+    std::env::var("CLAUDE_SESSIONS_IDLE_THRESHOLD_SECS")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(DEFAULT_IDLE_THRESHOLD_SECS)
+}
+
+impl SessionInfo {
+    /// Fill in `idle_seconds` and `healthy` for a running session.
+    ///
+    /// Idle time runs from `last_activity`, or without one from the last
+    /// log write (or creation, before any output); a session is healthy
+    /// while it is under `threshold_secs`.
+    pub fn assess_idle(&mut self, threshold_secs: u64, now: std::time::SystemTime) {
+        if !self.status.is_live() {
+            return;
+        }
+        let recorded = self
+            .last_activity
+            .as_deref()
+            .and_then(|at| chrono::DateTime::parse_from_rfc3339(at).ok())
+            .map(std::time::SystemTime::from);
+        let Some(last) = recorded
+            .or_else(|| crate::retention::last_activity(std::path::Path::new(&self.log_path), &self.created_at))
+        else {
+            return;
+        };
+        let idle = now.duration_since(last).map(|d| d.as_secs()).unwrap_or(0);
+        self.idle_seconds = Some(idle);
+        self.healthy = Some(idle < threshold_secs);
+    }
+}
+
+impl SessionDetail {
+    /// Add the computed fields (uptime, log size, idle time against the
+    /// daemon-wide threshold) to a session's info as of `now`
+    pub fn new(info: crate::session::SessionInfo, pid: Option<u32>, now: std::time::SystemTime) -> Self {
+        let mut info = SessionInfo::from(info);
+        info.assess_idle(default_idle_threshold(), now);
+
+        let uptime_seconds = match &info.status {
+            status if status.is_live() => chrono::DateTime::parse_from_rfc3339(&info.created_at)
+                .ok()
+                .and_then(|created| now.duration_since(created.into()).ok())
+                .map(|uptime| uptime.as_secs()),
+            _ => None,
+        };
+        let log_size_bytes = crate::logging::log_segments(std::path::Path::new(&info.log_path))
+            .iter()
+            .filter_map(|segment| std::fs::metadata(segment).ok())
+            .map(|m| m.len())
+            .sum();
+
+        SessionDetail { info, pid, uptime_seconds, log_size_bytes }
+    }
+}
+
+impl From<crate::manager::SessionEvent> for Response {
+    fn from(event: crate::manager::SessionEvent) -> Self {
+        use crate::manager::SessionEvent;
+        match event {
+            SessionEvent::Started { session_id, log_path } => Response::SessionStarted {
+                session_id: session_id.to_string(),
+                log_path: log_path.display().to_string(),
+            },
+            SessionEvent::Stopped { session_id } => Response::SessionStopped { session_id: session_id.to_string() },
+            SessionEvent::Exited { session_id, status, exit_code } => Response::SessionExited {
+                session_id: session_id.to_string(),
+                status,
+                exit_code,
+            },
+        }
+    }
+}
+
+impl From<crate::session::SessionInfo> for SessionInfo {
+    fn from(info: crate::session::SessionInfo) -> Self {
+        SessionInfo {
+            id: info.id,
+            label: info.label,
+            tags: info.tags,
+            working_dir: info.working_dir,
+            created_at: info.created_at,
+            status: info.status,
+            log_path: info.log_path,
+            last_error: info.last_error,
+            exit_code: info.exit_code,
+            last_activity: info.last_activity,
+            idle_seconds: None,
+            healthy: None,
+        }
+    }
 }
 
 impl From<crate::session::Session> for SessionInfo {
     fn from(session: crate::session::Session) -> Self {
+        let status = session.exit_status().unwrap_or(SessionStatus::Running);
         SessionInfo {
             id: session.id.to_string(),
+            label: session.label,
+            tags: session.tags,
             working_dir: session.working_dir.display().to_string(),
             created_at: session.created_at,
-            status: "running".to_string(),
+            status,
             log_path: session.log_path.display().to_string(),
+            last_error: session.crash_loop_error.or(session.stop_error),
+            exit_code: session.exit_code,
+            last_activity: session.last_activity,
+            idle_seconds: None,
+            healthy: None,
+        }
+    }
+}
+
+/// Longest request line the daemon reads; longer ones are rejected with
+/// `InvalidArgument` instead of being buffered without bound
+pub const MAX_REQUEST_BYTES: usize = 1024 * 1024;
+
+/// Longest response line a client reads (a `SessionList` of many sessions
+/// runs well past `MAX_REQUEST_BYTES`)
+pub const MAX_RESPONSE_BYTES: usize = 16 * 1024 * 1024;
+
+/// Read one line into `line` like `read_line`, but at most `max` bytes of
+/// it. Returns the number of bytes read, 0 at EOF.
+///
+/// A longer line, or one that isn't UTF-8, is an `InvalidData` error;
+/// the rest of the stream can't be trusted after it.
+pub async fn read_line_limited<R>(reader: &mut R, line: &mut String, max: usize) -> std::io::Result<usize>
+where
+    R: tokio::io::AsyncBufRead + Unpin,
+{
+    use tokio::io::{AsyncBufReadExt, AsyncReadExt};
+
+    let mut bytes = Vec::new();
+    let read = (&mut *reader).take(max as u64 + 1).read_until(b'\n', &mut bytes).await?;
+    if read > max {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidData,
+            format!("line longer than {} bytes", max),
+        ));
+    }
+    let text = std::str::from_utf8(&bytes).map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))?;
+    line.push_str(text);
+    Ok(read)
+}
+
+/// Other names `project` accepts for `SessionInfo` fields
+const FIELD_ALIASES: &[(&str, &str)] = &[("name", "label")];
+
+/// Keep only `fields` of a session, for a projected `ListSessions`. Each
+/// field keeps the name it was asked for, aliases included.
+pub fn project(session: &SessionInfo, fields: &[String]) -> serde_json::Map<String, serde_json::Value> {
+    let serde_json::Value::Object(mut all) = serde_json::to_value(session).unwrap_or_default() else {
+        return serde_json::Map::new();
+    };
+    let mut projected = serde_json::Map::new();
+    for field in fields {
+        let name = FIELD_ALIASES.iter().find(|(alias, _)| alias == field).map_or(field.as_str(), |(_, name)| name);
+        if let Some(value) = all.remove(name) {
+            projected.insert(field.clone(), value);
         }
     }
+    projected
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_error_codes_are_stable_on_the_wire() {
+        let response = Response::Error { message: "nope".to_string(), code: ErrorCode::InvalidArgument };
+        let json = serde_json::to_string(&response).unwrap();
+        assert_eq!(json, r#"{"type":"error","message":"nope","code":"invalid-argument"}"#);
+
+        // Codes from a newer daemon, and older daemons' missing code
+        let newer: Response = serde_json::from_str(r#"{"type":"error","message":"x","code":"rate-limited"}"#).unwrap();
+        let older: Response = serde_json::from_str(r#"{"type":"error","message":"x"}"#).unwrap();
+        for response in [newer, older] {
+            assert!(matches!(response, Response::Error { code: ErrorCode::Internal, .. }), "{:?}", response);
+        }
+        let unknown = Response::Error { message: "x".to_string(), code: ErrorCode::UnknownRequest };
+        assert!(serde_json::to_string(&unknown).unwrap().contains(r#""code":"unknown-request""#));
+
+        let code: ErrorCode = serde_json::from_str(r#""some-future-code""#).unwrap();
+        assert_eq!(code, ErrorCode::Internal);
+        for (code, wire) in [
+            (ErrorCode::Internal, "internal"),
+            (ErrorCode::NotFound, "not-found"),
+            (ErrorCode::AlreadyExists, "already-exists"),
+            (ErrorCode::ResourceExhausted, "resource-exhausted"),
+            (ErrorCode::VersionMismatch, "version-mismatch"),
+        ] {
+            assert_eq!(serde_json::to_string(&code).unwrap(), format!("\"{}\"", wire));
+            assert_eq!(serde_json::from_str::<ErrorCode>(&format!("\"{}\"", wire)).unwrap(), code);
+        }
+    }
+
+    #[test]
+    fn test_request_types_cover_every_request() {
+        let id = || "x".to_string();
+        let requests = [
+            Request::StartSession {
+                working_dir: PathBuf::from("/tmp"),
+                restart_on_crash: false,
+                log_dir: None,
+                descriptive_log_name: false,
+                env: vec![],
+                claude_args: vec![],
+                name: None,
+                tags: BTreeMap::new(),
+                allow_duplicate: false,
+            },
+            Request::ListSessions { fields: None, idle_threshold_secs: None },
+            Request::SessionInfo { session_id: id() },
+            Request::DaemonInfo,
+            Request::StopSession { session_id: id(), checkpoint: false, export_path: None, force_after_secs: None },
+            Request::StopAllSessions { include_orphaned: false },
+            Request::RestartSession { session_id: id() },
+            Request::RenameSession { session_id: id(), name: id() },
+            Request::SendInput { session_id: id(), text: id() },
+            Request::SendRaw { session_id: id(), data: vec![] },
+            Request::Resize { session_id: id(), rows: 24, cols: 80 },
+            Request::AttachSession { session_id: id(), since_seq: None },
+            Request::PruneSessions { older_than_secs: 0, dry_run: false, keep_logs: false },
+            Request::Subscribe,
+            Request::Flush { session_id: None },
+            Request::Authenticate { token: id() },
+            Request::Hello { protocol_version: PROTOCOL_VERSION, version: None },
+            Request::Ping { nonce: None },
+            Request::Shutdown { kill_sessions: false },
+        ];
+        for request in &requests {
+            assert_eq!(serde_json::to_value(request).unwrap()["type"], request.type_tag());
+        }
+        let types: Vec<&str> = requests.iter().map(Request::type_tag).collect();
+        assert_eq!(types, REQUEST_TYPES);
+    }
+
+    #[test]
+    fn test_unknown_response_types_parse_as_unknown() {
+        let newer: Response = serde_json::from_str(r#"{"type":"session_moved","session_id":"x"}"#).unwrap();
+        assert!(matches!(newer, Response::Unknown), "{:?}", newer);
+    }
+
     #[test]
     fn test_request_serialization() {
         let req = Request::StartSession {
             working_dir: PathBuf::from("/tmp"),
+            restart_on_crash: false,
+            log_dir: None,
+            descriptive_log_name: false,
+            env: vec![],
+            claude_args: vec![],
+            name: None,
+            tags: BTreeMap::new(),
+            allow_duplicate: false,
         };
         let json = serde_json::to_string(&req).unwrap();
         let parsed: Request = serde_json::from_str(&json).unwrap();
         
         match parsed {
-            Request::StartSession { working_dir } => {
+            Request::StartSession { working_dir, .. } => {
                 assert_eq!(working_dir, PathBuf::from("/tmp"));
             }
             _ => panic!("Wrong request type"),
@@ -120,4 +788,81 @@ mod tests {
             _ => panic!("Wrong response type"),
         }
     }
+
+    #[test]
+    fn test_ping_nonce_compat() {
+        // The original field-less ping still parses
+        let parsed: Request = serde_json::from_str(r#"{"type":"ping"}"#).unwrap();
+        assert!(matches!(parsed, Request::Ping { nonce: None }));
+
+        let json = serde_json::to_string(&Response::Pong { nonce: None, active_connections: None, max_connections: None }).unwrap();
+        assert_eq!(json, r#"{"type":"pong"}"#);
+    }
+
+    #[test]
+    fn test_list_sessions_projection() {
+        // Clients that don't know about projections still get everything
+        let parsed: Request = serde_json::from_str(r#"{"type":"list_sessions"}"#).unwrap();
+        assert!(matches!(parsed, Request::ListSessions { fields: None, .. }));
+
+        let session = SessionInfo {
+            id: "abc".to_string(),
+            working_dir: "/tmp".to_string(),
+            created_at: "2024-01-01T00:00:00Z".to_string(),
+            status: SessionStatus::Running,
+            log_path: "/tmp/abc.jsonl".to_string(),
+            last_error: None,
+            exit_code: None,
+            last_activity: None,
+            idle_seconds: None,
+            label: "api".to_string(),
+            tags: BTreeMap::new(),
+            healthy: None,
+        };
+        let fields = vec!["id".to_string(), "status".to_string(), "name".to_string()];
+        let projected = serde_json::to_string(&project(&session, &fields)).unwrap();
+        assert_eq!(projected, r#"{"id":"abc","name":"api","status":"running"}"#);
+
+        // Unknown names are skipped
+        let fields = vec!["id".to_string(), "colour".to_string()];
+        let projected = serde_json::to_string(&project(&session, &fields)).unwrap();
+        assert_eq!(projected, r#"{"id":"abc"}"#);
+    }
+
+    #[test]
+    fn test_healthy_depends_on_idle_threshold() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let log_path = temp_dir.path().join("s.jsonl");
+        let file = std::fs::File::create(&log_path).unwrap();
+        let now = std::time::SystemTime::now();
+        file.set_modified(now - std::time::Duration::from_secs(120)).unwrap();
+
+        let session = SessionInfo {
+            id: "abc".to_string(),
+            working_dir: "/tmp".to_string(),
+            created_at: "2024-01-01T00:00:00Z".to_string(),
+            status: SessionStatus::Running,
+            log_path: log_path.display().to_string(),
+            last_error: None,
+            exit_code: None,
+            last_activity: None,
+            idle_seconds: None,
+            label: String::new(),
+            tags: BTreeMap::new(),
+            healthy: None,
+        };
+
+        let mut strict = session.clone();
+        strict.assess_idle(60, now);
+        assert_eq!((strict.idle_seconds, strict.healthy), (Some(120), Some(false)));
+
+        let mut lenient = session.clone();
+        lenient.assess_idle(300, now);
+        assert_eq!((lenient.idle_seconds, lenient.healthy), (Some(120), Some(true)));
+
+        // Health only applies to running sessions
+        let mut stale = SessionInfo { status: SessionStatus::Stale, ..session };
+        stale.assess_idle(300, now);
+        assert_eq!(stale.healthy, None);
+    }
 }
@@ -1,16 +1,28 @@
 use serde::{Deserialize, Serialize};
 use std::fs::{File, OpenOptions};
-use std::io::Write;
+use std::io::{BufRead, BufReader, Lines, Write};
 use std::path::{Path, PathBuf};
 use uuid::Uuid;
-use anyhow::Result;
+use anyhow::{Context, Result};
+
+pub mod binary;
+pub mod render;
+
+use render::OutputFilter;
 
 /// Direction of PTY data flow
-#[derive(Debug, Clone, Serialize, Deserialize)]
+///
+/// Readers must tolerate directions they don't know: logs written by a
+/// newer version deserialize them as `Unknown`, which renderers skip.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 #[serde(rename_all = "lowercase")]
 pub enum Direction {
     Input,  // User → Claude
     Output, // Claude → User
+    Marker, // Synthetic marker written by the daemon (e.g. checkpoints)
+    Meta,   // JSON metadata about the session (e.g. `LaunchInfo`); not rendered
+    #[serde(other)]
+    Unknown, // Written by a newer version; never written by this one
 }
 
 /// A single log entry capturing PTY I/O
@@ -27,6 +39,12 @@ pub struct LogEntry {
     pub data: Vec<u8>,
     /// Optional: size of data in bytes
     pub size: usize,
+    /// Position of the entry in the order things happened in the session,
+    /// from 1. Input is numbered before it is written to the PTY, so its
+    /// echo always sorts after it even if the echo reached the file first
+    /// or within the same timestamp. 0 in logs from before numbering.
+    #[serde(default)]
+    pub seq: u64,
 }
 
 impl LogEntry {
@@ -39,12 +57,81 @@ impl LogEntry {
             direction,
             data,
             size,
+            seq: 0,
+        }
+    }
+
+    /// Keep the entry only if it is Claude's output (what an attached
+    /// viewer shows)
+    pub fn into_output(self) -> Option<Self> {
+        matches!(self.direction, Direction::Output).then_some(self)
+    }
+}
+
+/// On-disk format of a session log, told apart by its extension
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum LogFormat {
+    /// `.jsonl`: one JSON entry per line, data base64-encoded
+    #[default]
+    Jsonl,
+    /// `.clog`: length-prefixed records with raw data (see `binary`)
+    Binary,
+}
+
+impl LogFormat {
+    /// The format of the log at `path`
+    pub fn of(path: &Path) -> Self {
+        match path.extension().and_then(|e| e.to_str()) {
+            Some(binary::EXTENSION) => LogFormat::Binary,
+            _ => LogFormat::Jsonl,
+        }
+    }
+
+    /// File extension of logs in this format
+    pub fn extension(self) -> &'static str {
+        match self {
+            LogFormat::Jsonl => "jsonl",
+            LogFormat::Binary => binary::EXTENSION,
+        }
+    }
+
+    /// Format of new session logs: `CLAUDE_SESSIONS_LOG_FORMAT=binary`
+    /// for `.clog`, JSON lines otherwise. Existing logs keep the format
+    /// they were started in.
+    pub fn from_env() -> Self {
+        match std::env::var("CLAUDE_SESSIONS_LOG_FORMAT").as_deref().map(str::trim) {
+            Ok("binary") | Ok("clog") => LogFormat::Binary,
+            _ => LogFormat::Jsonl,
+        }
+    }
+}
+
+/// How a session's process was started, logged as a `Meta` entry before
+/// its first output
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LaunchInfo {
+    /// Resolved path of the binary
+    pub program: String,
+    pub args: Vec<String>,
+    pub working_dir: String,
+    /// Names of the variables in its environment, sorted; values may be
+    /// secrets and are never logged
+    pub env_keys: Vec<String>,
+}
+
+impl LaunchInfo {
+    /// The launch record carried by `entry`, if it is one (for tests)
+    #[cfg(test)]
+    pub fn from_entry(entry: &LogEntry) -> Option<Self> {
+        if entry.direction != Direction::Meta {
+            return None;
         }
+        serde_json::from_slice(&entry.data).ok()
     }
 }
 
 /// Custom serde module for base64 encoding/decoding
-mod base64_serde {
+pub(crate) mod base64_serde {
     use serde::{Deserialize, Deserializer, Serializer};
     use base64::{Engine as _, engine::general_purpose};
 
@@ -72,52 +159,1128 @@ pub struct SessionLogger {
     session_id: Uuid,
     log_file: File,
     log_path: PathBuf,
+    /// Chosen by `log_path`'s extension
+    format: LogFormat,
+    /// Rotate once the active file reaches this size
+    max_bytes: Option<u64>,
+    /// Size of the active file
+    written: u64,
+    /// When the last entry was logged (in memory only)
+    last_logged: Option<std::time::SystemTime>,
+    /// When input and output were last logged (in memory only)
+    last_input: Option<std::time::SystemTime>,
+    last_output: Option<std::time::SystemTime>,
+    /// `seq` of the next entry; carries on from the entries already in
+    /// the log. The logger is shared (behind a mutex) by the output reader
+    /// and `InputWriter::write`, so this is the session's one counter.
+    next_seq: u64,
+}
+
+/// Size at which session logs are rotated unless configured otherwise
+pub const DEFAULT_MAX_LOG_BYTES: u64 = 50 * 1024 * 1024;
+
+/// `seq` of the last entry in a log (across rotations), 0 if there is none.
+/// A log from before numbering is read through to number its entries the
+/// way `LogFollower` does.
+pub fn last_seq(log_path: &Path) -> u64 {
+    let last = log_segments(log_path).iter().rev().find_map(|segment| last_entry(segment));
+    match last {
+        Some(entry) if entry.seq == 0 => LogFollower::from_start(log_path)
+            .read_new_entries()
+            .ok()
+            .and_then(|entries| entries.last().map(|entry| entry.seq))
+            .unwrap_or(0),
+        last => last.map_or(0, |entry| entry.seq),
+    }
+}
+
+/// The last complete entry of one log file; for JSON lines, reading only
+/// its end when the entry fits in the last 64 KiB
+fn last_entry(path: &Path) -> Option<LogEntry> {
+    use std::io::{Read, Seek, SeekFrom};
+
+    if LogFormat::of(path) == LogFormat::Binary {
+        return binary::last_record(path);
+    }
+
+    const TAIL_BYTES: u64 = 64 * 1024;
+    let mut file = File::open(path).ok()?;
+    let len = file.metadata().ok()?.len();
+    let start = len.saturating_sub(TAIL_BYTES);
+    file.seek(SeekFrom::Start(start)).ok()?;
+    let mut tail = Vec::new();
+    file.read_to_end(&mut tail).ok()?;
+
+    let body = tail.strip_suffix(b"\n")?;
+    match body.iter().rposition(|&b| b == b'\n') {
+        Some(newline) => serde_json::from_slice(&body[newline + 1..]).ok(),
+        None if start == 0 => serde_json::from_slice(body).ok(),
+        // One entry longer than the tail: read the file through
+        None => {
+            let (lines, _) = crate::tail::last_lines(path, 1).ok()?;
+            serde_json::from_str(lines.last()?).ok()
+        }
+    }
+}
+
+/// Path of the `n`th rotated segment of a log: `<stem>.<n>.jsonl` (or
+/// `.clog`, like the log) next to `log_path`, numbered from 1 (oldest)
+pub fn segment_path(log_path: &Path, n: usize) -> PathBuf {
+    let stem = log_path.file_stem().unwrap_or_default().to_string_lossy();
+    log_path.with_file_name(format!("{}.{}.{}", stem, n, LogFormat::of(log_path).extension()))
+}
+
+/// Whether `path` has the extension of a session log in either format
+pub fn is_log_file(path: &Path) -> bool {
+    matches!(path.extension().and_then(|e| e.to_str()), Some("jsonl") | Some(binary::EXTENSION))
+}
+
+/// Whether `path` looks like a rotated segment (`<stem>.<n>.<ext>`)
+pub fn is_rotated_segment(path: &Path) -> bool {
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    stem.rsplit_once('.')
+        .is_some_and(|(_, n)| !n.is_empty() && n.bytes().all(|b| b.is_ascii_digit()))
+}
+
+/// How many rotated segments a log has
+pub fn rotated_count(log_path: &Path) -> usize {
+    (1..).take_while(|&n| segment_path(log_path, n).exists()).count()
+}
+
+/// Every file of a session log in order: the rotated segments, oldest
+/// first, then `log_path` itself (the active file)
+pub fn log_segments(log_path: &Path) -> Vec<PathBuf> {
+    let mut segments: Vec<PathBuf> =
+        (1..=rotated_count(log_path)).map(|n| segment_path(log_path, n)).collect();
+    segments.push(log_path.to_path_buf());
+    segments
+}
+
+/// Reads the entries appended to a session log, following it across
+/// rotations (see `crate::tail::read_new_lines` for a single file)
+#[derive(Debug, Clone)]
+pub struct LogFollower {
+    log_path: PathBuf,
+    /// Rotated segments already read in full
+    segments_read: usize,
+    /// Position in the file after those segments
+    offset: u64,
+    /// `seq` of the last entry returned
+    last_seq: u64,
+}
+
+impl LogFollower {
+    /// Start at the beginning of the oldest segment
+    pub fn from_start(log_path: &Path) -> Self {
+        LogFollower { log_path: log_path.to_path_buf(), segments_read: 0, offset: 0, last_seq: 0 }
+    }
+
+    /// Start at the current end of the log
+    pub fn at_end(log_path: &Path) -> Self {
+        LogFollower {
+            log_path: log_path.to_path_buf(),
+            segments_read: rotated_count(log_path),
+            offset: std::fs::metadata(log_path).map(|m| m.len()).unwrap_or(0),
+            last_seq: last_seq(log_path),
+        }
+    }
+
+    /// The complete entries written since the last call, in order.
+    /// Lines of a `.jsonl` log that aren't valid entries are skipped.
+    /// Entries from before numbering (`seq` 0) are numbered on from the
+    /// entry before them, so every entry returned has a `seq`.
+    pub fn read_new_entries(&mut self) -> std::io::Result<Vec<LogEntry>> {
+        let entries = self.read_unnumbered(true)?;
+        Ok(self.number(entries))
+    }
+
+    /// Like `read_new_entries`, but one file at a time: the next rotated
+    /// segment not read yet, else what is new in the active file. Empty
+    /// once caught up. Replays a long log without holding all of it.
+    pub fn read_next_entries(&mut self) -> std::io::Result<Vec<LogEntry>> {
+        let entries = self.read_unnumbered(false)?;
+        Ok(self.number(entries))
+    }
+
+    fn number(&mut self, mut entries: Vec<LogEntry>) -> Vec<LogEntry> {
+        for entry in &mut entries {
+            if entry.seq == 0 {
+                entry.seq = self.last_seq + 1;
+            }
+            self.last_seq = entry.seq;
+        }
+        entries
+    }
+
+    /// The entries since the last read, of every file (`whole`) or up to
+    /// the end of the first file that has any
+    fn read_unnumbered(&mut self, whole: bool) -> std::io::Result<Vec<LogEntry>> {
+        let mut entries = Vec::new();
+        loop {
+            // The file we were reading became segment `segments_read + 1`:
+            // finish it, then read any later segments whole
+            let rotated = rotated_count(&self.log_path);
+            while self.segments_read < rotated {
+                self.segments_read += 1;
+                let segment = segment_path(&self.log_path, self.segments_read);
+                entries.extend(read_new_entries(&segment, &mut self.offset)?);
+                self.offset = 0;
+                if !whole && !entries.is_empty() {
+                    return Ok(entries);
+                }
+            }
+
+            let mut offset = self.offset;
+            let active = read_new_entries(&self.log_path, &mut offset)?;
+            // Rotated while we read: those entries are in the new segment
+            if rotated_count(&self.log_path) == rotated {
+                self.offset = offset;
+                entries.extend(active);
+                return Ok(entries);
+            }
+        }
+    }
+}
+
+/// The complete entries appended to one log file since `offset`, in
+/// either format
+fn read_new_entries(path: &Path, offset: &mut u64) -> std::io::Result<Vec<LogEntry>> {
+    match LogFormat::of(path) {
+        LogFormat::Jsonl => Ok(crate::tail::read_new_lines(path, offset)?
+            .iter()
+            .filter_map(|line| serde_json::from_str(line).ok())
+            .collect()),
+        LogFormat::Binary => binary::read_new_records(path, offset),
+    }
 }
 
 impl SessionLogger {
-    /// Create a new session logger
-    /// 
-    /// Log files are stored in: `~/.claude-sessions/logs/<session_id>.jsonl`
-    pub fn new(session_id: Uuid) -> Result<Self> {
-        let log_dir = Self::log_directory()?;
-        std::fs::create_dir_all(&log_dir)?;
-
-        let log_path = log_dir.join(format!("{}.jsonl", session_id));
-        let log_file = OpenOptions::new()
-            .create(true)
-            .append(true)
-            .open(&log_path)?;
+    /// Create a session logger appending to an explicit log file, in the
+    /// format its extension names (see `LogFormat::of`)
+    pub fn with_path(session_id: Uuid, log_path: PathBuf) -> Result<Self> {
+        if let Some(parent) = log_path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+
+        let format = LogFormat::of(&log_path);
+        let (log_file, written) = Self::open_log(&log_path, format, session_id)?;
+        let next_seq = last_seq(&log_path) + 1;
 
         Ok(SessionLogger {
             session_id,
             log_file,
             log_path,
+            format,
+            max_bytes: None,
+            written,
+            last_logged: None,
+            last_input: None,
+            last_output: None,
+            next_seq,
         })
     }
 
-    /// Get the log directory path
-    fn log_directory() -> Result<PathBuf> {
-        let home = std::env::var("HOME")
-            .or_else(|_| std::env::var("USERPROFILE"))?;
-        Ok(PathBuf::from(home).join(".claude-sessions").join("logs"))
+    /// Rotate the log once it reaches `max_bytes` (`None`: never)
+    ///
+    /// The full file is renamed to the next `segment_path` and logging
+    /// continues in a fresh file at `log_path`; readers stitch the
+    /// segments back together (see `log_segments`).
+    pub fn with_max_bytes(mut self, max_bytes: Option<u64>) -> Self {
+        self.max_bytes = max_bytes;
+        self
+    }
+
+    /// Validate and create a custom log directory for a session.
+    ///
+    /// The path must be absolute (the CLI expands it before sending). If we
+    /// create the directory it is made owner-only on Unix, since logs carry
+    /// raw terminal I/O.
+    pub fn prepare_log_dir(dir: &Path) -> Result<()> {
+        if !dir.is_absolute() {
+            anyhow::bail!("Log directory must be an absolute path: {:?}", dir);
+        }
+
+        if !dir.exists() {
+            std::fs::create_dir_all(dir)
+                .with_context(|| format!("Failed to create log directory {:?}", dir))?;
+            #[cfg(unix)]
+            {
+                use std::os::unix::fs::PermissionsExt;
+                std::fs::set_permissions(dir, std::fs::Permissions::from_mode(0o700))?;
+            }
+        }
+
+        if !dir.is_dir() {
+            anyhow::bail!("Log directory is not a directory: {:?}", dir);
+        }
+
+        Ok(())
+    }
+
+    /// Open `log_path` for appending, starting a new binary file with its
+    /// header; returns the file and its size
+    fn open_log(log_path: &Path, format: LogFormat, session_id: Uuid) -> Result<(File, u64)> {
+        let mut log_file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(log_path)?;
+        let mut written = log_file.metadata()?.len();
+        if format == LogFormat::Binary && written == 0 {
+            let header = binary::encode_header(session_id);
+            log_file.write_all(&header)?;
+            written = header.len() as u64;
+        }
+        Ok((log_file, written))
     }
 
     /// Log an entry (non-blocking write)
     pub fn log(&mut self, direction: Direction, data: Vec<u8>) -> Result<()> {
-        let entry = LogEntry::new(self.session_id, direction, data);
-        let json = serde_json::to_string(&entry)?;
-        writeln!(self.log_file, "{}", json)?;
+        let seq = self.take_seq();
+        self.log_numbered(direction, data, seq)
+    }
+
+    /// Claim the next `seq`, for an entry that will be logged later with
+    /// `log_numbered` (input, numbered before it is sent)
+    pub fn take_seq(&mut self) -> u64 {
+        let seq = self.next_seq;
+        self.next_seq += 1;
+        seq
+    }
+
+    /// Log an entry under a `seq` claimed earlier with `take_seq`
+    pub fn log_numbered(&mut self, direction: Direction, data: Vec<u8>, seq: u64) -> Result<()> {
+        let entry = LogEntry { seq, ..LogEntry::new(self.session_id, direction, data) };
+        let bytes = match self.format {
+            LogFormat::Jsonl => {
+                let mut json = serde_json::to_vec(&entry)?;
+                json.push(b'\n');
+                json
+            }
+            LogFormat::Binary => binary::encode_record(&entry)?,
+        };
+        self.log_file.write_all(&bytes)?;
         // Flush to ensure data is written immediately
         self.log_file.flush()?;
+        let now = std::time::SystemTime::now();
+        self.last_logged = Some(now);
+        match entry.direction {
+            Direction::Input => self.last_input = Some(now),
+            Direction::Output => self.last_output = Some(now),
+            _ => {}
+        }
+
+        self.written += bytes.len() as u64;
+        if self.max_bytes.is_some_and(|max| self.written >= max) {
+            self.rotate()?;
+        }
+        Ok(())
+    }
+
+    /// When this logger last wrote an entry, if it has
+    pub fn last_logged(&self) -> Option<std::time::SystemTime> {
+        self.last_logged
+    }
+
+    /// When the input that is still waiting for output was logged: the
+    /// last input, if no output has been logged since
+    pub fn awaiting_output_since(&self) -> Option<std::time::SystemTime> {
+        self.last_input.filter(|&input| self.last_output.is_none_or(|output| output < input))
+    }
+
+    /// Move the active file aside as the next segment and start a new one
+    fn rotate(&mut self) -> Result<()> {
+        self.log_file.sync_data()?;
+        let segment = segment_path(&self.log_path, rotated_count(&self.log_path) + 1);
+        std::fs::rename(&self.log_path, &segment)
+            .with_context(|| format!("Failed to rotate {} to {}", self.log_path.display(), segment.display()))?;
+        (self.log_file, self.written) = Self::open_log(&self.log_path, self.format, self.session_id)?;
         Ok(())
     }
 
-    /// Get the path to this session's log file
-    pub fn log_path(&self) -> &Path {
-        &self.log_path
+    /// Flush and fsync the log file so everything written is durable
+    pub fn sync(&mut self) -> Result<()> {
+        self.log_file.flush()?;
+        self.log_file.sync_data()?;
+        Ok(())
+    }
+}
+
+/// Write a plain-text transcript of a session log.
+///
+/// Output is passed through an `OutputFilter` to drop TUI noise, input
+/// lines are prefixed with `>>> ` and markers are shown as separator lines.
+/// Corrupt lines are skipped and counted.
+pub fn write_transcript<W: Write>(log_path: &Path, writer: &mut W) -> Result<TranscriptStats> {
+    write_transcript_with(log_path, writer, &TranscriptOptions::default())
+}
+
+/// Which entries `read_entries` yields
+#[derive(Debug, Clone, Default)]
+pub struct EntryFilter {
+    /// Only entries in these directions (empty: every direction)
+    pub directions: Vec<Direction>,
+    /// Only entries logged at or after this time
+    pub since: Option<chrono::DateTime<chrono::FixedOffset>>,
+    /// Only entries logged before this time
+    pub until: Option<chrono::DateTime<chrono::FixedOffset>>,
+}
+
+impl EntryFilter {
+    /// Only entries in `direction`
+    pub fn direction(direction: Direction) -> Self {
+        EntryFilter { directions: vec![direction], ..Self::default() }
+    }
+
+    fn accepts(&self, entry: &LogEntry) -> bool {
+        if !self.directions.is_empty() && !self.directions.contains(&entry.direction) {
+            return false;
+        }
+        if self.since.is_none() && self.until.is_none() {
+            return true;
+        }
+        // Entries without a usable timestamp can't be placed in a window
+        let Ok(at) = chrono::DateTime::parse_from_rfc3339(&entry.timestamp) else {
+            return false;
+        };
+        self.since.is_none_or(|since| at >= since) && self.until.is_none_or(|until| at < until)
+    }
+}
+
+/// Stream the entries of a session log, oldest first, across its rotated
+/// segments.
+///
+/// The files are read entry by entry, never loaded whole, in whichever
+/// format each one is in. Lines that aren't valid entries (a torn final
+/// write, hand edits) are skipped and counted in `Entries::skipped`; read
+/// errors are yielded. Entries that `filter` rejects are skipped too.
+pub fn read_entries(log_path: &Path, filter: EntryFilter) -> Result<Entries> {
+    let mut segments = log_segments(log_path);
+    let first = segments.remove(0);
+    Ok(Entries { segment: Segment::open(&first)?, rest: segments.into(), filter, skipped: 0 })
+}
+
+/// The segment `Entries` is reading
+enum Segment {
+    Lines(Lines<BufReader<File>>),
+    Records(binary::Records<BufReader<File>>),
+}
+
+impl Segment {
+    fn open(path: &Path) -> Result<Self> {
+        match LogFormat::of(path) {
+            LogFormat::Jsonl => {
+                let file =
+                    File::open(path).with_context(|| format!("Failed to open log file {}", path.display()))?;
+                Ok(Segment::Lines(BufReader::new(file).lines()))
+            }
+            LogFormat::Binary => Ok(Segment::Records(binary::Records::open(path)?)),
+        }
+    }
+}
+
+/// Iterator returned by `read_entries`
+pub struct Entries {
+    segment: Segment,
+    /// Segments still to read
+    rest: std::collections::VecDeque<PathBuf>,
+    filter: EntryFilter,
+    skipped: usize,
+}
+
+impl Entries {
+    /// Lines skipped so far because they weren't valid entries
+    pub fn skipped(&self) -> usize {
+        self.skipped
+    }
+}
+
+impl Iterator for Entries {
+    type Item = Result<LogEntry>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let entry = match &mut self.segment {
+                Segment::Lines(lines) => match lines.next() {
+                    Some(Ok(line)) if line.trim().is_empty() => continue,
+                    Some(Ok(line)) => match serde_json::from_str::<LogEntry>(&line) {
+                        Ok(entry) => Some(entry),
+                        Err(_) => {
+                            self.skipped += 1;
+                            continue;
+                        }
+                    },
+                    Some(Err(e)) => return Some(Err(e.into())),
+                    None => None,
+                },
+                Segment::Records(records) => match records.next() {
+                    Some(Ok(entry)) => Some(entry),
+                    Some(Err(e)) => return Some(Err(e)),
+                    None => None,
+                },
+            };
+            match entry {
+                Some(entry) if self.filter.accepts(&entry) => return Some(Ok(entry)),
+                Some(_) => {}
+                None => match Segment::open(&self.rest.pop_front()?) {
+                    Ok(segment) => self.segment = segment,
+                    Err(e) => return Some(Err(e)),
+                },
+            }
+        }
+    }
+}
+
+/// Display options for `write_transcript_with`
+#[derive(Debug, Clone)]
+pub struct TranscriptOptions {
+    /// Truncate each rendered entry to this many bytes, with a marker
+    pub max_entry_bytes: Option<usize>,
+    /// Keep Claude's color (SGR) sequences
+    pub color: bool,
+    /// Prefix entries with when they were logged
+    pub timestamp: Option<TimestampFormat>,
+    /// With `timestamp`, prefix every line of an entry, not just its first
+    pub timestamp_every_line: bool,
+    /// Label every line with this session prefix
+    pub prefix: Option<LinePrefix>,
+    /// Plain text or Markdown
+    pub format: TranscriptFormat,
+}
+
+impl Default for TranscriptOptions {
+    fn default() -> Self {
+        TranscriptOptions {
+            max_entry_bytes: None,
+            color: true,
+            timestamp: None,
+            timestamp_every_line: false,
+            prefix: None,
+            format: TranscriptFormat::Text,
+        }
+    }
+}
+
+/// How `write_transcript_with` lays out a transcript
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum TranscriptFormat {
+    /// Input lines prefixed with `>>> `, output as rendered
+    Text,
+    /// A labelled section per input and per run of output, in code fences;
+    /// never colored
+    Markdown,
+}
+
+/// What `write_transcript_with` wrote
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct TranscriptStats {
+    /// Entries rendered
+    pub entries: usize,
+    /// Log lines skipped because they weren't valid entries
+    pub skipped_lines: usize,
+}
+
+/// Four backticks, so fences Claude prints itself stay inside
+const MARKDOWN_FENCE: &str = "````";
+
+/// Opens and closes Markdown sections as the direction changes
+#[derive(Debug, Default)]
+struct MarkdownSections {
+    open: bool,
+    /// The last byte written inside the open fence ended a line
+    at_line_start: bool,
+}
+
+impl MarkdownSections {
+    /// Close the open fence, if any
+    fn close(&mut self) -> Vec<u8> {
+        if !std::mem::take(&mut self.open) {
+            return Vec::new();
+        }
+        let newline = if self.at_line_start { "" } else { "\n" };
+        format!("{}{}\n", newline, MARKDOWN_FENCE).into_bytes()
+    }
+
+    /// Start a fenced section titled `label`
+    fn open(&mut self, label: &str, timestamp: &str) -> Vec<u8> {
+        let mut section = self.close();
+        section.extend_from_slice(format!("\n**{}** · {}\n\n{}\n", label, timestamp, MARKDOWN_FENCE).as_bytes());
+        self.open = true;
+        self.at_line_start = true;
+        section
+    }
+
+    /// Body bytes for the open fence
+    fn body(&mut self, body: &[u8]) -> Vec<u8> {
+        if let Some(&last) = body.last() {
+            self.at_line_start = last == b'\n';
+        }
+        body.to_vec()
     }
 }
 
+/// How `--timestamp` shows when an entry was logged
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum TimestampFormat {
+    /// Local time
+    Local,
+    /// UTC (RFC 3339)
+    Utc,
+    /// Time since the first entry shown
+    Relative,
+}
+
+/// Prefixes rendered entries with `[<time>] `.
+///
+/// Each stamped entry starts on a fresh line, since output entries are
+/// arbitrary PTY reads that often end mid-line. Timestamps that don't
+/// parse are shown as logged.
+#[derive(Debug, Clone)]
+pub struct Timestamper {
+    format: TimestampFormat,
+    every_line: bool,
+    start: Option<chrono::DateTime<chrono::FixedOffset>>,
+    at_line_start: bool,
+}
+
+impl Timestamper {
+    pub fn new(format: TimestampFormat, every_line: bool) -> Self {
+        Timestamper { format, every_line, start: None, at_line_start: true }
+    }
+
+    fn label(&mut self, timestamp: &str) -> String {
+        let Ok(at) = chrono::DateTime::parse_from_rfc3339(timestamp) else {
+            return timestamp.to_string();
+        };
+        match self.format {
+            TimestampFormat::Local => at.with_timezone(&chrono::Local).format("%Y-%m-%d %H:%M:%S%.3f").to_string(),
+            TimestampFormat::Utc => at.with_timezone(&chrono::Utc).format("%Y-%m-%dT%H:%M:%S%.3fZ").to_string(),
+            TimestampFormat::Relative => {
+                let start = *self.start.get_or_insert(at);
+                let millis = (at - start).num_milliseconds().max(0);
+                format!(
+                    "+{:02}:{:02}:{:02}.{:03}",
+                    millis / 3_600_000,
+                    millis / 60_000 % 60,
+                    millis / 1000 % 60,
+                    millis % 1000
+                )
+            }
+        }
+    }
+
+    /// Stamp one rendered entry logged at `timestamp`
+    pub fn stamp(&mut self, timestamp: &str, rendered: &[u8]) -> Vec<u8> {
+        // Input and marker entries open with a newline to start a fresh
+        // line; stamping already guarantees that
+        let body = &rendered[rendered.iter().take_while(|&&b| b == b'\n').count()..];
+        if body.is_empty() {
+            return Vec::new();
+        }
+
+        let prefix = format!("[{}] ", self.label(timestamp));
+        let mut stamped = Vec::with_capacity(body.len() + prefix.len() + 1);
+        if !self.at_line_start {
+            stamped.push(b'\n');
+        }
+        stamped.extend_from_slice(prefix.as_bytes());
+        for (i, &b) in body.iter().enumerate() {
+            stamped.push(b);
+            if b == b'\n' && self.every_line && i + 1 < body.len() {
+                stamped.extend_from_slice(prefix.as_bytes());
+            }
+        }
+        self.at_line_start = body.ends_with(b"\n");
+        stamped
+    }
+}
+
+/// Session details a `--prefix` template can refer to
+#[derive(Debug, Clone, Default)]
+pub struct PrefixFields {
+    pub id: String,
+    /// The session's label (by default its working directory's basename)
+    pub name: String,
+    pub dir: String,
+}
+
+/// Fill in a `--prefix` template.
+///
+/// `{id}`, `{name}`, `{dir}` and `{ts}` (the entry's log time) are
+/// replaced; `{id:.8}` keeps only the first 8 characters. Anything else,
+/// including unknown placeholders, is copied as is.
+pub fn render_prefix(template: &str, fields: &PrefixFields, timestamp: &str) -> String {
+    let mut rendered = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(open) = rest.find('{') {
+        rendered.push_str(&rest[..open]);
+        let Some(close) = rest[open..].find('}').map(|i| open + i) else {
+            rendered.push_str(&rest[open..]);
+            return rendered;
+        };
+        let placeholder = &rest[open + 1..close];
+        let (name, width) = match placeholder.split_once(":.") {
+            Some((name, width)) => (name, width.parse::<usize>().ok()),
+            None => (placeholder, None),
+        };
+        let value = match name {
+            "id" => Some(fields.id.as_str()),
+            "name" => Some(fields.name.as_str()),
+            "dir" => Some(fields.dir.as_str()),
+            "ts" => Some(timestamp),
+            _ => None,
+        };
+        match value {
+            Some(value) => rendered.extend(value.chars().take(width.unwrap_or(usize::MAX))),
+            None => rendered.push_str(&rest[open..=close]),
+        }
+        rest = &rest[close + 1..];
+    }
+    rendered.push_str(rest);
+    rendered
+}
+
+/// A `--prefix` template bound to one session, applied to the start of
+/// every line as entries stream past
+#[derive(Debug, Clone)]
+pub struct LinePrefix {
+    template: String,
+    fields: PrefixFields,
+    at_line_start: bool,
+}
+
+impl LinePrefix {
+    pub fn new(template: impl Into<String>, fields: PrefixFields) -> Self {
+        LinePrefix { template: template.into(), fields, at_line_start: true }
+    }
+
+    /// Prefix the lines of one rendered entry logged at `timestamp`
+    pub fn apply(&mut self, timestamp: &str, rendered: &[u8]) -> Vec<u8> {
+        // As with timestamps, an entry's leading newlines only exist to
+        // start a fresh line; don't turn them into empty labelled lines
+        let body = if self.at_line_start {
+            &rendered[rendered.iter().take_while(|&&b| b == b'\n').count()..]
+        } else {
+            rendered
+        };
+
+        let prefix = render_prefix(&self.template, &self.fields, timestamp);
+        let mut labelled = Vec::with_capacity(body.len() + prefix.len());
+        for &b in body {
+            if self.at_line_start {
+                labelled.extend_from_slice(prefix.as_bytes());
+            }
+            labelled.push(b);
+            self.at_line_start = b == b'\n';
+        }
+        labelled
+    }
+}
+
+/// Same as `write_transcript`, with display options
+pub fn write_transcript_with<W: Write>(
+    log_path: &Path,
+    writer: &mut W,
+    options: &TranscriptOptions,
+) -> Result<TranscriptStats> {
+    let mut entries = read_entries(log_path, EntryFilter::default())?;
+
+    let markdown = options.format == TranscriptFormat::Markdown;
+    let mut filter = OutputFilter::default();
+    filter.keep_colors = options.color && !markdown;
+    let mut sections = MarkdownSections::default();
+    let mut last_direction = None;
+    let mut stamper = options
+        .timestamp
+        .map(|format| Timestamper::new(format, options.timestamp_every_line));
+    let mut prefix = options.prefix.clone();
+    let mut count = 0;
+    for entry in entries.by_ref() {
+        let entry = entry?;
+        let text = String::from_utf8_lossy(&entry.data);
+        let mut rendered = match (entry.direction.clone(), markdown) {
+            (Direction::Input, false) => format!("\n>>> {}", text).into_bytes(),
+            (Direction::Output, false) => filter.feed(&entry.data),
+            (Direction::Marker, false) => format!("\n--- {} @ {} ---\n", text, entry.timestamp).into_bytes(),
+            (Direction::Input, true) => text.into_owned().into_bytes(),
+            (Direction::Output, true) => filter.feed(&entry.data),
+            (Direction::Marker, true) => {
+                let mut marker = sections.close();
+                marker.extend_from_slice(format!("\n---\n\n*{} @ {}*\n", text, entry.timestamp).as_bytes());
+                writer.write_all(&marker)?;
+                last_direction = Some(Direction::Marker);
+                count += 1;
+                continue;
+            }
+            (Direction::Meta | Direction::Unknown, _) => continue,
+        };
+        if let Some(max) = options.max_entry_bytes {
+            truncate_entry(&mut rendered, max);
+        }
+        if let Some(stamper) = stamper.as_mut() {
+            rendered = stamper.stamp(&entry.timestamp, &rendered);
+        }
+        if let Some(prefix) = prefix.as_mut() {
+            rendered = prefix.apply(&entry.timestamp, &rendered);
+        }
+        if markdown && rendered.is_empty() {
+            continue;
+        }
+        if markdown {
+            // Each input is its own section; a run of output shares one
+            let new_section = entry.direction == Direction::Input || last_direction != Some(Direction::Output);
+            if new_section {
+                let label = if entry.direction == Direction::Input { "Input" } else { "Output" };
+                writer.write_all(&sections.open(label, &entry.timestamp))?;
+            }
+            rendered = sections.body(&rendered);
+        }
+        writer.write_all(&rendered)?;
+        last_direction = Some(entry.direction);
+        count += 1;
+    }
+    writer.write_all(&sections.close())?;
+    writer.flush()?;
+
+    Ok(TranscriptStats { entries: count, skipped_lines: entries.skipped() })
+}
+
+/// Cut a rendered entry down to `max` bytes (backing off to a UTF-8
+/// boundary) and append a marker saying how much was dropped
+fn truncate_entry(rendered: &mut Vec<u8>, max: usize) {
+    if rendered.len() <= max {
+        return;
+    }
+    let mut cut = max;
+    while cut > 0 && (rendered[cut] & 0xC0) == 0x80 {
+        cut -= 1;
+    }
+    let omitted = rendered.len() - cut;
+    rendered.truncate(cut);
+    rendered.extend_from_slice(format!(" [… {} more bytes]\n", omitted).as_bytes());
+}
+
+/// A transcript line that matched a search
+#[derive(Debug, Clone)]
+pub struct SearchMatch {
+    /// Timestamp of the entry the line started in
+    pub timestamp: String,
+    pub direction: Direction,
+    pub line: String,
+}
+
+/// Longest output line a search holds in memory; longer lines (e.g. output
+/// that never ends one) are searched in windows of this size
+pub const MAX_SEARCH_LINE_BYTES: usize = 64 * 1024;
+
+/// Bounds for `search_logs`
+#[derive(Debug, Clone, Copy)]
+pub struct SearchLimits {
+    /// Stop once this many matches have been found
+    pub max_results: usize,
+    /// Log files scanned in parallel
+    pub workers: usize,
+}
+
+impl Default for SearchLimits {
+    fn default() -> Self {
+        SearchLimits {
+            max_results: 1000,
+            workers: std::thread::available_parallelism().map_or(1, |n| n.get()).min(4),
+        }
+    }
+}
+
+/// What `search_logs` found
+#[derive(Debug, Default)]
+pub struct SearchResults {
+    /// Matches as (index into the searched paths, match), in path order
+    pub matches: Vec<(usize, SearchMatch)>,
+    /// Log files that could be opened and were (at least partly) searched
+    pub files_scanned: usize,
+    /// The result cap was hit, so there may be more matches
+    pub truncated: bool,
+}
+
+/// Search session logs for transcript lines containing `query`.
+///
+/// Logs are streamed, never loaded whole, and up to `limits.workers` of
+/// them are scanned at once. Every worker stops as soon as
+/// `limits.max_results` matches have been found between them. Logs that
+/// don't exist (sessions without output yet) are skipped; any other
+/// failure to open or read one fails the search.
+pub fn search_logs(paths: &[PathBuf], query: &str, limits: SearchLimits) -> Result<SearchResults> {
+    use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+    use std::sync::Mutex;
+
+    let next = AtomicUsize::new(0);
+    let found = AtomicUsize::new(0);
+    let scanned = AtomicUsize::new(0);
+    let truncated = AtomicBool::new(false);
+    let matches = Mutex::new(Vec::new());
+    let error = Mutex::new(None);
+
+    std::thread::scope(|scope| {
+        for _ in 0..limits.workers.max(1) {
+            scope.spawn(|| loop {
+                let index = next.fetch_add(1, Ordering::SeqCst);
+                if index >= paths.len() || truncated.load(Ordering::SeqCst) {
+                    return;
+                }
+                let result = match read_entries(&paths[index], EntryFilter::default()) {
+                    Ok(entries) => {
+                        scanned.fetch_add(1, Ordering::SeqCst);
+                        scan_entries(entries, query, |m| {
+                            if found.fetch_add(1, Ordering::SeqCst) >= limits.max_results {
+                                truncated.store(true, Ordering::SeqCst);
+                                return false;
+                            }
+                            matches.lock().unwrap().push((index, m));
+                            true
+                        })
+                    }
+                    Err(e) if is_not_found(&e) => continue,
+                    Err(e) => Err(e),
+                };
+                if let Err(e) = result {
+                    error.lock().unwrap().get_or_insert(e.context(format!("Failed to search {}", paths[index].display())));
+                    truncated.store(true, Ordering::SeqCst);
+                    return;
+                }
+            });
+        }
+    });
+
+    if let Some(e) = error.into_inner().unwrap() {
+        return Err(e);
+    }
+    // Workers finish in any order; matches within one log keep theirs
+    let mut matches = matches.into_inner().unwrap();
+    matches.sort_by_key(|(index, _)| *index);
+    Ok(SearchResults {
+        matches,
+        files_scanned: scanned.into_inner(),
+        truncated: truncated.into_inner(),
+    })
+}
+
+/// Whether `error` comes down to a missing file
+fn is_not_found(error: &anyhow::Error) -> bool {
+    error
+        .root_cause()
+        .downcast_ref::<std::io::Error>()
+        .is_some_and(|e| e.kind() == std::io::ErrorKind::NotFound)
+}
+
+/// Feed the transcript lines of `entries` containing `query` to
+/// `on_match` until it returns `false`. Corrupt lines are skipped.
+fn scan_entries<F>(entries: impl Iterator<Item = Result<LogEntry>>, query: &str, mut on_match: F) -> Result<()>
+where
+    F: FnMut(SearchMatch) -> bool,
+{
+    // Kept when a long line is cut into windows, so a match straddling
+    // the cut is still found (and, being shorter than `query`, never
+    // matched twice)
+    let overlap = query.len().saturating_sub(1);
+    for_each_line(entries, overlap, |line, timestamp, direction| {
+        let line = String::from_utf8_lossy(line);
+        if !line.contains(query) {
+            return true;
+        }
+        on_match(SearchMatch {
+            timestamp: timestamp.to_string(),
+            direction,
+            line: line.trim_end_matches('\r').to_string(),
+        })
+    })
+}
+
+/// Feed the transcript lines of `entries` to `on_line`, with the
+/// timestamp of the entry each started in, until it returns `false`.
+///
+/// Output is cleaned with `OutputFilter` and reassembled into lines across
+/// entries (a PTY read rarely ends on a line boundary); input and marker
+/// entries are split into lines on their own. Output lines longer than
+/// `MAX_SEARCH_LINE_BYTES` are passed on in windows of that size, each
+/// starting with the last `overlap` bytes of the one before.
+fn for_each_line<F>(entries: impl Iterator<Item = Result<LogEntry>>, overlap: usize, mut on_line: F) -> Result<()>
+where
+    F: FnMut(&[u8], &str, Direction) -> bool,
+{
+    let mut filter = OutputFilter::default();
+    let mut pending: Vec<u8> = Vec::new();
+    let mut pending_timestamp = String::new();
+
+    for entry in entries {
+        let entry = entry?;
+
+        match entry.direction {
+            Direction::Output => {
+                if pending.is_empty() {
+                    pending_timestamp = entry.timestamp.clone();
+                }
+                for byte in filter.feed(&entry.data) {
+                    if byte == b'\n' {
+                        if !on_line(&pending, &pending_timestamp, Direction::Output) {
+                            return Ok(());
+                        }
+                        pending.clear();
+                        pending_timestamp = entry.timestamp.clone();
+                        continue;
+                    }
+                    pending.push(byte);
+                    if pending.len() >= MAX_SEARCH_LINE_BYTES {
+                        if !on_line(&pending, &pending_timestamp, Direction::Output) {
+                            return Ok(());
+                        }
+                        pending.drain(..pending.len() - overlap.min(pending.len()));
+                    }
+                }
+            }
+            Direction::Input | Direction::Marker => {
+                let text = entry.data.strip_suffix(b"\n").unwrap_or(&entry.data);
+                for line in text.split(|&b| b == b'\n') {
+                    if !on_line(line, &entry.timestamp, entry.direction.clone()) {
+                        return Ok(());
+                    }
+                }
+            }
+            Direction::Meta | Direction::Unknown => {}
+        }
+    }
+    if !pending.is_empty() {
+        on_line(&pending, &pending_timestamp, Direction::Output);
+    }
+
+    Ok(())
+}
+
+/// What `grep_log` looks for
+#[derive(Debug, Clone)]
+pub enum GrepPattern {
+    Text(String),
+    Regex(regex::Regex),
+}
+
+impl GrepPattern {
+    /// `pattern` as plain text, or with `regex` as a regular expression
+    pub fn new(pattern: &str, regex: bool) -> Result<Self> {
+        if !regex {
+            return Ok(GrepPattern::Text(pattern.to_string()));
+        }
+        regex::Regex::new(pattern)
+            .map(GrepPattern::Regex)
+            .with_context(|| format!("Invalid regular expression {:?}", pattern))
+    }
+
+    pub fn is_match(&self, line: &str) -> bool {
+        match self {
+            GrepPattern::Text(text) => line.contains(text.as_str()),
+            GrepPattern::Regex(regex) => regex.is_match(line),
+        }
+    }
+}
+
+/// How `grep_log` reports what it finds
+#[derive(Debug, Clone, Copy, Default)]
+pub struct GrepOptions {
+    /// Lines to pass on before and after each match
+    pub context: usize,
+    /// Pass lines on without color escape sequences
+    pub strip_ansi: bool,
+}
+
+/// A transcript line from `grep_log`: a match, or context around one
+#[derive(Debug, Clone, PartialEq)]
+pub struct GrepLine {
+    /// Position in the transcript, from 1; a gap from the previous line
+    /// means lines were left out
+    pub number: usize,
+    /// Timestamp of the entry the line started in
+    pub timestamp: String,
+    pub direction: Direction,
+    pub line: String,
+    /// `false` for context lines
+    pub is_match: bool,
+}
+
+/// Pass the transcript lines of the log at `log_path` that match
+/// `pattern`, with `options.context` lines around each, to `on_line` in
+/// order. Returns how many lines matched.
+///
+/// Lines are matched without their color escape sequences. The log is
+/// streamed (across rotated segments); only the context before a match is
+/// held in memory.
+pub fn grep_log<F>(log_path: &Path, pattern: &GrepPattern, options: GrepOptions, mut on_line: F) -> Result<usize>
+where
+    F: FnMut(GrepLine),
+{
+    let entries = read_entries(log_path, EntryFilter::default())?;
+    let mut before: std::collections::VecDeque<GrepLine> = std::collections::VecDeque::new();
+    let mut after = 0;
+    let mut number = 0;
+    let mut matches = 0;
+
+    for_each_line(entries, 0, |bytes, timestamp, direction| {
+        number += 1;
+        let line = String::from_utf8_lossy(bytes);
+        let line = line.trim_end_matches('\r');
+        let plain = String::from_utf8_lossy(&render::strip_ansi(line.as_bytes())).into_owned();
+        let is_match = pattern.is_match(&plain);
+        let line = GrepLine {
+            number,
+            timestamp: timestamp.to_string(),
+            direction,
+            line: if options.strip_ansi { plain } else { line.to_string() },
+            is_match,
+        };
+
+        if is_match {
+            matches += 1;
+            before.drain(..).for_each(&mut on_line);
+            on_line(line);
+            after = options.context;
+        } else if after > 0 {
+            after -= 1;
+            on_line(line);
+        } else if options.context > 0 {
+            if before.len() == options.context {
+                before.pop_front();
+            }
+            before.push_back(line);
+        }
+        true
+    })?;
+
+    Ok(matches)
+}
+
+/// Record how a session's process was started at the current end of its
+/// log, before the process produces any output
+pub fn write_launch_info(session_id: Uuid, log_path: &Path, launch: &LaunchInfo) -> Result<()> {
+    let mut logger = SessionLogger::with_path(session_id, log_path.to_path_buf())?;
+    logger.log(Direction::Meta, serde_json::to_vec(launch)?)?;
+    Ok(())
+}
+
+/// Write a final checkpoint to a session log before it stops.
+///
+/// Appends a `Marker` entry, fsyncs the log so the tail of the session is
+/// durable, and optionally exports a transcript to `export_path`.
+pub fn write_checkpoint(session_id: Uuid, log_path: &Path, export_path: Option<&Path>) -> Result<()> {
+    let mut logger = SessionLogger::with_path(session_id, log_path.to_path_buf())?;
+    logger.log(Direction::Marker, b"checkpoint".to_vec())?;
+    logger.sync()?;
+
+    if let Some(export_path) = export_path {
+        let mut file = File::create(export_path)
+            .with_context(|| format!("Failed to create {}", export_path.display()))?;
+        write_transcript(log_path, &mut file)?;
+        file.sync_all()?;
+    }
+
+    Ok(())
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -138,6 +1301,90 @@ mod tests {
         assert_eq!(entry.data, parsed.data);
     }
 
+    #[test]
+    fn test_into_output_keeps_only_output() {
+        let entry = |direction| LogEntry::new(Uuid::new_v4(), direction, b"hi".to_vec());
+
+        assert_eq!(entry(Direction::Output).into_output().unwrap().data, b"hi");
+        assert!(entry(Direction::Input).into_output().is_none());
+        assert!(entry(Direction::Marker).into_output().is_none());
+    }
+
+    #[test]
+    fn test_checkpoint_writes_marker_and_export() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let log_path = temp_dir.path().join("session.jsonl");
+        let export_path = temp_dir.path().join("transcript.txt");
+        let session_id = Uuid::new_v4();
+
+        let mut logger = SessionLogger::with_path(session_id, log_path.clone()).unwrap();
+        logger.log(Direction::Input, b"hello".to_vec()).unwrap();
+        logger.log(Direction::Output, b"Hi there!".to_vec()).unwrap();
+
+        write_checkpoint(session_id, &log_path, Some(&export_path)).unwrap();
+
+        let contents = std::fs::read_to_string(&log_path).unwrap();
+        let last: LogEntry = serde_json::from_str(contents.lines().last().unwrap()).unwrap();
+        assert!(matches!(last.direction, Direction::Marker));
+
+        let transcript = std::fs::read_to_string(&export_path).unwrap();
+        assert!(transcript.contains(">>> hello"));
+        assert!(transcript.contains("Hi there!"));
+        assert!(transcript.contains("--- checkpoint @"));
+    }
+
+    #[test]
+    fn test_launch_info_leads_the_log_and_stays_out_of_transcripts() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let log_path = temp_dir.path().join("session.jsonl");
+        let session_id = Uuid::new_v4();
+        let launch = LaunchInfo {
+            program: "/usr/bin/claude".to_string(),
+            args: vec!["--verbose".to_string()],
+            working_dir: "/repos/api".to_string(),
+            env_keys: vec!["HOME".to_string(), "PATH".to_string()],
+        };
+
+        write_launch_info(session_id, &log_path, &launch).unwrap();
+        let mut logger = SessionLogger::with_path(session_id, log_path.clone()).unwrap();
+        logger.log(Direction::Output, b"Hi there!".to_vec()).unwrap();
+
+        let contents = std::fs::read_to_string(&log_path).unwrap();
+        let first: LogEntry = serde_json::from_str(contents.lines().next().unwrap()).unwrap();
+        assert_eq!(first.direction, Direction::Meta);
+        assert_eq!(LaunchInfo::from_entry(&first), Some(launch));
+
+        let mut transcript = Vec::new();
+        write_transcript(&log_path, &mut transcript).unwrap();
+        let transcript = String::from_utf8(transcript).unwrap();
+        assert!(transcript.contains("Hi there!"));
+        assert!(!transcript.contains("/usr/bin/claude"), "{}", transcript);
+    }
+
+    #[test]
+    fn test_custom_log_dir() {
+        use crate::session::Session;
+
+        let temp_dir = tempfile::tempdir().unwrap();
+        let log_dir = temp_dir.path().join("project-logs");
+        SessionLogger::prepare_log_dir(&log_dir).unwrap();
+
+        let session = Session::with_log_dir(PathBuf::from("/tmp"), Some(&log_dir));
+        assert_eq!(session.log_path.parent().unwrap(), log_dir);
+        assert!(session.log_path.to_string_lossy().ends_with(".jsonl"));
+
+        let mut logger = SessionLogger::with_path(session.id, session.log_path.clone()).unwrap();
+        logger.log(Direction::Output, b"custom dir".to_vec()).unwrap();
+        assert!(session.log_path.exists());
+
+        // Export resolves through the stored path
+        let mut transcript = Vec::new();
+        write_transcript(&session.log_path, &mut transcript).unwrap();
+        assert_eq!(String::from_utf8(transcript).unwrap(), "custom dir");
+
+        assert!(SessionLogger::prepare_log_dir(Path::new("relative/logs")).is_err());
+    }
+
     #[test]
     fn test_direction_serialization() {
         let input_json = serde_json::to_string(&Direction::Input).unwrap();
@@ -146,4 +1393,347 @@ mod tests {
         assert_eq!(input_json, "\"input\"");
         assert_eq!(output_json, "\"output\"");
     }
+
+    #[test]
+    fn test_unknown_direction_is_tolerated() {
+        let direction: Direction = serde_json::from_str("\"telemetry\"").unwrap();
+        assert!(matches!(direction, Direction::Unknown));
+
+        // A whole entry from a newer writer still parses
+        let line = r#"{"timestamp":"2024-01-01T00:00:00Z","session_id":"s","direction":"note","data":"aGk=","size":2}"#;
+        let entry: LogEntry = serde_json::from_str(line).unwrap();
+        assert!(matches!(entry.direction, Direction::Unknown));
+    }
+
+    #[test]
+    fn test_max_entry_bytes_truncates_only_large_entries() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let log_path = temp_dir.path().join("session.jsonl");
+        let mut logger = SessionLogger::with_path(Uuid::new_v4(), log_path.clone()).unwrap();
+        logger.log(Direction::Output, b"short\n".to_vec()).unwrap();
+        logger.log(Direction::Output, vec![b'x'; 100]).unwrap();
+
+        let options = TranscriptOptions { max_entry_bytes: Some(10), ..Default::default() };
+        let mut transcript = Vec::new();
+        write_transcript_with(&log_path, &mut transcript, &options).unwrap();
+
+        let text = String::from_utf8(transcript).unwrap();
+        assert_eq!(text, format!("short\n{} [… 90 more bytes]\n", "x".repeat(10)));
+    }
+
+    #[test]
+    fn test_transcript_timestamps() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let log_path = temp_dir.path().join("session.jsonl");
+        let entries = [
+            ("2024-03-01T10:00:00.250+00:00", Direction::Output, "build\nstarted\n"),
+            ("2024-03-01T11:01:05+01:00", Direction::Input, "status"),
+            ("2024-03-01T10:01:07.5Z", Direction::Output, "done\n"),
+        ];
+        let mut log = String::new();
+        for (timestamp, direction, data) in entries {
+            let mut entry = LogEntry::new(Uuid::new_v4(), direction, data.as_bytes().to_vec());
+            entry.timestamp = timestamp.to_string();
+            log.push_str(&serde_json::to_string(&entry).unwrap());
+            log.push('\n');
+        }
+        std::fs::write(&log_path, log).unwrap();
+
+        let render = |timestamp, timestamp_every_line| {
+            let options = TranscriptOptions { timestamp: Some(timestamp), timestamp_every_line, ..Default::default() };
+            let mut transcript = Vec::new();
+            write_transcript_with(&log_path, &mut transcript, &options).unwrap();
+            String::from_utf8(transcript).unwrap()
+        };
+
+        assert_eq!(
+            render(TimestampFormat::Utc, false),
+            "[2024-03-01T10:00:00.250Z] build\nstarted\n\
+             [2024-03-01T10:01:05.000Z] >>> status\n\
+             [2024-03-01T10:01:07.500Z] done\n"
+        );
+        assert_eq!(
+            render(TimestampFormat::Relative, true),
+            "[+00:00:00.000] build\n[+00:00:00.000] started\n\
+             [+00:01:04.750] >>> status\n\
+             [+00:01:07.250] done\n"
+        );
+    }
+
+    #[test]
+    fn test_prefix_template_labels_every_line() {
+        let fields = PrefixFields {
+            id: "3f2a9c1e-7b4d-4e0a-9c55-1d2e3f4a5b6c".to_string(),
+            name: "api".to_string(),
+            dir: "/work/api".to_string(),
+        };
+        assert_eq!(
+            render_prefix("[{id:.8}] {name} {dir} {ts} {other} {", &fields, "2024-03-01T10:00:00Z"),
+            "[3f2a9c1e] api /work/api 2024-03-01T10:00:00Z {other} {"
+        );
+
+        let temp_dir = tempfile::tempdir().unwrap();
+        let log_path = temp_dir.path().join("session.jsonl");
+        let mut logger = SessionLogger::with_path(Uuid::new_v4(), log_path.clone()).unwrap();
+        // A line split across entries gets one label
+        logger.log(Direction::Output, b"compiling\nfin".to_vec()).unwrap();
+        logger.log(Direction::Output, b"ished\n".to_vec()).unwrap();
+
+        let options = TranscriptOptions {
+            prefix: Some(LinePrefix::new("[{name}] ", fields)),
+            ..Default::default()
+        };
+        let mut transcript = Vec::new();
+        write_transcript_with(&log_path, &mut transcript, &options).unwrap();
+        assert_eq!(String::from_utf8(transcript).unwrap(), "[api] compiling\n[api] finished\n");
+    }
+
+    #[test]
+    fn test_search_logs_is_bounded_and_capped() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let mut paths = Vec::new();
+        for i in 0..6 {
+            let log_path = temp_dir.path().join(format!("{}.jsonl", i));
+            let mut logger = SessionLogger::with_path(Uuid::new_v4(), log_path.clone()).unwrap();
+            for j in 0..500 {
+                let line = if j % 100 == 0 { format!("needle {}\n", j) } else { format!("hay {}\n", j) };
+                logger.log(Direction::Output, line.into_bytes()).unwrap();
+            }
+            paths.push(log_path);
+        }
+        // 4 MiB of output that never ends a line, with the needle near the end
+        let endless = temp_dir.path().join("endless.jsonl");
+        let mut logger = SessionLogger::with_path(Uuid::new_v4(), endless.clone()).unwrap();
+        for _ in 0..512 {
+            logger.log(Direction::Output, vec![b'x'; 8192]).unwrap();
+        }
+        logger.log(Direction::Output, b"needle".to_vec()).unwrap();
+        paths.push(endless);
+        paths.push(temp_dir.path().join("missing.jsonl"));
+
+        let all = search_logs(&paths, "needle", SearchLimits { max_results: 100, workers: 3 }).unwrap();
+        assert_eq!(all.matches.len(), 6 * 5 + 1);
+        assert_eq!(all.files_scanned, 7);
+        assert!(!all.truncated);
+        // Path order, and the endless line was only ever held a window at a time
+        assert!(all.matches.windows(2).all(|w| w[0].0 <= w[1].0));
+        let (index, last) = all.matches.last().unwrap();
+        assert_eq!(*index, 6);
+        assert!(last.line.ends_with("needle") && last.line.len() <= MAX_SEARCH_LINE_BYTES);
+
+        let capped = search_logs(&paths, "needle", SearchLimits { max_results: 7, workers: 3 }).unwrap();
+        assert_eq!(capped.matches.len(), 7);
+        assert!(capped.truncated);
+        assert!(capped.matches.iter().all(|(_, m)| m.line.contains("needle")));
+
+        // A log that can't be opened for any other reason fails the search
+        paths.push(paths[0].join("nested.jsonl"));
+        let error = search_logs(&paths, "needle", SearchLimits { max_results: 100, workers: 3 }).unwrap_err();
+        assert!(format!("{:#}", error).contains("nested.jsonl"), "{:#}", error);
+    }
+
+    #[test]
+    fn test_grep_log_matches_with_context() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let log_path = temp_dir.path().join("session.jsonl");
+        let mut logger = SessionLogger::with_path(Uuid::new_v4(), log_path.clone()).unwrap();
+        logger.log(Direction::Input, b"find the bug\r".to_vec()).unwrap();
+        for i in 1..=10 {
+            logger.log(Direction::Output, format!("line {}\n", i).into_bytes()).unwrap();
+        }
+        // Colors and a line split across entries don't hide a match
+        logger.log(Direction::Output, b"\x1b[31mbug\x1b[0m in ".to_vec()).unwrap();
+        logger.log(Direction::Output, b"parser.rs:42\n".to_vec()).unwrap();
+        logger.log(Direction::Output, b"done\n".to_vec()).unwrap();
+
+        let grep = |pattern: &str, regex: bool, options: GrepOptions| {
+            let pattern = GrepPattern::new(pattern, regex).unwrap();
+            let mut lines = Vec::new();
+            let count = grep_log(&log_path, &pattern, options, |line| lines.push(line)).unwrap();
+            (count, lines)
+        };
+
+        let (count, lines) = grep("bug", false, GrepOptions::default());
+        assert_eq!(count, 2);
+        assert_eq!(lines[0].direction, Direction::Input);
+        assert_eq!(lines[0].line, "find the bug");
+        assert_eq!(lines[1].number, 12);
+        assert_eq!(lines[1].line, "\x1b[31mbug\x1b[0m in parser.rs:42");
+
+        let (count, lines) = grep(r"\.rs:\d+$", true, GrepOptions { context: 2, strip_ansi: true });
+        assert_eq!(count, 1);
+        let shown: Vec<_> = lines.iter().map(|l| (l.number, l.line.as_str(), l.is_match)).collect();
+        assert_eq!(
+            shown,
+            [(10, "line 9", false), (11, "line 10", false), (12, "bug in parser.rs:42", true), (13, "done", false)]
+        );
+
+        assert_eq!(grep("line [", false, GrepOptions::default()).0, 0);
+        assert!(GrepPattern::new("line [", true).is_err());
+    }
+
+    #[test]
+    fn test_markdown_transcript_sections_and_skipped_lines() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let log_path = temp_dir.path().join("session.jsonl");
+        let mut logger = SessionLogger::with_path(Uuid::new_v4(), log_path.clone()).unwrap();
+        logger.log(Direction::Input, b"explain".to_vec()).unwrap();
+        logger.log(Direction::Output, b"\x1b[1mSure\x1b[0m, ".to_vec()).unwrap();
+        logger.log(Direction::Output, b"here it is\n".to_vec()).unwrap();
+        logger.log(Direction::Marker, b"checkpoint".to_vec()).unwrap();
+        drop(logger);
+        // A torn final write
+        let mut file = OpenOptions::new().append(true).open(&log_path).unwrap();
+        file.write_all(br#"{"timestamp":"2024-01-01T13:00:00Z","session"#).unwrap();
+
+        let options = TranscriptOptions { format: TranscriptFormat::Markdown, ..Default::default() };
+        let mut transcript = Vec::new();
+        let stats = write_transcript_with(&log_path, &mut transcript, &options).unwrap();
+        assert_eq!(stats, TranscriptStats { entries: 4, skipped_lines: 1 });
+
+        let text = String::from_utf8(transcript).unwrap();
+        assert!(!text.contains('\x1b'), "{:?}", text);
+        assert_eq!(text.matches("**Input**").count(), 1);
+        // Consecutive output shares one section
+        assert_eq!(text.matches("**Output**").count(), 1);
+        assert!(text.contains("````\nexplain\n````\n"), "{}", text);
+        assert!(text.contains("````\nSure, here it is\n````\n"), "{}", text);
+        assert!(text.contains("*checkpoint @ "), "{}", text);
+    }
+
+    #[test]
+    fn test_rotated_log_reads_back_in_order() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let log_path = temp_dir.path().join("session.jsonl");
+        // A couple of entries per file
+        let mut logger = SessionLogger::with_path(Uuid::new_v4(), log_path.clone()).unwrap().with_max_bytes(Some(300));
+        let mut follower = LogFollower::at_end(&log_path);
+
+        for i in 0..10 {
+            logger.log(Direction::Output, format!("chunk {}", i).into_bytes()).unwrap();
+        }
+        assert!(rotated_count(&log_path) >= 3);
+        assert!(temp_dir.path().join("session.1.jsonl").exists());
+        assert_eq!(log_segments(&log_path).last(), Some(&log_path));
+
+        let expected: Vec<String> = (0..10).map(|i| format!("chunk {}", i)).collect();
+        let data: Vec<String> = read_entries(&log_path, EntryFilter::default())
+            .unwrap()
+            .map(|entry| String::from_utf8(entry.unwrap().data).unwrap())
+            .collect();
+        assert_eq!(data, expected);
+
+        // Followers see every entry across the rotations, once
+        let followed = |entries: Vec<LogEntry>| -> Vec<String> {
+            entries.into_iter().map(|entry| String::from_utf8(entry.data).unwrap()).collect()
+        };
+        assert_eq!(followed(follower.read_new_entries().unwrap()), expected);
+        assert!(follower.read_new_entries().unwrap().is_empty());
+        assert_eq!(followed(LogFollower::from_start(&log_path).read_new_entries().unwrap()), expected);
+
+        // ...or a file at a time
+        let mut follower = LogFollower::from_start(&log_path);
+        let mut batches = Vec::new();
+        loop {
+            let batch = follower.read_next_entries().unwrap();
+            if batch.is_empty() {
+                break;
+            }
+            batches.push(followed(batch));
+        }
+        assert!(batches.len() >= 3, "{:?}", batches);
+        assert_eq!(batches.concat(), expected);
+    }
+
+    #[test]
+    fn test_follower_numbers_entries_from_before_numbering() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let log_path = temp_dir.path().join("session.jsonl");
+        let session_id = Uuid::new_v4();
+        let mut old_log = String::new();
+        for data in ["a", "b"] {
+            let entry = LogEntry::new(session_id, Direction::Output, data.as_bytes().to_vec());
+            old_log.push_str(&format!("{}\n", serde_json::to_string(&entry).unwrap()));
+        }
+        std::fs::write(&log_path, old_log).unwrap();
+
+        // A numbering logger carries on after them
+        let mut follower = LogFollower::from_start(&log_path);
+        let seqs = |entries: Vec<LogEntry>| entries.iter().map(|entry| entry.seq).collect::<Vec<_>>();
+        assert_eq!(seqs(follower.read_new_entries().unwrap()), vec![1, 2]);
+        let mut logger = SessionLogger::with_path(session_id, log_path.clone()).unwrap();
+        logger.log(Direction::Output, b"c".to_vec()).unwrap();
+        assert_eq!(seqs(follower.read_new_entries().unwrap()), vec![3]);
+    }
+
+    #[test]
+    fn test_rotated_binary_log_keeps_its_format() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let log_path = temp_dir.path().join("session.clog");
+        let mut logger = SessionLogger::with_path(Uuid::new_v4(), log_path.clone()).unwrap().with_max_bytes(Some(100));
+        let mut follower = LogFollower::at_end(&log_path);
+        for i in 0..10 {
+            logger.log(Direction::Output, format!("chunk {}", i).into_bytes()).unwrap();
+        }
+        assert_eq!(segment_path(&log_path, 1), temp_dir.path().join("session.1.clog"));
+        assert!(rotated_count(&log_path) >= 3);
+
+        let expected: Vec<String> = (0..10).map(|i| format!("chunk {}", i)).collect();
+        let data = |entries: Vec<LogEntry>| -> Vec<String> {
+            entries.into_iter().map(|entry| String::from_utf8(entry.data).unwrap()).collect()
+        };
+        let read: Vec<LogEntry> =
+            read_entries(&log_path, EntryFilter::default()).unwrap().map(Result::unwrap).collect();
+        assert_eq!(data(read), expected);
+        assert_eq!(data(follower.read_new_entries().unwrap()), expected);
+
+        // A reopened logger carries on numbering from the last segment
+        drop(logger);
+        let mut logger = SessionLogger::with_path(Uuid::new_v4(), log_path.clone()).unwrap();
+        assert_eq!(logger.take_seq(), 11);
+    }
+
+    #[test]
+    fn test_read_entries_streams_skips_and_filters() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let log_path = temp_dir.path().join("session.jsonl");
+        let id = Uuid::new_v4();
+        let entry = |timestamp: &str, direction, data: &str| {
+            let mut entry = LogEntry::new(id, direction, data.as_bytes().to_vec());
+            entry.timestamp = timestamp.to_string();
+            serde_json::to_string(&entry).unwrap()
+        };
+        let log = [
+            entry("2024-01-01T10:00:00Z", Direction::Input, "first"),
+            "{not json".to_string(),
+            entry("2024-01-01T11:00:00Z", Direction::Output, "second"),
+            entry("2024-01-01T12:00:00Z", Direction::Input, "third"),
+            r#"{"timestamp":"2024-01-01T13:00:00Z","session_id":"x""#.to_string(),
+        ]
+        .join("\n");
+        std::fs::write(&log_path, log).unwrap();
+
+        let data = |filter| -> Vec<String> {
+            read_entries(&log_path, filter)
+                .unwrap()
+                .map(|entry| String::from_utf8(entry.unwrap().data).unwrap())
+                .collect()
+        };
+
+        // Corrupt and torn lines are skipped
+        assert_eq!(data(EntryFilter::default()), vec!["first", "second", "third"]);
+        assert_eq!(data(EntryFilter::direction(Direction::Input)), vec!["first", "third"]);
+        let window = EntryFilter {
+            since: chrono::DateTime::parse_from_rfc3339("2024-01-01T10:30:00Z").ok(),
+            until: chrono::DateTime::parse_from_rfc3339("2024-01-01T12:00:00Z").ok(),
+            ..EntryFilter::default()
+        };
+        assert_eq!(data(window), vec!["second"]);
+
+        // Iteration is lazy: taking one entry reads no further
+        let mut entries = read_entries(&log_path, EntryFilter::default()).unwrap();
+        assert!(entries.next().is_some());
+
+        assert!(read_entries(&temp_dir.path().join("missing.jsonl"), EntryFilter::default()).is_err());
+    }
 }
@@ -0,0 +1,360 @@
+//! Compact binary session logs (`.clog`).
+//!
+//! The JSON lines format stores output base64-encoded, a third larger than
+//! the bytes themselves, and costs an encode per chunk. A `.clog` file
+//! stores the bytes as they are:
+//!
+//! ```text
+//! header:  "CLOG" | version: u8 | session id: 16 bytes
+//! record:  seq: u64 | timestamp: i64 (ns since the epoch, UTC)
+//!          | direction: u8 | length: u32 | data: length bytes
+//! ```
+//!
+//! Integers are little-endian. A record cut short by a crash mid-write is
+//! ignored, like a torn final line in a `.jsonl` log. JSON lines stay the
+//! default (they can be read with `jq`); see `LogFormat`.
+
+use super::{Direction, LogEntry};
+use anyhow::{Context, Result};
+use chrono::TimeZone;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, BufWriter, Read, Seek, SeekFrom, Write};
+use std::path::Path;
+use uuid::Uuid;
+
+/// File extension of binary logs
+pub const EXTENSION: &str = "clog";
+
+const MAGIC: &[u8; 4] = b"CLOG";
+const VERSION: u8 = 1;
+/// Bytes before the first record
+pub const HEADER_LEN: usize = MAGIC.len() + 1 + 16;
+/// Bytes of a record before its data
+const RECORD_HEADER_LEN: usize = 8 + 8 + 1 + 4;
+
+/// The file header for a log of `session_id`
+pub fn encode_header(session_id: Uuid) -> Vec<u8> {
+    let mut header = Vec::with_capacity(HEADER_LEN);
+    header.extend_from_slice(MAGIC);
+    header.push(VERSION);
+    header.extend_from_slice(session_id.as_bytes());
+    header
+}
+
+/// The session id in a file header
+fn decode_header(header: &[u8; HEADER_LEN]) -> Result<Uuid> {
+    if &header[..MAGIC.len()] != MAGIC {
+        anyhow::bail!("Not a binary session log");
+    }
+    if header[MAGIC.len()] != VERSION {
+        anyhow::bail!("Unsupported binary log version {}", header[MAGIC.len()]);
+    }
+    Ok(Uuid::from_slice(&header[MAGIC.len() + 1..])?)
+}
+
+/// One entry as a record (its `session_id` is in the file header instead)
+pub fn encode_record(entry: &LogEntry) -> Result<Vec<u8>> {
+    let timestamp = chrono::DateTime::parse_from_rfc3339(&entry.timestamp)
+        .ok()
+        .and_then(|at| at.timestamp_nanos_opt())
+        .with_context(|| format!("Invalid entry timestamp {:?}", entry.timestamp))?;
+    let len = u32::try_from(entry.data.len()).context("Entry too large for a binary log")?;
+
+    let mut record = Vec::with_capacity(RECORD_HEADER_LEN + entry.data.len());
+    record.extend_from_slice(&entry.seq.to_le_bytes());
+    record.extend_from_slice(&timestamp.to_le_bytes());
+    record.push(direction_code(&entry.direction));
+    record.extend_from_slice(&len.to_le_bytes());
+    record.extend_from_slice(&entry.data);
+    Ok(record)
+}
+
+fn direction_code(direction: &Direction) -> u8 {
+    match direction {
+        Direction::Input => 0,
+        Direction::Output => 1,
+        Direction::Marker => 2,
+        Direction::Meta => 3,
+        Direction::Unknown => u8::MAX,
+    }
+}
+
+fn direction_from_code(code: u8) -> Direction {
+    match code {
+        0 => Direction::Input,
+        1 => Direction::Output,
+        2 => Direction::Marker,
+        3 => Direction::Meta,
+        _ => Direction::Unknown,
+    }
+}
+
+/// Read the record at the reader's position; `None` at the end of the
+/// file or on a record cut short
+fn read_record<R: Read>(reader: &mut R, session_id: &str) -> io::Result<Option<LogEntry>> {
+    let mut header = [0u8; RECORD_HEADER_LEN];
+    if !read_full(reader, &mut header)? {
+        return Ok(None);
+    }
+    let seq = u64::from_le_bytes(header[0..8].try_into().unwrap());
+    let timestamp = i64::from_le_bytes(header[8..16].try_into().unwrap());
+    let direction = direction_from_code(header[16]);
+    let len = u32::from_le_bytes(header[17..21].try_into().unwrap()) as usize;
+
+    let mut data = vec![0u8; len];
+    if !read_full(reader, &mut data)? {
+        return Ok(None);
+    }
+    Ok(Some(LogEntry {
+        timestamp: chrono::Utc.timestamp_nanos(timestamp).to_rfc3339(),
+        session_id: session_id.to_string(),
+        direction,
+        data,
+        size: len,
+        seq,
+    }))
+}
+
+/// Fill `buf`; `false` if the input ended first
+fn read_full<R: Read>(reader: &mut R, buf: &mut [u8]) -> io::Result<bool> {
+    let mut filled = 0;
+    while filled < buf.len() {
+        match reader.read(&mut buf[filled..]) {
+            Ok(0) => return Ok(false),
+            Ok(n) => filled += n,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(true)
+}
+
+/// The entries of one binary log file, in file order
+pub struct Records<R> {
+    reader: R,
+    session_id: String,
+}
+
+impl Records<BufReader<File>> {
+    /// Open a binary log file and check its header
+    pub fn open(path: &Path) -> Result<Self> {
+        let file = File::open(path).with_context(|| format!("Failed to open log file {}", path.display()))?;
+        Self::new(BufReader::new(file)).with_context(|| format!("Failed to read log file {}", path.display()))
+    }
+}
+
+impl<R: Read> Records<R> {
+    pub fn new(mut reader: R) -> Result<Self> {
+        let mut header = [0u8; HEADER_LEN];
+        if !read_full(&mut reader, &mut header)? {
+            anyhow::bail!("Binary log is missing its header");
+        }
+        let session_id = decode_header(&header)?.to_string();
+        Ok(Records { reader, session_id })
+    }
+}
+
+impl<R: Read> Iterator for Records<R> {
+    type Item = Result<LogEntry>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        read_record(&mut self.reader, &self.session_id).map_err(Into::into).transpose()
+    }
+}
+
+/// Read the complete records appended to a binary log since `offset`, and
+/// advance `offset` past them (the binary counterpart of
+/// `crate::tail::read_new_lines`).
+///
+/// A record still being written is held back until it is complete. If
+/// the file shrinks (replaced) reading restarts from the beginning. A
+/// missing file, or one without its header yet, reads as empty.
+pub fn read_new_records(path: &Path, offset: &mut u64) -> io::Result<Vec<LogEntry>> {
+    let mut file = match File::open(path) {
+        Ok(file) => file,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e),
+    };
+    let len = file.metadata()?.len();
+    if len < *offset {
+        *offset = 0;
+    }
+
+    let mut header = [0u8; HEADER_LEN];
+    if !read_full(&mut file, &mut header)? {
+        return Ok(Vec::new());
+    }
+    let session_id = decode_header(&header)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e.to_string()))?
+        .to_string();
+    *offset = (*offset).max(HEADER_LEN as u64);
+    file.seek(SeekFrom::Start(*offset))?;
+
+    let mut reader = BufReader::new(file);
+    let mut entries = Vec::new();
+    while let Some(entry) = read_record(&mut reader, &session_id)? {
+        *offset += (RECORD_HEADER_LEN + entry.data.len()) as u64;
+        entries.push(entry);
+    }
+    Ok(entries)
+}
+
+/// The last complete entry of a binary log, skipping over the data of the
+/// records before it
+pub fn last_record(path: &Path) -> Option<LogEntry> {
+    let mut reader = BufReader::new(File::open(path).ok()?);
+    let mut header = [0u8; HEADER_LEN];
+    if !read_full(&mut reader, &mut header).ok()? {
+        return None;
+    }
+    let session_id = decode_header(&header).ok()?.to_string();
+
+    let len = reader.get_ref().metadata().ok()?.len();
+    let mut position = HEADER_LEN as u64;
+    let mut last = None;
+    loop {
+        let mut record_header = [0u8; RECORD_HEADER_LEN];
+        if !read_full(&mut reader, &mut record_header).ok()? {
+            break;
+        }
+        let data_len = u32::from_le_bytes(record_header[17..21].try_into().unwrap()) as u64;
+        let end = position + RECORD_HEADER_LEN as u64 + data_len;
+        if end > len {
+            break;
+        }
+        last = Some(position);
+        reader.seek_relative(data_len as i64).ok()?;
+        position = end;
+    }
+
+    reader.seek(SeekFrom::Start(last?)).ok()?;
+    read_record(&mut reader, &session_id).ok().flatten()
+}
+
+/// Convert one `.jsonl` log file to the binary format at `dst`; returns
+/// how many entries were written. Lines that aren't entries are dropped.
+pub fn from_jsonl(src: &Path, dst: &Path) -> Result<usize> {
+    let input = File::open(src).with_context(|| format!("Failed to open {}", src.display()))?;
+    let mut entries = BufReader::new(input)
+        .lines()
+        .map_while(|line| line.ok())
+        .filter_map(|line| serde_json::from_str::<LogEntry>(&line).ok())
+        .peekable();
+    let session_id = entries
+        .peek()
+        .and_then(|entry| Uuid::parse_str(&entry.session_id).ok())
+        .unwrap_or_default();
+
+    let output = File::create(dst).with_context(|| format!("Failed to create {}", dst.display()))?;
+    let mut writer = BufWriter::new(output);
+    writer.write_all(&encode_header(session_id))?;
+    let mut count = 0;
+    for entry in entries {
+        writer.write_all(&encode_record(&entry)?)?;
+        count += 1;
+    }
+    writer.into_inner().map_err(|e| e.into_error())?.sync_all()?;
+    Ok(count)
+}
+
+/// Convert one binary log file to JSON lines at `dst`; returns how many
+/// entries were written
+pub fn to_jsonl(src: &Path, dst: &Path) -> Result<usize> {
+    let records = Records::open(src)?;
+    let output = File::create(dst).with_context(|| format!("Failed to create {}", dst.display()))?;
+    let mut writer = BufWriter::new(output);
+    let mut count = 0;
+    for entry in records {
+        serde_json::to_writer(&mut writer, &entry?)?;
+        writer.write_all(b"\n")?;
+        count += 1;
+    }
+    writer.into_inner().map_err(|e| e.into_error())?.sync_all()?;
+    Ok(count)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::logging::SessionLogger;
+
+    /// Log the same session in both formats
+    fn write_both(dir: &Path, chunks: usize) -> (std::path::PathBuf, std::path::PathBuf) {
+        let session_id = Uuid::new_v4();
+        let jsonl = dir.join("session.jsonl");
+        let clog = dir.join("session.clog");
+        for path in [&jsonl, &clog] {
+            let mut logger = SessionLogger::with_path(session_id, path.clone()).unwrap();
+            logger.log(Direction::Meta, b"{}".to_vec()).unwrap();
+            for i in 0..chunks {
+                logger.log(Direction::Input, format!("prompt {}\n", i).into_bytes()).unwrap();
+                logger.log(Direction::Output, vec![b'x'; 4096]).unwrap();
+            }
+        }
+        (jsonl, clog)
+    }
+
+    #[test]
+    fn test_binary_log_round_trips_and_is_smaller() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let (jsonl, clog) = write_both(temp_dir.path(), 10);
+
+        let from_json: Vec<LogEntry> = crate::logging::read_entries(&jsonl, Default::default())
+            .unwrap()
+            .map(Result::unwrap)
+            .collect();
+        let from_binary: Vec<LogEntry> = Records::open(&clog).unwrap().map(Result::unwrap).collect();
+        assert_eq!(from_binary.len(), 21);
+        for (a, b) in from_json.iter().zip(&from_binary) {
+            assert_eq!((&a.session_id, &a.direction, &a.data, a.seq), (&b.session_id, &b.direction, &b.data, b.seq));
+        }
+
+        let size = |path: &Path| std::fs::metadata(path).unwrap().len();
+        assert!(size(&clog) * 4 < size(&jsonl) * 3, "{} vs {}", size(&clog), size(&jsonl));
+
+        // Converting back and forth keeps every entry, timestamps included
+        let converted = temp_dir.path().join("converted.clog");
+        let restored = temp_dir.path().join("restored.jsonl");
+        assert_eq!(from_jsonl(&jsonl, &converted).unwrap(), 21);
+        assert_eq!(to_jsonl(&converted, &restored).unwrap(), 21);
+        assert_eq!(std::fs::read_to_string(&restored).unwrap(), std::fs::read_to_string(&jsonl).unwrap());
+    }
+
+    #[test]
+    fn test_partial_record_is_held_back() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let (_, clog) = write_both(temp_dir.path(), 1);
+        let full = std::fs::read(&clog).unwrap();
+        std::fs::write(&clog, &full[..full.len() - 10]).unwrap();
+
+        let mut offset = 0;
+        assert_eq!(read_new_records(&clog, &mut offset).unwrap().len(), 2);
+        assert_eq!(last_record(&clog).unwrap().direction, Direction::Input);
+
+        std::fs::write(&clog, &full).unwrap();
+        let rest = read_new_records(&clog, &mut offset).unwrap();
+        assert_eq!(rest.len(), 1);
+        assert_eq!(rest[0].data, vec![b'x'; 4096]);
+        assert_eq!(offset, full.len() as u64);
+        assert_eq!(last_record(&clog).unwrap().seq, rest[0].seq);
+    }
+
+    /// Write-path comparison; run with
+    /// `cargo test --release bench_write_path -- --ignored --nocapture`
+    #[test]
+    #[ignore]
+    fn bench_write_path() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        for extension in ["jsonl", EXTENSION] {
+            let path = temp_dir.path().join(format!("bench.{}", extension));
+            let mut logger = SessionLogger::with_path(Uuid::new_v4(), path.clone()).unwrap();
+            let started = std::time::Instant::now();
+            for _ in 0..20_000 {
+                logger.log(Direction::Output, vec![b'x'; 4096]).unwrap();
+            }
+            let elapsed = started.elapsed();
+            let size = std::fs::metadata(&path).unwrap().len();
+            println!("{:>5}: {:>4} MiB in {:?}", extension, size / (1024 * 1024), elapsed);
+        }
+    }
+}
@@ -0,0 +1,203 @@
+//! Rendering helpers for turning raw PTY bytes into readable text.
+//!
+//! These only affect derived outputs (clean views, exports); the raw
+//! `.jsonl` logs always keep the exact bytes Claude produced.
+
+/// Filter that removes terminal noise from Claude's TUI output.
+///
+/// Stateful so escape sequences and alternate-screen regions that span
+/// several log entries are handled correctly: feed every output chunk of
+/// a session through the same filter, in order.
+///
+/// ## What gets removed
+///
+/// - Bell characters (`\x07`) when `drop_bells` is set
+/// - Everything between alternate-screen enter/exit (`CSI ?1049h` / `l`,
+///   and the older `?1047`/`?47` forms) when `collapse_alt_screen` is set
+/// - Cursor save/restore, positioning and erase sequences when
+///   `normalize_cursor` is set; cursor-down / next-line become `\n` and
+///   carriage returns are dropped
+/// - OSC sequences (window titles etc.), always
+///
+/// SGR color sequences (`CSI ... m`) are kept unless `keep_colors` is
+/// cleared.
+pub struct OutputFilter {
+    pub drop_bells: bool,
+    pub keep_colors: bool,
+    pub collapse_alt_screen: bool,
+    pub normalize_cursor: bool,
+    state: State,
+    params: Vec<u8>,
+    in_alt_screen: bool,
+}
+
+#[derive(Clone, Copy)]
+enum State {
+    Ground,
+    Escape,
+    Csi,
+    Osc,
+    OscEscape,
+}
+
+impl Default for OutputFilter {
+    fn default() -> Self {
+        OutputFilter {
+            drop_bells: true,
+            keep_colors: true,
+            collapse_alt_screen: true,
+            normalize_cursor: true,
+            state: State::Ground,
+            params: Vec::new(),
+            in_alt_screen: false,
+        }
+    }
+}
+
+impl OutputFilter {
+    /// A filter that keeps only text: colors and every other CSI, OSC and
+    /// escape sequence are removed, but alternate-screen content is kept
+    pub fn plain_text() -> Self {
+        OutputFilter {
+            keep_colors: false,
+            collapse_alt_screen: false,
+            ..OutputFilter::default()
+        }
+    }
+
+    /// Filter the next chunk of output
+    pub fn feed(&mut self, input: &[u8]) -> Vec<u8> {
+        let mut out = Vec::with_capacity(input.len());
+
+        for &b in input {
+            match self.state {
+                State::Ground => match b {
+                    0x1b => self.state = State::Escape,
+                    0x07 if self.drop_bells => {}
+                    b'\r' if self.normalize_cursor => {}
+                    _ => self.emit(&mut out, &[b]),
+                },
+                State::Escape => {
+                    self.state = State::Ground;
+                    match b {
+                        b'[' => {
+                            self.params.clear();
+                            self.state = State::Csi;
+                        }
+                        b']' => self.state = State::Osc,
+                        // ESC 7 / ESC 8 (save/restore), keypad modes, ...
+                        _ if self.normalize_cursor => {}
+                        _ => self.emit(&mut out, &[0x1b, b]),
+                    }
+                }
+                State::Csi => {
+                    if (0x40..=0x7e).contains(&b) {
+                        self.state = State::Ground;
+                        self.finish_csi(&mut out, b);
+                    } else {
+                        self.params.push(b);
+                    }
+                }
+                State::Osc => match b {
+                    0x07 => self.state = State::Ground,
+                    0x1b => self.state = State::OscEscape,
+                    _ => {}
+                },
+                State::OscEscape => self.state = State::Ground,
+            }
+        }
+
+        out
+    }
+
+    fn finish_csi(&mut self, out: &mut Vec<u8>, final_byte: u8) {
+        let is_alt_screen = matches!(self.params.as_slice(), b"?1049" | b"?1047" | b"?47");
+
+        if is_alt_screen && matches!(final_byte, b'h' | b'l') && self.collapse_alt_screen {
+            self.in_alt_screen = final_byte == b'h';
+            return;
+        }
+
+        if final_byte == b'm' && !self.keep_colors {
+            return;
+        }
+
+        if final_byte == b'm' || !self.normalize_cursor {
+            let mut seq = vec![0x1b, b'['];
+            seq.extend_from_slice(&self.params);
+            seq.push(final_byte);
+            self.emit(out, &seq);
+            return;
+        }
+
+        // Cursor movement that moves down a line keeps the line break
+        if matches!(final_byte, b'B' | b'E') {
+            self.emit(out, b"\n");
+        }
+    }
+
+    fn emit(&self, out: &mut Vec<u8>, bytes: &[u8]) {
+        if !(self.in_alt_screen && self.collapse_alt_screen) {
+            out.extend_from_slice(bytes);
+        }
+    }
+}
+
+/// Remove ANSI escape sequences (colors, cursor movement, screen clears,
+/// window titles) from a complete piece of output, keeping printable text
+/// and line breaks.
+///
+/// For output split over several log entries, feed the entries through
+/// one `OutputFilter::plain_text()` instead, so sequences split between
+/// them are still recognised.
+pub fn strip_ansi(input: &[u8]) -> Vec<u8> {
+    OutputFilter::plain_text().feed(input)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_filter_removes_bells_and_alt_screen() {
+        let mut filter = OutputFilter::default();
+        let mut cleaned = filter.feed(b"hello\x07 \x1b[?1049hTUI ");
+        cleaned.extend(filter.feed(b"redraw\x1b[2J\x1b[?1049l\x1b7world\x1b8\r\n"));
+        cleaned.extend(filter.feed(b"\x1b]0;title\x07\x1b[1mbold\x1b[0m"));
+
+        assert_eq!(
+            String::from_utf8(cleaned).unwrap(),
+            "hello world\n\x1b[1mbold\x1b[0m"
+        );
+    }
+
+    #[test]
+    fn test_strip_ansi_keeps_only_text() {
+        // Colors, cursor moves, clear-screen and a window title
+        let input = b"\x1b[2J\x1b[H\x1b[1;32mok\x1b[0m done\r\n\x1b[3;5Hnext\x1b[1Bline\x1b]0;claude\x07!";
+
+        assert_eq!(String::from_utf8(strip_ansi(input)).unwrap(), "ok done\nnext\nline!");
+    }
+
+    #[test]
+    fn test_plain_text_handles_sequences_split_across_chunks() {
+        let mut filter = OutputFilter::plain_text();
+        let mut cleaned = filter.feed(b"a\x1b[3");
+        cleaned.extend(filter.feed(b"1mb\x1b[?1049hc\x1b"));
+        cleaned.extend(filter.feed(b"[0m\n"));
+
+        assert_eq!(cleaned, b"abc\n");
+    }
+
+    #[test]
+    fn test_filter_options_can_be_disabled() {
+        let mut filter = OutputFilter {
+            drop_bells: false,
+            normalize_cursor: false,
+            ..OutputFilter::default()
+        };
+        let cleaned = filter.feed(b"a\x07\x1b[2Ab\r\n");
+
+        assert_eq!(cleaned, b"a\x07\x1b[2Ab\r\n");
+    }
+}
@@ -1,26 +1,74 @@
+mod auth;
+mod autostart;
 mod client;
+mod config;
 mod daemon;
+mod doctor;
+mod exec;
 mod ipc;
 mod logging;
 mod manager;
+mod output;
+mod paths;
 mod persistence;
 mod pty;
+#[cfg(unix)]
+mod reattach;
+mod repair;
+mod resolve;
+mod retention;
 mod session;
+mod stats;
+mod supervisor;
+mod tail;
+#[cfg(unix)]
+mod takeover;
+mod terminal;
 
 #[cfg(test)]
 mod tests;
 
-use clap::{Parser, Subcommand};
+use clap::{Parser, Subcommand, ValueEnum};
 use client::Client;
-use daemon::Daemon;
+use daemon::{Daemon, LockHeld, Probe, RunOutcome};
 use ipc::{Request, Response};
-use std::path::PathBuf;
+use session::SessionStatus;
+use output::{ColorChoice, Output};
+use std::io::Write;
+use std::path::{Path, PathBuf};
 
 /// Claude Sessions - A local session manager for Claude Code
 #[derive(Parser)]
 #[command(name = "claude-sessions")]
 #[command(about = "Manage multiple Claude Code sessions locally", long_about = None)]
 struct Cli {
+    /// Print only essential data (ids, paths, errors) with no formatting
+    #[arg(long, global = true)]
+    raw: bool,
+
+    /// Print machine-readable JSON (status, list, info, start, stop); errors
+    /// become `{"error": ...}`
+    #[arg(long, global = true, conflicts_with = "raw")]
+    json: bool,
+
+    /// When to use color in output
+    #[arg(long, global = true, value_name = "WHEN", default_value = "auto")]
+    color: ColorChoice,
+
+    /// Don't ask for confirmation before destructive actions
+    #[arg(short, long, global = true)]
+    yes: bool,
+
+    /// Start the daemon in the background if it isn't running
+    /// (default: true, or CLAUDE_SESSIONS_AUTOSTART)
+    #[arg(long, global = true, value_name = "BOOL", num_args = 0..=1, default_missing_value = "true")]
+    autostart: Option<bool>,
+
+    /// Talk to a daemon listening on TCP (`daemon --listen`) instead of
+    /// the local socket
+    #[arg(long, global = true, value_name = "HOST:PORT")]
+    connect: Option<String>,
+
     #[command(subcommand)]
     command: Commands,
 }
@@ -32,203 +80,1802 @@ enum Commands {
         /// Run daemon in foreground (don't daemonize)
         #[arg(short, long)]
         foreground: bool,
+        /// Delete logs of non-running sessions idle for more than N days (0 = keep forever;
+        /// default: `keep_logs_days` in ~/.claude-sessions/config.toml, or 0)
+        #[arg(long, value_name = "N")]
+        keep_logs_days: Option<u32>,
+        /// With --keep-logs-days, also forget expired sessions
+        #[arg(long)]
+        prune_records: bool,
+        /// Exit with an error if a daemon is already running
+        #[arg(long)]
+        fail_if_running: bool,
+        /// Shut down after SECS without running sessions or connected
+        /// clients (default: `idle_timeout_secs` in the config file, or
+        /// never)
+        #[arg(long, value_name = "SECS", value_parser = clap::value_parser!(u64).range(1..))]
+        idle_timeout: Option<u64>,
+        /// Also accept connections over TCP on ADDR, e.g. 127.0.0.1:7878
+        /// (loopback addresses only, unless with --require-token)
+        #[arg(long, value_name = "ADDR")]
+        listen: Option<std::net::SocketAddr>,
+        /// Require clients to present the token in ~/.claude-sessions/token
+        /// (created if missing)
+        #[arg(long)]
+        require_token: bool,
+        /// Keep running sessions' terminals open when stopped (without
+        /// --kill-sessions), and reattach to them on start (default:
+        /// `reattach` in the config file)
+        #[arg(long)]
+        reattach: bool,
+    },
+    /// Hold sessions' terminals between daemons (started by the daemon
+    /// for --reattach)
+    #[command(hide = true)]
+    KeepPtys {
+        #[arg(long)]
+        socket: PathBuf,
+        /// SESSION:PID:FD for each terminal passed in
+        terminals: Vec<String>,
     },
     /// Check daemon status
     Status,
     /// Stop the daemon
-    StopDaemon,
+    StopDaemon {
+        /// Also stop every running session's Claude (SIGTERM, then SIGKILL
+        /// after 5s); by default they aren't signalled, but still get
+        /// SIGHUP when their terminal closes with the daemon
+        #[arg(long)]
+        kill_sessions: bool,
+    },
+    /// Show the daemon's own log (~/.claude-sessions/daemon.log)
+    DaemonLogs {
+        /// Keep printing new lines as they are written
+        #[arg(short, long)]
+        follow: bool,
+        /// Number of lines to show
+        #[arg(short = 'n', long, value_name = "N", default_value_t = 20)]
+        lines: usize,
+    },
     /// Start a new Claude Code session in a directory
     Start {
-        /// Working directory for the session
+        /// Working directory for the session (`~` and `$VAR` are expanded)
         #[arg(value_name = "DIR")]
         directory: PathBuf,
+        /// Restart Claude automatically if it exits unexpectedly
+        #[arg(long)]
+        restart_on_crash: bool,
+        /// Write this session's logs to PATH instead of ~/.claude-sessions/logs
+        #[arg(long, value_name = "PATH")]
+        log_dir: Option<PathBuf>,
+        /// Name the log file after its creation time and directory
+        /// (`<created>-<dir>-<short id>.jsonl`) instead of the session ID
+        #[arg(long)]
+        descriptive_log_name: bool,
+        /// Set an environment variable for Claude (repeatable); added on
+        /// top of what the daemon passes through, replacing a daemon
+        /// variable of the same name
+        #[arg(long, value_name = "KEY=VALUE", value_parser = parse_env_var)]
+        env: Vec<(String, String)>,
+        /// Pass an argument to the Claude binary (repeatable, in order),
+        /// e.g. `--claude-arg=--model --claude-arg=opus`
+        #[arg(long = "claude-arg", value_name = "ARG", allow_hyphen_values = true)]
+        claude_args: Vec<String>,
+        /// Label the session NAME instead of the directory's basename; a
+        /// unique prefix of it works wherever a session ID does
+        #[arg(long, value_name = "NAME")]
+        name: Option<String>,
+        /// Tag the session (repeatable), e.g. `--tag project=api`, so it
+        /// can be picked out later with `send --tag`
+        #[arg(long = "tag", value_name = "KEY=VALUE", value_parser = parse_env_var)]
+        tags: Vec<(String, String)>,
+        /// Start even if another session is already running in DIR
+        #[arg(long)]
+        allow_duplicate: bool,
+        /// Stream the session's output here (read-only); Ctrl-C detaches
+        /// and leaves the session running
+        #[arg(long)]
+        follow: bool,
     },
     /// List all active sessions
-    List,
+    List {
+        /// Sort order (created: newest first; others ascending)
+        #[arg(long, value_enum, default_value_t = SortKey::Created)]
+        sort: SortKey,
+        /// Reverse the sort order
+        #[arg(long)]
+        reverse: bool,
+        /// Report running sessions idle for SECS or more as unhealthy
+        /// (default: the daemon's, 600 unless configured)
+        #[arg(long, value_name = "SECS")]
+        idle_threshold: Option<u64>,
+    },
+    /// Show everything known about one session (PID, uptime, log size)
+    Info {
+        /// Session ID or label (or a unique prefix of either)
+        #[arg(value_name = "SESSION_ID")]
+        session_id: String,
+    },
+    /// Resize a session's terminal (Claude redraws for the new size)
+    Resize {
+        /// Session ID or label (or a unique prefix of either)
+        #[arg(value_name = "SESSION_ID")]
+        session_id: String,
+        #[arg(value_name = "ROWS", value_parser = clap::value_parser!(u16).range(1..))]
+        rows: u16,
+        #[arg(value_name = "COLS", value_parser = clap::value_parser!(u16).range(1..))]
+        cols: u16,
+    },
+    /// Change a session's label
+    Rename {
+        /// Session ID or label (or a unique prefix of either)
+        #[arg(value_name = "SESSION_ID")]
+        session_id: String,
+        #[arg(value_name = "NAME")]
+        name: String,
+    },
+    /// Stop a session and start Claude again in the same directory
+    ///
+    /// The restarted session gets a new ID and a new log file; the old log
+    /// is kept. Env and Claude arguments carry over, unless the daemon has
+    /// restarted since the session was started.
+    Restart {
+        /// Session ID or label (or a unique prefix of either)
+        #[arg(value_name = "SESSION_ID")]
+        session_id: String,
+    },
     /// Stop a running session
     Stop {
-        /// Session ID to stop
+        /// Session ID or label to stop
+        #[arg(value_name = "SESSION_ID")]
+        session_id: String,
+        /// Write a final checkpoint marker first, optionally exporting a transcript to PATH
+        #[arg(long, value_name = "PATH", num_args = 0..=1)]
+        checkpoint: Option<Option<PathBuf>>,
+        /// Kill Claude right away instead of waiting for it to exit after SIGTERM
+        #[arg(long, conflicts_with = "timeout")]
+        force: bool,
+        /// How long Claude gets to exit after SIGTERM before it is killed, in seconds [default: 5]
+        #[arg(long, value_name = "SECS")]
+        timeout: Option<u64>,
+    },
+    /// Stop every running session
+    ///
+    /// Asks for confirmation first unless --yes is given. Orphaned sessions
+    /// (whose Claude outlived a previous daemon) are left running unless
+    /// --include-orphaned is given; stale, exited and crashed sessions are
+    /// kept either way (see `prune`).
+    StopAll {
+        /// Also terminate orphaned sessions' processes (by PID)
+        #[arg(long)]
+        include_orphaned: bool,
+    },
+    /// Summarize usage across all sessions
+    Stats {
+        /// Output format
+        #[arg(long, value_enum, default_value_t = StatsFormat::Text)]
+        format: StatsFormat,
+    },
+    /// Send a line of input to one or more sessions
+    Send {
+        /// Text to send (a newline is added if missing)
+        #[arg(value_name = "TEXT")]
+        text: String,
+        /// Session ID (or unique prefix) to send to; repeat for several
+        #[arg(
+            long = "session",
+            short = 's',
+            value_name = "SESSION_ID",
+            required_unless_present_any = ["all", "dir", "tags"]
+        )]
+        session_ids: Vec<String>,
+        /// Send to every running session
+        #[arg(long, conflicts_with_all = ["session_ids", "dir", "tags"])]
+        all: bool,
+        /// Send to every running session whose working directory is under PATH
+        #[arg(long, value_name = "PATH", conflicts_with = "session_ids")]
+        dir: Option<PathBuf>,
+        /// Send to every running session tagged KEY=VALUE (repeatable; a
+        /// session must have all of them)
+        #[arg(long = "tag", value_name = "KEY=VALUE", value_parser = parse_env_var, conflicts_with = "session_ids")]
+        tags: Vec<(String, String)>,
+    },
+    /// Run a prompt in a fresh session, print its output and stop the session
+    Exec {
+        /// Working directory for the session
+        #[arg(value_name = "DIR")]
+        directory: PathBuf,
+        /// Prompt to send
+        #[arg(value_name = "PROMPT")]
+        prompt: String,
+        /// Fail an attempt that produces no output within DURATION
+        #[arg(long, value_name = "DURATION", default_value = "60s", value_parser = exec::parse_duration)]
+        timeout: std::time::Duration,
+        /// Treat the output as complete once it has been quiet for DURATION
+        #[arg(long, value_name = "DURATION", default_value = "3s", value_parser = exec::parse_duration)]
+        quiet: std::time::Duration,
+        /// Retry up to N times when an attempt fails to spawn or produces no output
+        #[arg(long, value_name = "N", default_value_t = 0)]
+        retries: u32,
+        /// Wait DURATION between attempts
+        #[arg(long, value_name = "DURATION", default_value = "2s", value_parser = exec::parse_duration)]
+        retry_backoff: std::time::Duration,
+    },
+    /// Print just the log file path of a session
+    Which {
+        /// Session ID or label (or a unique prefix of either)
+        #[arg(value_name = "SESSION_ID")]
+        session_id: String,
+    },
+    /// Print decoded transcripts of session logs
+    Logs {
+        /// Session ID to show
+        #[arg(value_name = "SESSION_ID", required_unless_present = "dir", conflicts_with = "dir")]
+        session_id: Option<String>,
+        /// Show every session whose working directory is under PATH
+        #[arg(long, value_name = "PATH")]
+        dir: Option<PathBuf>,
+        /// Truncate each log entry to N bytes when printing
+        #[arg(long, value_name = "N")]
+        max_line_bytes: Option<usize>,
+        #[command(flatten)]
+        timestamps: TimestampArgs,
+        /// Label every line, e.g. "[{id:.8}] " ({id}, {name}, {dir}, {ts})
+        #[arg(long, value_name = "TEMPLATE")]
+        prefix: Option<String>,
+    },
+    /// Search decoded session logs for a string
+    Search {
+        /// Text to search for
+        #[arg(value_name = "QUERY")]
+        query: String,
+        /// Only search sessions whose working directory is under PATH
+        #[arg(long, value_name = "PATH")]
+        dir: Option<PathBuf>,
+        /// Stop after N matches
+        #[arg(long, value_name = "N", default_value_t = logging::SearchLimits::default().max_results)]
+        limit: usize,
+    },
+    /// Print the lines of one session's transcript that contain PATTERN
+    Grep {
+        /// Session ID or label
         #[arg(value_name = "SESSION_ID")]
         session_id: String,
+        /// Text to look for
+        #[arg(value_name = "PATTERN")]
+        pattern: String,
+        /// Treat PATTERN as a regular expression
+        #[arg(short = 'E', long)]
+        regex: bool,
+        /// Also print N lines before and after each match
+        #[arg(short = 'C', long, value_name = "N", default_value_t = 0)]
+        context: usize,
+        /// Drop color escape sequences even when writing to a terminal
+        #[arg(long)]
+        strip_ansi: bool,
+    },
+    /// Export decoded transcripts to a file (or stdout)
+    Export {
+        /// Session ID or label to export
+        #[arg(value_name = "SESSION_ID", required_unless_present = "dir", conflicts_with = "dir")]
+        session_id: Option<String>,
+        /// Export every session whose working directory is under PATH
+        #[arg(long, value_name = "PATH")]
+        dir: Option<PathBuf>,
+        /// Write to FILE instead of stdout
+        #[arg(short, long, value_name = "FILE")]
+        output: Option<PathBuf>,
+        /// Transcript layout
+        #[arg(long, value_enum, default_value_t = logging::TranscriptFormat::Text)]
+        format: logging::TranscriptFormat,
+        /// Drop color escape sequences even when writing to a terminal
+        #[arg(long)]
+        strip_ansi: bool,
     },
+    /// Convert a log file between JSON lines (.jsonl) and the compact
+    /// binary format (.clog); the extensions pick the direction
+    ConvertLog {
+        /// Log file to read
+        #[arg(value_name = "INPUT")]
+        input: PathBuf,
+        /// File to write (must not exist yet)
+        #[arg(value_name = "OUTPUT")]
+        output: PathBuf,
+    },
+    /// Forget finished sessions (exited, crashed, stale) and delete their logs
+    ///
+    /// Running and orphaned sessions are never touched. Logs left behind
+    /// by stopped sessions are deleted by the same age rule.
+    Prune {
+        /// Only sessions last active more than DAYS ago
+        #[arg(long, value_name = "DAYS", default_value_t = 7)]
+        older_than_days: u32,
+        /// List what would be removed without removing anything
+        #[arg(long)]
+        dry_run: bool,
+        /// Forget the sessions but keep their log files
+        #[arg(long)]
+        keep_logs: bool,
+    },
+    /// Force session logs and state to disk
+    Flush {
+        /// Only flush this session (default: all sessions)
+        #[arg(value_name = "SESSION_ID")]
+        session_id: Option<String>,
+    },
+    /// Reconcile persisted sessions with logs on disk and live processes
+    /// (the daemon must be stopped)
+    Repair {
+        /// Only report what would change
+        #[arg(long)]
+        dry_run: bool,
+        /// Directory to look for orphaned logs in (default: ~/.claude-sessions/logs)
+        #[arg(long, value_name = "PATH")]
+        log_dir: Option<PathBuf>,
+    },
+    /// Check that Claude, the data directory, the daemon socket and the
+    /// saved state are usable (exits non-zero if something isn't)
+    Doctor,
     /// Attach to a session's output (stream logs)
     Attach {
-        /// Session ID to attach to
+        /// Session ID or label to attach to
         #[arg(value_name = "SESSION_ID")]
         session_id: String,
+        /// Take over the session: raw terminal passthrough until Ctrl-]
+        #[arg(long)]
+        takeover: bool,
+        /// Resume after log entry SEQ (the last one seen) instead of
+        /// starting with live output
+        #[arg(long, value_name = "SEQ", conflicts_with = "takeover")]
+        since_seq: Option<u64>,
+        #[command(flatten)]
+        timestamps: TimestampArgs,
+        /// Label every line, e.g. "[{id:.8}] " ({id}, {name}, {dir}, {ts})
+        #[arg(long, value_name = "TEMPLATE", conflicts_with = "takeover")]
+        prefix: Option<String>,
     },
 }
 
+/// `--timestamp` options shared by the log viewers
+#[derive(clap::Args, Debug, Clone)]
+struct TimestampArgs {
+    /// Prefix each entry with when it was logged (default: local time)
+    #[arg(long, value_name = "FORMAT", num_args = 0..=1, default_missing_value = "local")]
+    timestamp: Option<logging::TimestampFormat>,
+    /// Prefix every line of an entry, not just its first
+    #[arg(long, requires = "timestamp")]
+    timestamp_every_line: bool,
+}
+
+impl TimestampArgs {
+    fn stamper(&self) -> Option<logging::Timestamper> {
+        self.timestamp
+            .map(|format| logging::Timestamper::new(format, self.timestamp_every_line))
+    }
+}
+
+/// Parse a `KEY=VALUE` argument
+fn parse_env_var(arg: &str) -> Result<(String, String), String> {
+    match arg.split_once('=') {
+        Some((key, value)) if !key.is_empty() => Ok((key.to_string(), value.to_string())),
+        _ => Err(format!("expected KEY=VALUE, got {:?}", arg)),
+    }
+}
+
+/// Output formats for `stats`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum StatsFormat {
+    Text,
+    Json,
+}
+
+/// Sort keys for `list`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum SortKey {
+    /// Creation time, newest first
+    Created,
+    /// Status, alphabetically
+    Status,
+    /// Working directory, alphabetically
+    Dir,
+    /// Label, alphabetically (the ID for unlabelled sessions)
+    Name,
+    /// Idle time, least idle first; sessions without one last
+    Idle,
+}
+
+/// Sort sessions for display.
+///
+/// Ties are broken by session ID so the order is stable across calls.
+fn sort_sessions(sessions: &mut [ipc::SessionInfo], key: SortKey, reverse: bool) {
+    sessions.sort_by(|a, b| {
+        let ordering = match key {
+            SortKey::Created => {
+                let parse = |s: &str| chrono::DateTime::parse_from_rfc3339(s).ok();
+                parse(&b.created_at)
+                    .cmp(&parse(&a.created_at))
+                    .then_with(|| b.created_at.cmp(&a.created_at))
+            }
+            SortKey::Status => a.status.as_str().cmp(b.status.as_str()),
+            SortKey::Dir => a.working_dir.cmp(&b.working_dir),
+            SortKey::Name => {
+                let name = |s: &ipc::SessionInfo| if s.label.is_empty() { s.id.clone() } else { s.label.clone() };
+                name(a).cmp(&name(b))
+            }
+            SortKey::Idle => match (a.idle_seconds, b.idle_seconds) {
+                (Some(a), Some(b)) => a.cmp(&b),
+                (a, b) => a.is_none().cmp(&b.is_none()),
+            },
+        };
+        let ordering = ordering.then_with(|| a.id.cmp(&b.id));
+        if reverse {
+            ordering.reverse()
+        } else {
+            ordering
+        }
+    });
+}
+
+/// Sessions whose working directory is `dir` or below it
+fn sessions_under_dir(sessions: Vec<ipc::SessionInfo>, dir: &Path) -> Vec<ipc::SessionInfo> {
+    sessions
+        .into_iter()
+        .filter(|s| paths::is_within(Path::new(&s.working_dir), dir))
+        .collect()
+}
+
+/// Pick the sessions `send` delivers to: the sessions named by ID, or every
+/// running session (optionally only those under `dir` and carrying all of
+/// `tags`)
+fn send_targets(
+    sessions: &[ipc::SessionInfo],
+    session_ids: &[String],
+    dir: Option<&Path>,
+    tags: &[(String, String)],
+) -> anyhow::Result<Vec<ipc::SessionInfo>> {
+    if !session_ids.is_empty() {
+        return session_ids
+            .iter()
+            .map(|id| resolve::resolve(sessions, id).cloned())
+            .collect();
+    }
+
+    let running = sessions
+        .iter()
+        .filter(|s| s.status.is_live())
+        .filter(|s| tags.iter().all(|(key, value)| s.tags.get(key) == Some(value)))
+        .cloned()
+        .collect();
+    Ok(match dir {
+        Some(dir) => sessions_under_dir(running, dir),
+        None => running,
+    })
+}
+
+/// Send `text` to each of `sessions`, over one connection while it lasts.
+///
+/// Every session is tried; the result for each is returned in order, with
+/// the daemon's error message on failure. A connection that breaks fails
+/// only the send it was carrying, and the next session gets a new one.
+async fn broadcast_input(client: &Client, sessions: &[ipc::SessionInfo], text: &str) -> Vec<Result<(), String>> {
+    let mut connection = None;
+    let mut results = Vec::with_capacity(sessions.len());
+    for session in sessions {
+        let mut requests = match connection.take() {
+            Some(requests) => requests,
+            None => match client.session().await {
+                Ok(requests) => requests,
+                Err(e) => {
+                    results.push(Err(format!("{:#}", e)));
+                    continue;
+                }
+            },
+        };
+        let request = Request::SendInput { session_id: session.id.clone(), text: text.to_string() };
+        let response = match requests.send_request(request).await {
+            Ok(response) => response,
+            Err(e) => {
+                results.push(Err(format!("{:#}", e)));
+                continue;
+            }
+        };
+        connection = Some(requests);
+        results.push(match response {
+            Response::Ok => Ok(()),
+            Response::Error { message, .. } => Err(message),
+            _ => Err("Unexpected response from daemon".to_string()),
+        });
+    }
+    results
+}
+
+/// Run `prune`: ask the daemon what it would remove, and unless this is a
+/// dry run, confirm that list before removing it.
+///
+/// Returns false if the user declined.
+async fn prune_sessions(
+    out: &Output,
+    client: &Client,
+    older_than_secs: u64,
+    dry_run: bool,
+    keep_logs: bool,
+) -> anyhow::Result<bool> {
+    let request = |dry_run| Request::PruneSessions { older_than_secs, dry_run, keep_logs };
+    let (session_ids, logs_removed) = match client.send_request(request(true)).await? {
+        Response::Pruned { session_ids, logs_removed, .. } => (session_ids, logs_removed),
+        Response::Error { message, code } => out.fail_with(code.exit_status(), format!("Failed to prune: {}", message)),
+        _ => out.fail("Unexpected response from daemon"),
+    };
+    if session_ids.is_empty() && logs_removed.is_empty() {
+        out.info("Nothing to prune");
+        return Ok(true);
+    }
+
+    let (session_ids, logs_removed) = if dry_run {
+        (session_ids, logs_removed)
+    } else {
+        let affected: Vec<String> = session_ids
+            .iter()
+            .map(|id| format!("session {}", id))
+            .chain(logs_removed.iter().map(|path| format!("log {}", path)))
+            .collect();
+        let question = format!("Remove {} session(s) and {} log(s)?", session_ids.len(), logs_removed.len());
+        if !out.confirm(question, &affected) {
+            return Ok(false);
+        }
+        match client.send_request(request(false)).await? {
+            Response::Pruned { session_ids, logs_removed, .. } => (session_ids, logs_removed),
+            Response::Error { message, code } => {
+                out.fail_with(code.exit_status(), format!("Failed to prune: {}", message))
+            }
+            _ => out.fail("Unexpected response from daemon"),
+        }
+    };
+
+    let verb = if dry_run { "Would remove" } else { "Removed" };
+    for id in &session_ids {
+        out.data(format!("session\t{}", id), format!("  🗑️  {} session {}", verb, id));
+    }
+    for path in &logs_removed {
+        out.data(format!("log\t{}", path), format!("  🗑️  {} log {}", verb, path));
+    }
+    out.info(format!(
+        "{} {} session(s) and {} log(s){}",
+        verb,
+        session_ids.len(),
+        logs_removed.len(),
+        if dry_run { " (dry run)" } else { "" }
+    ));
+    Ok(true)
+}
+
+/// Print the log path of the session `query` resolves to, undecorated so
+/// it can be used in `$(...)`
+fn print_which(out: &Output, sessions: &[ipc::SessionInfo], query: &str) -> anyhow::Result<()> {
+    let session = resolve::resolve(sessions, query)?;
+    out.data(&session.log_path, &session.log_path);
+    Ok(())
+}
+
+/// Resolve the sessions a log-reading command applies to: one session by
+/// ID, every session under a directory, or (with neither) all sessions.
+/// Sessions are returned oldest first.
+async fn select_sessions(
+    out: &Output,
+    client: &Client,
+    session_id: Option<String>,
+    dir: Option<PathBuf>,
+) -> anyhow::Result<Vec<ipc::SessionInfo>> {
+    let sessions = match client.send_request(Request::ListSessions { fields: None, idle_threshold_secs: None }).await? {
+        Response::SessionList { sessions } => sessions,
+        Response::Error { message, code } => {
+            out.fail_with(code.exit_status(), format!("Failed to list sessions: {}", message))
+        }
+        _ => out.fail("Unexpected response from daemon"),
+    };
+
+    let mut selected = match (session_id, dir) {
+        (Some(id), _) => match resolve::resolve(&sessions, &id) {
+            Ok(session) => vec![session.clone()],
+            Err(e) => out.fail(e),
+        },
+        (None, Some(dir)) => {
+            let dir = paths::expand_path(&dir)
+                .unwrap_or_else(|e| out.fail(format!("Invalid directory: {:#}", e)));
+            sessions_under_dir(sessions, &dir)
+        }
+        (None, None) => sessions,
+    };
+
+    sort_sessions(&mut selected, SortKey::Created, true);
+    Ok(selected)
+}
+
+/// Write the transcripts of `sessions` to `writer`, with a header per
+/// session when there is more than one
+fn write_transcripts<W: std::io::Write>(
+    out: &Output,
+    sessions: &[ipc::SessionInfo],
+    writer: &mut W,
+    options: &logging::TranscriptOptions,
+    prefix: Option<&str>,
+) -> anyhow::Result<()> {
+    for session in sessions {
+        if options.format == logging::TranscriptFormat::Markdown {
+            writeln!(writer, "## Session {}\n\n`{}` · started {}", session.id, session.working_dir, session.created_at)?;
+        } else if sessions.len() > 1 {
+            writeln!(writer, "\n==> {} ({}) <==", session.id, session.working_dir)?;
+        }
+        let options = logging::TranscriptOptions {
+            prefix: prefix.map(|template| logging::LinePrefix::new(template, prefix_fields(session))),
+            ..options.clone()
+        };
+        match logging::write_transcript_with(Path::new(&session.log_path), writer, &options) {
+            Ok(stats) if stats.skipped_lines > 0 => out.warn(format!(
+                "{}: skipped {} corrupt log line(s)",
+                session.id, stats.skipped_lines
+            )),
+            Ok(_) => {}
+            Err(e) => out.warn(format!("{}: {:#}", session.id, e)),
+        }
+    }
+    Ok(())
+}
+
+/// What a `--prefix` template can say about `session`
+fn prefix_fields(session: &ipc::SessionInfo) -> logging::PrefixFields {
+    let name = match session.label.as_str() {
+        "" => session::default_label(Path::new(&session.working_dir)),
+        label => label.to_string(),
+    };
+    logging::PrefixFields { id: session.id.clone(), name, dir: session.working_dir.clone() }
+}
+
+/// Reconnect attempts before `attach` gives up on a dropped stream
+const ATTACH_RETRIES: u32 = 5;
+
+/// What streamed session output goes through before it is printed. Kept
+/// across reconnects, as each part may hold an unfinished line or
+/// escape sequence.
+struct OutputRewrite {
+    stamper: Option<logging::Timestamper>,
+    prefix: Option<logging::LinePrefix>,
+    /// Strips escape sequences when color is off (see `Output::color`)
+    plain: Option<logging::render::OutputFilter>,
+}
+
+/// Print a session's output as it arrives.
+///
+/// If the stream drops, reconnect and resume after the last seq seen so
+/// nothing is missed or repeated. Returns once the session stops or
+/// exits; exits on error.
+async fn stream_attach(
+    out: &Output,
+    client: &Client,
+    session_id: &str,
+    mut since_seq: Option<u64>,
+    stamper: Option<logging::Timestamper>,
+    prefix: Option<logging::LinePrefix>,
+) {
+    let plain = (!out.color()).then(logging::render::OutputFilter::plain_text);
+    let mut rewrite = OutputRewrite { stamper, prefix, plain };
+    let mut announce = true;
+    let mut failures = 0;
+
+    loop {
+        let before = since_seq;
+        let result = attach_once(out, client, session_id, &mut since_seq, &mut rewrite, announce).await;
+        announce = false;
+        if let Ok(true) = result {
+            return;
+        }
+
+        // Only consecutive drops without progress count against the budget
+        if since_seq != before {
+            failures = 0;
+        }
+        failures += 1;
+        if failures > ATTACH_RETRIES {
+            let reason = result.err().map(|e| format!("{:#}", e)).unwrap_or_default();
+            out.fail(format!("Lost connection to session {}: {}", session_id, reason));
+        }
+        out.note(format!("🔌 Connection lost, reconnecting ({}/{})...", failures, ATTACH_RETRIES));
+        tokio::time::sleep(std::time::Duration::from_secs(1)).await;
+    }
+}
+
+/// One attach connection: handshake, then print chunks until it drops.
+/// `true` if it ended because the session did.
+async fn attach_once(
+    out: &Output,
+    client: &Client,
+    session_id: &str,
+    since_seq: &mut Option<u64>,
+    rewrite: &mut OutputRewrite,
+    announce: bool,
+) -> anyhow::Result<bool> {
+    use base64::{engine::general_purpose, Engine as _};
+
+    let mut conn = client.session().await?;
+    let request = Request::AttachSession {
+        session_id: session_id.to_string(),
+        since_seq: *since_seq,
+    };
+    match conn.send_request(request).await? {
+        Response::AttachStarted { pty_rows, pty_cols, scrollback_available, .. } => {
+            if announce {
+                out.data(
+                    format!("{}\t{}x{}", session_id, pty_cols, pty_rows),
+                    format!("🔗 Attached to {} ({}x{})", session_id, pty_cols, pty_rows),
+                );
+                if scrollback_available && since_seq.is_none() {
+                    out.note(format!("💡 Earlier output: claude-sessions logs {}", session_id));
+                }
+            }
+        }
+        Response::Error { code: ipc::ErrorCode::Unimplemented | ipc::ErrorCode::UnknownRequest, .. } => {
+            // Also makes sure the session exists
+            let sessions = select_sessions(out, client, Some(session_id.to_string()), None).await?;
+            let log_path = PathBuf::from(&sessions[0].log_path);
+            if announce {
+                out.note("💡 This daemon can't stream output; following the session log instead");
+            }
+            let from_seq = since_seq.map_or(0, |seq| seq + 1);
+            if let Err(e) = follow_session_log(out, &log_path, from_seq, rewrite).await {
+                out.fail(format!("Failed to follow {}: {}", log_path.display(), e));
+            }
+            return Ok(true);
+        }
+        Response::Error { message, code } => out.fail_with(code.exit_status(), message),
+        _ => out.fail("Unexpected response from daemon"),
+    }
+
+    while let Some(response) = conn.next_response().await? {
+        match response {
+            Response::LogChunk { seq, data, timestamp, .. } => {
+                let bytes = general_purpose::STANDARD.decode(data)?;
+                print_output(out, bytes, timestamp.as_deref(), rewrite)?;
+                *since_seq = Some(seq);
+            }
+            Response::Resync { from_seq } => {
+                out.note(format!("⚠️  Session log was replaced; replaying from entry {}", from_seq));
+            }
+            Response::SessionStopped { .. } => {
+                out.note(format!("🛑 Session {} was stopped", session_id));
+                return Ok(true);
+            }
+            Response::SessionExited { status, exit_code, .. } => {
+                let code = exit_code.map(|code| format!(" (code {})", code)).unwrap_or_default();
+                out.note(format!("🏁 Session {} {}{}", session_id, status, code));
+                return Ok(true);
+            }
+            _ => {}
+        }
+    }
+    Ok(false)
+}
+
+/// Color a session status for human output: green while running, yellow
+/// when it needs a look, red when it failed
+fn paint_status(out: &Output, status: &SessionStatus) -> String {
+    let sgr = match status {
+        SessionStatus::Running => "32",
+        SessionStatus::Unresponsive | SessionStatus::Orphaned | SessionStatus::Stale => "33",
+        SessionStatus::Crashed | SessionStatus::CrashLooping => "31",
+        _ => return status.to_string(),
+    };
+    out.paint(status, sgr)
+}
+
+/// Write one chunk of session output, with escape sequences stripped (when
+/// color is off) and the optional timestamp and line prefix applied
+fn print_output(
+    out: &Output,
+    mut bytes: Vec<u8>,
+    timestamp: Option<&str>,
+    rewrite: &mut OutputRewrite,
+) -> std::io::Result<()> {
+    if let Some(plain) = rewrite.plain.as_mut() {
+        bytes = plain.feed(&bytes);
+    }
+    if let (Some(stamper), Some(timestamp)) = (rewrite.stamper.as_mut(), timestamp) {
+        bytes = stamper.stamp(timestamp, &bytes);
+    }
+    if let Some(prefix) = rewrite.prefix.as_mut() {
+        bytes = prefix.apply(timestamp.unwrap_or_default(), &bytes);
+    }
+    let mut stdout = out.stdout();
+    stdout.write_all(&bytes)?;
+    stdout.flush()
+}
+
+/// Attach without the daemon's help: print a session's logged output from
+/// entry `from_seq` on, then poll the log for more like `tail -f`.
+///
+/// For daemons that can't stream (`AttachSession` is unimplemented). Runs
+/// until Ctrl-C or an error.
+async fn follow_session_log(
+    out: &Output,
+    log_path: &Path,
+    from_seq: u64,
+    rewrite: &mut OutputRewrite,
+) -> std::io::Result<()> {
+    let mut log = logging::LogFollower::from_start(log_path);
+    loop {
+        for entry in log.read_new_entries()? {
+            if entry.seq < from_seq {
+                continue;
+            }
+            if let Some(entry) = entry.into_output() {
+                print_output(out, entry.data, Some(&entry.timestamp), rewrite)?;
+            }
+        }
+        tokio::time::sleep(tail::POLL_INTERVAL).await;
+    }
+}
+
+/// Report a newly started session
+fn report_session_started(out: &Output, session_id: &str, directory: &Path, log_path: &str) {
+    if out.is_json() {
+        out.json(&serde_json::json!({ "session_id": session_id, "log_path": log_path }));
+        return;
+    }
+    out.data(session_id, format!("✅ Session started: {}", session_id));
+    out.info(format!("📂 Working directory: {:?}", directory));
+    out.info(format!("📝 Logs: {}", log_path));
+    out.info("\n💡 Use `claude-sessions list` to see all sessions");
+    out.info(format!("💡 Use `claude-sessions stop {}` to stop this session", session_id));
+}
+
+/// Send a `StartSession` request and report the result.
+///
+/// With `follow`, the new session's output is then streamed read-only via
+/// the attach path; no input is ever sent.
+async fn start_session(out: &Output, client: &Client, request: Request, follow: bool) -> anyhow::Result<()> {
+    let directory = match &request {
+        Request::StartSession { working_dir, .. } => working_dir.clone(),
+        _ => PathBuf::new(),
+    };
+    if follow && out.is_json() {
+        out.fail("--follow can't be combined with --json");
+    }
+
+    match client.send_request(request).await? {
+        Response::SessionStarted { session_id, log_path } => {
+            report_session_started(out, &session_id, &directory, &log_path);
+            if follow {
+                out.note("👀 Following output (read-only). Ctrl-C detaches; the session keeps running.");
+                stream_attach(out, client, &session_id, None, None, None).await;
+            }
+        }
+        Response::Error { message, code } => {
+            out.fail_with(code.exit_status(), format!("Failed to start session: {}", message));
+        }
+        _ => {
+            out.fail("Unexpected response from daemon");
+        }
+    }
+    Ok(())
+}
+
+/// Exit with an error unless the daemon is running, starting it in the
+/// background first with `autostart`
+async fn require_daemon(out: &Output, client: &Client, autostart: bool) {
+    // A daemon elsewhere can't be started from here
+    if autostart && client.remote().is_none() {
+        match autostart::ensure_daemon(client.socket(), &autostart::BackgroundDaemon::default()).await {
+            Ok(false) => {}
+            Ok(true) if autostart::first_time(client.socket()) => {
+                out.note("🚀 The daemon wasn't running, so it was started in the background.");
+                out.note("💡 Its output goes to `claude-sessions daemon-logs`; stop it with `claude-sessions stop-daemon`");
+                out.note("💡 To start it yourself instead, pass --autostart=false or set CLAUDE_SESSIONS_AUTOSTART=0");
+            }
+            Ok(true) => out.note("🚀 Started the daemon in the background"),
+            Err(e) => out.fail(format!("Daemon is not running and couldn't be started: {:#}", e)),
+        }
+        return;
+    }
+    if !client.is_daemon_running().await {
+        out.error("Daemon is not running");
+        out.note("💡 Start it with: claude-sessions daemon");
+        std::process::exit(1);
+    }
+}
+
 #[tokio::main]
-async fn main() -> anyhow::Result<()> {
+async fn main() {
     let cli = Cli::parse();
+    let out = Output::new(cli.raw)
+        .json_mode(cli.json)
+        .assume_yes(cli.yes)
+        .color_choice(cli.color);
+    if let Err(e) = run(cli, &out).await {
+        out.fail(format!("{:#}", e));
+    }
+}
+
+/// Run the parsed command. An error it returns is printed through `out`
+/// like any other failure (as JSON with `--json`).
+async fn run(cli: Cli, out: &Output) -> anyhow::Result<()> {
+    let autostart = cli.autostart.unwrap_or_else(autostart::enabled_from_env);
+    let connect = cli.connect;
+    let new_client = || -> anyhow::Result<Client> {
+        let client = Client::new()?;
+        Ok(match &connect {
+            Some(addr) => client.connect_to(addr.as_str()),
+            None => client,
+        })
+    };
 
     match cli.command {
-        Commands::Daemon { foreground } => {
-            if Daemon::is_running() {
-                eprintln!("❌ Daemon is already running");
-                std::process::exit(1);
+        Commands::Daemon {
+            foreground,
+            keep_logs_days,
+            prune_records,
+            fail_if_running,
+            idle_timeout,
+            listen,
+            require_token,
+            reattach,
+        } => {
+            if let Some(addr) = listen {
+                if let Err(e) = daemon::check_listen_addr(addr, require_token) {
+                    out.fail(format!("{:#}", e));
+                }
+            }
+            // Checked here too, so a bad file fails the command rather
+            // than only a backgrounded daemon
+            let mut config = config::Config::load().unwrap_or_else(|e| out.fail(format!("{:#}", e)));
+            if let Some(days) = keep_logs_days {
+                config.keep_logs_days = days;
+            }
+            config.prune_records |= prune_records;
+            config.reattach |= reattach;
+            if let Some(secs) = idle_timeout {
+                config.idle_timeout_secs = Some(secs);
+            }
+            // Probe before `Daemon::new`, which recovers (and rewrites) state
+            let already_running = |out: &Output| {
+                if fail_if_running {
+                    out.fail("Daemon is already running");
+                }
+                out.data("running", "✅ Daemon is already running");
+            };
+            if Daemon::probe(&Daemon::socket_path()?).await == Probe::Alive {
+                already_running(out);
+                return Ok(());
             }
-
             if foreground {
                 // Run in foreground (blocking)
-                println!("🚀 Starting daemon in foreground mode...");
-                let mut daemon = Daemon::new().await?;
-                daemon.run().await?;
+                out.info("🚀 Starting daemon in foreground mode...");
+                let token =
+                    if require_token { Some(auth::load_or_create_token(&auth::token_path()?)?) } else { None };
+                let daemon = match Daemon::new(config).await {
+                    Ok(daemon) => daemon,
+                    // Lost the race to a daemon started since the probe
+                    Err(e) if e.downcast_ref::<LockHeld>().is_some() => {
+                        already_running(out);
+                        return Ok(());
+                    }
+                    Err(e) => return Err(e),
+                };
+                let mut daemon = daemon.with_listen(listen).with_token(token);
+                if daemon.run().await? == RunOutcome::AlreadyRunning {
+                    already_running(out);
+                }
             } else {
-                // TODO: Fork and daemonize (for now, just run in foreground)
-                println!("⚠️  Daemonization not implemented yet. Running in foreground.");
-                println!("💡 Use `claude-sessions daemon --foreground` explicitly");
-                let mut daemon = Daemon::new().await?;
-                daemon.run().await?;
+                let mut args = Vec::new();
+                if let Some(days) = keep_logs_days {
+                    args.extend(["--keep-logs-days".to_string(), days.to_string()]);
+                }
+                if prune_records {
+                    args.push("--prune-records".to_string());
+                }
+                if let Some(secs) = idle_timeout {
+                    args.extend(["--idle-timeout".to_string(), secs.to_string()]);
+                }
+                if let Some(addr) = listen {
+                    args.extend(["--listen".to_string(), addr.to_string()]);
+                }
+                if require_token {
+                    args.push("--require-token".to_string());
+                }
+                if reattach {
+                    args.push("--reattach".to_string());
+                }
+                let spawner = autostart::BackgroundDaemon { args };
+                let socket_path = Daemon::socket_path()?;
+                if let Err(e) = autostart::ensure_daemon(&socket_path, &spawner).await {
+                    out.fail(format!("Failed to start daemon: {:#}", e));
+                }
+                let pid = Daemon::read_pid(&Daemon::pid_path()?);
+                out.data(
+                    pid.map(|pid| pid.to_string()).unwrap_or_default(),
+                    match pid {
+                        Some(pid) => format!("✅ Daemon started in the background (PID {})", pid),
+                        None => "✅ Daemon started in the background".to_string(),
+                    },
+                );
+                out.info(format!("📝 Logs: {}", Daemon::log_path()?.display()));
+            }
+        }
+        Commands::KeepPtys { socket, terminals } => {
+            #[cfg(unix)]
+            {
+                let terminals = terminals
+                    .iter()
+                    .map(|arg| reattach::HeldTerminal::from_arg(arg))
+                    .collect::<anyhow::Result<Vec<_>>>()?;
+                reattach::run_keeper(&socket, terminals)?;
+            }
+            #[cfg(not(unix))]
+            {
+                let _ = (socket, terminals);
+                out.fail("Reattaching needs Unix");
             }
         }
         Commands::Status => {
-            let client = Client::new()?;
-            if client.is_daemon_running() {
-                match client.send_request(Request::Ping).await {
-                    Ok(Response::Pong) => {
-                        println!("✅ Daemon is running");
+            let client = new_client()?;
+            if client.is_daemon_running().await {
+                let nonce = uuid::Uuid::new_v4().to_string();
+                let sent_at = std::time::Instant::now();
+                match client.send_request(Request::Ping { nonce: Some(nonce.clone()) }).await {
+                    Ok(Response::Pong { nonce: echoed, active_connections, max_connections })
+                        if echoed.as_deref() == Some(nonce.as_str()) =>
+                    {
+                        let elapsed = sent_at.elapsed();
+                        // Daemons from before `DaemonInfo` answer it with an error
+                        let daemon = match client.send_request(Request::DaemonInfo).await {
+                            Ok(Response::DaemonInfo { daemon }) => Some(daemon),
+                            _ => None,
+                        };
+                        let pid = match &daemon {
+                            Some(daemon) => Some(daemon.pid),
+                            None => Daemon::read_pid(&Daemon::pid_path()?),
+                        };
+                        if out.is_json() {
+                            out.json(&serde_json::json!({
+                                "status": "running",
+                                "pid": pid,
+                                "version": daemon.as_ref().map(|d| &d.version),
+                                "protocol_version": daemon.as_ref().map(|d| d.protocol_version),
+                                "started_at": daemon.as_ref().map(|d| &d.started_at),
+                                "uptime_seconds": daemon.as_ref().map(|d| d.uptime_seconds),
+                                "running_sessions": daemon.as_ref().map(|d| d.running_sessions),
+                                "latency_ms": elapsed.as_secs_f64() * 1000.0,
+                                "active_connections": active_connections,
+                                "max_connections": max_connections,
+                            }));
+                        } else {
+                            out.data(
+                                "running",
+                                format!("✅ Daemon is running ({:.1}ms)", elapsed.as_secs_f64() * 1000.0),
+                            );
+                            if let Some(pid) = pid {
+                                out.info(format!("   PID: {}", pid));
+                            }
+                            if let Some(daemon) = &daemon {
+                                out.info(format!("   Version: {} (protocol {})", daemon.version, daemon.protocol_version));
+                                out.info(format!(
+                                    "   Uptime: {} (since {})",
+                                    stats::format_duration(daemon.uptime_seconds),
+                                    daemon.started_at
+                                ));
+                                out.info(format!("   Running sessions: {}", daemon.running_sessions));
+                            }
+                            if let (Some(active), Some(max)) = (active_connections, max_connections) {
+                                out.info(format!("   Connections: {}/{}", active, max));
+                            }
+                        }
                     }
+                    Ok(_) if out.is_json() => out.json(&serde_json::json!({ "status": "unknown" })),
                     Ok(_) => {
-                        println!("⚠️  Daemon responded but with unexpected message");
+                        out.data("unknown", "⚠️  Daemon responded but with unexpected message");
                     }
+                    Err(e) if out.is_json() => out.json(&serde_json::json!({
+                        "status": "not-responding",
+                        "error": e.to_string(),
+                    })),
                     Err(e) => {
-                        println!("❌ Daemon not responding: {}", e);
+                        out.data(
+                            "not-responding",
+                            format!("❌ Daemon not responding: {}", e),
+                        );
                     }
                 }
+            } else if out.is_json() {
+                out.json(&serde_json::json!({ "status": "stopped" }));
             } else {
-                println!("❌ Daemon is not running");
-                println!("💡 Start it with: claude-sessions daemon");
+                out.data("stopped", "❌ Daemon is not running");
+                out.info("💡 Start it with: claude-sessions daemon");
             }
         }
-        Commands::StopDaemon => {
-            let client = Client::new()?;
-            if !client.is_daemon_running() {
-                println!("❌ Daemon is not running");
-                std::process::exit(1);
+        Commands::StopDaemon { kill_sessions } => {
+            let client = new_client()?;
+            if !client.is_daemon_running().await {
+                out.fail("Daemon is not running");
             }
 
-            match client.send_request(Request::Shutdown).await {
-                Ok(_) => {
-                    println!("✅ Daemon shutdown requested");
-                }
+            let pid = Daemon::read_pid(&Daemon::pid_path()?);
+            match client.send_request(Request::Shutdown { kill_sessions }).await {
+                Ok(_) => match pid {
+                    Some(pid) => out.info(format!("✅ Daemon shutdown requested (PID {})", pid)),
+                    None => out.info("✅ Daemon shutdown requested"),
+                },
                 Err(e) => {
-                    eprintln!("❌ Failed to stop daemon: {}", e);
-                    std::process::exit(1);
+                    out.fail(format!("Failed to stop daemon: {}", e));
                 }
             }
         }
-        Commands::Start { directory } => {
-            let client = Client::new()?;
-            if !client.is_daemon_running() {
-                eprintln!("❌ Daemon is not running");
-                eprintln!("💡 Start it with: claude-sessions daemon");
-                std::process::exit(1);
+        Commands::DaemonLogs { follow, lines } => {
+            let log_path = Daemon::log_path()?;
+            let (tail, offset) = match tail::last_lines(&log_path, lines) {
+                Ok(tail) => tail,
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                    out.info(format!("No daemon log at {}", log_path.display()));
+                    out.note("💡 The daemon only writes one when running in the background");
+                    return Ok(());
+                }
+                Err(e) => out.fail(format!("Failed to read {}: {}", log_path.display(), e)),
+            };
+
+            for line in &tail {
+                let _ = writeln!(out.stdout(), "{}", line);
+            }
+            if follow {
+                tail::follow(&log_path, offset, |line| {
+                    let _ = writeln!(out.stdout(), "{}", line);
+                })
+                .await?;
             }
+        }
+        Commands::Start {
+            directory,
+            restart_on_crash,
+            log_dir,
+            descriptive_log_name,
+            env,
+            claude_args,
+            name,
+            tags,
+            allow_duplicate,
+            follow,
+        } => {
+            let client = new_client()?;
+            require_daemon(out, &client, autostart).await;
+
+            let directory = paths::expand_path(&directory)
+                .and_then(|dir| paths::require_utf8(&dir).map(PathBuf::from))
+                .unwrap_or_else(|e| out.fail(format!("Invalid directory: {:#}", e)));
+            let log_dir = log_dir.map(|dir| {
+                paths::expand_path(&dir)
+                    .and_then(|dir| paths::require_utf8(&dir).map(PathBuf::from))
+                    .unwrap_or_else(|e| out.fail(format!("Invalid log directory: {:#}", e)))
+            });
 
             let request = Request::StartSession {
                 working_dir: directory.clone(),
+                restart_on_crash,
+                log_dir,
+                descriptive_log_name,
+                env,
+                claude_args,
+                name,
+                tags: tags.into_iter().collect(),
+                allow_duplicate,
             };
+            start_session(out, &client, request, follow).await?;
+        }
+        Commands::Exec { directory, prompt, timeout, quiet, retries, retry_backoff } => {
+            let client = new_client()?;
+            require_daemon(out, &client, autostart).await;
 
-            match client.send_request(request).await? {
-                Response::SessionStarted { session_id, log_path } => {
-                    println!("✅ Session started: {}", session_id);
-                    println!("📂 Working directory: {:?}", directory);
-                    println!("📝 Logs: {}", log_path);
-                    println!("\n💡 Use `claude-sessions list` to see all sessions");
-                    println!("💡 Use `claude-sessions stop {}` to stop this session", session_id);
-                }
-                Response::Error { message } => {
-                    eprintln!("❌ Failed to start session: {}", message);
-                    std::process::exit(1);
+            let working_dir = paths::expand_path(&directory)
+                .and_then(|dir| paths::require_utf8(&dir).map(PathBuf::from))
+                .unwrap_or_else(|e| out.fail(format!("Invalid directory: {:#}", e)));
+            let options = exec::ExecOptions { working_dir, prompt, timeout, quiet };
+
+            let (result, attempts) = exec::with_retries(retries, retry_backoff, |attempt| {
+                if attempt > 1 {
+                    out.note(format!("🔁 Retrying (attempt {} of {})", attempt, retries + 1));
                 }
-                _ => {
-                    eprintln!("❌ Unexpected response from daemon");
-                    std::process::exit(1);
+                exec::exec_once(&client, &options)
+            })
+            .await;
+            match result {
+                Ok(output) => {
+                    let mut stdout = out.stdout();
+                    stdout.write_all(&output)?;
+                    stdout.flush()?;
                 }
+                Err(e) => out.fail(format!(
+                    "exec failed after {} attempt{}: {:#}",
+                    attempts,
+                    if attempts == 1 { "" } else { "s" },
+                    e
+                )),
             }
         }
-        Commands::List => {
-            let client = Client::new()?;
-            if !client.is_daemon_running() {
-                eprintln!("❌ Daemon is not running");
-                std::process::exit(1);
-            }
+        Commands::List { sort, reverse, idle_threshold } => {
+            let client = new_client()?;
+            require_daemon(out, &client, autostart).await;
 
-            match client.send_request(Request::ListSessions).await? {
-                Response::SessionList { sessions } => {
-                    if sessions.is_empty() {
-                        println!("No active sessions");
+            let request = Request::ListSessions { fields: None, idle_threshold_secs: idle_threshold };
+            match client.send_request(request).await? {
+                Response::SessionList { mut sessions } => {
+                    sort_sessions(&mut sessions, sort, reverse);
+                    if out.is_json() {
+                        out.json(&sessions);
+                    } else if sessions.is_empty() {
+                        out.info("No active sessions");
                     } else {
-                        println!("📋 Active sessions ({}):\n", sessions.len());
+                        out.info(format!("📋 Active sessions ({}):\n", sessions.len()));
                         for session in sessions {
-                            println!("  🔹 {}", session.id);
-                            println!("     Directory: {}", session.working_dir);
-                            println!("     Created: {}", session.created_at);
-                            println!("     Status: {}", session.status);
-                            println!("     Logs: {}", session.log_path);
-                            println!();
+                            out.data(
+                                format!(
+                                    "{}\t{}\t{}\t{}\t{}",
+                                    session.id,
+                                    session.status,
+                                    session.working_dir,
+                                    session.log_path,
+                                    session.label
+                                ),
+                                match session.label.as_str() {
+                                    "" => format!("  🔹 {}", session.id),
+                                    label => format!("  🔹 {} ({})", label, session.id),
+                                },
+                            );
+                            out.info(format!("     Directory: {}", session.working_dir));
+                            out.info(format!("     Created: {}", session.created_at));
+                            match session.exit_code {
+                                Some(code) => out.info(format!("     Status: {} (code {})", paint_status(out, &session.status), code)),
+                                None => out.info(format!("     Status: {}", paint_status(out, &session.status))),
+                            }
+                            if let (Some(idle), Some(healthy)) = (session.idle_seconds, session.healthy) {
+                                let health = if healthy { "healthy" } else { "idle" };
+                                out.info(format!("     Idle for {} ({})", stats::format_duration(idle), health));
+                            }
+                            if let Some(error) = &session.last_error {
+                                out.info(format!("     Last error: {}", error));
+                            }
+                            out.info(format!("     Logs: {}", session.log_path));
+                            out.info("");
                         }
                     }
                 }
-                Response::Error { message } => {
-                    eprintln!("❌ Failed to list sessions: {}", message);
-                    std::process::exit(1);
+                Response::Error { message, code } => {
+                    out.fail_with(code.exit_status(), format!("Failed to list sessions: {}", message));
                 }
                 _ => {
-                    eprintln!("❌ Unexpected response from daemon");
-                    std::process::exit(1);
+                    out.fail("Unexpected response from daemon");
                 }
             }
         }
-        Commands::Stop { session_id } => {
-            let client = Client::new()?;
-            if !client.is_daemon_running() {
-                eprintln!("❌ Daemon is not running");
-                std::process::exit(1);
+        Commands::Resize { session_id, rows, cols } => {
+            let client = new_client()?;
+            require_daemon(out, &client, autostart).await;
+
+            let sessions = select_sessions(out, &client, Some(session_id), None).await?;
+            let session_id = sessions[0].id.clone();
+            match client.send_request(Request::Resize { session_id: session_id.clone(), rows, cols }).await? {
+                Response::Ok => out.data(
+                    format!("{}\t{}x{}", session_id, cols, rows),
+                    format!("📐 Resized {} to {}x{}", session_id, cols, rows),
+                ),
+                Response::Error { message, code } => {
+                    out.fail_with(code.exit_status(), format!("Failed to resize session: {}", message))
+                }
+                _ => out.fail("Unexpected response from daemon"),
             }
+        }
+        Commands::Info { session_id } => {
+            let client = new_client()?;
+            require_daemon(out, &client, autostart).await;
+
+            let sessions = select_sessions(out, &client, Some(session_id), None).await?;
+            let session_id = sessions[0].id.clone();
+            match client.send_request(Request::SessionInfo { session_id }).await? {
+                Response::SessionDetail { session } if out.is_json() => out.json(&session),
+                Response::SessionDetail { session } => {
+                    let info = &session.info;
+                    let field = |name: &str, value: String| {
+                        out.data(format!("{}\t{}", name, value), format!("   {:<10} {}", format!("{}:", name), value));
+                    };
+                    out.info(format!("🔹 {}", if info.label.is_empty() { &info.id } else { &info.label }));
+                    field("id", info.id.clone());
+                    field("label", info.label.clone());
+                    if !info.tags.is_empty() {
+                        let tags: Vec<String> = info.tags.iter().map(|(k, v)| format!("{}={}", k, v)).collect();
+                        field("tags", tags.join(","));
+                    }
+                    match info.exit_code {
+                        Some(code) => field("status", format!("{} (code {})", info.status, code)),
+                        None => field("status", info.status.to_string()),
+                    }
+                    if let Some(pid) = session.pid {
+                        field("pid", pid.to_string());
+                    }
+                    field("directory", info.working_dir.clone());
+                    field("created", info.created_at.clone());
+                    if let Some(uptime) = session.uptime_seconds {
+                        out.data(
+                            format!("uptime\t{}", uptime),
+                            format!("   {:<10} {}", "uptime:", stats::format_duration(uptime)),
+                        );
+                    }
+                    if let Some(at) = &info.last_activity {
+                        field("active", at.clone());
+                    }
+                    if let Some(idle) = info.idle_seconds {
+                        out.data(
+                            format!("idle\t{}", idle),
+                            format!("   {:<10} {}", "idle:", stats::format_duration(idle)),
+                        );
+                    }
+                    if let Some(error) = &info.last_error {
+                        field("error", error.clone());
+                    }
+                    field("log", info.log_path.clone());
+                    out.data(
+                        format!("log_size\t{}", session.log_size_bytes),
+                        format!("   {:<10} {} bytes", "log size:", session.log_size_bytes),
+                    );
+                }
+                Response::Error { message, code } => {
+                    out.fail_with(code.exit_status(), format!("Failed to get session info: {}", message))
+                }
+                _ => out.fail("Unexpected response from daemon"),
+            }
+        }
+        Commands::Rename { session_id, name } => {
+            let client = new_client()?;
+            require_daemon(out, &client, autostart).await;
+
+            let sessions = select_sessions(out, &client, Some(session_id), None).await?;
+            let session_id = sessions[0].id.clone();
+            let request = Request::RenameSession { session_id: session_id.clone(), name: name.clone() };
+            match client.send_request(request).await? {
+                Response::Ok => out.data(
+                    format!("{}\t{}", session_id, name.trim()),
+                    format!("🏷️  Renamed {} to {}", session_id, name.trim()),
+                ),
+                Response::Error { message, code } => {
+                    out.fail_with(code.exit_status(), format!("Failed to rename session: {}", message))
+                }
+                _ => out.fail("Unexpected response from daemon"),
+            }
+        }
+        Commands::Restart { session_id } => {
+            let client = new_client()?;
+            require_daemon(out, &client, autostart).await;
+
+            let sessions = select_sessions(out, &client, Some(session_id), None).await?;
+            let session_id = sessions[0].id.clone();
+            match client.send_request(Request::RestartSession { session_id }).await? {
+                Response::SessionRestarted { previous_id, session_id, log_path } => {
+                    out.data(
+                        format!("{}\t{}", session_id, log_path),
+                        format!("🔄 Restarted {} as {}", previous_id, session_id),
+                    );
+                    out.info(format!("📝 Logs: {}", log_path));
+                }
+                Response::Error { message, code } => {
+                    out.fail_with(code.exit_status(), format!("Failed to restart session: {}", message))
+                }
+                _ => out.fail("Unexpected response from daemon"),
+            }
+        }
+        Commands::Stop { session_id, checkpoint, force, timeout } => {
+            let client = new_client()?;
+            require_daemon(out, &client, autostart).await;
+
+            let sessions = select_sessions(out, &client, Some(session_id), None).await?;
+            let session_id = sessions[0].id.clone();
+
+            let export_path = checkpoint.clone().flatten().map(|path| {
+                paths::expand_path(&path).unwrap_or_else(|e| out.fail(format!("Invalid export path: {:#}", e)))
+            });
 
             let request = Request::StopSession {
                 session_id: session_id.clone(),
+                checkpoint: checkpoint.is_some(),
+                export_path: export_path.clone(),
+                force_after_secs: if force { Some(0) } else { timeout },
             };
 
             match client.send_request(request).await? {
+                Response::SessionStopped { session_id } if out.is_json() => {
+                    out.json(&serde_json::json!({ "session_id": session_id, "export_path": export_path }));
+                }
                 Response::SessionStopped { session_id } => {
-                    println!("✅ Session stopped: {}", session_id);
+                    if let Some(path) = &export_path {
+                        out.info(format!("📄 Transcript exported to {}", path.display()));
+                    }
+                    out.data(&session_id, format!("✅ Session stopped: {}", session_id));
                 }
-                Response::Error { message } => {
-                    eprintln!("❌ Failed to stop session: {}", message);
-                    std::process::exit(1);
+                Response::Error { message, code } => {
+                    out.fail_with(code.exit_status(), format!("Failed to stop session: {}", message));
                 }
                 _ => {
-                    eprintln!("❌ Unexpected response from daemon");
-                    std::process::exit(1);
+                    out.fail("Unexpected response from daemon");
                 }
             }
         }
-        Commands::Attach { session_id } => {
-            let client = Client::new()?;
-            if !client.is_daemon_running() {
-                eprintln!("❌ Daemon is not running");
-                std::process::exit(1);
+        Commands::Stats { format } => {
+            let client = new_client()?;
+            require_daemon(out, &client, autostart).await;
+
+            let sessions = select_sessions(out, &client, None, None).await?;
+            let stats = stats::compute(&sessions);
+            if format == StatsFormat::Json {
+                let json = serde_json::to_string_pretty(&stats)?;
+                out.data(&json, &json);
+                return Ok(());
             }
 
-            let request = Request::AttachSession {
-                session_id: session_id.clone(),
+            out.info(format!("📊 {} session(s)", stats.total_sessions));
+            for (status, count) in &stats.by_status {
+                out.data(format!("status\t{}\t{}", status, count), format!("   {}: {}", status, count));
+            }
+            out.data(
+                format!("bytes_logged\t{}", stats.bytes_logged),
+                format!("   Logged: {} bytes", stats.bytes_logged),
+            );
+            out.data(
+                format!("input_bytes\t{}", stats.input_bytes),
+                format!("   Input sent: {} bytes", stats.input_bytes),
+            );
+            if let (Some(average), Some(longest)) = (stats.average_duration_secs, stats.longest_duration_secs) {
+                out.data(
+                    format!("duration_secs\t{}\t{}", average, longest),
+                    format!(
+                        "   Duration: {} average, {} longest",
+                        stats::format_duration(average),
+                        stats::format_duration(longest)
+                    ),
+                );
+            }
+            if !stats.busiest_dirs.is_empty() {
+                out.info("   Busiest directories:");
+            }
+            for dir in &stats.busiest_dirs {
+                out.data(
+                    format!("dir\t{}\t{}", dir.sessions, dir.working_dir),
+                    format!("     {:>4}  {}", dir.sessions, dir.working_dir),
+                );
+            }
+        }
+        Commands::Send { text, session_ids, all: _, dir, tags } => {
+            let client = new_client()?;
+            require_daemon(out, &client, autostart).await;
+
+            let sessions = select_sessions(out, &client, None, None).await?;
+            let dir = dir.map(|dir| {
+                paths::expand_path(&dir).unwrap_or_else(|e| out.fail(format!("Invalid directory: {:#}", e)))
+            });
+            let targets =
+                send_targets(&sessions, &session_ids, dir.as_deref(), &tags).unwrap_or_else(|e| out.fail(e));
+            if targets.is_empty() {
+                out.fail("No running sessions to send to");
+            }
+
+            let results = broadcast_input(&client, &targets, &text).await;
+            let mut failed = 0;
+            for (session, result) in targets.iter().zip(&results) {
+                let short_id = session.id.get(..8).unwrap_or(&session.id);
+                match result {
+                    Ok(()) => out.data(format!("{}\tsent", session.id), format!("✅ {} sent", short_id)),
+                    Err(message) => {
+                        failed += 1;
+                        out.data(
+                            format!("{}\tfailed\t{}", session.id, message),
+                            format!("❌ {}: {}", short_id, message),
+                        );
+                    }
+                }
+            }
+            if failed > 0 {
+                out.fail(format!("Failed to send to {} of {} session(s)", failed, targets.len()));
+            }
+        }
+        Commands::Which { session_id } => {
+            let client = new_client()?;
+            require_daemon(out, &client, autostart).await;
+
+            let sessions = select_sessions(out, &client, None, None).await?;
+            if let Err(e) = print_which(out, &sessions, &session_id) {
+                out.fail(e);
+            }
+        }
+        Commands::Logs { session_id, dir, max_line_bytes, timestamps, prefix } => {
+            let client = new_client()?;
+            require_daemon(out, &client, autostart).await;
+
+            let sessions = select_sessions(out, &client, session_id, dir).await?;
+            if sessions.is_empty() {
+                out.info("No sessions under that directory");
+            }
+            let options = logging::TranscriptOptions {
+                max_entry_bytes: max_line_bytes,
+                color: out.color(),
+                timestamp: timestamps.timestamp,
+                timestamp_every_line: timestamps.timestamp_every_line,
+                prefix: None,
+                format: logging::TranscriptFormat::Text,
             };
+            write_transcripts(out, &sessions, &mut *out.stdout(), &options, prefix.as_deref())?;
+        }
+        Commands::Search { query, dir, limit } => {
+            let client = new_client()?;
+            require_daemon(out, &client, autostart).await;
 
-            match client.send_request(request).await? {
-                Response::Error { message } => {
-                    eprintln!("⚠️  {}", message);
-                    eprintln!("💡 For now, use: tail -f ~/.claude-sessions/logs/{}.jsonl", session_id);
+            let sessions = select_sessions(out, &client, None, dir).await?;
+            let paths: Vec<PathBuf> = sessions.iter().map(|s| PathBuf::from(&s.log_path)).collect();
+            let limits = logging::SearchLimits { max_results: limit, ..Default::default() };
+            let results = logging::search_logs(&paths, &query, limits)
+                .unwrap_or_else(|e| out.fail(format!("Search failed: {:#}", e)));
+            for (index, m) in &results.matches {
+                let session = &sessions[*index];
+                let short_id = session.id.get(..8).unwrap_or(&session.id);
+                let marker = if matches!(m.direction, logging::Direction::Input) { ">>> " } else { "" };
+                out.data(
+                    format!("{}\t{}\t{}", session.id, m.timestamp, m.line),
+                    format!("{} {} {}{}", short_id, m.timestamp, marker, m.line),
+                );
+            }
+            out.info(format!(
+                "\n🔎 {} match(es) across {} session(s), {} log(s) scanned",
+                results.matches.len(),
+                sessions.len(),
+                results.files_scanned
+            ));
+            if results.truncated {
+                out.note(format!("⚠️  Stopped at {} matches; raise --limit to see more", limit));
+            }
+        }
+        Commands::Grep { session_id, pattern, regex, context, strip_ansi } => {
+            let pattern = logging::GrepPattern::new(&pattern, regex)
+                .unwrap_or_else(|e| out.fail_with(ipc::ErrorCode::InvalidArgument.exit_status(), format!("{:#}", e)));
+            let client = new_client()?;
+            require_daemon(out, &client, autostart).await;
+
+            let sessions = select_sessions(out, &client, Some(session_id), None).await?;
+            let options = logging::GrepOptions { context, strip_ansi: strip_ansi || !out.color() };
+            let mut last_number = None;
+            let result = logging::grep_log(Path::new(&sessions[0].log_path), &pattern, options, |line| {
+                if context > 0 && last_number.is_some_and(|last| line.number > last + 1) {
+                    out.info("--");
+                }
+                last_number = Some(line.number);
+                let direction = match line.direction {
+                    logging::Direction::Input => "input",
+                    logging::Direction::Marker => "marker",
+                    _ => "output",
+                };
+                let separator = if line.is_match { ':' } else { '-' };
+                out.data(
+                    format!("{}\t{}\t{}\t{}", line.number, line.timestamp, direction, line.line),
+                    format!("{} {:<6}{} {}", line.timestamp, direction, separator, line.line),
+                );
+            });
+            match result {
+                Ok(0) => out.fail("No matches"),
+                Ok(count) => out.info(format!("\n🔎 {} match(es)", count)),
+                Err(e) => out.fail(format!("Failed to read log: {:#}", e)),
+            }
+        }
+        Commands::Export { session_id, dir, output, format, strip_ansi } => {
+            let client = new_client()?;
+            require_daemon(out, &client, autostart).await;
+
+            let sessions = select_sessions(out, &client, session_id, dir).await?;
+            match output {
+                Some(path) => {
+                    let path = paths::expand_path(&path)
+                        .unwrap_or_else(|e| out.fail(format!("Invalid output path: {:#}", e)));
+                    if path.exists()
+                        && !out.confirm("Overwrite existing file?", &[path.display().to_string()])
+                    {
+                        out.fail("Aborted");
+                    }
+                    let mut file = std::fs::File::create(&path)
+                        .unwrap_or_else(|e| out.fail(format!("Failed to create {}: {}", path.display(), e)));
+                    let options = logging::TranscriptOptions {
+                        color: out.color_for(false) && !strip_ansi,
+                        format,
+                        ..Default::default()
+                    };
+                    write_transcripts(out, &sessions, &mut file, &options, None)?;
+                    out.data(
+                        path.display(),
+                        format!("📄 Exported {} transcript(s) to {}", sessions.len(), path.display()),
+                    );
+                }
+                None => {
+                    let options = logging::TranscriptOptions {
+                        color: out.color() && !strip_ansi,
+                        format,
+                        ..Default::default()
+                    };
+                    write_transcripts(out, &sessions, &mut *out.stdout(), &options, None)?
+                }
+            }
+        }
+        Commands::StopAll { include_orphaned } => {
+            let client = new_client()?;
+            require_daemon(out, &client, autostart).await;
+
+            let request = Request::ListSessions { fields: None, idle_threshold_secs: None };
+            let sessions = match client.send_request(request).await? {
+                Response::SessionList { sessions } => sessions,
+                Response::Error { message, code } => {
+                    out.fail_with(code.exit_status(), format!("Failed to list sessions: {}", message))
+                }
+                _ => out.fail("Unexpected response from daemon"),
+            };
+            let affected: Vec<String> = sessions
+                .iter()
+                .filter(|s| {
+                    s.status.is_live() || (include_orphaned && s.status == SessionStatus::Orphaned)
+                })
+                .map(|s| match s.label.as_str() {
+                    "" => format!("{} ({})", s.id, s.status),
+                    label => format!("{} {} ({})", s.id, label, s.status),
+                })
+                .collect();
+            if affected.is_empty() {
+                out.info("No running sessions");
+                return Ok(());
+            }
+            if !out.confirm(format!("Stop {} session(s)?", affected.len()), &affected) {
+                out.fail("Aborted");
+            }
+
+            match client.send_request(Request::StopAllSessions { include_orphaned }).await? {
+                Response::StoppedAll { stopped, failed } => {
+                    for id in &stopped {
+                        out.data(id, format!("✅ Session stopped: {}", id));
+                    }
+                    for failure in &failed {
+                        out.error(format!("Failed to stop {}: {}", failure.session_id, failure.message));
+                    }
+                    if !failed.is_empty() {
+                        std::process::exit(1);
+                    }
+                }
+                Response::Error { message, code } => {
+                    out.fail_with(code.exit_status(), format!("Failed to stop sessions: {}", message))
+                }
+                _ => out.fail("Unexpected response from daemon"),
+            }
+        }
+        Commands::Prune { older_than_days, dry_run, keep_logs } => {
+            let client = new_client()?;
+            require_daemon(out, &client, autostart).await;
+
+            let older_than_secs = u64::from(older_than_days) * 24 * 60 * 60;
+            if !prune_sessions(out, &client, older_than_secs, dry_run, keep_logs).await? {
+                out.fail("Aborted");
+            }
+        }
+        Commands::Flush { session_id } => {
+            let client = new_client()?;
+            require_daemon(out, &client, autostart).await;
+
+            match client.send_request(Request::Flush { session_id }).await? {
+                Response::Flushed { sessions } => {
+                    out.data(
+                        sessions,
+                        format!("✅ Flushed state and {} session log(s) to disk", sessions),
+                    );
+                }
+                Response::Error { message, code } => {
+                    out.fail_with(code.exit_status(), format!("Failed to flush: {}", message));
                 }
                 _ => {
-                    eprintln!("❌ Unexpected response from daemon");
-                    std::process::exit(1);
+                    out.fail("Unexpected response from daemon");
                 }
             }
         }
+        Commands::Repair { dry_run, log_dir } => {
+            // A running daemon would overwrite the repaired state with its own
+            if Daemon::probe(&Daemon::socket_path()?).await == Probe::Alive {
+                out.fail("The daemon is running; stop it first with: claude-sessions stop-daemon");
+            }
+            let log_dir = match log_dir {
+                Some(dir) => paths::expand_path(&dir)
+                    .unwrap_or_else(|e| out.fail(format!("Invalid log directory: {:#}", e))),
+                None => session::Session::default_log_dir(),
+            };
+
+            let backend = persistence::from_env()?;
+            let mut sessions = backend.load_state()?;
+            let actions = repair::plan(&sessions, &log_dir);
+            if actions.is_empty() {
+                out.info("✅ Nothing to repair");
+                return Ok(());
+            }
+            for action in &actions {
+                out.data(action, format!("  • {}", action));
+            }
+
+            if dry_run {
+                out.info(format!("{} change(s) would be made (dry run)", actions.len()));
+            } else {
+                repair::apply(&mut sessions, &actions);
+                backend.write_state(&sessions)?;
+                backend.sync_state()?;
+                out.info(format!("✅ Made {} change(s)", actions.len()));
+            }
+        }
+        Commands::ConvertLog { input, output } => {
+            use logging::LogFormat;
+
+            if output.exists() {
+                out.fail(format!("{} already exists", output.display()));
+            }
+            let converted = match (LogFormat::of(&input), LogFormat::of(&output)) {
+                (LogFormat::Jsonl, LogFormat::Binary) => logging::binary::from_jsonl(&input, &output),
+                (LogFormat::Binary, LogFormat::Jsonl) => logging::binary::to_jsonl(&input, &output),
+                _ => out.fail("Convert from a .jsonl file to a .clog file, or the other way round"),
+            };
+            let count = converted.unwrap_or_else(|e| out.fail(format!("Failed to convert {}: {:#}", input.display(), e)));
+            out.data(count, format!("✅ Converted {} entries to {}", count, output.display()));
+        }
+        Commands::Doctor => {
+            let checks = doctor::run().await;
+            if out.is_json() {
+                out.json(&checks);
+            } else {
+                for check in &checks {
+                    out.data(check, check.human());
+                }
+            }
+            let failed = checks.iter().filter(|c| c.outcome == doctor::Outcome::Fail).count();
+            if failed > 0 && out.is_json() {
+                // The report already says so; keep stdout one document
+                std::process::exit(1);
+            }
+            if failed > 0 {
+                out.fail(format!("{} check(s) failed", failed));
+            }
+            out.info("\n✅ Ready to run sessions");
+        }
+        Commands::Attach { session_id, takeover: true, .. } => {
+            let client = new_client()?;
+            require_daemon(out, &client, autostart).await;
+
+            let sessions = select_sessions(out, &client, Some(session_id), None).await?;
+            let session_id = sessions[0].id.clone();
+            let log_path = PathBuf::from(&sessions[0].log_path);
+            let request = Request::AttachSession { session_id: session_id.clone(), since_seq: None };
+            match client.send_request(request).await? {
+                Response::AttachStarted { .. } => {}
+                Response::Error { message, code } => out.fail_with(code.exit_status(), message),
+                _ => out.fail("Unexpected response from daemon"),
+            }
+
+            #[cfg(unix)]
+            {
+                out.note(format!("🔗 Taking over {}. Detach with Ctrl-]", session_id));
+                takeover::run(&client, &session_id, &log_path).await?;
+                out.note("\r\n👋 Detached");
+            }
+            #[cfg(not(unix))]
+            {
+                let _ = log_path;
+                out.fail("Takeover is only supported on Unix");
+            }
+        }
+        Commands::Attach { session_id, takeover: false, since_seq, timestamps, prefix } => {
+            let client = new_client()?;
+            require_daemon(out, &client, autostart).await;
+
+            let sessions = select_sessions(out, &client, Some(session_id), None).await?;
+            let session_id = sessions[0].id.clone();
+            let prefix = prefix.map(|template| logging::LinePrefix::new(template, prefix_fields(&sessions[0])));
+            stream_attach(out, &client, &session_id, since_seq, timestamps.stamper(), prefix).await;
+        }
     }
 
     Ok(())
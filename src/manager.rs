@@ -1,13 +1,175 @@
-use crate::persistence::{is_process_alive, PersistenceManager, PersistedSession};
-use crate::pty::{spawn_claude_pty, SessionProcess};
-use crate::session::{Session, SessionInfo};
+use crate::config::Config;
+use crate::logging::{is_log_file, log_segments, write_checkpoint, SessionLogger};
+use crate::persistence::{self, is_same_process, Persistence, PersistedSession};
+use crate::pty::{spawn_claude_pty, ClaudeBinary, InputWriter, SessionProcess};
+use crate::retention::{last_activity, RetentionPolicy, SweepReport};
+use crate::session::{default_label, LogNaming, Session, SessionInfo, SessionStatus};
+use crate::supervisor::{supervise, RestartPolicy, RunResult, SupervisorOutcome};
 use anyhow::{Context, Result};
-use std::collections::HashMap;
-use std::path::PathBuf;
+use portable_pty::ExitStatus;
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
-use tokio::sync::Mutex;
+use std::time::{Duration, SystemTime};
+use tokio::sync::{broadcast, watch, Mutex};
 use uuid::Uuid;
 
+/// How long a stopped session's Claude gets to exit after SIGTERM before
+/// it is sent SIGKILL
+pub const STOP_GRACE: Duration = Duration::from_secs(5);
+
+/// What daemon shutdown does with the processes of running sessions
+/// (see `SessionManager::shutdown_all`)
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ShutdownMode {
+    /// Don't signal them. They still lose their terminal when the daemon
+    /// exits (the PTY master closes and they get SIGHUP); those that
+    /// outlive it are recovered as orphaned, the rest as stale.
+    #[default]
+    Leave,
+    /// SIGTERM them, then SIGKILL after `STOP_GRACE`; they are recovered
+    /// as stale
+    Kill,
+}
+
+/// How often the daemon saves running sessions' last activity
+pub const ACTIVITY_SAVE_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Running sessions allowed at once unless configured otherwise
+pub const DEFAULT_MAX_SESSIONS: usize = 32;
+
+/// Hang detection threshold: `CLAUDE_SESSIONS_HANG_SECS`. Off unless set
+/// to a positive number of seconds.
+pub fn hang_threshold_from_env() -> Option<Duration> {
+    std::env::var("CLAUDE_SESSIONS_HANG_SECS")
+        .ok()
+        .and_then(|v| v.trim().parse().ok())
+        .filter(|&secs| secs > 0)
+        .map(Duration::from_secs)
+}
+
+/// Options for starting a session
+#[derive(Debug, Clone, Default)]
+pub struct StartOptions {
+    /// Restart Claude automatically when it exits unexpectedly, subject to
+    /// the `RestartPolicy` and its crash-loop breaker
+    pub restart_on_crash: bool,
+    /// Write this session's log here instead of `~/.claude-sessions/logs`
+    pub log_dir: Option<PathBuf>,
+    /// How to name the log file
+    pub log_naming: LogNaming,
+    /// Extra environment variables for Claude, on top of what
+    /// `EnvPolicy` lets through from the daemon
+    pub env: Vec<(String, String)>,
+    /// Extra arguments for the Claude binary (see `ClaudeBinary`)
+    pub claude_args: Vec<String>,
+    /// Label for the session instead of the directory's basename
+    pub name: Option<String>,
+    /// `key=value` tags for the session
+    pub tags: BTreeMap<String, String>,
+    /// Start even if another session is already running in the directory
+    pub allow_duplicate: bool,
+}
+
+/// What `prune_sessions` removes
+#[derive(Debug, Clone)]
+pub struct PruneOptions {
+    /// Only sessions whose last activity is older than this
+    pub older_than: Duration,
+    /// Report what would be removed without removing it
+    pub dry_run: bool,
+    /// Forget the sessions but leave their log files
+    pub keep_logs: bool,
+}
+
+/// Failures callers may want to tell apart from internal errors.
+///
+/// Returned inside `anyhow::Error`; find them with `downcast_ref` (the
+/// daemon maps them to `ipc::ErrorCode`s).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SessionError {
+    /// No session with this ID
+    NotFound(Uuid),
+    /// No running process for this ID (unknown, or not running)
+    NotRunning(Uuid),
+    /// The request's arguments were rejected
+    InvalidArgument(String),
+    /// Starting another session would exceed the running-session limit
+    LimitReached { max: usize },
+    /// A session's working directory can't be used
+    InvalidWorkingDir { path: PathBuf, problem: WorkingDirProblem },
+    /// Another session is already running in the working directory
+    DuplicateWorkingDir { session_id: Uuid, path: PathBuf },
+}
+
+/// Why a working directory was rejected (see `resolve_working_dir`)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WorkingDirProblem {
+    NotFound,
+    NotADirectory,
+    PermissionDenied,
+}
+
+impl std::fmt::Display for SessionError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SessionError::NotFound(id) => write!(f, "Session not found: {}", id),
+            SessionError::NotRunning(id) => write!(f, "Session not found or not active (no PTY handle): {}", id),
+            SessionError::InvalidArgument(message) => f.write_str(message),
+            SessionError::LimitReached { max } => {
+                write!(f, "Too many running sessions (limit {}); stop one first", max)
+            }
+            SessionError::InvalidWorkingDir { path, problem } => match problem {
+                WorkingDirProblem::NotFound => write!(f, "Working directory does not exist: {:?}", path),
+                WorkingDirProblem::NotADirectory => write!(f, "Working directory is not a directory: {:?}", path),
+                WorkingDirProblem::PermissionDenied => {
+                    write!(f, "Permission denied for working directory: {:?}", path)
+                }
+            },
+            SessionError::DuplicateWorkingDir { session_id, path } => write!(
+                f,
+                "Session {} is already running in {:?}; pass --allow-duplicate to start another",
+                session_id, path
+            ),
+        }
+    }
+}
+
+impl std::error::Error for SessionError {}
+
+/// How many lifecycle events a slow subscriber may fall behind by before
+/// it starts missing them
+const EVENT_CAPACITY: usize = 256;
+
+/// A change in a session's lifecycle, broadcast to `subscribe`rs
+#[derive(Debug, Clone, PartialEq)]
+pub enum SessionEvent {
+    /// A session was started (restarts start a new session)
+    Started { session_id: Uuid, log_path: PathBuf },
+    /// A session was stopped on request and forgotten
+    Stopped { session_id: Uuid },
+    /// Claude exited on its own, or the supervisor gave up restarting it
+    /// (`CrashLooping`, no exit code)
+    Exited { session_id: Uuid, status: SessionStatus, exit_code: Option<u32> },
+}
+
+/// What `stop_all_sessions` did, in session ID order
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct StopAllReport {
+    pub stopped: Vec<Uuid>,
+    /// Sessions that couldn't be stopped, with why
+    pub failed: Vec<(Uuid, String)>,
+}
+
+/// What an attaching client needs to know up front
+#[derive(Debug, Clone)]
+pub struct AttachInfo {
+    pub pty_rows: u16,
+    pub pty_cols: u16,
+    pub scrollback_available: bool,
+}
+
 /// SessionManager owns all active Claude Code sessions.
 ///
 /// Responsibilities:
@@ -28,36 +190,121 @@ use uuid::Uuid;
 /// 1. Load persisted sessions from disk
 /// 2. For each session:
 ///    - If PID is unknown → mark as "stale"
-///    - If PID is known but process is dead → mark as "crashed"
+///    - If PID is known but process is dead (or the PID now belongs to a
+///      process with another start time) → mark as "crashed"
 ///    - If PID is known and process is alive → mark as "orphaned"
-/// 3. Do NOT attempt to reattach to orphaned processes
+/// 3. Do NOT attempt to reattach to orphaned processes, unless the daemon
+///    runs with `--reattach` (see `crate::reattach`)
 ///
 /// Conservative approach: we don't try to reconnect to existing PTYs.
 /// User must manually check orphaned sessions and stop them if needed.
+///
+/// Reattaching needs help from the previous daemon: the PTY master is
+/// only ever open in the daemon, so the kernel closes it (and hangs up the
+/// terminal) when the daemon exits. Nothing is left to reopen: the
+/// orphan's own descriptors are the slave side, and opening `/dev/ptmx`
+/// again creates a new terminal. So with `--reattach` the exiting daemon
+/// hands the masters to a keeper process (`crate::reattach`), and the
+/// next one takes them back.
 pub struct SessionManager {
     sessions: Arc<Mutex<HashMap<Uuid, Session>>>,
     processes: Arc<Mutex<HashMap<Uuid, SessionProcess>>>,
-    persistence: Arc<Mutex<PersistenceManager>>,
+    persistence: Arc<Mutex<Box<dyn Persistence>>>,
+    /// Set by `shutdown_all` so supervisors don't restart what it stops
+    shutting_down: Arc<AtomicBool>,
+    /// Lifecycle events; sending fails harmlessly with no subscribers
+    events: broadcast::Sender<SessionEvent>,
+    /// Most sessions with a live process at once (`None`: unlimited)
+    max_sessions: Option<usize>,
+    /// The program sessions run
+    claude: ClaudeBinary,
+    /// Size at which session logs are rotated (`None`: never)
+    max_log_bytes: Option<u64>,
+    /// Report running sessions silent this long after input as
+    /// unresponsive (`None`: hang detection off)
+    hang_threshold: Option<Duration>,
+    /// Held by `start_session` from the limit check until the new process
+    /// is registered, so concurrent starts can't overshoot the limit
+    start_lock: Arc<Mutex<()>>,
 }
 
 impl SessionManager {
     pub fn new() -> Self {
-        let persistence = PersistenceManager::new()
+        let persistence = persistence::from_env()
             .expect("Failed to initialize persistence manager");
+        Self::with_backend(persistence)
+    }
+
+    /// Create a session manager backed by a specific persistence backend
+    /// (for tests)
+    #[cfg(test)]
+    pub fn with_persistence<P: Persistence + 'static>(persistence: P) -> Self {
+        Self::with_backend(Box::new(persistence))
+    }
 
+    /// Create a session manager backed by `persistence` and recover the
+    /// sessions saved in it, as the daemon does on startup; also returns
+    /// the status each was recovered with (for tests)
+    #[cfg(test)]
+    pub async fn recovered_from<P: Persistence + 'static>(persistence: P) -> (Self, HashMap<Uuid, SessionStatus>) {
+        let manager = Self::with_persistence(persistence);
+        let recovered = manager.recover_sessions().await.unwrap();
+        (manager, recovered)
+    }
+
+    fn with_backend(persistence: Box<dyn Persistence>) -> Self {
+        let config = Config::from_env();
         SessionManager {
             sessions: Arc::new(Mutex::new(HashMap::new())),
             processes: Arc::new(Mutex::new(HashMap::new())),
             persistence: Arc::new(Mutex::new(persistence)),
+            shutting_down: Arc::new(AtomicBool::new(false)),
+            events: broadcast::channel(EVENT_CAPACITY).0,
+            max_sessions: config.max_sessions(),
+            claude: config.claude_binary(),
+            max_log_bytes: config.max_log_bytes(),
+            hang_threshold: hang_threshold_from_env(),
+            start_lock: Arc::new(Mutex::new(())),
         }
     }
 
-    /// Create a new session manager and recover persisted sessions
+    /// Take the session limit, Claude binary and log rotation size from
+    /// `config`
+    pub fn with_config(self, config: &Config) -> Self {
+        let mut manager = self.with_max_sessions(config.max_sessions());
+        manager.claude = config.claude_binary();
+        manager.max_log_bytes = config.max_log_bytes();
+        manager
+    }
+
+    /// Allow at most `max` running sessions (`None`: unlimited)
+    pub fn with_max_sessions(mut self, max: Option<usize>) -> Self {
+        self.max_sessions = max;
+        self
+    }
+
+    /// Report sessions that produce no output for `threshold` after input
+    /// as `Unresponsive` (`None`: never). For tests; the daemon takes it
+    /// from `CLAUDE_SESSIONS_HANG_SECS`
+    #[cfg(test)]
+    pub fn with_hang_threshold(mut self, threshold: Option<Duration>) -> Self {
+        self.hang_threshold = threshold;
+        self
+    }
+
+    /// Receive every lifecycle event from now on, until the receiver is
+    /// dropped
+    pub fn subscribe(&self) -> broadcast::Receiver<SessionEvent> {
+        self.events.subscribe()
+    }
+
+    /// Create a new session manager configured by `config` and recover
+    /// persisted sessions
     /// 
     /// This should be called when starting the daemon.
     /// Use `new()` for testing without recovery.
-    pub async fn with_recovery() -> Self {
-        let manager = Self::new();
+    pub async fn with_recovery(config: &Config) -> Self {
+        let manager = Self::new().with_config(config);
         
         // Attempt to recover sessions from disk
         if let Err(e) = manager.recover_sessions().await {
@@ -74,7 +321,8 @@ impl SessionManager {
     ///
     /// For each persisted session:
     /// 1. If no PID recorded → status = "stale" (unknown state)
-    /// 2. If PID recorded but process dead → status = "crashed"
+    /// 2. If PID recorded but process dead → status = "crashed", or
+    ///    "stale" if the daemon left it running when it shut down
     /// 3. If PID recorded and process alive → status = "orphaned"
     ///
     /// ## Why "orphaned"?
@@ -96,14 +344,17 @@ impl SessionManager {
     ///
     /// Rationale: Reconnecting to processes is fragile and error-prone.
     /// Better to be explicit about what we don't know.
-    async fn recover_sessions(&self) -> Result<()> {
+    ///
+    /// Returns the status each session was recovered with.
+    async fn recover_sessions(&self) -> Result<HashMap<Uuid, SessionStatus>> {
         let persistence = self.persistence.lock().await;
         let persisted = persistence.load_state()?;
         drop(persistence); // Release lock early
 
+        let mut recovered = HashMap::new();
         if persisted.is_empty() {
             println!("No sessions to recover");
-            return Ok(());
+            return Ok(recovered);
         }
 
         println!("Attempting to recover {} session(s)...", persisted.len());
@@ -115,31 +366,53 @@ impl SessionManager {
         let mut orphaned_count = 0;
 
         for (id, mut persisted_session) in persisted {
-            // Determine current status
-            let status = if let Some(pid) = persisted_session.pid {
-                if is_process_alive(pid) {
+            // Determine current status; an exit seen by the last daemon stands
+            let status = if let Some(code) = persisted_session.exit_code {
+                SessionStatus::from_exit_code(code)
+            } else if let Some(pid) = persisted_session.pid {
+                // A live PID that started at another time was reused
+                if is_same_process(pid, persisted_session.process_start_time) {
                     orphaned_count += 1;
-                    "orphaned"
+                    SessionStatus::Orphaned
+                } else if persisted_session.status == SessionStatus::Stale {
+                    // Left running at shutdown; it went with the daemon's terminal
+                    stale_count += 1;
+                    SessionStatus::Stale
                 } else {
                     crashed_count += 1;
-                    "crashed"
+                    SessionStatus::Crashed
                 }
             } else {
                 stale_count += 1;
-                "stale"
+                SessionStatus::Stale
             };
 
-            persisted_session.status = status.to_string();
+            persisted_session.status = status.clone();
 
             // Reconstruct Session from PersistedSession
+            let label = match persisted_session.label.as_str() {
+                "" => default_label(&persisted_session.working_dir),
+                label => label.to_string(),
+            };
             let session = Session {
                 id: persisted_session.id,
+                label,
+                tags: persisted_session.tags.clone(),
                 working_dir: persisted_session.working_dir.clone(),
                 created_at: persisted_session.created_at.clone(),
                 log_path: persisted_session.log_path.clone(),
+                crash_loop_error: None,
+                exit_code: persisted_session.exit_code,
+                options: StartOptions::default(),
+                orphan_pid: persisted_session.pid.filter(|_| status == SessionStatus::Orphaned),
+                process_start_time: persisted_session.process_start_time.filter(|_| status == SessionStatus::Orphaned),
+                last_activity: persisted_session.last_activity.clone(),
+                stopping: false,
+                stop_error: None,
             };
 
             sessions.insert(id, session);
+            recovered.insert(id, status.clone());
             recovered_count += 1;
 
             println!(
@@ -164,35 +437,36 @@ impl SessionManager {
             println!("   • {} orphaned (process alive but not managed)", orphaned_count);
         }
 
-        Ok(())
+        Ok(recovered)
     }
 
     /// Save current session state to disk
     ///
-    /// Called after:
-    /// - Starting a session
-    /// - Stopping a session
-    /// - Recovering sessions
+    /// Called after recovering sessions, flushing and retention sweeps.
+    /// Starting and stopping a session only touch that session's record.
     ///
     /// ## Error Handling
     ///
     /// If save fails, logs error but does not crash daemon.
     /// Session continues to exist in memory, but won't survive restart.
     async fn save_state(&self) -> Result<()> {
-        let sessions = self.sessions.lock().await;
+        let mut sessions = self.sessions.lock().await;
         let processes = self.processes.lock().await;
+        sync_activity(&mut sessions, &processes);
 
         let mut persisted = HashMap::new();
 
         for (id, session) in sessions.iter() {
-            // Get PID if process is active
-            let pid = processes.get(id).and_then(|_| {
-                // TODO: Extract PID from SessionProcess
-                // For now, we don't track PID (would require PTY changes)
-                None
-            });
+            // Get PID if process is active, or the one an orphan left running
+            // (so the next daemon still finds it, or reattaches it)
+            let pid = processes.get(id).and_then(SessionProcess::pid).or(session.orphan_pid);
 
-            let persisted_session = PersistedSession::from_session(session, pid);
+            let mut persisted_session = PersistedSession::from_session(session, pid);
+            // Still running during shutdown: left running on purpose, so
+            // dying with the daemon isn't a crash (see `recover_sessions`)
+            if self.shutting_down.load(Ordering::SeqCst) && processes.contains_key(id) {
+                persisted_session.status = SessionStatus::Stale;
+            }
             persisted.insert(*id, persisted_session);
         }
 
@@ -205,29 +479,87 @@ impl SessionManager {
         Ok(())
     }
 
+    /// Save the last activity of running sessions that have had input or
+    /// output since it was last saved; returns how many were saved.
+    ///
+    /// Activity is only tracked in memory as it happens (see
+    /// `SessionProcess::last_activity`), so the daemon calls this
+    /// periodically rather than writing state on every byte.
+    pub async fn save_activity(&self) -> Result<usize> {
+        let changed: Vec<PersistedSession> = {
+            let mut sessions = self.sessions.lock().await;
+            let processes = self.processes.lock().await;
+            sync_activity(&mut sessions, &processes)
+                .into_iter()
+                .filter_map(|id| {
+                    let pid = processes.get(&id).and_then(SessionProcess::pid);
+                    Some(PersistedSession::from_session(sessions.get(&id)?, pid))
+                })
+                .collect()
+        };
+
+        let persistence = self.persistence.lock().await;
+        for persisted in &changed {
+            persistence.upsert(persisted)?;
+        }
+        Ok(changed.len())
+    }
+
     /// Start a new Claude Code session in the given working directory.
     ///
-    /// Returns the session ID on success.
+    /// Returns the new session's info on success.
     ///
     /// ## Persistence
     ///
     /// Session is saved to disk after successful start.
     /// If save fails, logs error but session remains active.
-    pub async fn start_session(&self, working_dir: PathBuf) -> Result<Uuid> {
-        // Validate that the directory exists
-        if !working_dir.exists() {
-            anyhow::bail!("Working directory does not exist: {:?}", working_dir);
+    pub async fn start_session(&self, working_dir: PathBuf, options: StartOptions) -> Result<SessionInfo> {
+        // Stored resolved, so every spelling of a directory is the same one
+        let working_dir = resolve_working_dir(&working_dir)?;
+        let invalid = |e: anyhow::Error| SessionError::InvalidArgument(format!("{:#}", e));
+        crate::paths::require_utf8(&working_dir).map_err(invalid)?;
+        if let Some(log_dir) = &options.log_dir {
+            crate::paths::require_utf8(log_dir).map_err(invalid)?;
+        }
+
+        if let Some(log_dir) = &options.log_dir {
+            SessionLogger::prepare_log_dir(log_dir)?;
+        }
+
+        // Only sessions with a live process count: stale, crashed and
+        // orphaned ones don't
+        let starting = self.start_lock.lock().await;
+        if let Some(max) = self.max_sessions {
+            if self.processes.lock().await.len() >= max {
+                return Err(SessionError::LimitReached { max }.into());
+            }
+        }
+        if !options.allow_duplicate {
+            if let Some(session_id) = self.running_in(&working_dir).await {
+                return Err(SessionError::DuplicateWorkingDir { session_id, path: working_dir }.into());
+            }
         }
 
         // Create session metadata
-        let session = Session::new(working_dir.clone());
+        let mut session =
+            Session::with_log_options(working_dir.clone(), options.log_dir.as_deref(), options.log_naming);
+        if let Some(name) = &options.name {
+            session.label = validate_label(name)?;
+        }
+        session.tags = validate_tags(&options.tags)?;
+        session.options = options.clone();
         let session_id = session.id;
+        let log_path = session.log_path.clone();
 
         // Spawn Claude as a PTY subprocess
-        let pty_pair = spawn_claude_pty(&working_dir)
-            .context("Failed to spawn Claude Code PTY")?;
-        let process = SessionProcess::new(session_id, pty_pair)
-            .context("Failed to create session process with logging")?;
+        let claude = self.claude.clone().with_args(options.claude_args.clone());
+        let process =
+            spawn_session_process(session_id, &working_dir, &log_path, &claude, &options.env, self.max_log_bytes)?;
+        let exit_watch = process.exit_watch();
+        let status_watch = process.status_watch();
+
+        let info = session_info(&session, SessionStatus::Running);
+        let persisted = PersistedSession::from_session(&session, process.pid());
 
         // Store session and process
         {
@@ -238,84 +570,823 @@ impl SessionManager {
             let mut processes = self.processes.lock().await;
             processes.insert(session_id, process);
         }
+        drop(starting);
 
-        // Save to disk
-        if let Err(e) = self.save_state().await {
+        // Save to disk (only this session's record changes)
+        if let Err(e) = self.persistence.lock().await.upsert(&persisted) {
             eprintln!("⚠️  Failed to save session state: {}", e);
             eprintln!("Session will be lost on daemon restart");
         }
 
+        if options.restart_on_crash {
+            self.spawn_supervisor(session_id, working_dir.clone(), log_path, claude, options.env.clone(), exit_watch);
+        } else {
+            self.track_exit(session_id, status_watch);
+        }
+
         println!("✅ Started session {} in {:?}", session_id, working_dir);
-        Ok(session_id)
+        let _ = self.events.send(SessionEvent::Started { session_id, log_path: PathBuf::from(&info.log_path) });
+        Ok(info)
+    }
+
+    /// A session with a live process in `working_dir` (already resolved);
+    /// exited, crashed and stale sessions don't count
+    async fn running_in(&self, working_dir: &Path) -> Option<Uuid> {
+        let running: Vec<Uuid> = self.processes.lock().await.keys().copied().collect();
+        let sessions = self.sessions.lock().await;
+        running.into_iter().find(|id| {
+            sessions.get(id).is_some_and(|session| {
+                // Sessions recorded before directories were resolved
+                session.working_dir == working_dir
+                    || session.working_dir.canonicalize().is_ok_and(|dir| dir == working_dir)
+            })
+        })
+    }
+
+    /// When Claude exits on its own, record its exit code on the session
+    /// and let go of the dead process, so the session lists as "exited"
+    /// (code 0) or "crashed" rather than "running".
+    ///
+    /// Exits caused by `stop_session` or `shutdown_all` aren't recorded.
+    pub fn track_exit(&self, session_id: Uuid, mut status: watch::Receiver<Option<ExitStatus>>) {
+        let sessions = Arc::clone(&self.sessions);
+        let processes = Arc::clone(&self.processes);
+        let persistence = Arc::clone(&self.persistence);
+        let shutting_down = Arc::clone(&self.shutting_down);
+        let events = self.events.clone();
+
+        tokio::spawn(async move {
+            let code = match status.wait_for(Option::is_some).await {
+                Ok(status) => status.as_ref().map_or(1, ExitStatus::exit_code),
+                Err(_) => return,
+            };
+            if shutting_down.load(Ordering::SeqCst) {
+                return;
+            }
+
+            let persisted = {
+                let mut sessions = sessions.lock().await;
+                sync_activity(&mut sessions, &*processes.lock().await);
+                // Gone or going: stopped on purpose
+                let Some(session) = sessions.get_mut(&session_id).filter(|s| !s.stopping) else {
+                    return;
+                };
+                session.exit_code = Some(code);
+                PersistedSession::from_session(session, None)
+            };
+            if let Err(e) = persistence.lock().await.upsert(&persisted) {
+                eprintln!("⚠️  Failed to save session state: {}", e);
+            }
+            processes.lock().await.remove(&session_id);
+            println!("🏁 Session {} {} (code {})", session_id, persisted.status, code);
+            let _ = events.send(SessionEvent::Exited {
+                session_id,
+                status: persisted.status,
+                exit_code: Some(code),
+            });
+        });
+    }
+
+    /// Restart a session whenever Claude exits while the session still exists.
+    ///
+    /// Until exit codes are tracked, every exit that wasn't caused by
+    /// `stop_session` counts as a crash. If the crash-loop breaker opens or
+    /// the restart budget runs out, the session is left without a process
+    /// and marked "crash-looping" with the last error; the user has to stop
+    /// and start it again.
+    fn spawn_supervisor(
+        &self,
+        session_id: Uuid,
+        working_dir: PathBuf,
+        log_path: PathBuf,
+        claude: ClaudeBinary,
+        env: Vec<(String, String)>,
+        first_exit: tokio::sync::watch::Receiver<bool>,
+    ) {
+        let sessions = Arc::clone(&self.sessions);
+        let processes = Arc::clone(&self.processes);
+        let persistence = Arc::clone(&self.persistence);
+        let shutting_down = Arc::clone(&self.shutting_down);
+        let events = self.events.clone();
+        let max_log_bytes = self.max_log_bytes;
+
+        tokio::spawn(async move {
+            let policy = RestartPolicy::from_env();
+            let mut next_exit = Some(first_exit);
+
+            let outcome = supervise(&policy, || {
+                let sessions = Arc::clone(&sessions);
+                let processes = Arc::clone(&processes);
+                let persistence = Arc::clone(&persistence);
+                let shutting_down = Arc::clone(&shutting_down);
+                let working_dir = working_dir.clone();
+                let log_path = log_path.clone();
+                let claude = claude.clone();
+                let env = env.clone();
+                let current = next_exit.take();
+
+                async move {
+                    let mut exited = match current {
+                        Some(exited) => exited,
+                        None => {
+                            // Session may have been stopped while we backed off
+                            if shutting_down.load(Ordering::SeqCst)
+                                || sessions.lock().await.get(&session_id).is_none_or(|s| s.stopping)
+                            {
+                                return RunResult::Stopped;
+                            }
+                            let spawned = spawn_session_process(
+                                session_id,
+                                &working_dir,
+                                &log_path,
+                                &claude,
+                                &env,
+                                max_log_bytes,
+                            );
+                            match spawned {
+                                Ok(process) => {
+                                    let exited = process.exit_watch();
+                                    // Keep the persisted PID pointing at the live process
+                                    let persisted = sessions
+                                        .lock()
+                                        .await
+                                        .get(&session_id)
+                                        .map(|session| PersistedSession::from_session(session, process.pid()));
+                                    processes.lock().await.insert(session_id, process);
+                                    if let Some(persisted) = persisted {
+                                        if let Err(e) = persistence.lock().await.upsert(&persisted) {
+                                            eprintln!("⚠️  Failed to save session state: {}", e);
+                                        }
+                                    }
+                                    println!("🔁 Restarted session {}", session_id);
+                                    exited
+                                }
+                                Err(e) => return RunResult::Crashed(format!("{:#}", e)),
+                            }
+                        }
+                    };
+
+                    let _ = exited.wait_for(|&done| done).await;
+
+                    // stop_session marks the session before terminating its process;
+                    // shutdown_all flags the shutdown first
+                    if shutting_down.load(Ordering::SeqCst) {
+                        return RunResult::Stopped;
+                    }
+                    let mut sessions = sessions.lock().await;
+                    if sessions.get(&session_id).is_some_and(|s| !s.stopping) {
+                        // Keep the dead process's activity before a restart replaces it
+                        sync_activity(&mut sessions, &*processes.lock().await);
+                        RunResult::Crashed("Claude exited unexpectedly".to_string())
+                    } else {
+                        RunResult::Stopped
+                    }
+                }
+            })
+            .await;
+
+            let last_error = match outcome {
+                SupervisorOutcome::Stopped => return,
+                SupervisorOutcome::RestartLimit { restarts, last_error } => {
+                    eprintln!(
+                        "⚠️  Session {} gave up after {} restart(s): {}",
+                        session_id, restarts, last_error
+                    );
+                    last_error
+                }
+                SupervisorOutcome::CrashLooping { crashes, last_error } => {
+                    eprintln!(
+                        "⚠️  Session {} is crash-looping ({} crashes), not restarting: {}",
+                        session_id, crashes, last_error
+                    );
+                    last_error
+                }
+            };
+
+            processes.lock().await.remove(&session_id);
+            if let Some(session) = sessions.lock().await.get_mut(&session_id) {
+                session.crash_loop_error = Some(last_error);
+            }
+            let _ = events.send(SessionEvent::Exited {
+                session_id,
+                status: SessionStatus::CrashLooping,
+                exit_code: None,
+            });
+        });
     }
 
     /// Stop a running session by ID.
     ///
-    /// This removes the session metadata and drops the PTY process,
-    /// which should terminate the Claude subprocess.
+    /// This removes the session metadata, terminates Claude (SIGTERM, then
+    /// SIGKILL after `STOP_GRACE`) and closes the PTY once the output reader
+    /// has finished. Returns the stopped session's info (status "stopped").
     ///
     /// ## Persistence
     ///
     /// Session is removed from disk after successful stop.
-    pub async fn stop_session(&self, session_id: Uuid) -> Result<()> {
+    pub async fn stop_session(&self, session_id: Uuid) -> Result<SessionInfo> {
+        self.stop_session_with(session_id, None).await
+    }
+
+    /// Stop a session, giving Claude `force_after` (default `STOP_GRACE`)
+    /// between SIGTERM and SIGKILL.
+    ///
+    /// The session is only forgotten once its process is gone. If it can't
+    /// be terminated, the session stays listed with the error and can be
+    /// stopped again.
+    pub async fn stop_session_with(&self, session_id: Uuid, force_after: Option<Duration>) -> Result<SessionInfo> {
         {
             let mut sessions = self.sessions.lock().await;
-            if !sessions.contains_key(&session_id) {
-                anyhow::bail!("Session not found: {}", session_id);
+            let session = sessions.get_mut(&session_id).ok_or(SessionError::NotFound(session_id))?;
+            if session.stopping {
+                return Err(SessionError::InvalidArgument(format!("Session {} is already being stopped", session_id)).into());
             }
-            sessions.remove(&session_id);
+            session.stopping = true;
+            session.stop_error = None;
         }
-        {
-            let mut processes = self.processes.lock().await;
-            processes.remove(&session_id);
-            // Dropping the PTY should terminate the child process
+        let process = self.processes.lock().await.remove(&session_id);
+        let grace = force_after.unwrap_or(STOP_GRACE);
+        if let Some(process) = process {
+            match process.terminate(grace).await {
+                Ok(killed) => {
+                    if killed {
+                        println!("💀 Killed session {} after {:?} grace period", session_id, grace);
+                    }
+                    process.close().await;
+                }
+                Err(e) => {
+                    // Still running: keep managing it
+                    self.processes.lock().await.insert(session_id, process);
+                    if let Some(session) = self.sessions.lock().await.get_mut(&session_id) {
+                        session.stopping = false;
+                        session.stop_error = Some(format!("{:#}", e));
+                    }
+                    return Err(e.context("Failed to stop session; it is kept"));
+                }
+            }
         }
 
-        // Save to disk
-        if let Err(e) = self.save_state().await {
+        let session = self
+            .sessions
+            .lock()
+            .await
+            .remove(&session_id)
+            .ok_or(SessionError::NotFound(session_id))?;
+        // Remove from disk
+        if let Err(e) = self.persistence.lock().await.remove(session_id) {
             eprintln!("⚠️  Failed to save session state: {}", e);
         }
 
+        let _ = self.events.send(SessionEvent::Stopped { session_id });
         println!("✅ Stopped session {}", session_id);
+        Ok(session_info(&session, SessionStatus::Stopped))
+    }
+
+    /// Stop every running session, one after the other, the same way as
+    /// `stop_session` (SIGTERM, then SIGKILL after `STOP_GRACE`).
+    ///
+    /// Orphaned sessions (whose Claude outlived the previous daemon) are
+    /// left alone unless `include_orphaned`; then their process is
+    /// signalled by PID the same way and the session forgotten. Stale,
+    /// exited and crashed sessions have nothing to stop and are kept.
+    pub async fn stop_all_sessions(&self, include_orphaned: bool) -> StopAllReport {
+        let mut running: Vec<Uuid> = self.processes.lock().await.keys().copied().collect();
+        running.sort();
+        let mut orphaned: Vec<(Uuid, u32, Option<u64>)> = if include_orphaned {
+            let sessions = self.sessions.lock().await;
+            let processes = self.processes.lock().await;
+            sessions
+                .values()
+                .filter(|s| !processes.contains_key(&s.id))
+                .filter(|s| s.orphan_alive())
+                .filter_map(|s| Some((s.id, s.orphan_pid?, s.process_start_time)))
+                .collect()
+        } else {
+            Vec::new()
+        };
+        orphaned.sort();
+
+        let mut report = StopAllReport::default();
+        for id in running {
+            match self.stop_session(id).await {
+                Ok(_) => report.stopped.push(id),
+                Err(e) => report.failed.push((id, format!("{:#}", e))),
+            }
+        }
+        for (id, pid, start_time) in orphaned {
+            match self.stop_orphan(id, pid, start_time).await {
+                Ok(()) => report.stopped.push(id),
+                Err(e) => report.failed.push((id, format!("{:#}", e))),
+            }
+        }
+        report
+    }
+
+    /// Terminate an orphaned session's process by PID and forget the session
+    async fn stop_orphan(&self, session_id: Uuid, pid: u32, start_time: Option<u64>) -> Result<()> {
+        if terminate_pid(pid, start_time, STOP_GRACE).await? {
+            println!("💀 Killed orphaned session {} after {:?} grace period", session_id, STOP_GRACE);
+        }
+        self.sessions.lock().await.remove(&session_id);
+        if let Err(e) = self.persistence.lock().await.remove(session_id) {
+            eprintln!("⚠️  Failed to save session state: {}", e);
+        }
+        println!("✅ Stopped orphaned session {}", session_id);
+        let _ = self.events.send(SessionEvent::Stopped { session_id });
         Ok(())
     }
 
+    /// Stop a session and start a fresh Claude in its directory, with the
+    /// options it was started with.
+    ///
+    /// The new process is a new session: it gets a new ID and a new log
+    /// file next to the old one, which stays on disk. (Reusing the ID would
+    /// let the old session's exit tracking mistake the new process for the
+    /// one that exited.) Returns the new session's info.
+    pub async fn restart_session(&self, session_id: Uuid) -> Result<SessionInfo> {
+        let (working_dir, log_path, mut options) = {
+            let sessions = self.sessions.lock().await;
+            let session = sessions
+                .get(&session_id)
+                .ok_or(SessionError::NotFound(session_id))?;
+            let mut options = session.options.clone();
+            options.name = Some(session.label.clone());
+            options.tags = session.tags.clone();
+            // The session is only replacing itself in its directory
+            options.allow_duplicate = true;
+            (session.working_dir.clone(), session.log_path.clone(), options)
+        };
+        // Recovered sessions don't know their log dir; keep the log beside the old one
+        if options.log_dir.is_none() {
+            let old_dir = log_path.parent().filter(|dir| *dir != Session::default_log_dir());
+            options.log_dir = old_dir.map(Path::to_path_buf);
+        }
+        // Check before the old session is gone
+        resolve_working_dir(&working_dir)?;
+
+        self.stop_session(session_id).await?;
+        self.start_session(working_dir, options)
+            .await
+            .with_context(|| format!("Stopped session {}, but couldn't start it again", session_id))
+    }
+
+    /// Change a session's label
+    pub async fn rename_session(&self, session_id: Uuid, name: &str) -> Result<()> {
+        let label = validate_label(name)?;
+        let persisted = {
+            let mut sessions = self.sessions.lock().await;
+            let session = sessions
+                .get_mut(&session_id)
+                .ok_or(SessionError::NotFound(session_id))?;
+            session.label = label;
+            let pid = self.processes.lock().await.get(&session_id).and_then(SessionProcess::pid);
+            PersistedSession::from_session(session, pid)
+        };
+        if let Err(e) = self.persistence.lock().await.upsert(&persisted) {
+            eprintln!("⚠️  Failed to save session state: {}", e);
+        }
+        Ok(())
+    }
+
+    /// Write a final checkpoint for a session before it is stopped.
+    ///
+    /// Appends a marker entry to the session log, fsyncs it, and exports a
+    /// transcript to `export_path` if given. The session itself is left
+    /// untouched; callers stop it afterwards.
+    pub async fn checkpoint_session(&self, session_id: Uuid, export_path: Option<&Path>) -> Result<()> {
+        let log_path = {
+            let sessions = self.sessions.lock().await;
+            sessions
+                .get(&session_id)
+                .map(|s| s.log_path.clone())
+                .ok_or(SessionError::NotFound(session_id))?
+        };
+
+        write_checkpoint(session_id, &log_path, export_path)
+            .context("Failed to write checkpoint")?;
+
+        println!("📌 Checkpointed session {}", session_id);
+        Ok(())
+    }
+
+    /// Make everything written so far durable.
+    ///
+    /// fsyncs the log of `session_id` (or of every session when `None`)
+    /// and synchronously rewrites and fsyncs the persisted state. Loggers
+    /// write through on every entry, so syncing the file is enough to
+    /// cover data still held by the output reader tasks.
+    ///
+    /// Returns the number of session logs synced.
+    pub async fn flush(&self, session_id: Option<Uuid>) -> Result<usize> {
+        let log_paths: Vec<PathBuf> = {
+            let sessions = self.sessions.lock().await;
+            match session_id {
+                Some(id) => vec![sessions
+                    .get(&id)
+                    .map(|s| s.log_path.clone())
+                    .ok_or(SessionError::NotFound(id))?],
+                None => sessions.values().map(|s| s.log_path.clone()).collect(),
+            }
+        };
+
+        let mut synced = 0;
+        for log_path in &log_paths {
+            // A session that hasn't produced output yet has no log file
+            if !log_path.exists() {
+                continue;
+            }
+            std::fs::File::open(log_path)
+                .and_then(|f| f.sync_data())
+                .with_context(|| format!("Failed to sync log {:?}", log_path))?;
+            synced += 1;
+        }
+
+        self.save_state().await?;
+        self.persistence.lock().await.sync_state()?;
+
+        Ok(synced)
+    }
+
+    /// Apply the log retention policy.
+    ///
+    /// Removes the logs of sessions that aren't running and whose last
+    /// activity is older than the retention window, plus any leftover
+    /// `*.jsonl` and `*.clog` logs in `log_dir` that no known session owns
+    /// (sessions that were stopped). With `prune_records`, expired sessions
+    /// are also forgotten. Running sessions are never touched.
+    pub async fn sweep_retention(
+        &self,
+        policy: &RetentionPolicy,
+        log_dir: &Path,
+        now: SystemTime,
+    ) -> Result<SweepReport> {
+        let mut report = SweepReport::default();
+        if !policy.is_enabled() {
+            return Ok(report);
+        }
+
+        let known_logs: Vec<PathBuf> = {
+            let mut sessions = self.sessions.lock().await;
+            let processes = self.processes.lock().await;
+
+            let expired: Vec<Uuid> = sessions
+                .values()
+                .filter(|s| !processes.contains_key(&s.id))
+                // Claude still running from before a daemon restart
+                .filter(|s| !s.orphan_alive())
+                .filter(|s| {
+                    last_activity(&s.log_path, &s.created_at)
+                        .is_some_and(|at| policy.is_expired(at, now))
+                })
+                .map(|s| s.id)
+                .collect();
+
+            for id in expired {
+                for log_path in log_segments(&sessions[&id].log_path) {
+                    if log_path.exists() {
+                        std::fs::remove_file(&log_path)
+                            .with_context(|| format!("Failed to remove log {:?}", log_path))?;
+                        report.logs_removed.push(log_path);
+                    }
+                }
+                if policy.prune_records {
+                    sessions.remove(&id);
+                    report.records_removed.push(id);
+                }
+            }
+
+            // Rotated segments belong to their session too
+            sessions.values().flat_map(|s| log_segments(&s.log_path)).collect()
+        };
+
+        if let Ok(entries) = std::fs::read_dir(log_dir) {
+            for entry in entries.flatten() {
+                let path = entry.path();
+                if !is_log_file(&path) || known_logs.contains(&path) {
+                    continue;
+                }
+                let expired = entry
+                    .metadata()
+                    .and_then(|m| m.modified())
+                    .is_ok_and(|at| policy.is_expired(at, now));
+                if expired {
+                    std::fs::remove_file(&path)
+                        .with_context(|| format!("Failed to remove log {:?}", path))?;
+                    report.logs_removed.push(path);
+                }
+            }
+        }
+
+        if !report.records_removed.is_empty() {
+            self.save_state().await?;
+        }
+        Ok(report)
+    }
+
+    /// Forget finished sessions last active more than `options.older_than`
+    /// ago, deleting their logs unless `options.keep_logs`.
+    ///
+    /// Finished means exited, crashed, crash-looping or stale: running
+    /// sessions and orphaned ones (whose Claude outlived the previous
+    /// daemon) are never touched. Stopped sessions are forgotten when
+    /// stopped, so their leftover logs in `log_dir` are pruned by age too.
+    /// With `options.dry_run` nothing is changed; the report says what
+    /// would be.
+    pub async fn prune_sessions(&self, options: &PruneOptions, log_dir: &Path, now: SystemTime) -> Result<SweepReport> {
+        let policy = |at: SystemTime| now.duration_since(at).is_ok_and(|age| age > options.older_than);
+        let mut report = SweepReport::default();
+
+        let known_logs: Vec<PathBuf> = {
+            let mut sessions = self.sessions.lock().await;
+            let processes = self.processes.lock().await;
+
+            let mut prunable: Vec<Uuid> = sessions
+                .values()
+                .filter(|s| !processes.contains_key(&s.id))
+                .filter(|s| !s.orphan_alive())
+                .filter(|s| last_activity(&s.log_path, &s.created_at).is_some_and(policy))
+                .map(|s| s.id)
+                .collect();
+            prunable.sort();
+
+            for id in prunable {
+                if !options.keep_logs {
+                    for log_path in log_segments(&sessions[&id].log_path) {
+                        if log_path.exists() {
+                            if !options.dry_run {
+                                std::fs::remove_file(&log_path)
+                                    .with_context(|| format!("Failed to remove log {:?}", log_path))?;
+                            }
+                            report.logs_removed.push(log_path);
+                        }
+                    }
+                }
+                if !options.dry_run {
+                    sessions.remove(&id);
+                    if let Err(e) = self.persistence.lock().await.remove(id) {
+                        eprintln!("⚠️  Failed to save session state: {}", e);
+                    }
+                }
+                report.records_removed.push(id);
+            }
+
+            sessions.values().flat_map(|s| log_segments(&s.log_path)).collect()
+        };
+
+        if !options.keep_logs {
+            let mut leftovers: Vec<PathBuf> = std::fs::read_dir(log_dir)
+                .into_iter()
+                .flatten()
+                .flatten()
+                .filter(|entry| entry.metadata().and_then(|m| m.modified()).is_ok_and(policy))
+                .map(|entry| entry.path())
+                .filter(|path| is_log_file(path))
+                .filter(|path| !known_logs.contains(path) && !report.logs_removed.contains(path))
+                .collect();
+            leftovers.sort();
+            for path in leftovers {
+                if !options.dry_run {
+                    std::fs::remove_file(&path).with_context(|| format!("Failed to remove log {:?}", path))?;
+                }
+                report.logs_removed.push(path);
+            }
+        }
+        Ok(report)
+    }
+
+    /// Wind the running sessions down for daemon shutdown; returns how
+    /// many there were.
+    ///
+    /// With `ShutdownMode::Kill` every process is stopped the way
+    /// `stop_session` does it, all at once, and its log synced once the
+    /// output reader has drained. With `Leave` the logs are synced and the
+    /// processes left alone. Either way the sessions stay persisted
+    /// (unlike `stop_session`) and the state is written and fsynced last,
+    /// so it is durable before the daemon exits.
+    pub async fn shutdown_all(&self, mode: ShutdownMode) -> usize {
+        self.shutting_down.store(true, Ordering::SeqCst);
+        let count = match mode {
+            ShutdownMode::Leave => {
+                let processes = self.processes.lock().await;
+                for process in processes.values() {
+                    if let Err(e) = process.sync_log() {
+                        eprintln!("⚠️  Failed to sync the log of session {}: {}", process.session_id(), e);
+                    }
+                }
+                processes.len()
+            }
+            ShutdownMode::Kill => {
+                let processes: Vec<SessionProcess> = self.processes.lock().await.drain().map(|(_, p)| p).collect();
+                let count = processes.len();
+                let mut stopping = tokio::task::JoinSet::new();
+                for process in processes {
+                    stopping.spawn(async move {
+                        if let Err(e) = process.terminate(STOP_GRACE).await {
+                            eprintln!("⚠️  Failed to stop session {}: {}", process.session_id(), e);
+                        }
+                        process.close().await;
+                    });
+                }
+                while stopping.join_next().await.is_some() {}
+                count
+            }
+        };
+
+        // Killed sessions have no PID left to save, so a deliberate
+        // shutdown isn't mistaken for a crash on recovery; left ones keep
+        // theirs, so any that outlive the daemon recover as orphaned, and
+        // are marked stale so the rest don't recover as crashed
+        let saved = match self.save_state().await {
+            Ok(()) => self.persistence.lock().await.sync_state(),
+            Err(e) => Err(e),
+        };
+        if let Err(e) = saved {
+            eprintln!("⚠️  Failed to save session state: {}", e);
+        }
+        count
+    }
+
+    /// How many sessions have a running process
+    pub async fn running_count(&self) -> usize {
+        self.processes.lock().await.len()
+    }
+
+    /// Write raw bytes to a running session, exactly as given
+    pub async fn send_raw(&self, session_id: Uuid, data: &[u8]) -> Result<()> {
+        let input = self.input_writer(session_id).await?;
+        input.write(data).await.context("Failed to write to PTY")
+    }
+
+    /// Where to write a running session's input. Taken out so a write that
+    /// waits for Claude to read doesn't hold `processes`.
+    async fn input_writer(&self, session_id: Uuid) -> Result<InputWriter> {
+        let processes = self.processes.lock().await;
+        let process = processes
+            .get(&session_id)
+            .ok_or(SessionError::NotRunning(session_id))?;
+        Ok(process.input_writer())
+    }
+
+    /// Resize a running session's PTY
+    pub async fn resize(&self, session_id: Uuid, rows: u16, cols: u16) -> Result<()> {
+        if rows == 0 || cols == 0 {
+            return Err(SessionError::InvalidArgument(format!(
+                "Rows and columns must be non-zero (got {}x{})",
+                rows, cols
+            ))
+            .into());
+        }
+        let processes = self.processes.lock().await;
+        let process = processes
+            .get(&session_id)
+            .ok_or(SessionError::NotRunning(session_id))?;
+        process.resize(rows, cols)
+    }
+
+    /// Describe a running session for an attaching client
+    pub async fn attach_info(&self, session_id: Uuid) -> Result<AttachInfo> {
+        let processes = self.processes.lock().await;
+        let process = processes
+            .get(&session_id)
+            .ok_or(SessionError::NotRunning(session_id))?;
+
+        let size = process.pty_size()?;
+        let scrollback_available = std::fs::metadata(process.log_path())
+            .map(|m| m.len() > 0)
+            .unwrap_or(false);
+
+        Ok(AttachInfo {
+            pty_rows: size.rows,
+            pty_cols: size.cols,
+            scrollback_available,
+        })
+    }
+
+    /// Log file of a session
+    pub async fn log_path(&self, session_id: Uuid) -> Result<PathBuf> {
+        let sessions = self.sessions.lock().await;
+        sessions
+            .get(&session_id)
+            .map(|s| s.log_path.clone())
+            .ok_or_else(|| SessionError::NotFound(session_id).into())
+    }
+
+    /// The running sessions' terminals as (session, PID, PTY master), for
+    /// handing to a keeper after a `Leave` shutdown (see `crate::reattach`).
+    /// The descriptors stay open as long as the processes are held here,
+    /// and dropping the processes no longer ends the sessions' input.
+    #[cfg(unix)]
+    pub async fn release_terminals(&self) -> Vec<(Uuid, u32, std::os::fd::RawFd)> {
+        self.processes
+            .lock()
+            .await
+            .values()
+            .filter_map(|process| {
+                let terminal = (process.session_id(), process.pid()?, process.master_fd()?);
+                process.keep_input_open();
+                Some(terminal)
+            })
+            .collect()
+    }
+
+    /// Give sessions recovered as orphaned their terminals back (see
+    /// `crate::reattach`); returns how many were reattached.
+    ///
+    /// Each gets a `SessionProcess` again, so it takes input, logs output
+    /// and has its exit recorded like a session started by this daemon.
+    /// A terminal whose session isn't orphaned, or whose process fails the
+    /// PID and start time check, is closed and the session stays as it was.
+    #[cfg(unix)]
+    pub async fn reattach(&self, terminals: Vec<(Uuid, std::os::fd::OwnedFd)>) -> usize {
+        let persisted = match self.persistence.lock().await.load_state() {
+            Ok(persisted) => persisted,
+            Err(e) => {
+                eprintln!("⚠️  Not reattaching any session: failed to load the session state: {}", e);
+                return 0;
+            }
+        };
+
+        let mut reattached = 0;
+        for (session_id, master) in terminals {
+            let mut sessions = self.sessions.lock().await;
+            let orphan = sessions.get_mut(&session_id).and_then(|session| {
+                let pid = session.orphan_pid?;
+                let start_time = persisted.get(&session_id).filter(|record| record.pid == Some(pid))?.process_start_time;
+                Some((session, pid, start_time))
+            });
+            let Some((session, pid, start_time)) = orphan else {
+                eprintln!("⚠️  Not reattaching session {}: it isn't orphaned", session_id);
+                continue;
+            };
+
+            match crate::reattach::adopt(session_id, master, pid, start_time, session.log_path.clone(), self.max_log_bytes) {
+                Ok(process) => {
+                    session.orphan_pid = None;
+                    session.process_start_time = None;
+                    let record = PersistedSession::from_session(session, Some(pid));
+                    let status_watch = process.status_watch();
+                    drop(sessions);
+                    self.processes.lock().await.insert(session_id, process);
+                    if let Err(e) = self.persistence.lock().await.upsert(&record) {
+                        eprintln!("⚠️  Failed to save session state: {}", e);
+                    }
+                    self.track_exit(session_id, status_watch);
+                    println!("🔗 Reattached session {} (PID {})", session_id, pid);
+                    reattached += 1;
+                }
+                Err(e) => eprintln!("⚠️  Session {} stays orphaned: {:#}", session_id, e),
+            }
+        }
+        reattached
+    }
+
+    /// Insert a running process without spawning Claude (for tests)
+    #[cfg(test)]
+    pub async fn insert_process(&self, process: SessionProcess) {
+        self.processes.lock().await.insert(process.session_id(), process);
+    }
+
+    /// Insert a session without spawning a process (for tests)
+    #[cfg(test)]
+    pub async fn insert_session(&self, session: Session) {
+        self.sessions.lock().await.insert(session.id, session);
+    }
+
     /// List all active sessions.
     ///
     /// Returns a vector of SessionInfo structs (without PTY handles).
     ///
     /// ## Status Field
     ///
-    /// - "running": Has active PTY process
-    /// - "stale": Loaded from disk, no PTY (daemon restarted)
-    /// - "crashed": Process was alive but died
-    /// - "orphaned": Process is alive but not managed
+    /// - `Running`: Has active PTY process
+    /// - `Unresponsive`: Has one, but it has been silent since input for
+    ///   longer than the hang threshold
+    /// - `CrashLooping`: The supervisor gave up restarting it
+    /// - `Exited`/`Crashed`: Claude exited with code 0 / non-zero (see `exit_code`)
+    /// - `Stale`: Loaded from disk, no PTY (daemon restarted)
     pub async fn list_sessions(&self) -> Vec<SessionInfo> {
         let sessions = self.sessions.lock().await;
         let processes = self.processes.lock().await;
 
         sessions
             .values()
-            .map(|s| {
-                // Determine status based on whether we have an active process
-                let status = if processes.contains_key(&s.id) {
-                    "running".to_string()
-                } else {
-                    // Check persisted status
-                    "stale".to_string()
-                };
-
-                SessionInfo {
-                    id: s.id.to_string(),
-                    working_dir: s.working_dir.display().to_string(),
-                    created_at: s.created_at.clone(),
-                    status,
-                    log_path: s.log_path.display().to_string(),
-                }
-            })
+            .map(|s| live_session_info(s, processes.get(&s.id), self.hang_threshold))
             .collect()
     }
 
+    /// One session's info and the PID of its Claude process: the running
+    /// child, or for an orphaned session the one left by the previous daemon.
+    /// `None` if there is no such session.
+    pub async fn describe_session(&self, session_id: Uuid) -> Option<(SessionInfo, Option<u32>)> {
+        let sessions = self.sessions.lock().await;
+        let processes = self.processes.lock().await;
+
+        let session = sessions.get(&session_id)?;
+        let process = processes.get(&session_id);
+        let info = live_session_info(session, process, self.hang_threshold);
+        Some((info, process.and_then(|p| p.pid()).or(session.orphan_pid)))
+    }
+
     /// Send input to a running session
     ///
     /// Writes the text to the session's PTY, which forwards it to Claude.
@@ -326,22 +1397,193 @@ impl SessionManager {
     /// - Session exists but no active PTY (stale/crashed)
     /// - PTY write failed
     pub async fn send_input(&self, session_id: Uuid, text: String) -> Result<()> {
-        let processes = self.processes.lock().await;
-        
-        if let Some(process) = processes.get(&session_id) {
-            // Add newline if not present
-            let input = if text.ends_with('\n') {
-                text
-            } else {
-                format!("{}\n", text)
-            };
-            
-            process.write_input(input.as_bytes())
-                .context("Failed to write to PTY")?;
-            
-            Ok(())
+        let writer = self.input_writer(session_id).await?;
+
+        // Add newline if not present
+        let input = if text.ends_with('\n') {
+            text
         } else {
-            anyhow::bail!("Session not found or not active (no PTY handle)")
+            format!("{}\n", text)
+        };
+
+        writer.write(input.as_bytes())
+            .await
+            .context("Failed to write to PTY")?;
+
+        Ok(())
+    }
+}
+
+/// Build the list/response view of a session
+/// Status as listed: running while there is an active process, orphaned
+/// while the previous daemon's process lives on, otherwise how it ended
+/// (or stale if unknown)
+fn session_status(session: &Session, has_process: bool) -> SessionStatus {
+    if has_process {
+        SessionStatus::Running
+    } else if session.orphan_alive() {
+        SessionStatus::Orphaned
+    } else if session.crash_loop_error.is_some() {
+        SessionStatus::CrashLooping
+    } else {
+        session.exit_status().unwrap_or(SessionStatus::Stale)
+    }
+}
+
+fn session_info(session: &Session, status: SessionStatus) -> SessionInfo {
+    SessionInfo {
+        id: session.id.to_string(),
+        label: session.label.clone(),
+        tags: session.tags.clone(),
+        working_dir: session.working_dir.display().to_string(),
+        created_at: session.created_at.clone(),
+        status,
+        log_path: session.log_path.display().to_string(),
+        last_error: session.crash_loop_error.clone().or_else(|| session.stop_error.clone()),
+        exit_code: session.exit_code,
+        last_activity: session.last_activity.clone(),
+    }
+}
+
+/// A session's info as of now: while it runs, its process knows its last
+/// activity better than the last saved value, and whether it has gone
+/// silent on input for `hang_threshold`
+fn live_session_info(session: &Session, process: Option<&SessionProcess>, hang_threshold: Option<Duration>) -> SessionInfo {
+    let mut info = session_info(session, session_status(session, process.is_some()));
+    if let (Some(process), Some(threshold)) = (process, hang_threshold) {
+        if process.is_unresponsive(threshold, SystemTime::now()) {
+            info.status = SessionStatus::Unresponsive;
+        }
+    }
+    if let Some(at) = process.and_then(SessionProcess::last_activity) {
+        info.last_activity = Some(chrono::DateTime::<chrono::Utc>::from(at).to_rfc3339());
+    }
+    info
+}
+
+/// Copy running sessions' last activity from their processes onto their
+/// records; returns the sessions whose value changed
+fn sync_activity(sessions: &mut HashMap<Uuid, Session>, processes: &HashMap<Uuid, SessionProcess>) -> Vec<Uuid> {
+    let mut changed = Vec::new();
+    for (id, process) in processes {
+        let (Some(session), Some(at)) = (sessions.get_mut(id), process.last_activity()) else {
+            continue;
+        };
+        let at = Some(chrono::DateTime::<chrono::Utc>::from(at).to_rfc3339());
+        if session.last_activity != at {
+            session.last_activity = at;
+            changed.push(*id);
         }
     }
+    changed
+}
+
+/// SIGTERM a process we have no handle for, wait up to `grace` for it to
+/// go away, then SIGKILL it. Returns `true` if it had to be killed.
+///
+/// Nothing is signalled once `pid` no longer names the process that
+/// started at `start_time`: it exited and the PID may have been reused.
+#[cfg(unix)]
+async fn terminate_pid(pid: u32, start_time: Option<u64>, grace: Duration) -> Result<bool> {
+    let gone = move |wait: Duration| async move {
+        let deadline = tokio::time::Instant::now() + wait;
+        while is_same_process(pid, start_time) {
+            if tokio::time::Instant::now() >= deadline {
+                return false;
+            }
+            tokio::time::sleep(Duration::from_millis(50)).await;
+        }
+        true
+    };
+
+    if !is_same_process(pid, start_time) {
+        return Ok(false);
+    }
+    unsafe { libc::kill(pid as libc::pid_t, libc::SIGTERM) };
+    if gone(grace).await || !is_same_process(pid, start_time) {
+        return Ok(false);
+    }
+    unsafe { libc::kill(pid as libc::pid_t, libc::SIGKILL) };
+    if !gone(crate::pty::KILL_WAIT).await {
+        anyhow::bail!("Process {} survived SIGKILL", pid);
+    }
+    Ok(true)
+}
+
+#[cfg(not(unix))]
+async fn terminate_pid(pid: u32, _start_time: Option<u64>, _grace: Duration) -> Result<bool> {
+    anyhow::bail!("Can't signal process {} on this platform", pid)
+}
+
+/// The canonical form of a session's working directory: absolute, with
+/// symlinks and `.`/`..` resolved.
+///
+/// It must be an existing directory the daemon can read. Relative paths
+/// are rejected rather than resolved against the daemon's own working
+/// directory (the CLI makes them absolute against the caller's).
+pub fn resolve_working_dir(path: &Path) -> Result<PathBuf, SessionError> {
+    if !path.is_absolute() {
+        return Err(SessionError::InvalidArgument(format!(
+            "Working directory must be an absolute path: {:?}",
+            path
+        )));
+    }
+    let rejected = |problem| SessionError::InvalidWorkingDir { path: path.to_path_buf(), problem };
+    let from_io = |e: std::io::Error| match e.kind() {
+        std::io::ErrorKind::NotFound => rejected(WorkingDirProblem::NotFound),
+        std::io::ErrorKind::PermissionDenied => rejected(WorkingDirProblem::PermissionDenied),
+        _ => SessionError::InvalidArgument(format!("Can't use working directory {:?}: {}", path, e)),
+    };
+
+    let canonical = std::fs::canonicalize(path).map_err(from_io)?;
+    if !canonical.is_dir() {
+        return Err(rejected(WorkingDirProblem::NotADirectory));
+    }
+    std::fs::read_dir(&canonical).map_err(from_io)?;
+    Ok(canonical)
+}
+
+/// Check a user-supplied label: trimmed, non-empty and a single line
+fn validate_label(name: &str) -> Result<String> {
+    let label = name.trim();
+    if label.is_empty() {
+        return Err(SessionError::InvalidArgument("Session name can't be empty".to_string()).into());
+    }
+    if label.chars().any(char::is_control) {
+        return Err(SessionError::InvalidArgument("Session name can't contain control characters".to_string()).into());
+    }
+    Ok(label.to_string())
+}
+
+/// Check user-supplied tags: non-empty keys without `=`, and no control
+/// characters in keys or values
+fn validate_tags(tags: &BTreeMap<String, String>) -> Result<BTreeMap<String, String>> {
+    for (key, value) in tags {
+        if key.is_empty() || key.contains('=') {
+            return Err(SessionError::InvalidArgument(format!("Invalid tag key {:?}", key)).into());
+        }
+        if key.chars().chain(value.chars()).any(char::is_control) {
+            return Err(SessionError::InvalidArgument("Tags can't contain control characters".to_string()).into());
+        }
+    }
+    Ok(tags.clone())
+}
+
+/// Spawn Claude in a PTY and wrap it in a logging `SessionProcess`
+fn spawn_session_process(
+    session_id: Uuid,
+    working_dir: &Path,
+    log_path: &Path,
+    claude: &ClaudeBinary,
+    env: &[(String, String)],
+    max_log_bytes: Option<u64>,
+) -> Result<SessionProcess> {
+    let (pty_pair, child, launch) = spawn_claude_pty(working_dir, claude, env)
+        .context("Failed to spawn Claude Code PTY")?;
+    // Ahead of the output reader, so it's the first entry of this run
+    if let Err(e) = crate::logging::write_launch_info(session_id, log_path, &launch) {
+        eprintln!("⚠️  Failed to log how session {} was launched: {:#}", session_id, e);
+    }
+    SessionProcess::with_max_log_bytes(session_id, pty_pair, child, log_path.to_path_buf(), max_log_bytes)
+        .context("Failed to create session process with logging")
 }
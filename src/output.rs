@@ -0,0 +1,379 @@
+use std::fmt::Display;
+use std::io::{BufRead, IsTerminal, Write};
+use std::sync::{Mutex, MutexGuard};
+
+/// When to emit ANSI color (`--color`)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum ColorChoice {
+    /// Color when writing to a terminal and `NO_COLOR` is unset
+    Auto,
+    Always,
+    Never,
+}
+
+impl ColorChoice {
+    /// Decide whether to color output going to a destination that is
+    /// (or isn't) a terminal. `NO_COLOR` only affects `Auto`.
+    pub fn resolve(self, is_tty: bool, no_color: bool) -> bool {
+        match self {
+            ColorChoice::Always => true,
+            ColorChoice::Never => false,
+            ColorChoice::Auto => is_tty && !no_color,
+        }
+    }
+}
+
+/// Output helper for CLI commands.
+///
+/// Every user-facing line printed by the CLI goes through this type so
+/// that global switches (like `--raw`) are applied consistently across
+/// all commands.
+///
+/// ## Raw Mode
+///
+/// With `--raw`, commands emit only essential data: ids, paths and
+/// error messages. No emoji, no decoration, no hint lines. This is meant
+/// for `$(...)` capture when embedding the CLI in other programs:
+///
+/// ```text
+/// id=$(claude-sessions --raw start ~/projects/app)
+/// ```
+///
+/// ## JSON Mode
+///
+/// With `--json`, commands that support it (`status`, `list`, `info`,
+/// `start`, `stop`) print a single JSON document to stdout via `json` and
+/// nothing else. Everything else behaves as in raw mode, and errors become
+/// a JSON object with an `error` field:
+///
+/// ```text
+/// {"error":"Daemon is not running"}
+/// ```
+///
+/// ## Confirmation
+///
+/// Destructive commands ask before acting via `confirm`. `--yes` answers
+/// for the user; without it, a non-interactive stdin is treated as "no"
+/// so scripts never destroy data by accident.
+pub struct Output {
+    raw: bool,
+    json: bool,
+    assume_yes: bool,
+    /// Whether `confirm` may prompt on stdin (off for captured output)
+    can_prompt: bool,
+    color: ColorChoice,
+    stdout: Mutex<Box<dyn Write + Send>>,
+    stderr: Mutex<Box<dyn Write + Send>>,
+}
+
+impl Output {
+    /// Create an output helper writing to the process stdout/stderr
+    pub fn new(raw: bool) -> Self {
+        Self::with_writers(raw, Box::new(std::io::stdout()), Box::new(std::io::stderr()))
+    }
+
+    /// Create an output helper with custom writers
+    pub fn with_writers(
+        raw: bool,
+        stdout: Box<dyn Write + Send>,
+        stderr: Box<dyn Write + Send>,
+    ) -> Self {
+        Output {
+            raw,
+            json: false,
+            assume_yes: false,
+            can_prompt: true,
+            color: ColorChoice::Auto,
+            stdout: Mutex::new(stdout),
+            stderr: Mutex::new(stderr),
+        }
+    }
+
+    /// Print JSON documents instead of human text (`--json`).
+    ///
+    /// Implies raw mode for commands without a JSON form.
+    pub fn json_mode(mut self, json: bool) -> Self {
+        self.json = json;
+        self.raw |= json;
+        self
+    }
+
+    /// Whether `--json` is in effect
+    pub fn is_json(&self) -> bool {
+        self.json
+    }
+
+    /// Print `value` as one line of JSON to stdout
+    pub fn json(&self, value: &impl serde::Serialize) {
+        match serde_json::to_string(value) {
+            Ok(line) => Self::write_line(&self.stdout, line),
+            Err(e) => self.fail(format!("Failed to encode JSON output: {}", e)),
+        }
+    }
+
+    /// Set the color policy (`--color`)
+    pub fn color_choice(mut self, color: ColorChoice) -> Self {
+        self.color = color;
+        self
+    }
+
+    /// Whether renderers writing to stdout should emit color
+    pub fn color(&self) -> bool {
+        self.color_for(std::io::stdout().is_terminal())
+    }
+
+    /// Whether to emit color to a destination that is (or isn't) a terminal
+    pub fn color_for(&self, is_tty: bool) -> bool {
+        let no_color = std::env::var_os("NO_COLOR").is_some_and(|v| !v.is_empty());
+        self.color.resolve(is_tty, no_color)
+    }
+
+    /// Wrap `text` in the SGR attributes `sgr` (e.g. `"31"` for red) when
+    /// renderers should emit color, or return it unchanged
+    pub fn paint(&self, text: impl Display, sgr: &str) -> String {
+        if self.color() {
+            format!("\x1b[{}m{}\x1b[0m", sgr, text)
+        } else {
+            text.to_string()
+        }
+    }
+
+    /// Answer every confirmation prompt with "yes" (`--yes`)
+    pub fn assume_yes(mut self, yes: bool) -> Self {
+        self.assume_yes = yes;
+        self
+    }
+
+    /// Ask the user to confirm a destructive action.
+    ///
+    /// Lists `affected` and prompts on stderr. Returns true straight away
+    /// with `--yes`, and false without prompting when stdin isn't a TTY.
+    pub fn confirm(&self, question: impl Display, affected: &[String]) -> bool {
+        let stdin = std::io::stdin();
+        let interactive = self.can_prompt && stdin.is_terminal();
+        self.confirm_with(question, affected, interactive, &mut stdin.lock())
+    }
+
+    fn confirm_with<R: BufRead>(
+        &self,
+        question: impl Display,
+        affected: &[String],
+        interactive: bool,
+        input: &mut R,
+    ) -> bool {
+        if self.assume_yes {
+            return true;
+        }
+        if !interactive {
+            self.warn("stdin is not a terminal; pass --yes to confirm");
+            return false;
+        }
+
+        {
+            let mut stderr = self.stderr.lock().unwrap_or_else(|e| e.into_inner());
+            for item in affected {
+                let _ = writeln!(stderr, "  • {}", item);
+            }
+            let _ = write!(stderr, "{} [y/N] ", question);
+            let _ = stderr.flush();
+        }
+
+        let mut answer = String::new();
+        if input.read_line(&mut answer).is_err() {
+            return false;
+        }
+        matches!(answer.trim().to_ascii_lowercase().as_str(), "y" | "yes")
+    }
+
+    /// Print decorated, human-facing text to stdout.
+    ///
+    /// Suppressed entirely in raw mode.
+    pub fn info(&self, text: impl Display) {
+        if !self.raw {
+            Self::write_line(&self.stdout, text);
+        }
+    }
+
+    /// Print essential data to stdout.
+    ///
+    /// In raw mode only `value` is printed; otherwise the decorated
+    /// `human` form is printed instead.
+    pub fn data(&self, value: impl Display, human: impl Display) {
+        if self.raw {
+            Self::write_line(&self.stdout, value);
+        } else {
+            Self::write_line(&self.stdout, human);
+        }
+    }
+
+    /// Direct access to stdout for bulk data (e.g. transcripts).
+    ///
+    /// Don't print through the helper while holding the returned guard.
+    pub fn stdout(&self) -> MutexGuard<'_, Box<dyn Write + Send>> {
+        self.stdout.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Print a hint or warning to stderr.
+    ///
+    /// Suppressed entirely in raw mode.
+    pub fn note(&self, text: impl Display) {
+        if !self.raw {
+            Self::write_line(&self.stderr, text);
+        }
+    }
+
+    /// Print a warning to stderr.
+    ///
+    /// Always printed; the `⚠️` decoration is dropped in raw mode.
+    pub fn warn(&self, message: impl Display) {
+        if self.raw {
+            Self::write_line(&self.stderr, message);
+        } else {
+            Self::write_line(&self.stderr, format!("⚠️  {}", message));
+        }
+    }
+
+    /// Print an error message to stderr.
+    ///
+    /// Always printed; the `❌` decoration is dropped in raw mode. In JSON
+    /// mode it goes to stdout as `{"error": message}` instead.
+    pub fn error(&self, message: impl Display) {
+        if self.json {
+            let error = serde_json::json!({ "error": message.to_string() });
+            Self::write_line(&self.stdout, error);
+        } else if self.raw {
+            Self::write_line(&self.stderr, message);
+        } else {
+            Self::write_line(&self.stderr, format!("❌ {}", message));
+        }
+    }
+
+    /// Print an error message and exit with status 1
+    pub fn fail(&self, message: impl Display) -> ! {
+        self.fail_with(1, message)
+    }
+
+    /// Print an error message and exit with `status` (e.g. a daemon
+    /// error's `ErrorCode::exit_status`)
+    pub fn fail_with(&self, status: i32, message: impl Display) -> ! {
+        self.error(message);
+        std::process::exit(status);
+    }
+
+    fn write_line(writer: &Mutex<Box<dyn Write + Send>>, text: impl Display) {
+        let mut writer = writer.lock().unwrap_or_else(|e| e.into_inner());
+        let _ = writeln!(writer, "{}", text);
+        let _ = writer.flush();
+    }
+}
+
+/// In-memory writer for capturing CLI output in tests
+#[cfg(test)]
+#[derive(Clone, Default)]
+pub struct Captured(std::sync::Arc<Mutex<Vec<u8>>>);
+
+#[cfg(test)]
+impl Captured {
+    /// Everything written so far, as UTF-8
+    pub fn contents(&self) -> String {
+        String::from_utf8_lossy(&self.0.lock().unwrap()).to_string()
+    }
+}
+
+#[cfg(test)]
+impl Write for Captured {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0.lock().unwrap().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+impl Output {
+    /// Create an output helper that captures stdout/stderr in memory.
+    ///
+    /// It never prompts: `confirm` behaves as if stdin weren't a TTY.
+    pub fn captured(raw: bool) -> (Self, Captured, Captured) {
+        let stdout = Captured::default();
+        let stderr = Captured::default();
+        let mut output = Self::with_writers(raw, Box::new(stdout.clone()), Box::new(stderr.clone()));
+        output.can_prompt = false;
+        (output, stdout, stderr)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_raw_suppresses_decoration() {
+        let (out, stdout, stderr) = Output::captured(true);
+        out.info("📋 Active sessions");
+        out.data("abc", "✅ Session started: abc");
+        out.note("💡 hint");
+        out.error("boom");
+
+        assert_eq!(stdout.contents(), "abc\n");
+        assert_eq!(stderr.contents(), "boom\n");
+    }
+
+    #[test]
+    fn test_confirm_fails_safe_without_yes() {
+        let affected = vec!["/tmp/export.txt".to_string()];
+
+        let (out, _, _) = Output::captured(false);
+        assert!(!out.confirm_with("Overwrite?", &affected, false, &mut "y\n".as_bytes()));
+
+        let (out, _, _) = Output::captured(false);
+        let out = out.assume_yes(true);
+        assert!(out.confirm_with("Overwrite?", &affected, false, &mut "".as_bytes()));
+    }
+
+    #[test]
+    fn test_color_choice_resolution() {
+        assert!(ColorChoice::Always.resolve(false, true));
+        assert!(!ColorChoice::Never.resolve(true, false));
+        assert!(ColorChoice::Auto.resolve(true, false));
+        // Piped output, or NO_COLOR, disables auto color
+        assert!(!ColorChoice::Auto.resolve(false, false));
+        assert!(!ColorChoice::Auto.resolve(true, true));
+    }
+
+    #[test]
+    fn test_paint_follows_color_choice() {
+        let (out, _, _) = Output::captured(false);
+        let out = out.color_choice(ColorChoice::Always);
+        assert_eq!(out.paint("running", "32"), "\x1b[32mrunning\x1b[0m");
+
+        let out = out.color_choice(ColorChoice::Never);
+        assert_eq!(out.paint("running", "32"), "running");
+    }
+
+    #[test]
+    fn test_json_mode_prints_only_json() {
+        let (out, stdout, stderr) = Output::captured(false);
+        let out = out.json_mode(true);
+        out.info("📋 Active sessions");
+        out.note("💡 hint");
+        out.json(&serde_json::json!({ "session_id": "abc" }));
+        out.error("boom");
+
+        assert_eq!(stdout.contents(), "{\"session_id\":\"abc\"}\n{\"error\":\"boom\"}\n");
+        assert_eq!(stderr.contents(), "");
+    }
+
+    #[test]
+    fn test_human_mode_keeps_decoration() {
+        let (out, stdout, stderr) = Output::captured(false);
+        out.data("abc", "✅ Session started: abc");
+        out.error("boom");
+
+        assert_eq!(stdout.contents(), "✅ Session started: abc\n");
+        assert_eq!(stderr.contents(), "❌ boom\n");
+    }
+}
@@ -0,0 +1,204 @@
+use anyhow::{Context, Result};
+use std::path::{Path, PathBuf};
+
+/// Expand a user-supplied path on the client side.
+///
+/// The shell usually does this for us, but when the CLI is invoked
+/// programmatically (or the argument is quoted) the literal string would
+/// otherwise reach the daemon and fail its existence check confusingly.
+///
+/// ## Precedence
+///
+/// 1. A leading `~` or `~user` is expanded to the (given user's) home
+///    directory. Tilde is only recognized at the very start of the input.
+/// 2. `$VAR` and `${VAR}` anywhere in the remainder are replaced by their
+///    environment values. Substituted values are not re-scanned, so a
+///    variable containing `~` or `$` is used literally.
+/// 3. A relative result is made absolute against the client's current
+///    directory (the daemon runs with a different cwd).
+///
+/// Unset variables and unknown users are errors rather than silently
+/// expanding to an empty string.
+pub fn expand_path(path: &Path) -> Result<PathBuf> {
+    expand_path_with(path, |name| std::env::var(name).ok())
+}
+
+/// Same as `expand_path`, with a custom environment lookup
+pub fn expand_path_with<F>(path: &Path, lookup: F) -> Result<PathBuf>
+where
+    F: Fn(&str) -> Option<String>,
+{
+    // Non-UTF-8 input can't contain anything we'd expand meaningfully
+    let Some(input) = path.to_str() else {
+        return absolutize(path.to_path_buf());
+    };
+
+    let (prefix, rest) = expand_tilde(input, &lookup)?;
+    let expanded = format!("{}{}", prefix, expand_vars(rest, &lookup)?);
+
+    absolutize(PathBuf::from(expanded))
+}
+
+/// Expand a leading `~` / `~user`, returning the expanded prefix and the
+/// unconsumed remainder of the input
+fn expand_tilde<'a, F>(input: &'a str, lookup: &F) -> Result<(String, &'a str)>
+where
+    F: Fn(&str) -> Option<String>,
+{
+    let Some(after) = input.strip_prefix('~') else {
+        return Ok((String::new(), input));
+    };
+
+    let end = after.find('/').unwrap_or(after.len());
+    let (user, rest) = after.split_at(end);
+
+    let home = if user.is_empty() {
+        lookup("HOME")
+            .or_else(|| lookup("USERPROFILE"))
+            .context("Cannot expand `~`: home directory is not set")?
+    } else {
+        user_home(user)
+            .with_context(|| format!("Cannot expand `~{}`: unknown user", user))?
+    };
+
+    Ok((home, rest))
+}
+
+/// Replace `$VAR` / `${VAR}` occurrences with their values
+fn expand_vars<F>(input: &str, lookup: &F) -> Result<String>
+where
+    F: Fn(&str) -> Option<String>,
+{
+    let mut result = String::with_capacity(input.len());
+    let mut chars = input.char_indices().peekable();
+
+    while let Some((_, c)) = chars.next() {
+        if c != '$' {
+            result.push(c);
+            continue;
+        }
+
+        let name = if chars.peek().map(|&(_, c)| c) == Some('{') {
+            chars.next();
+            let mut name = String::new();
+            loop {
+                match chars.next() {
+                    Some((_, '}')) => break,
+                    Some((_, c)) => name.push(c),
+                    None => anyhow::bail!("Unterminated `${{` in path: {}", input),
+                }
+            }
+            name
+        } else {
+            let mut name = String::new();
+            while let Some(&(_, c)) = chars.peek() {
+                if c.is_ascii_alphanumeric() || c == '_' {
+                    name.push(c);
+                    chars.next();
+                } else {
+                    break;
+                }
+            }
+            name
+        };
+
+        if name.is_empty() {
+            // A lone `$` is kept literally
+            result.push('$');
+            continue;
+        }
+
+        let value = lookup(&name)
+            .with_context(|| format!("Environment variable not set: {}", name))?;
+        result.push_str(&value);
+    }
+
+    Ok(result)
+}
+
+/// Require `path` to be valid UTF-8, returning it as a string.
+///
+/// Session paths travel as JSON strings (IPC, `sessions.json`, log
+/// listings), which can't carry arbitrary bytes, and stringifying them
+/// lossily would point sessions at a different directory. So non-UTF-8
+/// working and log directories are rejected up front instead.
+pub fn require_utf8(path: &Path) -> Result<&str> {
+    path.to_str().with_context(|| {
+        format!(
+            "Path is not valid UTF-8, which sessions don't support: {}",
+            path.display()
+        )
+    })
+}
+
+/// Whether `path` is `dir` itself or somewhere below it.
+///
+/// Both sides are canonicalized when possible so symlinks and `..`
+/// components don't affect the answer; paths that no longer exist are
+/// compared as given. Comparison is per component, so `/src/ab` is not
+/// within `/src/a`.
+pub fn is_within(path: &Path, dir: &Path) -> bool {
+    let canonical = |p: &Path| p.canonicalize().unwrap_or_else(|_| p.to_path_buf());
+    canonical(path).starts_with(canonical(dir))
+}
+
+fn absolutize(path: PathBuf) -> Result<PathBuf> {
+    if path.is_absolute() {
+        return Ok(path);
+    }
+    let cwd = std::env::current_dir().context("Cannot determine current directory")?;
+    Ok(cwd.join(path))
+}
+
+/// Look up another user's home directory
+#[cfg(unix)]
+fn user_home(user: &str) -> Option<String> {
+    let name = std::ffi::CString::new(user).ok()?;
+    unsafe {
+        let pw = libc::getpwnam(name.as_ptr());
+        if pw.is_null() || (*pw).pw_dir.is_null() {
+            return None;
+        }
+        let dir = std::ffi::CStr::from_ptr((*pw).pw_dir);
+        dir.to_str().ok().map(String::from)
+    }
+}
+
+#[cfg(not(unix))]
+fn user_home(_user: &str) -> Option<String> {
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lookup(name: &str) -> Option<String> {
+        match name {
+            "HOME" => Some("/home/tester".to_string()),
+            "PROJECT" => Some("app".to_string()),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn test_tilde_and_home_var_agree() {
+        let tilde = expand_path_with(Path::new("~/x"), lookup).unwrap();
+        let var = expand_path_with(Path::new("$HOME/x"), lookup).unwrap();
+        let braced = expand_path_with(Path::new("${HOME}/x"), lookup).unwrap();
+
+        assert_eq!(tilde, PathBuf::from("/home/tester/x"));
+        assert_eq!(tilde, var);
+        assert_eq!(tilde, braced);
+    }
+
+    #[test]
+    fn test_expansion_errors_and_literals() {
+        assert_eq!(
+            expand_path_with(Path::new("/srv/$PROJECT/a$"), lookup).unwrap(),
+            PathBuf::from("/srv/app/a$")
+        );
+        assert!(expand_path_with(Path::new("/srv/$MISSING"), lookup).is_err());
+        assert!(expand_path_with(Path::new("/srv/${HOME"), lookup).is_err());
+    }
+}
@@ -1,16 +1,22 @@
-use crate::session::Session;
+use crate::session::{Session, SessionStatus};
 use anyhow::{Context, Result};
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 use std::fs;
-use std::path::PathBuf;
+use std::io::Write;
+use std::path::{Path, PathBuf};
 use uuid::Uuid;
 
+#[cfg(feature = "sqlite")]
+mod sqlite;
+#[cfg(feature = "sqlite")]
+pub use sqlite::SqlitePersistence;
+
 /// Persisted session state on disk
 ///
 /// This is the source of truth for what sessions exist.
 /// The daemon reads this on startup to recover sessions.
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct PersistedSession {
     pub id: Uuid,
     pub working_dir: PathBuf,
@@ -25,15 +31,24 @@ pub struct PersistedSession {
     /// 
     /// Always check process status before trusting this.
     pub pid: Option<u32>,
-    /// Current session status
-    /// 
-    /// Valid states:
-    /// - "running": Process is alive and responding
-    /// - "stopped": Process was stopped cleanly
-    /// - "crashed": Process died unexpectedly
-    /// - "stale": Daemon restarted, process status unknown
-    /// - "orphaned": PID exists but not our process
-    pub status: String,
+    /// When that process started (see `process_start_time`), so a reused
+    /// PID can be told apart from it; absent where the platform doesn't say
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub process_start_time: Option<u64>,
+    /// Current session status (see `SessionStatus`)
+    pub status: SessionStatus,
+    /// Claude's exit code, once it has exited on its own
+    #[serde(default)]
+    pub exit_code: Option<u32>,
+    /// The session's label; empty in records written before labels
+    #[serde(default)]
+    pub label: String,
+    /// The session's tags
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub tags: BTreeMap<String, String>,
+    /// Last input or output (RFC 3339), as of the last save
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_activity: Option<String>,
 }
 
 impl PersistedSession {
@@ -44,11 +59,124 @@ impl PersistedSession {
             created_at: session.created_at.clone(),
             log_path: session.log_path.clone(),
             pid,
-            status: "running".to_string(),
+            process_start_time: pid.and_then(process_start_time),
+            status: session.exit_status().unwrap_or(SessionStatus::Running),
+            exit_code: session.exit_code,
+            label: session.label.clone(),
+            tags: session.tags.clone(),
+            last_activity: session.last_activity.clone(),
         }
     }
 }
 
+/// Current schema version of `sessions.json`
+///
+/// History:
+/// - v0: bare map of session id → `PersistedSession`
+/// - v1: versioned envelope `{ "version": 1, "sessions": { ... } }`
+pub const STATE_VERSION: u32 = 1;
+
+/// On-disk envelope for `sessions.json` (write side)
+#[derive(Serialize)]
+struct StateFileRef<'a> {
+    version: u32,
+    sessions: &'a HashMap<Uuid, PersistedSession>,
+}
+
+/// On-disk envelope for `sessions.json` (read side)
+#[derive(Deserialize)]
+struct StateFile {
+    version: u32,
+    sessions: HashMap<Uuid, PersistedSession>,
+}
+
+/// Storage backend for persisted session state.
+///
+/// The daemon only talks to this trait, so the on-disk layout can be
+/// swapped without touching the manager. Backends:
+/// - `PersistenceManager`: a single `sessions.json` (default)
+/// - `SessionFiles`: one JSON file per session under `sessions/`, so a
+///   change to one session doesn't rewrite all of them
+/// - `SqlitePersistence`: an indexed `sessions.db` (`--features sqlite`)
+///
+/// Select with `CLAUDE_SESSIONS_PERSISTENCE=json|files|sqlite` (see `from_env`).
+pub trait Persistence: Send {
+    /// Load every persisted session
+    fn load_state(&self) -> Result<HashMap<Uuid, PersistedSession>>;
+    /// Replace the whole persisted state with `sessions`
+    fn write_state(&self, sessions: &HashMap<Uuid, PersistedSession>) -> Result<()>;
+    /// Insert or update a single session
+    fn upsert(&self, session: &PersistedSession) -> Result<()>;
+    /// Forget a single session (no error if it isn't stored)
+    fn remove(&self, session_id: Uuid) -> Result<()>;
+    /// Make the last write durable
+    fn sync_state(&self) -> Result<()>;
+}
+
+/// Create the backend selected by `CLAUDE_SESSIONS_PERSISTENCE`
+/// (`json`, the default, `files`, or `sqlite` when built with it)
+pub fn from_env() -> Result<Box<dyn Persistence>> {
+    match std::env::var("CLAUDE_SESSIONS_PERSISTENCE").as_deref() {
+        Ok("files") => Ok(Box::new(SessionFiles::new()?)),
+        #[cfg(feature = "sqlite")]
+        Ok("sqlite") => Ok(Box::new(SqlitePersistence::new()?)),
+        #[cfg(not(feature = "sqlite"))]
+        Ok("sqlite") => anyhow::bail!("This build has no sqlite support (rebuild with --features sqlite)"),
+        Ok("json") | Err(_) => Ok(Box::new(PersistenceManager::new()?)),
+        Ok(other) => anyhow::bail!(
+            "Unknown CLAUDE_SESSIONS_PERSISTENCE backend: {} (expected json, files or sqlite)",
+            other
+        ),
+    }
+}
+
+fn state_dir() -> Result<PathBuf> {
+    let home = std::env::var("HOME")
+        .or_else(|_| std::env::var("USERPROFILE"))
+        .context("Cannot determine home directory")?;
+    Ok(PathBuf::from(home).join(".claude-sessions"))
+}
+
+/// Write `contents` to `path` atomically and durably.
+///
+/// The temp file is fsynced before it is renamed over `path`, and the
+/// directory after, so a crash leaves either the old or the new contents,
+/// never a truncated file.
+fn write_atomic(path: &Path, contents: &str) -> Result<()> {
+    let mut temp_name = path.file_name().context("State path has no file name")?.to_os_string();
+    temp_name.push(".tmp");
+    let temp_file = path.with_file_name(temp_name);
+
+    let mut file = fs::File::create(&temp_file)
+        .context("Failed to write temp state file")?;
+    file.write_all(contents.as_bytes())
+        .and_then(|()| file.sync_all())
+        .context("Failed to write temp state file")?;
+    drop(file);
+    fs::rename(&temp_file, path)
+        .context("Failed to rename state file")?;
+    sync_parent_dir(path)
+}
+
+/// fsync the directory holding `path`, so a rename into it is durable
+#[cfg(unix)]
+fn sync_parent_dir(path: &Path) -> Result<()> {
+    let dir = match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+    fs::File::open(dir)
+        .and_then(|d| d.sync_all())
+        .with_context(|| format!("Failed to sync directory {:?}", dir))
+}
+
+/// Directories can't be opened for syncing here; the rename is the best
+/// we can do
+#[cfg(not(unix))]
+fn sync_parent_dir(_path: &Path) -> Result<()> {
+    Ok(())
+}
+
 /// Persistence manager for session metadata
 /// 
 /// Writes session state to disk and recovers it on daemon restart.
@@ -57,7 +185,9 @@ impl PersistedSession {
 /// 
 /// 1. **Disk full**: write_state() fails, in-memory state preserved
 /// 2. **Permission denied**: write_state() fails, in-memory state preserved
-/// 3. **Corrupted state file**: load_state() returns empty HashMap
+/// 3. **Corrupted state file**: load_state() falls back to the backup
+///    (`sessions.json.bak`, the previous good state), and only fails if
+///    that can't be read either
 /// 4. **Concurrent writes**: Last write wins (file is overwritten atomically)
 /// 5. **Daemon crash**: Most recent write is preserved, PID may be stale
 pub struct PersistenceManager {
@@ -66,8 +196,11 @@ pub struct PersistenceManager {
 
 impl PersistenceManager {
     pub fn new() -> Result<Self> {
-        let state_file = Self::state_file_path()?;
-        
+        Self::with_state_file(Self::state_file_path()?)
+    }
+
+    /// Create a persistence manager for an explicit state file
+    pub fn with_state_file(state_file: PathBuf) -> Result<Self> {
         // Ensure directory exists
         if let Some(parent) = state_file.parent() {
             fs::create_dir_all(parent)?;
@@ -77,12 +210,45 @@ impl PersistenceManager {
     }
 
     fn state_file_path() -> Result<PathBuf> {
-        let home = std::env::var("HOME")
-            .or_else(|_| std::env::var("USERPROFILE"))
-            .context("Cannot determine home directory")?;
-        Ok(PathBuf::from(home)
-            .join(".claude-sessions")
-            .join("sessions.json"))
+        Ok(state_dir()?.join("sessions.json"))
+    }
+
+    /// The copy of the previous good state, next to the state file
+    fn backup_path(&self) -> PathBuf {
+        let mut name = self.state_file.file_name().unwrap_or_default().to_os_string();
+        name.push(".bak");
+        self.state_file.with_file_name(name)
+    }
+
+    /// Where a state file from a newer build (schema `version`) is kept
+    fn newer_state_path(&self, version: u32) -> PathBuf {
+        let mut name = self.state_file.file_name().unwrap_or_default().to_os_string();
+        name.push(format!(".v{}", version));
+        self.state_file.with_file_name(name)
+    }
+
+    /// Copy the current state file to the backup, if it is readable.
+    ///
+    /// A state file that doesn't parse is never backed up, so it can't
+    /// replace a good backup. One written by a newer build is also kept
+    /// as `sessions.json.v<N>`, which later writes never replace, so
+    /// the fields this build drops survive a downgrade.
+    fn back_up_state(&self) -> Result<()> {
+        let Ok(json) = fs::read_to_string(&self.state_file) else {
+            return Ok(());
+        };
+        let version = match serde_json::from_str(&json).map_err(anyhow::Error::from).and_then(Self::decode_state) {
+            Ok((version, _)) => version,
+            Err(_) => return Ok(()),
+        };
+        if version > STATE_VERSION {
+            let keep = self.newer_state_path(version);
+            if !keep.exists() {
+                eprintln!("⚠️  Keeping the schema v{} state file as {:?} before rewriting it", version, keep);
+                write_atomic(&keep, &json)?;
+            }
+        }
+        write_atomic(&self.backup_path(), &json)
     }
 
     /// Save current session state to disk
@@ -97,17 +263,36 @@ impl PersistenceManager {
     /// If write fails, logs error but does not crash daemon.
     /// In-memory state is still valid, but recovery after crash will fail.
     pub fn write_state(&self, sessions: &HashMap<Uuid, PersistedSession>) -> Result<()> {
-        let json = serde_json::to_string_pretty(sessions)
+        let state = StateFileRef {
+            version: STATE_VERSION,
+            sessions,
+        };
+        let json = serde_json::to_string_pretty(&state)
             .context("Failed to serialize sessions")?;
 
-        // Write atomically: write to temp file, then rename
-        let temp_file = self.state_file.with_extension("json.tmp");
-        fs::write(&temp_file, json)
-            .context("Failed to write temp state file")?;
-        fs::rename(&temp_file, &self.state_file)
-            .context("Failed to rename state file")?;
+        // Keep the previous state, then replace it atomically
+        if let Err(e) = self.back_up_state() {
+            eprintln!("⚠️  Failed to back up state file: {:#}", e);
+        }
+        write_atomic(&self.state_file, &json)
+    }
 
-        Ok(())
+    /// Read the state file without migrating or reporting anything
+    fn read_state(&self) -> Result<(u32, HashMap<Uuid, PersistedSession>)> {
+        Self::read_state_file(&self.state_file)
+    }
+
+    /// Read and decode one state file (the primary or the backup)
+    fn read_state_file(path: &Path) -> Result<(u32, HashMap<Uuid, PersistedSession>)> {
+        if !path.exists() {
+            return Ok((STATE_VERSION, HashMap::new()));
+        }
+
+        let json = fs::read_to_string(path)
+            .context("Failed to read state file")?;
+        let value: serde_json::Value = serde_json::from_str(&json)
+            .context("Failed to parse state file")?;
+        Self::decode_state(value)
     }
 
     /// Load session state from disk
@@ -117,22 +302,55 @@ impl PersistenceManager {
     /// ## Failure Modes
     /// 
     /// 1. **File doesn't exist**: Returns empty HashMap (first run)
-    /// 2. **File corrupted**: Logs error, returns empty HashMap
-    /// 3. **File readable but invalid JSON**: Logs error, returns empty HashMap
-    /// 
-    /// Conservative approach: if we can't parse state, start fresh.
-    /// User can manually inspect/fix sessions.json if needed.
+    /// 2. **File corrupted or invalid JSON**: Logs the error and loads the
+    ///    backup (`sessions.json.bak`) instead
+    /// 3. **Backup unusable too**: Returns the original error; the daemon
+    ///    starts with no sessions
+    ///
+    /// The corrupt file is left alone until the next write replaces it, so
+    /// the user can inspect it.
+    ///
+    /// ## Schema Versions
+    ///
+    /// Older files (see `STATE_VERSION`) are migrated transparently and
+    /// rewritten in the current format. Files from a newer build are loaded
+    /// as far as we understand them (unknown fields are ignored) and are
+    /// not rewritten here; the first later write keeps a copy (see
+    /// `back_up_state`), so a downgrade doesn't destroy data.
     pub fn load_state(&self) -> Result<HashMap<Uuid, PersistedSession>> {
         if !self.state_file.exists() {
             // First run, no state to load
             return Ok(HashMap::new());
         }
 
-        let json = fs::read_to_string(&self.state_file)
-            .context("Failed to read state file")?;
+        let (version, sessions) = match self.read_state() {
+            Ok(state) => state,
+            Err(e) => {
+                let backup = self.backup_path();
+                eprintln!("⚠️  {:#}", e);
+                match Self::read_state_file(&backup) {
+                    Ok(state) if backup.exists() => {
+                        eprintln!("⚠️  Loading the last good state from {:?}", backup);
+                        state
+                    }
+                    _ => return Err(e),
+                }
+            }
+        };
 
-        let sessions: HashMap<Uuid, PersistedSession> = serde_json::from_str(&json)
-            .context("Failed to parse state file")?;
+        if version < STATE_VERSION {
+            println!(
+                "Migrating sessions.json from schema v{} to v{}",
+                version, STATE_VERSION
+            );
+            self.write_state(&sessions)
+                .context("Failed to rewrite migrated state file")?;
+        } else if version > STATE_VERSION {
+            eprintln!(
+                "⚠️  sessions.json uses schema v{} (this build understands v{}), loading known fields only",
+                version, STATE_VERSION
+            );
+        }
 
         println!(
             "Loaded {} session(s) from disk",
@@ -142,10 +360,140 @@ impl PersistenceManager {
         Ok(sessions)
     }
 
-    /// Delete the state file (for cleanup)
-    pub fn delete_state(&self) -> Result<()> {
+    /// Decode a parsed state file into its schema version and sessions
+    fn decode_state(value: serde_json::Value) -> Result<(u32, HashMap<Uuid, PersistedSession>)> {
+        match value.get("version").and_then(|v| v.as_u64()) {
+            Some(_) => {
+                let state: StateFile = serde_json::from_value(value)
+                    .context("Failed to parse state file")?;
+                Ok((state.version, state.sessions))
+            }
+            None => {
+                // v0: bare map keyed by session id
+                let sessions = serde_json::from_value(value)
+                    .context("Failed to parse legacy state file")?;
+                Ok((0, sessions))
+            }
+        }
+    }
+
+    /// fsync the state file so the last `write_state` is durable
+    pub fn sync_state(&self) -> Result<()> {
         if self.state_file.exists() {
-            fs::remove_file(&self.state_file)?;
+            fs::File::open(&self.state_file)
+                .and_then(|f| f.sync_all())
+                .context("Failed to sync state file")?;
+        }
+        Ok(())
+    }
+}
+
+impl Persistence for PersistenceManager {
+    fn load_state(&self) -> Result<HashMap<Uuid, PersistedSession>> {
+        PersistenceManager::load_state(self)
+    }
+
+    fn write_state(&self, sessions: &HashMap<Uuid, PersistedSession>) -> Result<()> {
+        PersistenceManager::write_state(self, sessions)
+    }
+
+    fn upsert(&self, session: &PersistedSession) -> Result<()> {
+        let (_, mut sessions) = self.read_state()?;
+        sessions.insert(session.id, session.clone());
+        self.write_state(&sessions)
+    }
+
+    fn remove(&self, session_id: Uuid) -> Result<()> {
+        let (_, mut sessions) = self.read_state()?;
+        if sessions.remove(&session_id).is_some() {
+            self.write_state(&sessions)?;
+        }
+        Ok(())
+    }
+
+    fn sync_state(&self) -> Result<()> {
+        PersistenceManager::sync_state(self)
+    }
+}
+
+/// One JSON file per session (`sessions/<id>.json`).
+///
+/// Starting or stopping a session touches only that session's file, so
+/// saves stay cheap with many sessions. Each file holds the same
+/// versioned `PersistedSession` fields as an entry of `sessions.json`.
+pub struct SessionFiles {
+    dir: PathBuf,
+}
+
+impl SessionFiles {
+    pub fn new() -> Result<Self> {
+        Self::with_dir(state_dir()?.join("sessions"))
+    }
+
+    /// Store session files in an explicit directory
+    pub fn with_dir(dir: PathBuf) -> Result<Self> {
+        fs::create_dir_all(&dir)?;
+        Ok(SessionFiles { dir })
+    }
+
+    fn path_for(&self, session_id: Uuid) -> PathBuf {
+        self.dir.join(format!("{}.json", session_id))
+    }
+}
+
+impl Persistence for SessionFiles {
+    fn load_state(&self) -> Result<HashMap<Uuid, PersistedSession>> {
+        let mut sessions = HashMap::new();
+        for entry in fs::read_dir(&self.dir).context("Failed to read sessions directory")? {
+            let path = entry?.path();
+            if path.extension().and_then(|e| e.to_str()) != Some("json") {
+                continue;
+            }
+            let parsed = fs::read_to_string(&path)
+                .map_err(anyhow::Error::from)
+                .and_then(|json| Ok(serde_json::from_str::<PersistedSession>(&json)?));
+            match parsed {
+                Ok(session) => {
+                    sessions.insert(session.id, session);
+                }
+                // One bad file shouldn't lose every other session
+                Err(e) => eprintln!("⚠️  Skipping unreadable session file {:?}: {}", path, e),
+            }
+        }
+        Ok(sessions)
+    }
+
+    fn write_state(&self, sessions: &HashMap<Uuid, PersistedSession>) -> Result<()> {
+        for session in sessions.values() {
+            self.upsert(session)?;
+        }
+        for stored in self.load_state()?.keys() {
+            if !sessions.contains_key(stored) {
+                self.remove(*stored)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn upsert(&self, session: &PersistedSession) -> Result<()> {
+        let json = serde_json::to_string_pretty(session)
+            .context("Failed to serialize session")?;
+        write_atomic(&self.path_for(session.id), &json)
+    }
+
+    fn remove(&self, session_id: Uuid) -> Result<()> {
+        match fs::remove_file(self.path_for(session_id)) {
+            Ok(()) => Ok(()),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(()),
+            Err(e) => Err(e).context("Failed to remove session file"),
+        }
+    }
+
+    fn sync_state(&self) -> Result<()> {
+        for entry in fs::read_dir(&self.dir)? {
+            fs::File::open(entry?.path())
+                .and_then(|f| f.sync_all())
+                .context("Failed to sync session file")?;
         }
         Ok(())
     }
@@ -207,6 +555,60 @@ pub fn is_process_alive(pid: u32) -> bool {
     }
 }
 
+/// When process `pid` started, in a platform-specific unit that is only
+/// meant to be compared with another reading for the same PID
+///
+/// Linux: clock ticks since boot (`/proc/<pid>/stat`, field 22)
+/// macOS: microseconds since the epoch (`proc_pidinfo`)
+/// Elsewhere, or if the process is gone: `None`
+pub fn process_start_time(pid: u32) -> Option<u64> {
+    #[cfg(target_os = "linux")]
+    {
+        let stat = fs::read_to_string(format!("/proc/{}/stat", pid)).ok()?;
+        // The command name may contain spaces and parentheses; the fields
+        // after it start with the state (field 3)
+        let (_, fields) = stat.rsplit_once(')')?;
+        fields.split_whitespace().nth(19)?.parse().ok()
+    }
+
+    #[cfg(target_os = "macos")]
+    {
+        let mut info: libc::proc_bsdinfo = unsafe { std::mem::zeroed() };
+        let size = std::mem::size_of::<libc::proc_bsdinfo>() as i32;
+        let written = unsafe {
+            libc::proc_pidinfo(
+                pid as i32,
+                libc::PROC_PIDTBSDINFO,
+                0,
+                &mut info as *mut libc::proc_bsdinfo as *mut libc::c_void,
+                size,
+            )
+        };
+        (written == size).then(|| info.pbi_start_tvsec * 1_000_000 + info.pbi_start_tvusec)
+    }
+
+    #[cfg(not(any(target_os = "linux", target_os = "macos")))]
+    {
+        let _ = pid;
+        None
+    }
+}
+
+/// Whether `pid` is still the process that started at `start_time`
+///
+/// A live PID with a different start time was reused by another process.
+/// Without a start time to compare (not recorded, or the platform can't
+/// tell) this falls back to `is_process_alive`.
+pub fn is_same_process(pid: u32, start_time: Option<u64>) -> bool {
+    if !is_process_alive(pid) {
+        return false;
+    }
+    match (start_time, process_start_time(pid)) {
+        (Some(expected), Some(actual)) => expected == actual,
+        _ => true,
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -217,7 +619,7 @@ mod tests {
         let temp_dir = TempDir::new().unwrap();
         let state_file = temp_dir.path().join("sessions.json");
 
-        let mut pm = PersistenceManager {
+        let pm = PersistenceManager {
             state_file: state_file.clone(),
         };
 
@@ -229,7 +631,12 @@ mod tests {
             created_at: "2024-01-01T00:00:00Z".to_string(),
             log_path: PathBuf::from("/tmp/test.log"),
             pid: Some(12345),
-            status: "running".to_string(),
+            process_start_time: None,
+            status: SessionStatus::Running,
+            exit_code: None,
+            label: String::new(),
+            tags: BTreeMap::new(),
+            last_activity: None,
         };
         sessions.insert(session.id, session);
 
@@ -243,6 +650,173 @@ mod tests {
         assert_eq!(loaded.keys().next().unwrap(), sessions.keys().next().unwrap());
     }
 
+    #[test]
+    fn test_legacy_bare_map_is_migrated() {
+        let temp_dir = TempDir::new().unwrap();
+        let state_file = temp_dir.path().join("sessions.json");
+
+        // v0 fixture: bare map, no version envelope
+        let mut legacy = HashMap::new();
+        for _ in 0..3 {
+            let session = PersistedSession {
+                id: Uuid::new_v4(),
+                working_dir: PathBuf::from("/tmp/test"),
+                created_at: "2024-01-01T00:00:00Z".to_string(),
+                log_path: PathBuf::from("/tmp/test.log"),
+                pid: None,
+                process_start_time: None,
+                status: SessionStatus::Stale,
+                exit_code: None,
+                label: String::new(),
+                tags: BTreeMap::new(),
+                last_activity: None,
+            };
+            legacy.insert(session.id, session);
+        }
+        fs::write(&state_file, serde_json::to_string_pretty(&legacy).unwrap()).unwrap();
+
+        let pm = PersistenceManager {
+            state_file: state_file.clone(),
+        };
+        let loaded = pm.load_state().unwrap();
+        assert_eq!(loaded.len(), 3);
+        for id in legacy.keys() {
+            assert!(loaded.contains_key(id));
+        }
+
+        // File was upgraded in place
+        let rewritten: serde_json::Value =
+            serde_json::from_str(&fs::read_to_string(&state_file).unwrap()).unwrap();
+        assert_eq!(rewritten["version"], STATE_VERSION);
+        assert_eq!(rewritten["sessions"].as_object().unwrap().len(), 3);
+
+        // ...and the unversioned original is kept as the backup
+        let backup: serde_json::Value =
+            serde_json::from_str(&fs::read_to_string(temp_dir.path().join("sessions.json.bak")).unwrap()).unwrap();
+        assert!(backup.get("version").is_none());
+    }
+
+    #[test]
+    fn test_newer_schema_loads_without_rewrite() {
+        let temp_dir = TempDir::new().unwrap();
+        let state_file = temp_dir.path().join("sessions.json");
+        let session = persisted(SessionStatus::Stale);
+        let json = serde_json::json!({
+            "version": STATE_VERSION + 1,
+            "sessions": { session.id.to_string(): session },
+            "written_by": "a future build",
+        })
+        .to_string();
+        fs::write(&state_file, &json).unwrap();
+
+        let pm = PersistenceManager::with_state_file(state_file.clone()).unwrap();
+        let loaded = pm.load_state().unwrap();
+        assert!(loaded.contains_key(&session.id));
+        assert_eq!(fs::read_to_string(&state_file).unwrap(), json);
+
+        // Saving rewrites it in our schema, but the newer file is kept
+        // and stays put through later saves
+        let kept = temp_dir.path().join(format!("sessions.json.v{}", STATE_VERSION + 1));
+        pm.write_state(&loaded).unwrap();
+        pm.write_state(&HashMap::new()).unwrap();
+        assert_eq!(fs::read_to_string(&kept).unwrap(), json);
+    }
+
+    #[test]
+    fn test_corrupt_state_falls_back_to_backup() {
+        let temp_dir = TempDir::new().unwrap();
+        let state_file = temp_dir.path().join("sessions.json");
+        let pm = PersistenceManager::with_state_file(state_file.clone()).unwrap();
+
+        let first = persisted(SessionStatus::Running);
+        let second = persisted(SessionStatus::Stale);
+        pm.write_state(&HashMap::from([(first.id, first.clone())])).unwrap();
+        // The second write backs up the first
+        pm.write_state(&HashMap::from([(first.id, first.clone()), (second.id, second.clone())]))
+            .unwrap();
+        assert!(temp_dir.path().join("sessions.json.bak").exists());
+
+        // Torn write: the primary is truncated
+        fs::write(&state_file, "{\"version\": 1, \"sess").unwrap();
+        let loaded = pm.load_state().unwrap();
+        assert_eq!(loaded.keys().collect::<Vec<_>>(), vec![&first.id]);
+
+        // Writing over the corrupt primary keeps the good backup
+        pm.write_state(&HashMap::new()).unwrap();
+        fs::write(&state_file, "garbage").unwrap();
+        assert!(pm.load_state().unwrap().contains_key(&first.id));
+
+        // With no usable backup either, loading fails
+        fs::write(temp_dir.path().join("sessions.json.bak"), "garbage").unwrap();
+        assert!(pm.load_state().is_err());
+    }
+
+    pub(super) fn persisted(status: SessionStatus) -> PersistedSession {
+        PersistedSession {
+            id: Uuid::new_v4(),
+            working_dir: PathBuf::from("/tmp/test"),
+            created_at: "2024-01-01T00:00:00Z".to_string(),
+            log_path: PathBuf::from("/tmp/test.log"),
+            pid: None,
+            process_start_time: None,
+            status,
+            exit_code: None,
+            label: String::new(),
+            tags: BTreeMap::new(),
+            last_activity: None,
+        }
+    }
+
+    /// Round-trip contract every backend must satisfy
+    pub(super) fn backend_roundtrip(backend: &dyn Persistence) {
+        assert!(backend.load_state().unwrap().is_empty());
+
+        let first = persisted(SessionStatus::Running);
+        let second = persisted(SessionStatus::Running);
+        let sessions: HashMap<_, _> = [(first.id, first.clone()), (second.id, second.clone())].into();
+        backend.write_state(&sessions).unwrap();
+        assert_eq!(backend.load_state().unwrap().len(), 2);
+
+        // upsert updates one entry and adds another
+        let mut updated = first.clone();
+        updated.status = SessionStatus::Crashed;
+        updated.exit_code = Some(2);
+        backend.upsert(&updated).unwrap();
+        let third = persisted(SessionStatus::Running);
+        backend.upsert(&third).unwrap();
+        let loaded = backend.load_state().unwrap();
+        assert_eq!(loaded.len(), 3);
+        assert_eq!(loaded[&first.id].status, SessionStatus::Crashed);
+        assert_eq!(loaded[&first.id].exit_code, Some(2));
+        assert_eq!(loaded[&third.id].exit_code, None);
+
+        // remove forgets one entry; removing it again is fine
+        backend.remove(second.id).unwrap();
+        backend.remove(second.id).unwrap();
+        assert!(!backend.load_state().unwrap().contains_key(&second.id));
+
+        // write_state replaces everything
+        let only: HashMap<_, _> = [(third.id, third.clone())].into();
+        backend.write_state(&only).unwrap();
+        let loaded = backend.load_state().unwrap();
+        assert_eq!(loaded.keys().collect::<Vec<_>>(), vec![&third.id]);
+        backend.sync_state().unwrap();
+    }
+
+    #[test]
+    fn test_json_backend_contract() {
+        let temp_dir = TempDir::new().unwrap();
+        let backend = PersistenceManager::with_state_file(temp_dir.path().join("sessions.json")).unwrap();
+        backend_roundtrip(&backend);
+    }
+
+    #[test]
+    fn test_session_files_backend_contract() {
+        let temp_dir = TempDir::new().unwrap();
+        let backend = SessionFiles::with_dir(temp_dir.path().join("sessions")).unwrap();
+        backend_roundtrip(&backend);
+    }
+
     #[test]
     fn test_is_process_alive() {
         // Test with current process (should be alive)
@@ -254,4 +828,22 @@ mod tests {
         // Using very high PID that likely doesn't exist
         assert!(!is_process_alive(999999));
     }
+
+    #[test]
+    fn test_is_same_process_checks_start_time() {
+        let current_pid = std::process::id();
+        let start_time = process_start_time(current_pid);
+        #[cfg(any(target_os = "linux", target_os = "macos"))]
+        assert!(start_time.is_some());
+
+        assert!(is_same_process(current_pid, start_time));
+        // Nothing recorded: only liveness can be checked
+        assert!(is_same_process(current_pid, None));
+        assert!(!is_same_process(999999, None));
+
+        // The PID now belongs to a process that started at another time
+        if let Some(start_time) = start_time {
+            assert!(!is_same_process(current_pid, Some(start_time + 1)));
+        }
+    }
 }
@@ -0,0 +1,245 @@
+//! SQLite persistence backend (`--features sqlite`).
+//!
+//! Sessions live in one indexed table, so upserts and removals are single
+//! statements rather than whole-file rewrites, and several readers can
+//! query the database while the daemon writes (WAL mode).
+//!
+//! On first open an existing `sessions.json` next to the database is
+//! imported, so switching backends keeps every known session.
+
+use super::{Persistence, PersistedSession, PersistenceManager};
+use crate::session::SessionStatus;
+use anyhow::{Context, Result};
+use rusqlite::{params, Connection};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use uuid::Uuid;
+
+/// Schema version stored in `PRAGMA user_version`
+///
+/// History:
+/// - 1: sessions table
+/// - 2: `exit_code` column
+/// - 3: `label` column
+/// - 4: `last_activity` column
+/// - 5: `process_start_time` column
+/// - 6: `tags` column (a JSON object)
+const SCHEMA_VERSION: i64 = 6;
+
+pub struct SqlitePersistence {
+    conn: Connection,
+}
+
+impl SqlitePersistence {
+    pub fn new() -> Result<Self> {
+        let dir = super::state_dir()?;
+        std::fs::create_dir_all(&dir)?;
+        Self::open(&dir.join("sessions.db"), Some(&dir.join("sessions.json")))
+    }
+
+    /// Open (creating if needed) the database at `path`.
+    ///
+    /// When the database is new and `json_state` exists, its sessions are
+    /// imported.
+    pub fn open(path: &Path, json_state: Option<&Path>) -> Result<Self> {
+        let conn = Connection::open(path)
+            .with_context(|| format!("Failed to open session database {:?}", path))?;
+        conn.pragma_update(None, "journal_mode", "WAL")?;
+
+        let backend = SqlitePersistence { conn };
+        let version: i64 = backend.conn.pragma_query_value(None, "user_version", |row| row.get(0))?;
+        if version < SCHEMA_VERSION {
+            if version < 1 {
+                backend.conn.execute_batch(
+                    "CREATE TABLE IF NOT EXISTS sessions (
+                         id          TEXT PRIMARY KEY,
+                         working_dir TEXT NOT NULL,
+                         created_at  TEXT NOT NULL,
+                         log_path    TEXT NOT NULL,
+                         pid         INTEGER,
+                         status      TEXT NOT NULL
+                     );
+                     CREATE INDEX IF NOT EXISTS sessions_status ON sessions (status);
+                     CREATE INDEX IF NOT EXISTS sessions_created_at ON sessions (created_at);",
+                )?;
+            }
+            if version < 2 {
+                backend.conn.execute_batch("ALTER TABLE sessions ADD COLUMN exit_code INTEGER;")?;
+            }
+            if version < 3 {
+                backend.conn.execute_batch("ALTER TABLE sessions ADD COLUMN label TEXT NOT NULL DEFAULT '';")?;
+            }
+            if version < 4 {
+                backend.conn.execute_batch("ALTER TABLE sessions ADD COLUMN last_activity TEXT;")?;
+            }
+            if version < 5 {
+                backend.conn.execute_batch("ALTER TABLE sessions ADD COLUMN process_start_time INTEGER;")?;
+            }
+            if version < 6 {
+                backend.conn.execute_batch("ALTER TABLE sessions ADD COLUMN tags TEXT NOT NULL DEFAULT '{}';")?;
+            }
+            if version == 0 {
+                if let Some(json_state) = json_state.filter(|p| p.exists()) {
+                    backend.import_json(json_state)?;
+                }
+            }
+            backend.conn.pragma_update(None, "user_version", SCHEMA_VERSION)?;
+        }
+
+        Ok(backend)
+    }
+
+    /// Copy every session from a `sessions.json` state file
+    fn import_json(&self, json_state: &Path) -> Result<()> {
+        let sessions = PersistenceManager::with_state_file(json_state.to_path_buf())?
+            .load_state()
+            .with_context(|| format!("Failed to import {:?}", json_state))?;
+        self.write_state(&sessions)?;
+        println!("📥 Imported {} session(s) from {:?}", sessions.len(), json_state);
+        Ok(())
+    }
+
+    fn insert(conn: &Connection, session: &PersistedSession) -> Result<()> {
+        conn.execute(
+            "INSERT INTO sessions
+                 (id, working_dir, created_at, log_path, pid, status, exit_code, label, last_activity, process_start_time, tags)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11)
+             ON CONFLICT (id) DO UPDATE SET
+                 working_dir = excluded.working_dir,
+                 created_at = excluded.created_at,
+                 log_path = excluded.log_path,
+                 pid = excluded.pid,
+                 status = excluded.status,
+                 exit_code = excluded.exit_code,
+                 label = excluded.label,
+                 last_activity = excluded.last_activity,
+                 process_start_time = excluded.process_start_time,
+                 tags = excluded.tags",
+            params![
+                session.id.to_string(),
+                session.working_dir.to_string_lossy(),
+                session.created_at,
+                session.log_path.to_string_lossy(),
+                session.pid,
+                session.status.as_str(),
+                session.exit_code,
+                session.label,
+                session.last_activity,
+                // SQLite integers are signed; start times are far below i64::MAX
+                session.process_start_time.map(|t| t as i64),
+                serde_json::to_string(&session.tags)?,
+            ],
+        )
+        .context("Failed to save session")?;
+        Ok(())
+    }
+}
+
+impl Persistence for SqlitePersistence {
+    fn load_state(&self) -> Result<HashMap<Uuid, PersistedSession>> {
+        let mut statement = self.conn.prepare(
+            "SELECT id, working_dir, created_at, log_path, pid, status, exit_code, label, last_activity, process_start_time, tags
+             FROM sessions",
+        )?;
+        let rows = statement.query_map([], |row| {
+            Ok((
+                row.get::<_, String>(0)?,
+                row.get::<_, String>(1)?,
+                row.get::<_, String>(2)?,
+                row.get::<_, String>(3)?,
+                row.get::<_, Option<u32>>(4)?,
+                row.get::<_, String>(5)?,
+                row.get::<_, Option<u32>>(6)?,
+                row.get::<_, String>(7)?,
+                row.get::<_, Option<String>>(8)?,
+                row.get::<_, Option<i64>>(9)?,
+                row.get::<_, String>(10)?,
+            ))
+        })?;
+
+        let mut sessions = HashMap::new();
+        for row in rows {
+            let (id, working_dir, created_at, log_path, pid, status, exit_code, label, last_activity, process_start_time, tags) =
+                row?;
+            let id = Uuid::parse_str(&id).with_context(|| format!("Invalid session id in database: {}", id))?;
+            sessions.insert(
+                id,
+                PersistedSession {
+                    id,
+                    working_dir: PathBuf::from(working_dir),
+                    created_at,
+                    log_path: PathBuf::from(log_path),
+                    pid,
+                    process_start_time: process_start_time.map(|t| t as u64),
+                    status: SessionStatus::from(status),
+                    exit_code,
+                    label,
+                    tags: serde_json::from_str(&tags).unwrap_or_default(),
+                    last_activity,
+                },
+            );
+        }
+        Ok(sessions)
+    }
+
+    fn write_state(&self, sessions: &HashMap<Uuid, PersistedSession>) -> Result<()> {
+        let tx = self.conn.unchecked_transaction()?;
+        tx.execute("DELETE FROM sessions", [])?;
+        for session in sessions.values() {
+            Self::insert(&tx, session)?;
+        }
+        tx.commit()?;
+        Ok(())
+    }
+
+    fn upsert(&self, session: &PersistedSession) -> Result<()> {
+        Self::insert(&self.conn, session)
+    }
+
+    fn remove(&self, session_id: Uuid) -> Result<()> {
+        self.conn
+            .execute("DELETE FROM sessions WHERE id = ?1", params![session_id.to_string()])
+            .context("Failed to remove session")?;
+        Ok(())
+    }
+
+    fn sync_state(&self) -> Result<()> {
+        // Fold the WAL into the main database file
+        self.conn.pragma_query(None, "wal_checkpoint", |_| Ok(()))?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_sqlite_backend_contract() {
+        let temp_dir = TempDir::new().unwrap();
+        let backend = SqlitePersistence::open(&temp_dir.path().join("sessions.db"), None).unwrap();
+        super::super::tests::backend_roundtrip(&backend);
+    }
+
+    #[test]
+    fn test_sqlite_imports_json_state() {
+        let temp_dir = TempDir::new().unwrap();
+        let json_path = temp_dir.path().join("sessions.json");
+        let json = PersistenceManager::with_state_file(json_path.clone()).unwrap();
+        let session = super::super::tests::persisted(SessionStatus::Running);
+        json.write_state(&[(session.id, session.clone())].into()).unwrap();
+
+        let db_path = temp_dir.path().join("sessions.db");
+        let backend = SqlitePersistence::open(&db_path, Some(&json_path)).unwrap();
+        let loaded = backend.load_state().unwrap();
+        assert_eq!(loaded.len(), 1);
+        assert_eq!(loaded[&session.id].working_dir, session.working_dir);
+
+        // Only imported once: later opens keep the database's own state
+        backend.remove(session.id).unwrap();
+        drop(backend);
+        let reopened = SqlitePersistence::open(&db_path, Some(&json_path)).unwrap();
+        assert!(reopened.load_state().unwrap().is_empty());
+    }
+}
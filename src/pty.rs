@@ -1,18 +1,155 @@
 use anyhow::{Context, Result};
-use portable_pty::{CommandBuilder, MasterPty, PtyPair, PtySize};
+use portable_pty::{Child, CommandBuilder, ExitStatus, PtyPair, PtySize};
 use std::io::{Read, Write};
-use std::path::Path;
-use std::sync::Arc;
-use tokio::sync::mpsc;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::{mpsc, watch};
 use uuid::Uuid;
 
-use crate::logging::{Direction, SessionLogger};
+use crate::logging::{Direction, LaunchInfo, SessionLogger};
+
+/// Which of the daemon's own environment variables sessions inherit.
+///
+/// Without configuration everything passes, as before. Configure with
+/// comma-separated names in `CLAUDE_SESSIONS_ENV_ALLOW` (only these pass)
+/// and `CLAUDE_SESSIONS_ENV_DENY` (never pass); a trailing `*` matches a
+/// prefix, e.g. `LC_*`. Deny wins over allow. Per-session `--env` values
+/// are always added on top.
+#[derive(Debug, Clone, Default)]
+pub struct EnvPolicy {
+    pub allow: Option<Vec<String>>,
+    pub deny: Vec<String>,
+}
+
+impl EnvPolicy {
+    /// Build a policy from the daemon's environment
+    pub fn from_env() -> Self {
+        let list = |name| {
+            std::env::var(name).ok().map(|value| {
+                value
+                    .split(',')
+                    .map(str::trim)
+                    .filter(|s| !s.is_empty())
+                    .map(String::from)
+                    .collect::<Vec<_>>()
+            })
+        };
+        EnvPolicy {
+            allow: list("CLAUDE_SESSIONS_ENV_ALLOW"),
+            deny: list("CLAUDE_SESSIONS_ENV_DENY").unwrap_or_default(),
+        }
+    }
+
+    /// Whether either list is configured
+    pub fn is_set(&self) -> bool {
+        self.allow.is_some() || !self.deny.is_empty()
+    }
+
+    /// Whether the daemon variable `name` reaches sessions
+    pub fn passes(&self, name: &str) -> bool {
+        let matches = |pattern: &String| match pattern.strip_suffix('*') {
+            Some(prefix) => name.starts_with(prefix),
+            None => name == pattern,
+        };
+        let allowed = self.allow.as_ref().is_none_or(|allow| allow.iter().any(matches));
+        allowed && !self.deny.iter().any(matches)
+    }
+}
+
+/// Which program sessions run, and with what arguments.
+///
+/// The program is `claude` from `PATH` unless `claude_bin` in the config
+/// file or `CLAUDE_SESSIONS_BIN` names another one (a different name, a
+/// wrapper script, an absolute path).
+/// Arguments come per session, e.g. `start --claude-arg=--model --claude-arg=opus`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ClaudeBinary {
+    pub program: String,
+    pub args: Vec<String>,
+}
+
+impl Default for ClaudeBinary {
+    fn default() -> Self {
+        ClaudeBinary { program: "claude".to_string(), args: Vec::new() }
+    }
+}
+
+impl ClaudeBinary {
+    pub fn with_args(mut self, args: Vec<String>) -> Self {
+        self.args = args;
+        self
+    }
+
+    /// Find the program: a name containing `/` is taken as a path, anything
+    /// else is looked up in `search_path` (a `PATH`-style list).
+    ///
+    /// Fails naming what was tried, so a missing binary doesn't surface as
+    /// a bare spawn error.
+    pub fn resolve(&self, search_path: Option<&std::ffi::OsStr>) -> Result<PathBuf> {
+        let hint = "set claude_bin in ~/.claude-sessions/config.toml or CLAUDE_SESSIONS_BIN to the claude executable";
+        if self.program.contains('/') {
+            let path = PathBuf::from(&self.program);
+            if path.is_file() {
+                return Ok(path);
+            }
+            anyhow::bail!("Claude binary not found at {} ({})", path.display(), hint);
+        }
+        search_path
+            .into_iter()
+            .flat_map(std::env::split_paths)
+            .map(|dir| dir.join(&self.program))
+            .find(|candidate| candidate.is_file())
+            .with_context(|| format!("Claude binary `{}` not found on PATH ({})", self.program, hint))
+    }
+}
+
+/// Build the command that runs `program` (see `ClaudeBinary`): the daemon
+/// variables `policy` lets through (from `daemon_env`), then the session's
+/// own `extra_env`. Session variables extend the inherited environment and
+/// win over a daemon variable of the same name.
+pub fn claude_command(
+    working_dir: &Path,
+    program: &Path,
+    args: &[String],
+    policy: &EnvPolicy,
+    daemon_env: impl IntoIterator<Item = (std::ffi::OsString, std::ffi::OsString)>,
+    extra_env: &[(String, String)],
+) -> CommandBuilder {
+    let mut cmd = CommandBuilder::new(program);
+    cmd.args(args);
+    cmd.cwd(working_dir);
+
+    // Without a policy the command keeps the environment it inherits,
+    // untouched, down to variables that aren't valid UTF-8
+    if policy.is_set() {
+        // Start from nothing so the result doesn't depend on how the daemon
+        // was launched beyond what the policy admits
+        cmd.env_clear();
+        for (name, value) in daemon_env {
+            if policy.passes(&name.to_string_lossy()) {
+                cmd.env(name, value);
+            }
+        }
+    }
+    for (name, value) in extra_env {
+        cmd.env(name, value);
+    }
+    cmd
+}
 
 /// Spawns a Claude Code session as a PTY subprocess.
 /// 
 /// Important: We treat `claude` CLI as a black box.
 /// We simply spawn it in the given working directory and let it run.
-pub fn spawn_claude_pty(working_dir: &Path) -> Result<PtyPair> {
+/// Also returns what was run, for the session log.
+pub fn spawn_claude_pty(
+    working_dir: &Path,
+    claude: &ClaudeBinary,
+    extra_env: &[(String, String)],
+) -> Result<(PtyPair, Box<dyn Child + Send + Sync>, LaunchInfo)> {
+    let program = claude.resolve(std::env::var_os("PATH").as_deref())?;
+
     // Create a PTY pair (master + slave)
     let pty_system = portable_pty::native_pty_system();
     let pair = pty_system
@@ -25,72 +162,333 @@ pub fn spawn_claude_pty(working_dir: &Path) -> Result<PtyPair> {
         .context("Failed to create PTY pair")?;
 
     // Build the command to spawn `claude`
-    let mut cmd = CommandBuilder::new("claude");
-    cmd.cwd(working_dir);
+    let policy = EnvPolicy::from_env();
+    let daemon_env: Vec<_> = std::env::vars_os().collect();
+    let mut env_keys: Vec<String> = daemon_env
+        .iter()
+        .filter_map(|(name, _)| name.to_str())
+        .filter(|name| policy.passes(name))
+        .map(String::from)
+        .chain(extra_env.iter().map(|(name, _)| name.clone()))
+        .collect();
+    env_keys.sort();
+    env_keys.dedup();
+    let launch = LaunchInfo {
+        program: program.display().to_string(),
+        args: claude.args.clone(),
+        working_dir: working_dir.display().to_string(),
+        env_keys,
+    };
+    let cmd = claude_command(working_dir, &program, &claude.args, &policy, daemon_env, extra_env);
 
     // Spawn the process in the PTY slave
-    let _child = pair
+    let child = pair
         .slave
         .spawn_command(cmd)
-        .context("Failed to spawn claude process")?;
+        .with_context(|| format!("Failed to spawn {}", program.display()))?;
 
-    // Note: We return the PtyPair. The caller is responsible for:
+    // Note: We return the PtyPair and the child. The caller is responsible for:
     // - Keeping the master alive to interact with the PTY
-    // - Managing the child process lifecycle
-    Ok(pair)
+    // - Reaping the child (`SessionProcess` does this in a waiter task)
+    Ok((pair, child, launch))
 }
 
 /// SessionProcess holds the PTY pair for a running Claude session
 /// and manages I/O logging.
+///
+/// The PTY pair sits behind a `Mutex` so the process (and with it the
+/// `SessionManager`) is `Send + Sync` and can be used from spawned
+/// connection handlers.
 pub struct SessionProcess {
-    pub pty_pair: Arc<PtyPair>,
+    pub pty_pair: Arc<Mutex<PtyPair>>,
+    /// The master's writer; `take_writer` only hands it out once
+    writer: Arc<Mutex<Box<dyn Write + Send>>>,
+    /// The one open handle on the session log, shared by input and the
+    /// output reader
+    logger: Arc<Mutex<SessionLogger>>,
     session_id: Uuid,
+    log_path: PathBuf,
     output_task: Option<tokio::task::JoinHandle<()>>,
     shutdown_tx: Option<mpsc::Sender<()>>,
+    exit_rx: watch::Receiver<bool>,
+    status_rx: watch::Receiver<Option<ExitStatus>>,
+    pid: Option<u32>,
 }
 
+/// How long `terminate` waits for a SIGKILLed child to be reaped
+#[cfg(unix)]
+pub(crate) const KILL_WAIT: Duration = Duration::from_secs(5);
+
+/// How long `close` waits for the output reader to drain and stop
+const READER_WAIT: Duration = Duration::from_secs(2);
+
+/// How long input may wait for room in the PTY (Claude not reading it)
+/// before the write fails
+const WRITE_TIMEOUT: Duration = Duration::from_secs(10);
+
 impl SessionProcess {
-    /// Create a new session process with logging enabled
-    pub fn new(session_id: Uuid, pty_pair: PtyPair) -> Result<Self> {
-        let pty_pair = Arc::new(pty_pair);
+    /// Create a new session process logging to `log_path`, never rotating
+    /// the log.
+    ///
+    /// `child` is the process running in the PTY; it is moved into a
+    /// waiter task that reaps it and publishes its exit status.
+    #[cfg(test)]
+    pub fn new(
+        session_id: Uuid,
+        pty_pair: PtyPair,
+        child: Box<dyn Child + Send + Sync>,
+        log_path: PathBuf,
+    ) -> Result<Self> {
+        Self::with_max_log_bytes(session_id, pty_pair, child, log_path, None)
+    }
+
+    /// `new`, rotating the log once it reaches `max_log_bytes`
+    pub fn with_max_log_bytes(
+        session_id: Uuid,
+        pty_pair: PtyPair,
+        child: Box<dyn Child + Send + Sync>,
+        log_path: PathBuf,
+        max_log_bytes: Option<u64>,
+    ) -> Result<Self> {
+        let writer = pty_pair.master.take_writer().context("Failed to get PTY writer")?;
+        let logger = SessionLogger::with_path(session_id, log_path.clone())
+            .with_context(|| format!("Failed to open log {}", log_path.display()))?
+            .with_max_bytes(max_log_bytes);
+        let logger = Arc::new(Mutex::new(logger));
+        let pty_pair = Arc::new(Mutex::new(pty_pair));
         let (shutdown_tx, shutdown_rx) = mpsc::channel(1);
+        let (exit_tx, exit_rx) = watch::channel(false);
 
         // Spawn PTY output reader task
         let output_task = Self::spawn_output_reader(
             session_id,
+            Arc::clone(&logger),
             Arc::clone(&pty_pair),
             shutdown_rx,
+            exit_tx,
         )?;
+        let pid = child.process_id();
+        let status_rx = Self::spawn_waiter(session_id, child);
 
         Ok(SessionProcess {
             pty_pair,
+            writer: Arc::new(Mutex::new(writer)),
+            logger,
             session_id,
+            log_path,
             output_task: Some(output_task),
             shutdown_tx: Some(shutdown_tx),
+            exit_rx,
+            status_rx,
+            pid,
         })
     }
 
+    /// OS process id of the child, if the PTY backend reports one
+    pub fn pid(&self) -> Option<u32> {
+        self.pid
+    }
+
+    /// The PTY master's descriptor, if the PTY backend has one
+    #[cfg(unix)]
+    pub fn master_fd(&self) -> Option<std::os::fd::RawFd> {
+        self.pty_pair.lock().unwrap().master.as_raw_fd()
+    }
+
+    /// Stop dropping this process from ending its input: portable-pty's
+    /// writer sends a newline and EOF on drop, which would end a session
+    /// whose terminal is handed on (see `crate::reattach`). Input is
+    /// discarded from then on.
+    #[cfg(unix)]
+    pub fn keep_input_open(&self) {
+        let writer = std::mem::replace(&mut *self.writer.lock().unwrap(), Box::new(std::io::sink()));
+        std::mem::forget(writer);
+    }
+
+    /// Make sure the child is gone: SIGTERM, wait up to `grace` for it to
+    /// exit, then SIGKILL.
+    ///
+    /// Returns `true` if it had to be killed.
+    #[cfg(unix)]
+    pub async fn terminate(&self, grace: Duration) -> Result<bool> {
+        // Already reaped: the PID may belong to someone else by now
+        if self.status_rx.borrow().is_some() {
+            return Ok(false);
+        }
+        let pid = self.pid.context("Session process has no PID")? as libc::pid_t;
+
+        unsafe { libc::kill(pid, libc::SIGTERM) };
+        if tokio::time::timeout(grace, self.wait_exit()).await.is_ok() {
+            return Ok(false);
+        }
+
+        unsafe { libc::kill(pid, libc::SIGKILL) };
+        tokio::time::timeout(KILL_WAIT, self.wait_exit())
+            .await
+            .with_context(|| format!("Process {} survived SIGKILL", pid))?;
+        Ok(true)
+    }
+
+    /// Without signals, dropping the PTY is all we can do
+    #[cfg(not(unix))]
+    pub async fn terminate(&self, _grace: Duration) -> Result<bool> {
+        Ok(false)
+    }
+
+    /// Stop the output reader and wait for it to finish, fsync the log,
+    /// then release the PTY.
+    ///
+    /// Call after `terminate`, so the reader has seen the last output. A
+    /// reader that doesn't stop within `READER_WAIT` is abandoned.
+    pub async fn close(mut self) {
+        if let Some(tx) = self.shutdown_tx.take() {
+            let _ = tx.try_send(());
+        }
+        if let Some(mut handle) = self.output_task.take() {
+            if tokio::time::timeout(READER_WAIT, &mut handle).await.is_err() {
+                eprintln!("⚠️  Output reader for session {} didn't stop; abandoning it", self.session_id);
+                handle.abort();
+            }
+        }
+        if let Err(e) = self.sync_log() {
+            eprintln!("⚠️  Failed to sync the log of session {}: {}", self.session_id, e);
+        }
+    }
+
+    /// Flush and fsync the session log (see `SessionLogger::sync`)
+    pub fn sync_log(&self) -> Result<()> {
+        self.logger.lock().unwrap().sync()
+    }
+
+    /// Wait for the child process to exit and return its status.
+    ///
+    /// Any number of callers can await this concurrently; all of them see
+    /// the same status, and callers arriving after the exit get it at once.
+    pub async fn wait_exit(&self) -> ExitStatus {
+        let mut status_rx = self.status_rx.clone();
+        let status = status_rx.wait_for(|status| status.is_some()).await.map(|status| status.clone());
+        match status {
+            Ok(status) => status.expect("waited for Some"),
+            // The waiter task is gone without reporting (it panicked)
+            Err(_) => ExitStatus::with_exit_code(1),
+        }
+    }
+
+    /// Move the child into a blocking task that waits for it to exit and
+    /// broadcasts the status
+    fn spawn_waiter(
+        session_id: Uuid,
+        mut child: Box<dyn Child + Send + Sync>,
+    ) -> watch::Receiver<Option<ExitStatus>> {
+        let (status_tx, status_rx) = watch::channel(None);
+
+        tokio::task::spawn_blocking(move || {
+            let status = child.wait().unwrap_or_else(|e| {
+                eprintln!("Failed to wait for session {}: {}", session_id, e);
+                ExitStatus::with_exit_code(1)
+            });
+            let _ = status_tx.send(Some(status));
+        });
+
+        status_rx
+    }
+
+    /// Watch that holds the child's exit status once it has exited
+    pub fn status_watch(&self) -> watch::Receiver<Option<ExitStatus>> {
+        self.status_rx.clone()
+    }
+
+    /// Watch that flips to `true` once the output reader has stopped
+    /// (the PTY hit EOF or the process was shut down)
+    pub fn exit_watch(&self) -> watch::Receiver<bool> {
+        self.exit_rx.clone()
+    }
+
     /// Spawn a background task to read PTY output and log it
+    ///
+    /// On Unix the PTY master is registered with tokio's reactor so reads
+    /// are event-driven and don't tie up a blocking thread. If that isn't
+    /// possible (non-Unix, or the master exposes no raw fd) we fall back to
+    /// the blocking reader.
     fn spawn_output_reader(
         session_id: Uuid,
-        pty_pair: Arc<PtyPair>,
+        logger: Arc<Mutex<SessionLogger>>,
+        pty_pair: Arc<Mutex<PtyPair>>,
+        shutdown_rx: mpsc::Receiver<()>,
+        exit_tx: watch::Sender<bool>,
+    ) -> Result<tokio::task::JoinHandle<()>> {
+        #[cfg(unix)]
+        {
+            let fd = pty_pair.lock().unwrap().master.as_raw_fd();
+            if let Some(fd) = fd {
+                match async_reader::PtyFd::dup_nonblocking(fd)
+                    .and_then(tokio::io::unix::AsyncFd::new)
+                {
+                    Ok(async_fd) => {
+                        return Ok(Self::spawn_async_reader(
+                            session_id,
+                            logger,
+                            async_fd,
+                            shutdown_rx,
+                            exit_tx,
+                        ));
+                    }
+                    Err(e) => {
+                        eprintln!(
+                            "Falling back to blocking PTY reader for session {}: {}",
+                            session_id, e
+                        );
+                    }
+                }
+            }
+        }
+
+        Self::spawn_blocking_reader(session_id, logger, pty_pair, shutdown_rx, exit_tx)
+    }
+
+    /// Event-driven output reader (Unix)
+    #[cfg(unix)]
+    fn spawn_async_reader(
+        session_id: Uuid,
+        logger: Arc<Mutex<SessionLogger>>,
+        async_fd: tokio::io::unix::AsyncFd<async_reader::PtyFd>,
         mut shutdown_rx: mpsc::Receiver<()>,
+        exit_tx: watch::Sender<bool>,
+    ) -> tokio::task::JoinHandle<()> {
+        tokio::spawn(async move {
+            let result = async_reader::read_loop(&async_fd, &mut shutdown_rx, |data| {
+                if let Err(e) = logger.lock().unwrap().log(Direction::Output, data.to_vec()) {
+                    eprintln!("Failed to log output for session {}: {}", session_id, e);
+                }
+            })
+            .await;
+
+            if let Err(e) = result {
+                eprintln!("Error reading from PTY for session {}: {}", session_id, e);
+            }
+
+            println!("PTY output reader stopped for session {}", session_id);
+            let _ = exit_tx.send(true);
+        })
+    }
+
+    /// Portable blocking output reader (fallback)
+    fn spawn_blocking_reader(
+        session_id: Uuid,
+        logger: Arc<Mutex<SessionLogger>>,
+        pty_pair: Arc<Mutex<PtyPair>>,
+        mut shutdown_rx: mpsc::Receiver<()>,
+        exit_tx: watch::Sender<bool>,
     ) -> Result<tokio::task::JoinHandle<()>> {
         // Clone the master reader for the background task
         let mut reader = pty_pair
+            .lock()
+            .unwrap()
             .master
             .try_clone_reader()
             .context("Failed to clone PTY reader")?;
 
         let handle = tokio::task::spawn_blocking(move || {
-            let mut logger = match SessionLogger::new(session_id) {
-                Ok(l) => l,
-                Err(e) => {
-                    eprintln!("Failed to create logger for session {}: {}", session_id, e);
-                    return;
-                }
-            };
-
             let mut buffer = [0u8; 8192];
 
             loop {
@@ -107,7 +505,7 @@ impl SessionProcess {
                     }
                     Ok(n) => {
                         let data = buffer[..n].to_vec();
-                        if let Err(e) = logger.log(Direction::Output, data) {
+                        if let Err(e) = logger.lock().unwrap().log(Direction::Output, data) {
                             eprintln!("Failed to log output for session {}: {}", session_id, e);
                         }
                     }
@@ -124,27 +522,72 @@ impl SessionProcess {
             }
 
             println!("PTY output reader stopped for session {}", session_id);
+            let _ = exit_tx.send(true);
         });
 
         Ok(handle)
     }
 
-    /// Write input to the PTY and log it
-    pub fn write_input(&self, data: &[u8]) -> Result<()> {
-        // Write to PTY master
-        let mut writer = self
-            .pty_pair
+    /// Write input to the PTY and log it (see `InputWriter::write`)
+    #[cfg(test)]
+    pub async fn write_input(&self, data: &[u8]) -> Result<()> {
+        self.input_writer().write(data).await
+    }
+
+    /// A handle for writing input that doesn't borrow the process, so a
+    /// slow write doesn't hold up whoever holds the process
+    pub fn input_writer(&self) -> InputWriter {
+        InputWriter {
+            pty_pair: Arc::clone(&self.pty_pair),
+            writer: Arc::clone(&self.writer),
+            logger: Arc::clone(&self.logger),
+        }
+    }
+
+    /// When input or output was last logged for this session
+    pub fn last_activity(&self) -> Option<std::time::SystemTime> {
+        self.logger.lock().unwrap().last_logged()
+    }
+
+    /// Whether the process was sent input at least `threshold` before `now`
+    /// and has produced no output since
+    pub fn is_unresponsive(&self, threshold: Duration, now: std::time::SystemTime) -> bool {
+        self.logger
+            .lock()
+            .unwrap()
+            .awaiting_output_since()
+            .and_then(|since| now.duration_since(since).ok())
+            .is_some_and(|silent| silent >= threshold)
+    }
+
+    /// Resize the PTY (the child gets SIGWINCH)
+    pub fn resize(&self, rows: u16, cols: u16) -> Result<()> {
+        self.pty_pair
+            .lock()
+            .unwrap()
             .master
-            .take_writer()
-            .context("Failed to get PTY writer")?;
-        writer.write_all(data)?;
-        writer.flush()?;
+            .resize(PtySize {
+                rows,
+                cols,
+                pixel_width: 0,
+                pixel_height: 0,
+            })
+            .context("Failed to resize PTY")
+    }
 
-        // Log the input
-        let mut logger = SessionLogger::new(self.session_id)?;
-        logger.log(Direction::Input, data.to_vec())?;
+    /// Current size of the PTY
+    pub fn pty_size(&self) -> Result<PtySize> {
+        self.pty_pair
+            .lock()
+            .unwrap()
+            .master
+            .get_size()
+            .context("Failed to get PTY size")
+    }
 
-        Ok(())
+    /// Path of this session's log file
+    pub fn log_path(&self) -> &Path {
+        &self.log_path
     }
 
     /// Get the session ID
@@ -160,25 +603,545 @@ impl Drop for SessionProcess {
             let _ = tx.try_send(());
         }
 
-        // Note: We don't wait for the output reader to finish here
-        // to avoid blocking during Drop. The task will clean up on its own.
-        // The PTY will be closed when pty_pair is dropped.
+        // Drop can't wait for the output reader (use `close` for that);
+        // it stops on the signal or once the PTY is closed.
         if let Some(_handle) = self.output_task.take() {
             // Task will notice PTY closure and exit naturally
         }
     }
 }
 
+/// Writes a session's input (see `SessionProcess::input_writer`)
+#[derive(Clone)]
+pub struct InputWriter {
+    pty_pair: Arc<Mutex<PtyPair>>,
+    writer: Arc<Mutex<Box<dyn Write + Send>>>,
+    logger: Arc<Mutex<SessionLogger>>,
+}
+
+impl InputWriter {
+    /// Write input to the PTY and log it.
+    ///
+    /// A paste bigger than the PTY buffers waits for Claude to read it, so
+    /// the write runs on the blocking pool, and fails once it has waited
+    /// `WRITE_TIMEOUT`.
+    pub async fn write(&self, data: &[u8]) -> Result<()> {
+        let input = self.clone();
+        let data = data.to_vec();
+        tokio::task::spawn_blocking(move || input.write_blocking(data, WRITE_TIMEOUT))
+            .await
+            .context("PTY write task failed")?
+    }
+
+    fn write_blocking(&self, data: Vec<u8>, timeout: Duration) -> Result<()> {
+        let deadline = std::time::Instant::now() + timeout;
+        // Numbered before Claude can see it, so the echo and any reply
+        // come after it in `seq` order
+        let mut writer = self.writer.lock().unwrap();
+        let seq = self.logger.lock().unwrap().take_seq();
+
+        // Write to PTY master
+        write_all_waiting(&mut **writer, &data, deadline, |timeout| wait_writable(&self.pty_pair, timeout))?;
+        writer.flush()?;
+        drop(writer);
+
+        // Log the input
+        self.logger.lock().unwrap().log_numbered(Direction::Input, data, seq)?;
+
+        Ok(())
+    }
+}
+
+/// `write_all` for the PTY master, which may be non-blocking (see
+/// `async_reader`): on `WouldBlock`, `wait` (at most the given time) until
+/// the child has read some input, then carry on. Fails with `TimedOut` if
+/// there is still no room at `deadline`.
+fn write_all_waiting(
+    writer: &mut dyn Write,
+    mut data: &[u8],
+    deadline: std::time::Instant,
+    wait: impl Fn(Duration),
+) -> std::io::Result<()> {
+    while !data.is_empty() {
+        match writer.write(data) {
+            Ok(0) => return Err(std::io::ErrorKind::WriteZero.into()),
+            Ok(written) => data = &data[written..],
+            Err(e) if e.kind() == std::io::ErrorKind::Interrupted => {}
+            Err(e) if e.kind() == std::io::ErrorKind::WouldBlock => {
+                let left = deadline.saturating_duration_since(std::time::Instant::now());
+                if left.is_zero() {
+                    return Err(std::io::Error::new(
+                        std::io::ErrorKind::TimedOut,
+                        "Timed out waiting for the session to read its input",
+                    ));
+                }
+                wait(left);
+            }
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(())
+}
+
+/// Wait (briefly, and at most `timeout`) for room in the PTY's input
+/// buffer
+fn wait_writable(pty_pair: &Mutex<PtyPair>, timeout: Duration) {
+    let timeout = timeout.min(Duration::from_millis(100));
+    #[cfg(unix)]
+    {
+        // Not locked while waiting, so a resize isn't held up
+        let fd = pty_pair.lock().unwrap().master.as_raw_fd();
+        if let Some(fd) = fd {
+            let mut poll_fd = libc::pollfd { fd, events: libc::POLLOUT, revents: 0 };
+            unsafe { libc::poll(&mut poll_fd, 1, timeout.as_millis() as libc::c_int) };
+            return;
+        }
+    }
+    std::thread::sleep(timeout.min(Duration::from_millis(5)));
+}
+
+/// Non-blocking PTY reads driven by tokio's reactor.
+///
+/// We `dup` the master fd so the reader owns its own descriptor, switch it
+/// to `O_NONBLOCK` and wrap it in `AsyncFd`. `O_NONBLOCK` lives on the open
+/// file description, so the PTY writer sees it too: a paste bigger than the
+/// PTY's input buffer gets `WouldBlock`, which `InputWriter::write` waits out.
+#[cfg(unix)]
+mod async_reader {
+    use std::io;
+    use std::os::fd::{AsRawFd, FromRawFd, OwnedFd, RawFd};
+    use tokio::io::unix::AsyncFd;
+    use tokio::sync::mpsc;
+
+    /// An owned, non-blocking file descriptor
+    pub struct PtyFd(OwnedFd);
+
+    impl PtyFd {
+        /// Duplicate `fd` and put the copy into non-blocking mode
+        pub fn dup_nonblocking(fd: RawFd) -> io::Result<Self> {
+            let dup = unsafe { libc::dup(fd) };
+            if dup < 0 {
+                return Err(io::Error::last_os_error());
+            }
+            let owned = unsafe { OwnedFd::from_raw_fd(dup) };
+
+            let flags = unsafe { libc::fcntl(dup, libc::F_GETFL) };
+            if flags < 0 {
+                return Err(io::Error::last_os_error());
+            }
+            if unsafe { libc::fcntl(dup, libc::F_SETFL, flags | libc::O_NONBLOCK) } < 0 {
+                return Err(io::Error::last_os_error());
+            }
+
+            Ok(PtyFd(owned))
+        }
+
+        fn read(&self, buf: &mut [u8]) -> io::Result<usize> {
+            let n = unsafe {
+                libc::read(
+                    self.0.as_raw_fd(),
+                    buf.as_mut_ptr() as *mut libc::c_void,
+                    buf.len(),
+                )
+            };
+            if n < 0 {
+                Err(io::Error::last_os_error())
+            } else {
+                Ok(n as usize)
+            }
+        }
+    }
+
+    impl AsRawFd for PtyFd {
+        fn as_raw_fd(&self) -> RawFd {
+            self.0.as_raw_fd()
+        }
+    }
+
+    /// Read until EOF or shutdown, handing each chunk to `on_data`
+    pub async fn read_loop<F: FnMut(&[u8])>(
+        fd: &AsyncFd<PtyFd>,
+        shutdown_rx: &mut mpsc::Receiver<()>,
+        mut on_data: F,
+    ) -> io::Result<()> {
+        let mut buffer = [0u8; 8192];
+
+        loop {
+            tokio::select! {
+                _ = shutdown_rx.recv() => return Ok(()),
+                ready = fd.readable() => {
+                    let mut guard = ready?;
+                    match guard.try_io(|inner| inner.get_ref().read(&mut buffer)) {
+                        // EOF - Claude process exited
+                        Ok(Ok(0)) => return Ok(()),
+                        Ok(Ok(n)) => on_data(&buffer[..n]),
+                        // Linux reports EIO on the master once the slave side closes
+                        Ok(Err(e)) if e.raw_os_error() == Some(libc::EIO) => return Ok(()),
+                        Ok(Err(e)) => return Err(e),
+                        // Spurious wakeup, readiness was cleared
+                        Err(_would_block) => continue,
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Fixtures for tests that need a live session process
+#[cfg(test)]
+pub mod test_support {
+    use super::*;
+
+    /// `cat` in a PTY, standing in for a running Claude that logs to `log_path`
+    pub fn cat_process(session_id: Uuid, log_path: &Path) -> SessionProcess {
+        let pair = portable_pty::native_pty_system().openpty(PtySize::default()).unwrap();
+        let child = pair.slave.spawn_command(CommandBuilder::new("cat")).unwrap();
+        SessionProcess::new(session_id, pair, child, log_path.to_path_buf()).unwrap()
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
-    use std::path::PathBuf;
+    use super::test_support::cat_process;
+
+    #[test]
+    fn test_env_allowlist_limits_inherited_vars() {
+        let policy = EnvPolicy {
+            allow: Some(vec!["PATH".to_string(), "LC_*".to_string(), "SECRET_TOKEN".to_string()]),
+            deny: vec!["SECRET_*".to_string()],
+        };
+        let daemon_env = [
+            ("PATH", "/usr/bin"),
+            ("LC_ALL", "C.UTF-8"),
+            ("HOME", "/root"),
+            ("SECRET_TOKEN", "hunter2"),
+        ]
+        .map(|(k, v)| (std::ffi::OsString::from(k), std::ffi::OsString::from(v)));
+
+        let extra = [("ANTHROPIC_MODEL".to_string(), "opus".to_string())];
+
+        let cmd = claude_command(Path::new("/tmp"), Path::new("claude"), &[], &policy, daemon_env, &extra);
+
+        assert_eq!(cmd.get_env("PATH").unwrap(), "/usr/bin");
+        assert_eq!(cmd.get_env("LC_ALL").unwrap(), "C.UTF-8");
+        assert!(cmd.get_env("HOME").is_none());
+        assert!(cmd.get_env("SECRET_TOKEN").is_none());
+        assert_eq!(cmd.get_env("ANTHROPIC_MODEL").unwrap(), "opus");
+
+        // Session values override inherited ones
+        let policy = EnvPolicy { allow: None, deny: vec!["SECRET_*".to_string()] };
+        let daemon_env = [("PATH", "/usr/bin"), ("ANTHROPIC_API_KEY", "daemon-key")]
+            .map(|(k, v)| (std::ffi::OsString::from(k), std::ffi::OsString::from(v)));
+        let extra = [("ANTHROPIC_API_KEY".to_string(), "session-key".to_string())];
+        let cmd = claude_command(Path::new("/tmp"), Path::new("claude"), &[], &policy, daemon_env, &extra);
+        assert_eq!(cmd.get_env("ANTHROPIC_API_KEY").unwrap(), "session-key");
+        assert_eq!(cmd.get_env("PATH").unwrap(), "/usr/bin");
+
+        // No configuration: everything passes
+        assert!(EnvPolicy::default().passes("HOME"));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_env_policy_keeps_non_utf8_vars() {
+        use std::ffi::OsString;
+        use std::os::unix::ffi::OsStringExt;
+
+        let name = OsString::from_vec(b"LATIN1_\xe9".to_vec());
+        let value = OsString::from_vec(b"caf\xe9".to_vec());
+
+        // A deny list doesn't drop names it can't read as UTF-8
+        let policy = EnvPolicy { allow: None, deny: vec!["SECRET_*".to_string()] };
+        let cmd = claude_command(Path::new("/tmp"), Path::new("claude"), &[], &policy, [(name.clone(), value.clone())], &[]);
+        assert_eq!(cmd.get_env(&name).unwrap(), value.as_os_str());
+
+        // Without a policy the inherited environment is left alone
+        let cmd = claude_command(Path::new("/tmp"), Path::new("claude"), &[], &EnvPolicy::default(), [(name.clone(), value)], &[]);
+        assert!(cmd.get_env(&name).is_none());
+        assert_eq!(cmd.get_env("PATH"), std::env::var_os("PATH").as_deref());
+    }
+
+    #[test]
+    fn test_claude_binary_default_override_and_args() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        std::fs::write(temp_dir.path().join("claude"), "").unwrap();
+        std::fs::write(temp_dir.path().join("my-claude"), "").unwrap();
+        let search_path = std::env::join_paths([Path::new("/nonexistent"), temp_dir.path()]).unwrap();
+
+        // Default: `claude` from PATH, no arguments
+        let default = ClaudeBinary::default();
+        let program = default.resolve(Some(&search_path)).unwrap();
+        assert_eq!(program, temp_dir.path().join("claude"));
+        let cmd = claude_command(temp_dir.path(), &program, &default.args, &EnvPolicy::default(), [], &[]);
+        assert_eq!(cmd.get_argv().len(), 1);
+
+        // Overridden name, plus extra arguments after the program
+        let custom = ClaudeBinary { program: "my-claude".to_string(), args: Vec::new() }
+            .with_args(vec!["--model".to_string(), "opus".to_string()]);
+        let program = custom.resolve(Some(&search_path)).unwrap();
+        assert_eq!(program, temp_dir.path().join("my-claude"));
+        let cmd = claude_command(temp_dir.path(), &program, &custom.args, &EnvPolicy::default(), [], &[]);
+        assert_eq!(cmd.get_argv()[1..], ["--model", "opus"].map(std::ffi::OsString::from));
+
+        // Missing binaries name what was tried
+        let missing = ClaudeBinary { program: "/opt/nope/claude".to_string(), args: Vec::new() };
+        let err = missing.resolve(Some(&search_path)).unwrap_err().to_string();
+        assert!(err.contains("/opt/nope/claude"), "{}", err);
+        let missing = ClaudeBinary { program: "no-such-claude".to_string(), args: Vec::new() };
+        let err = missing.resolve(Some(&search_path)).unwrap_err().to_string();
+        assert!(err.contains("`no-such-claude`"), "{}", err);
+    }
 
     #[test]
     #[ignore] // Requires `claude` to be installed
     fn test_pty_spawn() {
         let temp_dir = tempfile::tempdir().unwrap();
-        let result = spawn_claude_pty(temp_dir.path());
+        let result = spawn_claude_pty(temp_dir.path(), &ClaudeBinary::default(), &[]);
         assert!(result.is_ok());
     }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_wait_exit_shared_by_two_waiters() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let pair = portable_pty::native_pty_system()
+            .openpty(PtySize { rows: 24, cols: 80, pixel_width: 0, pixel_height: 0 })
+            .unwrap();
+        let mut cmd = CommandBuilder::new("sh");
+        cmd.args(["-c", "exit 3"]);
+        let child = pair.slave.spawn_command(cmd).unwrap();
+
+        let process = Arc::new(
+            SessionProcess::new(Uuid::new_v4(), pair, child, temp_dir.path().join("s.jsonl")).unwrap(),
+        );
+
+        let waiters: Vec<_> = (0..2)
+            .map(|_| {
+                let process = Arc::clone(&process);
+                tokio::spawn(async move { process.wait_exit().await })
+            })
+            .collect();
+
+        for waiter in waiters {
+            let status = tokio::time::timeout(std::time::Duration::from_secs(5), waiter)
+                .await
+                .unwrap()
+                .unwrap();
+            assert_eq!(status.exit_code(), 3);
+            assert!(!status.success());
+        }
+    }
+
+    #[tokio::test]
+    async fn test_write_input_twice() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let log_path = temp_dir.path().join("s.jsonl");
+        let process = cat_process(Uuid::new_v4(), &log_path);
+
+        process.write_input(b"first\n").await.unwrap();
+        process.write_input(b"second\n").await.unwrap();
+
+        let inputs: Vec<_> = crate::logging::read_entries(&log_path, crate::logging::EntryFilter::direction(Direction::Input))
+            .unwrap()
+            .map(|entry| entry.unwrap().data)
+            .collect();
+        assert_eq!(inputs, vec![b"first\n".to_vec(), b"second\n".to_vec()]);
+    }
+
+    #[tokio::test]
+    async fn test_input_and_echo_are_numbered_in_order() {
+        use crate::logging::{read_entries, EntryFilter};
+
+        let temp_dir = tempfile::tempdir().unwrap();
+        let log_path = temp_dir.path().join("s.jsonl");
+        let process = cat_process(Uuid::new_v4(), &log_path);
+
+        process.write_input(b"ping\n").await.unwrap();
+        let deadline = std::time::Instant::now() + Duration::from_secs(5);
+        let entries = loop {
+            let entries: Vec<_> = read_entries(&log_path, EntryFilter::default()).unwrap().map(Result::unwrap).collect();
+            if entries.iter().any(|e| e.direction == Direction::Output) || std::time::Instant::now() > deadline {
+                break entries;
+            }
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        };
+
+        let mut seqs: Vec<u64> = entries.iter().map(|e| e.seq).collect();
+        seqs.sort();
+        assert!(seqs[0] >= 1 && seqs.windows(2).all(|w| w[0] < w[1]), "{:?}", seqs);
+        let input = entries.iter().find(|e| e.direction == Direction::Input).unwrap();
+        let echo = entries.iter().find(|e| e.direction == Direction::Output).expect("cat echoes input");
+        assert!(input.seq < echo.seq);
+
+        // A logger reopening the log carries on from the last number
+        let mut logger = SessionLogger::with_path(Uuid::new_v4(), log_path.clone()).unwrap();
+        assert!(logger.take_seq() > *seqs.last().unwrap());
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_inputs_share_one_log_handle() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let log_path = temp_dir.path().join("s.jsonl");
+        let process = cat_process(Uuid::new_v4(), &log_path);
+
+        // A logger reopening the path would recreate it; the open handle
+        // follows the file instead
+        let moved = temp_dir.path().join("moved.jsonl");
+        std::fs::rename(&log_path, &moved).unwrap();
+        for text in ["one\n", "two\n", "three\n"] {
+            process.write_input(text.as_bytes()).await.unwrap();
+        }
+
+        assert!(!log_path.exists());
+        let inputs = crate::logging::read_entries(&moved, crate::logging::EntryFilter::direction(Direction::Input))
+            .unwrap()
+            .count();
+        assert_eq!(inputs, 3);
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_terminate_kills_process_ignoring_sigterm() {
+        use std::time::Instant;
+
+        let temp_dir = tempfile::tempdir().unwrap();
+        let log_path = temp_dir.path().join("s.jsonl");
+        let pair = portable_pty::native_pty_system()
+            .openpty(PtySize { rows: 24, cols: 80, pixel_width: 0, pixel_height: 0 })
+            .unwrap();
+        let mut cmd = CommandBuilder::new("sh");
+        cmd.args(["-c", "trap '' TERM; echo ready; exec sleep 30"]);
+        let child = pair.slave.spawn_command(cmd).unwrap();
+        let process = SessionProcess::new(Uuid::new_v4(), pair, child, log_path.clone()).unwrap();
+
+        // Don't signal before the trap is in place
+        let deadline = Instant::now() + Duration::from_secs(5);
+        let output = || -> Vec<u8> {
+            crate::logging::read_entries(&log_path, Default::default())
+                .map(|entries| entries.flatten().flat_map(|entry| entry.data).collect())
+                .unwrap_or_default()
+        };
+        while !String::from_utf8_lossy(&output()).contains("ready") {
+            assert!(Instant::now() < deadline, "dummy process never got ready");
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+
+        let grace = Duration::from_millis(300);
+        let started = Instant::now();
+        assert!(process.terminate(grace).await.unwrap(), "expected SIGKILL");
+        assert!(started.elapsed() >= grace);
+        assert!(started.elapsed() < grace + Duration::from_secs(2));
+        assert!(!process.wait_exit().await.success());
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_async_reader_low_latency() {
+        use std::time::Instant;
+
+        let mut fds = [0; 2];
+        assert_eq!(unsafe { libc::pipe(fds.as_mut_ptr()) }, 0);
+        let reader = async_reader::PtyFd::dup_nonblocking(fds[0]).unwrap();
+        unsafe { libc::close(fds[0]) };
+        let async_fd = tokio::io::unix::AsyncFd::new(reader).unwrap();
+
+        let (_shutdown_tx, mut shutdown_rx) = mpsc::channel(1);
+        let (data_tx, mut data_rx) = mpsc::unbounded_channel();
+        let task = tokio::spawn(async move {
+            async_reader::read_loop(&async_fd, &mut shutdown_rx, |data| {
+                let _ = data_tx.send((Instant::now(), data.to_vec()));
+            })
+            .await
+        });
+
+        // Give the reader a moment to register with the reactor
+        tokio::time::sleep(Duration::from_millis(20)).await;
+
+        let sent_at = Instant::now();
+        let written = unsafe { libc::write(fds[1], b"hello".as_ptr() as *const libc::c_void, 5) };
+        assert_eq!(written, 5);
+
+        let (received_at, data) = data_rx.recv().await.unwrap();
+        assert_eq!(data, b"hello");
+        assert!(received_at.duration_since(sent_at) < Duration::from_millis(10));
+
+        // Closing the write end produces EOF and ends the loop
+        unsafe { libc::close(fds[1]) };
+        task.await.unwrap().unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_write_waits_out_a_full_nonblocking_fd() {
+        use std::fs::File;
+        use std::os::fd::FromRawFd;
+
+        let mut fds = [0; 2];
+        assert_eq!(unsafe { libc::pipe(fds.as_mut_ptr()) }, 0);
+        // Like the PTY master once the output reader has made it non-blocking
+        let writer_fd = async_reader::PtyFd::dup_nonblocking(fds[1]).unwrap();
+        let mut writer = unsafe { File::from_raw_fd(fds[1]) };
+        let mut reader = unsafe { File::from_raw_fd(fds[0]) };
+        let drain = std::thread::spawn(move || {
+            let mut read = Vec::new();
+            reader.read_to_end(&mut read).unwrap();
+            read
+        });
+
+        // Far past what the pipe buffers
+        let data: Vec<u8> = (0..1_000_000u32).map(|i| i as u8).collect();
+        let deadline = std::time::Instant::now() + Duration::from_secs(10);
+        write_all_waiting(&mut writer, &data, deadline, |timeout| {
+            let mut poll_fd = libc::pollfd { fd: fds[1], events: libc::POLLOUT, revents: 0 };
+            unsafe { libc::poll(&mut poll_fd, 1, timeout.as_millis() as libc::c_int) };
+        })
+        .unwrap();
+        drop((writer, writer_fd));
+        assert_eq!(drain.join().unwrap(), data);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_write_gives_up_when_nothing_reads() {
+        use std::fs::File;
+        use std::os::fd::FromRawFd;
+
+        let mut fds = [0; 2];
+        assert_eq!(unsafe { libc::pipe(fds.as_mut_ptr()) }, 0);
+        let writer_fd = async_reader::PtyFd::dup_nonblocking(fds[1]).unwrap();
+        let mut writer = unsafe { File::from_raw_fd(fds[1]) };
+        let reader = unsafe { File::from_raw_fd(fds[0]) };
+
+        // The pipe fills up and is never drained
+        let data = vec![b'x'; 1_000_000];
+        let deadline = std::time::Instant::now() + Duration::from_millis(200);
+        let error = write_all_waiting(&mut writer, &data, deadline, std::thread::sleep).unwrap_err();
+        assert_eq!(error.kind(), std::io::ErrorKind::TimedOut);
+        drop((writer, writer_fd, reader));
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_handed_on_terminal_gets_no_eof_when_dropped() {
+        use crate::persistence::is_process_alive;
+        use std::os::fd::BorrowedFd;
+
+        let temp_dir = tempfile::tempdir().unwrap();
+        let process = cat_process(Uuid::new_v4(), &temp_dir.path().join("s.jsonl"));
+        let pid = process.pid().unwrap();
+
+        // Whoever the terminal is handed to holds it open
+        let master = process.master_fd().unwrap();
+        let held = unsafe { BorrowedFd::borrow_raw(master) }.try_clone_to_owned().unwrap();
+        process.keep_input_open();
+        drop(process);
+
+        // cat would exit on the EOF the writer otherwise sends
+        tokio::time::sleep(Duration::from_millis(300)).await;
+        assert!(is_process_alive(pid));
+        drop(held);
+        unsafe { libc::kill(pid as libc::pid_t, libc::SIGKILL) };
+    }
 }
@@ -0,0 +1,465 @@
+//! `daemon --reattach`: keep sessions' terminals open across a daemon
+//! restart.
+//!
+//! A session's PTY master is only open in the daemon, so normally the
+//! terminal hangs up when the daemon exits and the Claude left behind is
+//! orphaned for good (see `SessionManager`). With reattaching on, a `Leave`
+//! shutdown first hands every running session's master to a keeper
+//! (`claude-sessions keep-ptys`, not meant to be run by hand). The keeper
+//! only holds them open and serves them on `ptys.sock` next to the daemon
+//! socket, one `SCM_RIGHTS` message per session. The next daemon started
+//! with reattaching on takes them back and rebuilds a `SessionProcess` for
+//! each session it recovered as orphaned, so input, output logging and
+//! attaching work again.
+//!
+//! The risk is PID reuse: a terminal is only reattached if the session's
+//! recorded PID still has its recorded start time and leads the terminal's
+//! session. Anything that doesn't check out stays orphaned, and its
+//! terminal is closed. Output written while no daemon runs waits in the
+//! terminal (Claude blocks once that is full). A daemon that crashes, or
+//! is stopped with `--kill-sessions`, hands nothing over. The exit code of
+//! an adopted process isn't ours to collect, so it is recorded as 1.
+
+use crate::persistence::{is_same_process, process_start_time};
+use crate::pty::SessionProcess;
+use anyhow::{Context, Result};
+use portable_pty::{Child, ChildKiller, CommandBuilder, ExitStatus, MasterPty, PtyPair, PtySize, SlavePty};
+use std::fs::File;
+use std::io;
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd, RawFd};
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+use uuid::Uuid;
+
+/// Data of a handoff message: the session id (hyphenated), with the
+/// session's master attached
+const MESSAGE_LEN: usize = 36;
+
+/// How long a new daemon waits on the keeper, and the daemon handing off
+/// for the keeper to start serving
+const HANDOFF_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// How often the keeper looks for a daemon and for ended sessions
+const KEEPER_POLL: Duration = Duration::from_millis(200);
+
+/// How often an adopted process is checked for having exited
+const EXIT_POLL: Duration = Duration::from_millis(100);
+
+/// Where the keeper for the daemon on `socket_path` serves its terminals
+pub fn keeper_socket_path(socket_path: &Path) -> PathBuf {
+    socket_path.with_file_name("ptys.sock")
+}
+
+/// A session's terminal held by the keeper
+#[derive(Debug)]
+pub struct HeldTerminal {
+    pub session_id: Uuid,
+    pub pid: u32,
+    pub master: OwnedFd,
+}
+
+impl HeldTerminal {
+    /// Parse a `SESSION:PID:FD` argument of `keep-ptys`, taking ownership
+    /// of the inherited descriptor `FD`
+    pub fn from_arg(arg: &str) -> Result<Self> {
+        let invalid = || format!("Expected SESSION:PID:FD, got {:?}", arg);
+        let mut parts = arg.splitn(3, ':');
+        let (Some(session_id), Some(pid), Some(fd)) = (parts.next(), parts.next(), parts.next()) else {
+            anyhow::bail!(invalid());
+        };
+        let session_id = Uuid::parse_str(session_id).with_context(invalid)?;
+        let pid = pid.parse().with_context(invalid)?;
+        let fd: RawFd = fd.parse().with_context(invalid)?;
+        if unsafe { libc::fcntl(fd, libc::F_GETFD) } < 0 {
+            anyhow::bail!("No terminal was passed in on descriptor {}", fd);
+        }
+        // Not inherited any further
+        unsafe { libc::fcntl(fd, libc::F_SETFD, libc::FD_CLOEXEC) };
+        Ok(HeldTerminal { session_id, pid, master: unsafe { OwnedFd::from_raw_fd(fd) } })
+    }
+}
+
+/// Start a keeper holding `terminals` (session, PID, master) on `socket`,
+/// and wait until it serves them. The terminals stay open after this
+/// process exits.
+pub fn spawn_keeper(socket: &Path, terminals: &[(Uuid, u32, RawFd)]) -> Result<()> {
+    use std::os::unix::process::CommandExt;
+
+    let exe = std::env::current_exe().context("Cannot find the claude-sessions binary")?;
+    remove_stale(socket)?;
+    let mut command = std::process::Command::new(exe);
+    command
+        .arg("keep-ptys")
+        .arg("--socket")
+        .arg(socket)
+        .args(terminals.iter().map(|(session_id, pid, fd)| format!("{}:{}:{}", session_id, pid, fd)))
+        .stdin(std::process::Stdio::null())
+        .stdout(std::process::Stdio::null())
+        .stderr(std::process::Stdio::null());
+    let fds: Vec<RawFd> = terminals.iter().map(|&(_, _, fd)| fd).collect();
+    unsafe {
+        command.pre_exec(move || {
+            // Own session, like a backgrounded daemon; and the masters
+            // (but nothing else) survive the exec
+            libc::setsid();
+            for &fd in &fds {
+                if libc::fcntl(fd, libc::F_SETFD, 0) < 0 {
+                    return Err(io::Error::last_os_error());
+                }
+            }
+            Ok(())
+        });
+    }
+    let mut keeper = command.spawn().context("Failed to start the terminal keeper")?;
+
+    let deadline = Instant::now() + HANDOFF_TIMEOUT;
+    while !socket.exists() {
+        if let Some(status) = keeper.try_wait()? {
+            anyhow::bail!("The terminal keeper exited early ({})", status);
+        }
+        if Instant::now() > deadline {
+            anyhow::bail!("The terminal keeper didn't start serving {:?}", socket);
+        }
+        std::thread::sleep(Duration::from_millis(10));
+    }
+    Ok(())
+}
+
+/// `keep-ptys`: hold `terminals` until a daemon takes them from `socket`
+/// or every session they belong to has ended
+pub fn run_keeper(socket: &Path, terminals: Vec<HeldTerminal>) -> Result<()> {
+    remove_stale(socket)?;
+    let listener = UnixListener::bind(socket).with_context(|| format!("Failed to bind {:?}", socket))?;
+    crate::daemon::restrict_permissions(socket, 0o600)?;
+    let served = serve_terminals(&listener, terminals);
+    let _ = std::fs::remove_file(socket);
+    served
+}
+
+/// Hand `terminals` to the first daemon that connects to `listener`.
+/// Returns without a handoff once none of their processes is alive.
+pub fn serve_terminals(listener: &UnixListener, terminals: Vec<HeldTerminal>) -> Result<()> {
+    let start_times: Vec<Option<u64>> = terminals.iter().map(|held| process_start_time(held.pid)).collect();
+    let alive = |held: &HeldTerminal, start_time: Option<u64>| is_same_process(held.pid, start_time);
+
+    listener.set_nonblocking(true)?;
+    loop {
+        match listener.accept() {
+            Ok((stream, _)) => {
+                stream.set_nonblocking(false)?;
+                for (held, &start_time) in terminals.iter().zip(&start_times) {
+                    if alive(held, start_time) {
+                        send_terminal(&stream, held.session_id, held.master.as_raw_fd())?;
+                    }
+                }
+                return Ok(());
+            }
+            Err(e) if e.kind() == io::ErrorKind::WouldBlock => {}
+            Err(e) => return Err(e.into()),
+        }
+        if !terminals.iter().zip(&start_times).any(|(held, &start_time)| alive(held, start_time)) {
+            return Ok(());
+        }
+        std::thread::sleep(KEEPER_POLL);
+    }
+}
+
+/// Take every terminal the keeper on `socket` holds (none if no keeper
+/// is running)
+pub fn take_terminals(socket: &Path) -> Result<Vec<(Uuid, OwnedFd)>> {
+    let stream = match UnixStream::connect(socket) {
+        Ok(stream) => stream,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) if e.kind() == io::ErrorKind::ConnectionRefused => {
+            let _ = std::fs::remove_file(socket);
+            return Ok(Vec::new());
+        }
+        Err(e) => return Err(e).with_context(|| format!("Failed to connect to the terminal keeper at {:?}", socket)),
+    };
+    stream.set_read_timeout(Some(HANDOFF_TIMEOUT))?;
+
+    let mut terminals = Vec::new();
+    while let Some(terminal) = receive_terminal(&stream).context("Failed to take a terminal from the keeper")? {
+        terminals.push(terminal);
+    }
+    Ok(terminals)
+}
+
+/// Rebuild the `SessionProcess` of a session whose Claude, `pid` (which
+/// started at `start_time`), runs in the terminal `master`.
+///
+/// Refused if `pid` is gone, now belongs to a process with another start
+/// time, or doesn't lead the terminal's session.
+pub fn adopt(
+    session_id: Uuid,
+    master: OwnedFd,
+    pid: u32,
+    start_time: Option<u64>,
+    log_path: PathBuf,
+    max_log_bytes: Option<u64>,
+) -> Result<SessionProcess> {
+    if !is_same_process(pid, start_time) {
+        anyhow::bail!("Process {} has exited or was replaced", pid);
+    }
+    let leader = unsafe { libc::tcgetsid(master.as_raw_fd()) };
+    if leader != pid as libc::pid_t {
+        anyhow::bail!("The terminal handed back isn't process {}'s (its session leader is {})", pid, leader);
+    }
+
+    let pair = PtyPair { slave: Box::new(AdoptedSlave), master: Box::new(AdoptedMaster { fd: master }) };
+    let child = AdoptedChild { pid, start_time: start_time.or_else(|| process_start_time(pid)) };
+    SessionProcess::with_max_log_bytes(session_id, pair, Box::new(child), log_path, max_log_bytes)
+}
+
+/// Remove a socket left behind by a keeper that is gone
+fn remove_stale(socket: &Path) -> Result<()> {
+    match std::fs::remove_file(socket) {
+        Ok(()) => Ok(()),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(()),
+        Err(e) => Err(e).with_context(|| format!("Failed to remove {:?}", socket)),
+    }
+}
+
+/// Control buffer for one `SCM_RIGHTS` descriptor, aligned for `cmsghdr`
+fn control_buffer() -> Vec<u64> {
+    let space = unsafe { libc::CMSG_SPACE(std::mem::size_of::<RawFd>() as u32) } as usize;
+    vec![0; space.div_ceil(8)]
+}
+
+/// Send one handoff message: `session_id` with `master` attached
+fn send_terminal(stream: &UnixStream, session_id: Uuid, master: RawFd) -> io::Result<()> {
+    let data = session_id.hyphenated().to_string();
+    let mut iov = libc::iovec { iov_base: data.as_ptr() as *mut libc::c_void, iov_len: data.len() };
+    let mut control = control_buffer();
+    let mut msg: libc::msghdr = unsafe { std::mem::zeroed() };
+    msg.msg_iov = &mut iov;
+    msg.msg_iovlen = 1;
+    msg.msg_control = control.as_mut_ptr().cast();
+    msg.msg_controllen = (control.len() * 8) as _;
+    unsafe {
+        let cmsg = libc::CMSG_FIRSTHDR(&msg);
+        (*cmsg).cmsg_level = libc::SOL_SOCKET;
+        (*cmsg).cmsg_type = libc::SCM_RIGHTS;
+        (*cmsg).cmsg_len = libc::CMSG_LEN(std::mem::size_of::<RawFd>() as u32) as _;
+        std::ptr::write_unaligned(libc::CMSG_DATA(cmsg) as *mut RawFd, master);
+    }
+
+    let sent = unsafe { libc::sendmsg(stream.as_raw_fd(), &msg, 0) };
+    if sent < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    if sent as usize != data.len() {
+        return Err(io::Error::new(io::ErrorKind::WriteZero, "Handoff message was cut short"));
+    }
+    Ok(())
+}
+
+/// Receive one handoff message (`None` once the keeper is done)
+fn receive_terminal(stream: &UnixStream) -> io::Result<Option<(Uuid, OwnedFd)>> {
+    let mut data = [0u8; MESSAGE_LEN];
+    let mut iov = libc::iovec { iov_base: data.as_mut_ptr().cast(), iov_len: data.len() };
+    let mut control = control_buffer();
+    let mut msg: libc::msghdr = unsafe { std::mem::zeroed() };
+    msg.msg_iov = &mut iov;
+    msg.msg_iovlen = 1;
+    msg.msg_control = control.as_mut_ptr().cast();
+    msg.msg_controllen = (control.len() * 8) as _;
+
+    let received = unsafe { libc::recvmsg(stream.as_raw_fd(), &mut msg, 0) };
+    if received < 0 {
+        return Err(io::Error::last_os_error());
+    }
+
+    // Owned first, so it is closed whatever else is wrong with the message
+    let mut master = None;
+    unsafe {
+        let mut cmsg = libc::CMSG_FIRSTHDR(&msg);
+        while !cmsg.is_null() {
+            if (*cmsg).cmsg_level == libc::SOL_SOCKET && (*cmsg).cmsg_type == libc::SCM_RIGHTS {
+                let fd = std::ptr::read_unaligned(libc::CMSG_DATA(cmsg) as *const RawFd);
+                libc::fcntl(fd, libc::F_SETFD, libc::FD_CLOEXEC);
+                master = Some(OwnedFd::from_raw_fd(fd));
+            }
+            cmsg = libc::CMSG_NXTHDR(&msg, cmsg);
+        }
+    }
+
+    if received == 0 {
+        return Ok(None);
+    }
+    let invalid = |what: &str| io::Error::new(io::ErrorKind::InvalidData, format!("Bad handoff message: {}", what));
+    if received as usize != MESSAGE_LEN {
+        return Err(invalid("wrong length"));
+    }
+    let session_id = std::str::from_utf8(&data)
+        .ok()
+        .and_then(|id| Uuid::parse_str(id).ok())
+        .ok_or_else(|| invalid("not a session id"))?;
+    let master = master.ok_or_else(|| invalid("no terminal attached"))?;
+    Ok(Some((session_id, master)))
+}
+
+/// A PTY master taken over from the keeper
+struct AdoptedMaster {
+    fd: OwnedFd,
+}
+
+impl MasterPty for AdoptedMaster {
+    fn resize(&self, size: PtySize) -> Result<()> {
+        let winsize = libc::winsize {
+            ws_row: size.rows,
+            ws_col: size.cols,
+            ws_xpixel: size.pixel_width,
+            ws_ypixel: size.pixel_height,
+        };
+        if unsafe { libc::ioctl(self.fd.as_raw_fd(), libc::TIOCSWINSZ, &winsize) } != 0 {
+            return Err(io::Error::last_os_error().into());
+        }
+        Ok(())
+    }
+
+    fn get_size(&self) -> Result<PtySize> {
+        let mut winsize: libc::winsize = unsafe { std::mem::zeroed() };
+        if unsafe { libc::ioctl(self.fd.as_raw_fd(), libc::TIOCGWINSZ, &mut winsize) } != 0 {
+            return Err(io::Error::last_os_error().into());
+        }
+        Ok(PtySize {
+            rows: winsize.ws_row,
+            cols: winsize.ws_col,
+            pixel_width: winsize.ws_xpixel,
+            pixel_height: winsize.ws_ypixel,
+        })
+    }
+
+    fn try_clone_reader(&self) -> Result<Box<dyn io::Read + Send>> {
+        Ok(Box::new(File::from(self.fd.try_clone()?)))
+    }
+
+    fn take_writer(&self) -> Result<Box<dyn io::Write + Send>> {
+        Ok(Box::new(File::from(self.fd.try_clone()?)))
+    }
+
+    fn process_group_leader(&self) -> Option<libc::pid_t> {
+        let group = unsafe { libc::tcgetpgrp(self.fd.as_raw_fd()) };
+        (group > 0).then_some(group)
+    }
+
+    fn as_raw_fd(&self) -> Option<RawFd> {
+        Some(self.fd.as_raw_fd())
+    }
+}
+
+/// The slave side of an adopted terminal is the session's own; nothing
+/// new is spawned into it
+struct AdoptedSlave;
+
+impl SlavePty for AdoptedSlave {
+    fn spawn_command(&self, _cmd: CommandBuilder) -> Result<Box<dyn Child + Send + Sync>> {
+        anyhow::bail!("Can't start a process in a reattached session's terminal")
+    }
+}
+
+/// A process started by an earlier daemon. It isn't our child, so exiting
+/// is noticed by polling and its exit code is unknown (reported as 1).
+#[derive(Debug, Clone)]
+struct AdoptedChild {
+    pid: u32,
+    start_time: Option<u64>,
+}
+
+impl AdoptedChild {
+    fn is_running(&self) -> bool {
+        is_same_process(self.pid, self.start_time)
+    }
+}
+
+impl ChildKiller for AdoptedChild {
+    fn kill(&mut self) -> io::Result<()> {
+        if self.is_running() {
+            unsafe { libc::kill(self.pid as libc::pid_t, libc::SIGKILL) };
+        }
+        Ok(())
+    }
+
+    fn clone_killer(&self) -> Box<dyn ChildKiller + Send + Sync> {
+        Box::new(self.clone())
+    }
+}
+
+impl Child for AdoptedChild {
+    fn try_wait(&mut self) -> io::Result<Option<ExitStatus>> {
+        Ok((!self.is_running()).then(|| ExitStatus::with_exit_code(1)))
+    }
+
+    fn wait(&mut self) -> io::Result<ExitStatus> {
+        while self.is_running() {
+            std::thread::sleep(EXIT_POLL);
+        }
+        Ok(ExitStatus::with_exit_code(1))
+    }
+
+    fn process_id(&self) -> Option<u32> {
+        Some(self.pid)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_keeper_hands_over_live_sessions_terminals() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let socket = keeper_socket_path(&temp_dir.path().join("daemon.sock"));
+        let pty = portable_pty::native_pty_system();
+        let mut terminals = Vec::new();
+        let mut children = Vec::new();
+        for _ in 0..2 {
+            let pair = pty.openpty(PtySize::default()).unwrap();
+            let child = pair.slave.spawn_command(CommandBuilder::new("cat")).unwrap();
+            let master = unsafe { OwnedFd::from_raw_fd(libc::dup(pair.master.as_raw_fd().unwrap())) };
+            terminals.push(HeldTerminal { session_id: Uuid::new_v4(), pid: child.process_id().unwrap(), master });
+            children.push(child);
+        }
+        let ended = terminals[1].session_id;
+        children[1].kill().unwrap();
+        children[1].wait().unwrap();
+        let expected = (terminals[0].session_id, terminals[0].pid);
+
+        let listener = UnixListener::bind(&socket).unwrap();
+        let keeper = std::thread::spawn(move || serve_terminals(&listener, terminals));
+        let taken = take_terminals(&socket).unwrap();
+        keeper.join().unwrap().unwrap();
+
+        // Only the live session's, and the very same terminal
+        assert_eq!(taken.len(), 1);
+        let (session_id, master) = &taken[0];
+        assert_ne!(*session_id, ended);
+        assert_eq!(*session_id, expected.0);
+        assert_eq!(unsafe { libc::tcgetsid(master.as_raw_fd()) }, expected.1 as libc::pid_t);
+
+        children[0].kill().unwrap();
+        children[0].wait().unwrap();
+    }
+
+    #[test]
+    fn test_no_keeper_means_nothing_to_take() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let socket = keeper_socket_path(&temp_dir.path().join("daemon.sock"));
+        assert!(take_terminals(&socket).unwrap().is_empty());
+
+        // A keeper that is gone left its socket behind
+        drop(UnixListener::bind(&socket).unwrap());
+        assert!(take_terminals(&socket).unwrap().is_empty());
+        assert!(!socket.exists());
+    }
+
+    #[test]
+    fn test_held_terminal_args() {
+        assert!(HeldTerminal::from_arg("not-a-session:1:3").is_err());
+        assert!(HeldTerminal::from_arg(&format!("{}:1", Uuid::new_v4())).is_err());
+        // Nothing open there
+        assert!(HeldTerminal::from_arg(&format!("{}:1:987654", Uuid::new_v4())).is_err());
+    }
+}
@@ -0,0 +1,293 @@
+//! `claude-sessions repair`: reconcile persisted session state with the
+//! logs on disk and the processes that are actually alive.
+//!
+//! Repair works on the persisted state directly, so the daemon must be
+//! stopped (it would overwrite the result with its in-memory view). It is
+//! split into `plan`, which only looks, and `apply`, so `--dry-run` shows
+//! exactly the changes a real run would make.
+
+use crate::logging::{is_log_file, is_rotated_segment, read_entries, EntryFilter};
+use crate::persistence::{is_same_process, PersistedSession};
+use crate::session::SessionStatus;
+use std::collections::HashMap;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use uuid::Uuid;
+
+/// One change repair makes to the persisted state
+#[derive(Debug, Clone, PartialEq)]
+pub enum RepairAction {
+    /// Forget a session whose log is gone and whose process isn't alive
+    RemoveMissingLog { id: Uuid, log_path: PathBuf },
+    /// Add a record for a log no session owns
+    ImportOrphanLog(PersistedSession),
+    /// Correct a status that disagrees with the session's process
+    UpdateStatus { id: Uuid, from: SessionStatus, to: SessionStatus },
+    /// Replace an unusable field value
+    FixField { id: Uuid, field: &'static str, from: String, to: String },
+}
+
+impl fmt::Display for RepairAction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RepairAction::RemoveMissingLog { id, log_path } => {
+                write!(f, "remove {}: log {} is missing", id, log_path.display())
+            }
+            RepairAction::ImportOrphanLog(session) => {
+                write!(f, "import {} from orphaned log {}", session.id, session.log_path.display())
+            }
+            RepairAction::UpdateStatus { id, from, to } => {
+                write!(f, "status of {}: {} -> {}", id, from, to)
+            }
+            RepairAction::FixField { id, field, from, to } => {
+                write!(f, "{} of {}: {:?} -> {:?}", field, id, from, to)
+            }
+        }
+    }
+}
+
+/// Work out what repair would change, without changing anything.
+///
+/// `log_dir` is scanned for `*.jsonl` and `*.clog` logs that no session
+/// references.
+pub fn plan(sessions: &HashMap<Uuid, PersistedSession>, log_dir: &Path) -> Vec<RepairAction> {
+    let mut actions = Vec::new();
+
+    let mut ids: Vec<&Uuid> = sessions.keys().collect();
+    ids.sort();
+    for id in ids {
+        let session = &sessions[id];
+
+        if chrono::DateTime::parse_from_rfc3339(&session.created_at).is_err() {
+            let created_at = modified_rfc3339(&session.log_path).unwrap_or_else(|| chrono::Utc::now().to_rfc3339());
+            actions.push(RepairAction::FixField {
+                id: *id,
+                field: "created_at",
+                from: session.created_at.clone(),
+                to: created_at,
+            });
+        }
+        let mut log_path = session.log_path.clone();
+        if log_path.as_os_str().is_empty() {
+            log_path = log_dir.join(format!("{}.jsonl", id));
+            actions.push(RepairAction::FixField {
+                id: *id,
+                field: "log_path",
+                from: String::new(),
+                to: log_path.display().to_string(),
+            });
+        }
+
+        let alive = session.pid.is_some_and(|pid| is_same_process(pid, session.process_start_time));
+        if !alive && !log_path.exists() {
+            actions.push(RepairAction::RemoveMissingLog { id: *id, log_path });
+            continue;
+        }
+
+        // The same rules the daemon applies when recovering (no daemon
+        // means nothing can still be "running")
+        let status = match session.pid {
+            Some(_) if alive => SessionStatus::Orphaned,
+            Some(_) if session.status == SessionStatus::Stale => SessionStatus::Stale,
+            Some(_) => SessionStatus::Crashed,
+            None if session.status == SessionStatus::Imported => SessionStatus::Imported,
+            None => SessionStatus::Stale,
+        };
+        if session.status != status {
+            actions.push(RepairAction::UpdateStatus {
+                id: *id,
+                from: session.status.clone(),
+                to: status,
+            });
+        }
+    }
+
+    let known_logs: Vec<&Path> = sessions.values().map(|s| s.log_path.as_path()).collect();
+    let mut orphans: Vec<PathBuf> = std::fs::read_dir(log_dir)
+        .into_iter()
+        .flatten()
+        .flatten()
+        .map(|entry| entry.path())
+        .filter(|path| is_log_file(path))
+        // Segments go with their session's active log
+        .filter(|path| !is_rotated_segment(path))
+        .filter(|path| !known_logs.contains(&path.as_path()))
+        .collect();
+    orphans.sort();
+    for log_path in orphans {
+        if let Some(session) = import_log(&log_path) {
+            // A log claiming an existing session's ID is a copy, not an orphan
+            let duplicate = sessions.contains_key(&session.id)
+                || actions.iter().any(|a| matches!(a, RepairAction::ImportOrphanLog(s) if s.id == session.id));
+            if !duplicate {
+                actions.push(RepairAction::ImportOrphanLog(session));
+            }
+        }
+    }
+
+    actions
+}
+
+/// Apply the actions from `plan`
+pub fn apply(sessions: &mut HashMap<Uuid, PersistedSession>, actions: &[RepairAction]) {
+    for action in actions {
+        match action {
+            RepairAction::RemoveMissingLog { id, .. } => {
+                sessions.remove(id);
+            }
+            RepairAction::ImportOrphanLog(session) => {
+                sessions.insert(session.id, session.clone());
+            }
+            RepairAction::UpdateStatus { id, to, .. } => {
+                if let Some(session) = sessions.get_mut(id) {
+                    session.status = to.clone();
+                }
+            }
+            RepairAction::FixField { id, field, to, .. } => {
+                if let Some(session) = sessions.get_mut(id) {
+                    match *field {
+                        "created_at" => session.created_at = to.clone(),
+                        "log_path" => session.log_path = PathBuf::from(to),
+                        _ => {}
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Rebuild a session record from its log's first entry.
+///
+/// The working directory isn't logged, so it is left empty.
+fn import_log(log_path: &Path) -> Option<PersistedSession> {
+    let entry = read_entries(log_path, EntryFilter::default()).ok()?.next()?.ok()?;
+    Some(PersistedSession {
+        id: Uuid::parse_str(&entry.session_id).ok()?,
+        working_dir: PathBuf::new(),
+        created_at: entry.timestamp,
+        log_path: log_path.to_path_buf(),
+        pid: None,
+        process_start_time: None,
+        status: SessionStatus::Imported,
+        exit_code: None,
+        label: String::new(),
+        tags: Default::default(),
+        last_activity: None,
+    })
+}
+
+fn modified_rfc3339(path: &Path) -> Option<String> {
+    let modified = std::fs::metadata(path).and_then(|m| m.modified()).ok()?;
+    Some(chrono::DateTime::<chrono::Utc>::from(modified).to_rfc3339())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::logging::{Direction, SessionLogger};
+
+    fn persisted(log_path: PathBuf, status: SessionStatus) -> PersistedSession {
+        PersistedSession {
+            id: Uuid::new_v4(),
+            working_dir: PathBuf::from("/tmp/project"),
+            created_at: "2024-01-01T00:00:00+00:00".to_string(),
+            log_path,
+            pid: None,
+            process_start_time: None,
+            status,
+            exit_code: None,
+            label: String::new(),
+            tags: Default::default(),
+            last_activity: None,
+        }
+    }
+
+    #[test]
+    fn test_reused_pid_is_crashed_not_orphaned() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let pid = std::process::id();
+        let Some(start_time) = crate::persistence::process_start_time(pid) else {
+            return; // No start times on this platform
+        };
+        let record = |process_start_time| {
+            let mut session = persisted(temp_dir.path().join("s.jsonl"), SessionStatus::Running);
+            SessionLogger::with_path(session.id, session.log_path.clone()).unwrap();
+            session.pid = Some(pid);
+            session.process_start_time = Some(process_start_time);
+            session
+        };
+
+        for (session, expected) in [
+            (record(start_time), SessionStatus::Orphaned),
+            (record(start_time + 1), SessionStatus::Crashed),
+        ] {
+            let sessions = HashMap::from([(session.id, session.clone())]);
+            let actions = plan(&sessions, temp_dir.path());
+            assert!(
+                actions.contains(&RepairAction::UpdateStatus { id: session.id, from: SessionStatus::Running, to: expected }),
+                "{:#?}",
+                actions
+            );
+        }
+    }
+
+    #[test]
+    fn test_repair_reconciles_state() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let log_dir = temp_dir.path();
+        let write_log = |id: Uuid| {
+            let path = log_dir.join(format!("{}.jsonl", id));
+            let mut logger = SessionLogger::with_path(id, path.clone()).unwrap();
+            logger.log(Direction::Output, b"hello".to_vec()).unwrap();
+            path
+        };
+
+        // Healthy apart from a status the daemon left behind
+        let mut healthy = persisted(PathBuf::new(), SessionStatus::Running);
+        healthy.log_path = write_log(healthy.id);
+        // Log deleted by hand
+        let missing = persisted(log_dir.join("gone.jsonl"), SessionStatus::Stale);
+        // Garbage timestamp
+        let mut garbled = persisted(PathBuf::new(), SessionStatus::Stale);
+        garbled.log_path = write_log(garbled.id);
+        garbled.created_at = "yesterday".to_string();
+        // A log with no record at all
+        let orphan_id = Uuid::new_v4();
+        let orphan_log = write_log(orphan_id);
+
+        let mut sessions: HashMap<_, _> = [healthy.clone(), missing.clone(), garbled.clone()]
+            .into_iter()
+            .map(|s| (s.id, s))
+            .collect();
+        let actions = plan(&sessions, log_dir);
+
+        assert_eq!(actions.len(), 4, "{:#?}", actions);
+        assert!(actions.contains(&RepairAction::RemoveMissingLog {
+            id: missing.id,
+            log_path: missing.log_path.clone(),
+        }));
+        assert!(actions.contains(&RepairAction::UpdateStatus {
+            id: healthy.id,
+            from: SessionStatus::Running,
+            to: SessionStatus::Stale,
+        }));
+
+        // Planning alone changes nothing (--dry-run)
+        assert_eq!(sessions.len(), 3);
+        assert_eq!(sessions[&healthy.id].status, SessionStatus::Running);
+
+        apply(&mut sessions, &actions);
+        let mut ids: Vec<Uuid> = sessions.keys().copied().collect();
+        ids.sort();
+        let mut expected = vec![healthy.id, garbled.id, orphan_id];
+        expected.sort();
+        assert_eq!(ids, expected);
+        assert_eq!(sessions[&healthy.id].status, SessionStatus::Stale);
+        assert!(chrono::DateTime::parse_from_rfc3339(&sessions[&garbled.id].created_at).is_ok());
+        assert_eq!(sessions[&orphan_id].status, SessionStatus::Imported);
+        assert_eq!(sessions[&orphan_id].log_path, orphan_log);
+
+        // A repaired state needs no further repair
+        assert!(plan(&sessions, log_dir).is_empty());
+    }
+}
@@ -0,0 +1,99 @@
+use crate::ipc::SessionInfo;
+use anyhow::Result;
+
+/// Shortest ID prefix accepted when resolving a session
+pub const MIN_PREFIX_LEN: usize = 4;
+
+/// Find the session a user meant by `query`.
+///
+/// An exact ID wins, then an exact label. Otherwise the query must be a
+/// prefix of exactly one session's label, or of its ID if at least
+/// `MIN_PREFIX_LEN` characters long (the 8-character short IDs shown by
+/// `list` and `search` work). Unknown and ambiguous queries are errors.
+pub fn resolve<'a>(sessions: &'a [SessionInfo], query: &str) -> Result<&'a SessionInfo> {
+    if let Some(session) = sessions.iter().find(|s| s.id == query) {
+        return Ok(session);
+    }
+
+    let exact: Vec<&SessionInfo> = sessions.iter().filter(|s| !s.label.is_empty() && s.label == query).collect();
+    let matches = if exact.is_empty() {
+        sessions
+            .iter()
+            .filter(|s| {
+                (query.len() >= MIN_PREFIX_LEN && s.id.starts_with(query))
+                    || (!query.is_empty() && s.label.starts_with(query))
+            })
+            .collect()
+    } else {
+        exact
+    };
+    match matches.as_slice() {
+        [session] => Ok(session),
+        [] => anyhow::bail!("Session not found: {}", query),
+        _ => {
+            let candidates: Vec<String> = matches
+                .iter()
+                .map(|s| match s.label.as_str() {
+                    "" => s.id.clone(),
+                    label => format!("{} ({})", s.id, label),
+                })
+                .collect();
+            anyhow::bail!("Ambiguous session {}: matches {}", query, candidates.join(", "));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn session(id: &str) -> SessionInfo {
+        labeled(id, "")
+    }
+
+    fn labeled(id: &str, label: &str) -> SessionInfo {
+        SessionInfo {
+            id: id.to_string(),
+            working_dir: "/tmp".to_string(),
+            created_at: "2024-01-01T00:00:00+00:00".to_string(),
+            status: crate::session::SessionStatus::Running,
+            log_path: format!("/logs/{}.jsonl", id),
+            last_error: None,
+            exit_code: None,
+            last_activity: None,
+            idle_seconds: None,
+            label: label.to_string(),
+            tags: Default::default(),
+            healthy: None,
+        }
+    }
+
+    #[test]
+    fn test_resolve_exact_and_prefix() {
+        let sessions = vec![session("abcd1234-0000"), session("abcd5678-0000")];
+
+        assert_eq!(resolve(&sessions, "abcd5678-0000").unwrap().id, "abcd5678-0000");
+        assert_eq!(resolve(&sessions, "abcd1").unwrap().id, "abcd1234-0000");
+        assert!(resolve(&sessions, "abcd").unwrap_err().to_string().contains("Ambiguous"));
+        assert!(resolve(&sessions, "abc").is_err());
+        assert!(resolve(&sessions, "ffff").is_err());
+    }
+
+    #[test]
+    fn test_resolve_by_label() {
+        let sessions = vec![
+            labeled("abcd1234-0000", "api"),
+            labeled("abcd5678-0000", "api-v2"),
+            labeled("ef012345-0000", "web"),
+        ];
+
+        // An exact label beats the longer label it prefixes
+        assert_eq!(resolve(&sessions, "api").unwrap().id, "abcd1234-0000");
+        assert_eq!(resolve(&sessions, "api-").unwrap().id, "abcd5678-0000");
+        assert_eq!(resolve(&sessions, "w").unwrap().id, "ef012345-0000");
+
+        let err = resolve(&sessions, "ap").unwrap_err().to_string();
+        assert!(err.contains("Ambiguous") && err.contains("(api-v2)"), "{}", err);
+        assert!(resolve(&sessions, "mobile").unwrap_err().to_string().contains("not found"));
+    }
+}
@@ -0,0 +1,195 @@
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+use uuid::Uuid;
+
+/// How often the daemon re-runs the retention sweep
+pub const SWEEP_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+/// Log retention policy for the daemon (`daemon --keep-logs-days N`).
+///
+/// Opt-in: with `keep_logs_days == 0` nothing is ever deleted. Otherwise,
+/// logs of sessions that aren't running and have been idle for longer than
+/// the window are removed on daemon startup and every `SWEEP_INTERVAL`.
+/// Running sessions are always exempt.
+#[derive(Debug, Clone, Default)]
+pub struct RetentionPolicy {
+    /// Days to keep logs after a session's last activity (0 = forever)
+    pub keep_logs_days: u32,
+    /// Also forget the persisted records of expired sessions
+    pub prune_records: bool,
+}
+
+impl RetentionPolicy {
+    pub fn is_enabled(&self) -> bool {
+        self.keep_logs_days > 0
+    }
+
+    /// Whether something last active at `last_activity` is past the window
+    pub fn is_expired(&self, last_activity: SystemTime, now: SystemTime) -> bool {
+        if !self.is_enabled() {
+            return false;
+        }
+        let window = Duration::from_secs(u64::from(self.keep_logs_days) * 24 * 60 * 60);
+        now.duration_since(last_activity)
+            .map(|age| age > window)
+            .unwrap_or(false)
+    }
+}
+
+/// What a retention sweep removed
+#[derive(Debug, Default)]
+pub struct SweepReport {
+    pub logs_removed: Vec<PathBuf>,
+    pub records_removed: Vec<Uuid>,
+}
+
+impl SweepReport {
+    pub fn is_empty(&self) -> bool {
+        self.logs_removed.is_empty() && self.records_removed.is_empty()
+    }
+}
+
+/// Last activity of a session: its log's mtime, or `created_at` if the
+/// log doesn't exist (yet)
+pub fn last_activity(log_path: &Path, created_at: &str) -> Option<SystemTime> {
+    if let Ok(modified) = std::fs::metadata(log_path).and_then(|m| m.modified()) {
+        return Some(modified);
+    }
+    chrono::DateTime::parse_from_rfc3339(created_at)
+        .ok()
+        .map(SystemTime::from)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::logging::{Direction, SessionLogger};
+    use crate::manager::SessionManager;
+    use crate::persistence::PersistenceManager;
+    use crate::session::Session;
+
+    const DAY: Duration = Duration::from_secs(24 * 60 * 60);
+
+    /// A non-running session whose log was last written `age` ago
+    fn backdated_session(dir: &Path, age: Duration) -> Session {
+        let mut session = Session::with_log_dir(dir.to_path_buf(), Some(dir));
+        let then = SystemTime::now() - age;
+        session.created_at = chrono::DateTime::<chrono::Utc>::from(then).to_rfc3339();
+
+        let mut logger = SessionLogger::with_path(session.id, session.log_path.clone()).unwrap();
+        logger.log(Direction::Output, b"old output".to_vec()).unwrap();
+        std::fs::File::options()
+            .write(true)
+            .open(&session.log_path)
+            .unwrap()
+            .set_modified(then)
+            .unwrap();
+        session
+    }
+
+    #[tokio::test]
+    async fn test_sweep_removes_only_expired_sessions() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let persistence =
+            PersistenceManager::with_state_file(temp_dir.path().join("sessions.json")).unwrap();
+        let manager = SessionManager::with_persistence(persistence);
+
+        let expired = backdated_session(temp_dir.path(), 10 * DAY);
+        let recent = backdated_session(temp_dir.path(), DAY);
+        manager.insert_session(expired.clone()).await;
+        manager.insert_session(recent.clone()).await;
+
+        let policy = RetentionPolicy { keep_logs_days: 7, prune_records: true };
+        let report = manager
+            .sweep_retention(&policy, temp_dir.path(), SystemTime::now())
+            .await
+            .unwrap();
+
+        assert_eq!(report.logs_removed, vec![expired.log_path.clone()]);
+        assert_eq!(report.records_removed, vec![expired.id]);
+        assert!(!expired.log_path.exists());
+        assert!(recent.log_path.exists());
+
+        let remaining: Vec<String> = manager.list_sessions().await.into_iter().map(|s| s.id).collect();
+        assert_eq!(remaining, vec![recent.id.to_string()]);
+    }
+
+    #[tokio::test]
+    async fn test_sweep_spares_live_orphans() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let persistence =
+            PersistenceManager::with_state_file(temp_dir.path().join("sessions.json")).unwrap();
+        let manager = SessionManager::with_persistence(persistence);
+
+        // Its Claude outlived the previous daemon and still writes the log
+        let mut orphaned = backdated_session(temp_dir.path(), 10 * DAY);
+        orphaned.orphan_pid = Some(std::process::id());
+        manager.insert_session(orphaned.clone()).await;
+
+        let policy = RetentionPolicy { keep_logs_days: 7, prune_records: true };
+        let report = manager
+            .sweep_retention(&policy, temp_dir.path(), SystemTime::now())
+            .await
+            .unwrap();
+
+        assert!(report.is_empty(), "{:?}", report);
+        assert!(orphaned.log_path.exists());
+        assert_eq!(manager.list_sessions().await.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_prune_spares_orphaned_and_honours_dry_run() {
+        use crate::manager::PruneOptions;
+
+        let temp_dir = tempfile::tempdir().unwrap();
+        let persistence =
+            PersistenceManager::with_state_file(temp_dir.path().join("sessions.json")).unwrap();
+        let manager = SessionManager::with_persistence(persistence);
+
+        let mut exited = backdated_session(temp_dir.path(), 10 * DAY);
+        exited.exit_code = Some(0);
+        let mut orphaned = backdated_session(temp_dir.path(), 10 * DAY);
+        orphaned.orphan_pid = Some(std::process::id());
+        let recent = backdated_session(temp_dir.path(), DAY);
+        // Left behind by a stopped session
+        let stopped = backdated_session(temp_dir.path(), 10 * DAY);
+        for session in [&exited, &orphaned, &recent] {
+            manager.insert_session(session.clone()).await;
+        }
+
+        let prune = |dry_run, keep_logs| PruneOptions {
+            older_than: 7 * DAY,
+            dry_run,
+            keep_logs,
+        };
+        let mut expected_logs = vec![exited.log_path.clone(), stopped.log_path.clone()];
+        expected_logs.sort();
+
+        let report = manager
+            .prune_sessions(&prune(true, false), temp_dir.path(), SystemTime::now())
+            .await
+            .unwrap();
+        assert_eq!(report.records_removed, vec![exited.id]);
+        let mut logs = report.logs_removed.clone();
+        logs.sort();
+        assert_eq!(logs, expected_logs);
+        assert!(exited.log_path.exists() && stopped.log_path.exists());
+        assert_eq!(manager.list_sessions().await.len(), 3);
+
+        let report = manager
+            .prune_sessions(&prune(false, true), temp_dir.path(), SystemTime::now())
+            .await
+            .unwrap();
+        assert_eq!(report.records_removed, vec![exited.id]);
+        assert!(report.logs_removed.is_empty());
+        assert!(exited.log_path.exists());
+        assert!(orphaned.log_path.exists());
+        assert_eq!(manager.list_sessions().await.len(), 2);
+    }
+
+    #[test]
+    fn test_zero_keeps_forever() {
+        let policy = RetentionPolicy::default();
+        assert!(!policy.is_expired(SystemTime::UNIX_EPOCH, SystemTime::now()));
+    }
+}
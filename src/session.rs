@@ -1,7 +1,116 @@
+use crate::logging::LogFormat;
 use serde::{Deserialize, Serialize};
-use std::path::PathBuf;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::path::{Path, PathBuf};
 use uuid::Uuid;
 
+/// Where a session is in its lifecycle.
+///
+/// Serialized as the lowercase strings used on disk and on the wire since
+/// before this was an enum ("running", "crash-looping", ...). Statuses
+/// written by a newer version read as `Unknown`, which keeps the spelling
+/// so they are written back unchanged.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(from = "String", into = "String")]
+pub enum SessionStatus {
+    /// Has a live PTY process
+    Running,
+    /// Has a live PTY process that has produced no output for a while
+    /// since it was sent input (only with hang detection turned on)
+    Unresponsive,
+    /// Stopped on request
+    Stopped,
+    /// Loaded from disk with no process information (daemon restarted)
+    Stale,
+    /// Claude exited with a non-zero code, or died while the daemon was down
+    Crashed,
+    /// Its process is alive but no daemon manages it
+    Orphaned,
+    /// Claude exited with code 0
+    Exited,
+    /// Restarted too often; the supervisor gave up
+    CrashLooping,
+    /// Rebuilt by `repair` from an orphaned log
+    Imported,
+    /// A status this version doesn't know, as it was spelled
+    Unknown(String),
+}
+
+impl SessionStatus {
+    /// Status of a session whose Claude exited with `code`
+    pub fn from_exit_code(code: u32) -> Self {
+        if code == 0 {
+            SessionStatus::Exited
+        } else {
+            SessionStatus::Crashed
+        }
+    }
+
+    /// Whether the session has a live process (possibly a hung one)
+    pub fn is_live(&self) -> bool {
+        matches!(self, SessionStatus::Running | SessionStatus::Unresponsive)
+    }
+
+    /// The on-disk/wire spelling
+    pub fn as_str(&self) -> &str {
+        match self {
+            SessionStatus::Running => "running",
+            SessionStatus::Unresponsive => "unresponsive",
+            SessionStatus::Stopped => "stopped",
+            SessionStatus::Stale => "stale",
+            SessionStatus::Crashed => "crashed",
+            SessionStatus::Orphaned => "orphaned",
+            SessionStatus::Exited => "exited",
+            SessionStatus::CrashLooping => "crash-looping",
+            SessionStatus::Imported => "imported",
+            SessionStatus::Unknown(status) => status,
+        }
+    }
+}
+
+impl From<String> for SessionStatus {
+    /// Parse the on-disk spelling; anything unrecognised is `Unknown`
+    fn from(status: String) -> Self {
+        match status.as_str() {
+            "running" => SessionStatus::Running,
+            "unresponsive" => SessionStatus::Unresponsive,
+            "stopped" => SessionStatus::Stopped,
+            "stale" => SessionStatus::Stale,
+            "crashed" => SessionStatus::Crashed,
+            "orphaned" => SessionStatus::Orphaned,
+            "exited" => SessionStatus::Exited,
+            "crash-looping" => SessionStatus::CrashLooping,
+            "imported" => SessionStatus::Imported,
+            _ => SessionStatus::Unknown(status),
+        }
+    }
+}
+
+impl From<SessionStatus> for String {
+    fn from(status: SessionStatus) -> Self {
+        match status {
+            SessionStatus::Unknown(status) => status,
+            known => known.as_str().to_string(),
+        }
+    }
+}
+
+impl std::str::FromStr for SessionStatus {
+    type Err = std::convert::Infallible;
+
+    /// Parse the on-disk spelling; anything unrecognised is `Unknown`
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(SessionStatus::from(s.to_string()))
+    }
+}
+
+impl fmt::Display for SessionStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
 /// Represents a single Claude Code session.
 /// 
 /// Each session has:
@@ -15,31 +124,161 @@ pub struct Session {
     pub working_dir: PathBuf,
     pub created_at: String,
     pub log_path: PathBuf,
+    /// Human-readable name, accepted wherever a session ID is (see
+    /// `resolve`). Defaults to the working directory's basename.
+    #[serde(default)]
+    pub label: String,
+    /// `key=value` tags given at start, for picking out groups of
+    /// sessions (`send --tag`)
+    #[serde(default)]
+    pub tags: BTreeMap<String, String>,
+    /// Last error if the session's restart circuit breaker opened
+    #[serde(default)]
+    pub crash_loop_error: Option<String>,
+    /// Claude's exit code, once it has exited on its own
+    #[serde(default)]
+    pub exit_code: Option<u32>,
+    /// How the session was started, for `restart`. Kept in memory only
+    /// (the env may hold secrets): recovered sessions restart with defaults.
+    #[serde(skip)]
+    pub options: crate::manager::StartOptions,
+    /// PID of a Claude the previous daemon left running (an "orphaned"
+    /// session found on recovery); nothing here manages that process
+    #[serde(skip)]
+    pub orphan_pid: Option<u32>,
+    /// When `orphan_pid`'s process started, to tell it from a later
+    /// process given the same PID (see `is_same_process`)
+    #[serde(skip)]
+    pub process_start_time: Option<u64>,
+    /// When input was last written or output last logged (RFC 3339).
+    /// While the session runs its process has the current value; this
+    /// copy is refreshed when state is listed or saved.
+    #[serde(default)]
+    pub last_activity: Option<String>,
+    /// Set while `stop_session` terminates the process, so its exit isn't
+    /// taken for a crash
+    #[serde(skip)]
+    pub stopping: bool,
+    /// Why the last `stop_session` failed (the session is kept)
+    #[serde(skip)]
+    pub stop_error: Option<String>,
 }
 
 impl Session {
-    /// Create a new session for a given working directory
+    /// Create a new session for a given working directory (for tests)
+    #[cfg(test)]
     pub fn new(working_dir: PathBuf) -> Self {
+        Self::with_log_dir(working_dir, None)
+    }
+
+    /// Create a new session whose log is written to `log_dir` instead of
+    /// the default `~/.claude-sessions/logs` (for tests)
+    #[cfg(test)]
+    pub fn with_log_dir(working_dir: PathBuf, log_dir: Option<&Path>) -> Self {
+        Self::with_log_options(working_dir, log_dir, LogNaming::Id)
+    }
+
+    /// Create a new session with full control over where its log goes and
+    /// how the log file is named.
+    ///
+    /// The chosen path is stored in `log_path`; everything that reads a
+    /// session's log goes through it rather than rebuilding the name. Its
+    /// extension sets the log's format (`LogFormat::from_env`).
+    pub fn with_log_options(working_dir: PathBuf, log_dir: Option<&Path>, naming: LogNaming) -> Self {
         let id = Uuid::new_v4();
-        let log_path = Self::log_path_for_session(id);
+        let created_at = chrono::Utc::now();
+        let file_name = naming.file_name(id, created_at, &working_dir, LogFormat::from_env());
+        let log_path = match log_dir {
+            Some(dir) => dir.join(file_name),
+            None => Self::default_log_dir().join(file_name),
+        };
         
         Session {
             id,
+            label: default_label(&working_dir),
+            tags: BTreeMap::new(),
             working_dir,
-            created_at: chrono::Utc::now().to_rfc3339(),
+            created_at: created_at.to_rfc3339(),
             log_path,
+            crash_loop_error: None,
+            exit_code: None,
+            options: Default::default(),
+            orphan_pid: None,
+            process_start_time: None,
+            last_activity: None,
+            stopping: false,
+            stop_error: None,
         }
     }
 
-    /// Get the log file path for a session
-    fn log_path_for_session(session_id: Uuid) -> PathBuf {
+    /// Whether the Claude the previous daemon left running (`orphan_pid`)
+    /// is still alive
+    pub fn orphan_alive(&self) -> bool {
+        self.orphan_pid.is_some_and(|pid| crate::persistence::is_same_process(pid, self.process_start_time))
+    }
+
+    /// `Exited` if Claude exited cleanly, `Crashed` if it failed, `None`
+    /// if it hasn't been seen exiting
+    pub fn exit_status(&self) -> Option<SessionStatus> {
+        self.exit_code.map(SessionStatus::from_exit_code)
+    }
+
+    /// Default directory for session logs (`~/.claude-sessions/logs`)
+    pub fn default_log_dir() -> PathBuf {
         let home = std::env::var("HOME")
             .or_else(|_| std::env::var("USERPROFILE"))
             .unwrap_or_else(|_| String::from("."));
         PathBuf::from(home)
             .join(".claude-sessions")
             .join("logs")
-            .join(format!("{}.jsonl", session_id))
+    }
+}
+
+/// Label for a session that wasn't given one: its directory's basename
+pub fn default_label(working_dir: &Path) -> String {
+    working_dir
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_else(|| working_dir.display().to_string())
+}
+
+/// How a new session's log file is named
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum LogNaming {
+    /// `<uuid>.jsonl`
+    #[default]
+    Id,
+    /// `<created>-<dir basename>-<short uuid>.jsonl`, e.g.
+    /// `20240101T120000Z-api-1a2b3c4d.jsonl`, which sorts by creation
+    /// time and is recognisable when browsing the logs directory
+    Descriptive,
+}
+
+impl LogNaming {
+    /// The file name, with the extension of `format` (`.jsonl` above)
+    fn file_name(
+        self,
+        id: Uuid,
+        created_at: chrono::DateTime<chrono::Utc>,
+        working_dir: &Path,
+        format: LogFormat,
+    ) -> String {
+        let extension = format.extension();
+        match self {
+            LogNaming::Id => format!("{}.{}", id, extension),
+            LogNaming::Descriptive => {
+                let basename: String = working_dir
+                    .file_name()
+                    .map(|name| name.to_string_lossy())
+                    .unwrap_or_default()
+                    .chars()
+                    .map(|c| if c.is_alphanumeric() || c == '-' || c == '_' { c } else { '-' })
+                    .collect();
+                let basename = if basename.is_empty() { "session".to_string() } else { basename };
+                let short_id = &id.simple().to_string()[..8];
+                format!("{}-{}-{}.{}", created_at.format("%Y%m%dT%H%M%SZ"), basename, short_id, extension)
+            }
+        }
     }
 }
 
@@ -47,8 +286,17 @@ impl Session {
 #[derive(Debug, Serialize, Deserialize)]
 pub struct SessionInfo {
     pub id: String,
+    #[serde(default)]
+    pub label: String,
+    #[serde(default)]
+    pub tags: BTreeMap<String, String>,
     pub working_dir: String,
     pub created_at: String,
-    pub status: String,
+    pub status: SessionStatus,
     pub log_path: String,
+    pub last_error: Option<String>,
+    #[serde(default)]
+    pub exit_code: Option<u32>,
+    #[serde(default)]
+    pub last_activity: Option<String>,
 }
@@ -0,0 +1,164 @@
+//! `claude-sessions stats`: usage summarized across all sessions.
+//!
+//! Everything except input volume comes from session metadata and log
+//! file metadata (size, mtime), so large logs are never read for it.
+//! Input volume needs the log contents, streamed with `read_entries`.
+
+use crate::ipc::SessionInfo;
+use crate::logging::{read_entries, Direction, EntryFilter};
+use crate::retention::last_activity;
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap};
+use std::path::Path;
+
+/// How many working directories `busiest_dirs` lists
+pub const BUSIEST_DIRS: usize = 5;
+
+#[derive(Debug, Default, Serialize)]
+pub struct Stats {
+    pub total_sessions: usize,
+    pub by_status: BTreeMap<String, usize>,
+    /// Size of all session logs on disk
+    pub bytes_logged: u64,
+    /// Bytes of input sent to sessions
+    pub input_bytes: u64,
+    /// From creation to last activity (the log's mtime)
+    pub average_duration_secs: Option<u64>,
+    pub longest_duration_secs: Option<u64>,
+    /// Working directories with the most sessions, most first
+    pub busiest_dirs: Vec<DirCount>,
+}
+
+#[derive(Debug, Serialize, PartialEq, Eq)]
+pub struct DirCount {
+    pub working_dir: String,
+    pub sessions: usize,
+}
+
+/// Summarize `sessions` and their logs
+pub fn compute(sessions: &[SessionInfo]) -> Stats {
+    let mut stats = Stats { total_sessions: sessions.len(), ..Stats::default() };
+    let mut durations = Vec::new();
+    let mut dirs: HashMap<&str, usize> = HashMap::new();
+
+    for session in sessions {
+        *stats.by_status.entry(session.status.to_string()).or_default() += 1;
+        *dirs.entry(session.working_dir.as_str()).or_default() += 1;
+
+        let log_path = Path::new(&session.log_path);
+        // Sessions that haven't produced output yet have no log
+        if let Ok(metadata) = std::fs::metadata(log_path) {
+            stats.bytes_logged += metadata.len();
+            stats.input_bytes += input_bytes(log_path);
+        }
+
+        let created = chrono::DateTime::parse_from_rfc3339(&session.created_at).ok();
+        let last = last_activity(log_path, &session.created_at);
+        if let (Some(created), Some(last)) = (created, last) {
+            let created = std::time::SystemTime::from(created);
+            durations.push(last.duration_since(created).map(|d| d.as_secs()).unwrap_or(0));
+        }
+    }
+
+    if !durations.is_empty() {
+        stats.average_duration_secs = Some(durations.iter().sum::<u64>() / durations.len() as u64);
+        stats.longest_duration_secs = durations.iter().max().copied();
+    }
+
+    let mut dirs: Vec<DirCount> = dirs
+        .into_iter()
+        .map(|(working_dir, sessions)| DirCount { working_dir: working_dir.to_string(), sessions })
+        .collect();
+    dirs.sort_by(|a, b| b.sessions.cmp(&a.sessions).then_with(|| a.working_dir.cmp(&b.working_dir)));
+    dirs.truncate(BUSIEST_DIRS);
+    stats.busiest_dirs = dirs;
+
+    stats
+}
+
+/// Total size of the input entries in a log
+fn input_bytes(log_path: &Path) -> u64 {
+    let Ok(entries) = read_entries(log_path, EntryFilter::direction(Direction::Input)) else {
+        return 0;
+    };
+    entries.map_while(Result::ok).map(|entry| entry.size as u64).sum()
+}
+
+/// Format a duration in seconds as e.g. `2h 05m` or `42s`
+pub fn format_duration(secs: u64) -> String {
+    match secs {
+        0..=59 => format!("{}s", secs),
+        60..=3599 => format!("{}m {:02}s", secs / 60, secs % 60),
+        _ => format!("{}h {:02}m", secs / 3600, secs / 60 % 60),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::logging::SessionLogger;
+    use crate::session::SessionStatus;
+    use uuid::Uuid;
+
+    #[test]
+    fn test_stats_over_seeded_sessions() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let mut sessions = Vec::new();
+        let seeds = [
+            ("/repos/api", SessionStatus::Running, vec![(Direction::Input, "hello"), (Direction::Output, "0123456789")]),
+            ("/repos/api", SessionStatus::Stale, vec![(Direction::Input, "abc")]),
+            ("/repos/web", SessionStatus::Running, vec![]),
+        ];
+        for (working_dir, status, entries) in seeds {
+            let id = Uuid::new_v4();
+            let log_path = temp_dir.path().join(format!("{}.jsonl", id));
+            if !entries.is_empty() {
+                let mut logger = SessionLogger::with_path(id, log_path.clone()).unwrap();
+                for (direction, data) in entries {
+                    logger.log(direction, data.as_bytes().to_vec()).unwrap();
+                }
+            }
+            sessions.push(SessionInfo {
+                id: id.to_string(),
+                working_dir: working_dir.to_string(),
+                created_at: "2024-01-01T00:00:00+00:00".to_string(),
+                status,
+                log_path: log_path.display().to_string(),
+                last_error: None,
+                exit_code: None,
+                last_activity: None,
+                idle_seconds: None,
+                label: String::new(),
+                tags: Default::default(),
+                healthy: None,
+            });
+        }
+
+        let stats = compute(&sessions);
+        assert_eq!(stats.total_sessions, 3);
+        assert_eq!(stats.by_status["running"], 2);
+        assert_eq!(stats.by_status["stale"], 1);
+        assert_eq!(stats.input_bytes, 8);
+        let on_disk: u64 = sessions
+            .iter()
+            .filter_map(|s| std::fs::metadata(&s.log_path).ok())
+            .map(|m| m.len())
+            .sum();
+        assert_eq!(stats.bytes_logged, on_disk);
+        assert_eq!(
+            stats.busiest_dirs,
+            vec![
+                DirCount { working_dir: "/repos/api".to_string(), sessions: 2 },
+                DirCount { working_dir: "/repos/web".to_string(), sessions: 1 },
+            ]
+        );
+        assert!(stats.longest_duration_secs >= stats.average_duration_secs);
+    }
+
+    #[test]
+    fn test_format_duration() {
+        assert_eq!(format_duration(42), "42s");
+        assert_eq!(format_duration(125), "2m 05s");
+        assert_eq!(format_duration(7500), "2h 05m");
+    }
+}
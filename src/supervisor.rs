@@ -0,0 +1,183 @@
+use std::collections::VecDeque;
+use std::future::Future;
+use std::time::{Duration, Instant};
+
+/// Restart policy for sessions started with `--restart-on-crash`.
+///
+/// Defaults can be overridden through the environment:
+/// - `CLAUDE_SESSIONS_MAX_RESTARTS` (default 5)
+/// - `CLAUDE_SESSIONS_CRASH_LOOP_THRESHOLD` (default 3)
+/// - `CLAUDE_SESSIONS_CRASH_LOOP_WINDOW_SECS` (default 60)
+#[derive(Debug, Clone)]
+pub struct RestartPolicy {
+    /// Maximum number of restarts over the session's lifetime
+    pub max_restarts: u32,
+    /// Number of crashes within `crash_loop_window` that opens the breaker
+    pub crash_loop_threshold: usize,
+    /// Sliding window for crash-loop detection
+    pub crash_loop_window: Duration,
+    /// Delay before each restart
+    pub backoff: Duration,
+}
+
+impl Default for RestartPolicy {
+    fn default() -> Self {
+        RestartPolicy {
+            max_restarts: 5,
+            crash_loop_threshold: 3,
+            crash_loop_window: Duration::from_secs(60),
+            backoff: Duration::from_secs(1),
+        }
+    }
+}
+
+impl RestartPolicy {
+    /// Build a policy from defaults plus environment overrides
+    pub fn from_env() -> Self {
+        let mut policy = Self::default();
+        if let Some(n) = env_parse("CLAUDE_SESSIONS_MAX_RESTARTS") {
+            policy.max_restarts = n;
+        }
+        if let Some(n) = env_parse("CLAUDE_SESSIONS_CRASH_LOOP_THRESHOLD") {
+            policy.crash_loop_threshold = n;
+        }
+        if let Some(secs) = env_parse("CLAUDE_SESSIONS_CRASH_LOOP_WINDOW_SECS") {
+            policy.crash_loop_window = Duration::from_secs(secs);
+        }
+        policy
+    }
+}
+
+fn env_parse<T: std::str::FromStr>(name: &str) -> Option<T> {
+    std::env::var(name).ok()?.parse().ok()
+}
+
+/// Circuit breaker that opens after too many crashes in a short window.
+///
+/// A session that crashes instantly (e.g. misconfigured binary) would
+/// otherwise burn through its whole restart budget, spamming logs and CPU.
+pub struct CrashLoopBreaker {
+    threshold: usize,
+    window: Duration,
+    crashes: VecDeque<Instant>,
+}
+
+impl CrashLoopBreaker {
+    pub fn new(threshold: usize, window: Duration) -> Self {
+        CrashLoopBreaker {
+            threshold,
+            window,
+            crashes: VecDeque::new(),
+        }
+    }
+
+    /// Record a crash; returns true if the breaker is now open
+    pub fn record_crash(&mut self, at: Instant) -> bool {
+        self.crashes.push_back(at);
+        while let Some(&oldest) = self.crashes.front() {
+            if at.duration_since(oldest) > self.window {
+                self.crashes.pop_front();
+            } else {
+                break;
+            }
+        }
+        self.crashes.len() >= self.threshold
+    }
+}
+
+/// Result of one run of a supervised session
+#[derive(Debug)]
+pub enum RunResult {
+    /// The session was stopped on purpose; don't restart
+    Stopped,
+    /// The process exited unexpectedly (or failed to spawn)
+    Crashed(String),
+}
+
+/// Why supervision ended
+#[derive(Debug)]
+pub enum SupervisorOutcome {
+    /// The session was stopped on purpose
+    Stopped,
+    /// The restart budget was used up
+    RestartLimit { restarts: u32, last_error: String },
+    /// The crash-loop breaker opened
+    CrashLooping { crashes: usize, last_error: String },
+}
+
+/// Run a session under the restart policy.
+///
+/// `run` is called once per incarnation and resolves when that
+/// incarnation ends. Supervision stops when the session is stopped on
+/// purpose, the restart budget is exhausted, or the crash-loop breaker
+/// opens (whichever comes first).
+pub async fn supervise<F, Fut>(policy: &RestartPolicy, mut run: F) -> SupervisorOutcome
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = RunResult>,
+{
+    let mut breaker = CrashLoopBreaker::new(policy.crash_loop_threshold, policy.crash_loop_window);
+    let mut restarts = 0;
+    let mut crashes = 0;
+
+    loop {
+        let last_error = match run().await {
+            RunResult::Stopped => return SupervisorOutcome::Stopped,
+            RunResult::Crashed(error) => error,
+        };
+        crashes += 1;
+
+        if breaker.record_crash(Instant::now()) {
+            return SupervisorOutcome::CrashLooping { crashes, last_error };
+        }
+        if restarts >= policy.max_restarts {
+            return SupervisorOutcome::RestartLimit { restarts, last_error };
+        }
+
+        restarts += 1;
+        tokio::time::sleep(policy.backoff).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_breaker_window() {
+        let start = Instant::now();
+        let mut breaker = CrashLoopBreaker::new(3, Duration::from_secs(10));
+
+        assert!(!breaker.record_crash(start));
+        assert!(!breaker.record_crash(start + Duration::from_secs(1)));
+        // First crash fell out of the window
+        assert!(!breaker.record_crash(start + Duration::from_secs(11)));
+        assert!(breaker.record_crash(start + Duration::from_secs(11)));
+    }
+
+    #[tokio::test]
+    async fn test_always_failing_command_opens_breaker() {
+        let policy = RestartPolicy {
+            max_restarts: 100,
+            crash_loop_threshold: 3,
+            crash_loop_window: Duration::from_secs(60),
+            backoff: Duration::from_millis(1),
+        };
+
+        let mut attempts = 0;
+        let outcome = supervise(&policy, || {
+            attempts += 1;
+            async { RunResult::Crashed("spawn failed: no such binary".to_string()) }
+        })
+        .await;
+
+        match outcome {
+            SupervisorOutcome::CrashLooping { crashes, last_error } => {
+                assert_eq!(crashes, 3);
+                assert!(last_error.contains("spawn failed"));
+            }
+            other => panic!("Expected crash loop, got {:?}", other),
+        }
+        assert_eq!(attempts, 3);
+    }
+}
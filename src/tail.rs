@@ -0,0 +1,118 @@
+use std::io::{self, BufRead, BufReader, Read, Seek, SeekFrom};
+use std::path::Path;
+use std::time::Duration;
+
+/// How often `follow` checks the file for new data
+pub const POLL_INTERVAL: Duration = Duration::from_millis(200);
+
+/// Read the last `n` lines of a text file.
+///
+/// Also returns the byte offset the file ended at, to hand to `follow`.
+pub fn last_lines(path: &Path, n: usize) -> io::Result<(Vec<String>, u64)> {
+    let file = std::fs::File::open(path)?;
+    let len = file.metadata()?.len();
+
+    let mut lines = std::collections::VecDeque::with_capacity(n);
+    for line in BufReader::new(file).lines() {
+        if lines.len() == n {
+            lines.pop_front();
+        }
+        if n > 0 {
+            lines.push_back(line?);
+        }
+    }
+
+    Ok((lines.into(), len))
+}
+
+/// Read the complete lines appended to a text file since `offset`, and
+/// advance `offset` past them.
+///
+/// Partial lines are held back until their newline arrives. If the file
+/// shrinks (truncated or replaced) reading restarts from the beginning. A
+/// missing file reads as empty.
+pub fn read_new_lines(path: &Path, offset: &mut u64) -> io::Result<Vec<String>> {
+    let mut file = match std::fs::File::open(path) {
+        Ok(file) => file,
+        // Not created yet (or briefly missing while being replaced)
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e),
+    };
+
+    if file.metadata()?.len() < *offset {
+        *offset = 0;
+    }
+    file.seek(SeekFrom::Start(*offset))?;
+    let mut buffer = Vec::new();
+    file.read_to_end(&mut buffer)?;
+
+    let Some(end) = buffer.iter().rposition(|&b| b == b'\n') else {
+        return Ok(Vec::new());
+    };
+    *offset += end as u64 + 1;
+    Ok(buffer[..end]
+        .split(|&b| b == b'\n')
+        .map(|line| String::from_utf8_lossy(line).into_owned())
+        .collect())
+}
+
+/// Follow a text file from `offset`, calling `on_line` for each complete
+/// line appended to it (see `read_new_lines`).
+///
+/// Runs until the returned future is dropped.
+pub async fn follow<F: FnMut(&str)>(path: &Path, mut offset: u64, mut on_line: F) -> io::Result<()> {
+    loop {
+        for line in read_new_lines(path, &mut offset)? {
+            on_line(&line);
+        }
+        tokio::time::sleep(POLL_INTERVAL).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+
+    #[test]
+    fn test_last_lines() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let path = temp_dir.path().join("daemon.log");
+        std::fs::write(&path, "one\ntwo\nthree\n").unwrap();
+
+        let (lines, offset) = last_lines(&path, 2).unwrap();
+        assert_eq!(lines, vec!["two", "three"]);
+        assert_eq!(offset, 14);
+
+        let (lines, _) = last_lines(&path, 10).unwrap();
+        assert_eq!(lines.len(), 3);
+    }
+
+    #[tokio::test]
+    async fn test_follow_emits_appended_lines() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let path = temp_dir.path().join("daemon.log");
+        std::fs::write(&path, "old\n").unwrap();
+        let (_, offset) = last_lines(&path, 0).unwrap();
+
+        let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+        let follow_path = path.clone();
+        let task = tokio::spawn(async move {
+            follow(&follow_path, offset, |line| {
+                let _ = tx.send(line.to_string());
+            })
+            .await
+        });
+
+        let mut file = std::fs::OpenOptions::new().append(true).open(&path).unwrap();
+        write!(file, "new\npart").unwrap();
+
+        let line = tokio::time::timeout(Duration::from_secs(2), rx.recv()).await.unwrap();
+        assert_eq!(line.as_deref(), Some("new"));
+        // The unterminated line is held back
+        tokio::time::sleep(POLL_INTERVAL * 2).await;
+        assert!(rx.try_recv().is_err());
+
+        task.abort();
+    }
+}
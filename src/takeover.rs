@@ -0,0 +1,116 @@
+//! `attach --takeover`: drive a session from the local terminal as if
+//! `claude` were running locally.
+//!
+//! The local terminal is put into raw mode and bytes pass through untouched:
+//! keystrokes go to the session's PTY via `SendRaw`, and the session's output
+//! is replayed from its log as it is written. Terminal resizes are forwarded
+//! with `Resize`. Ctrl-] detaches; SIGTERM/SIGHUP end the takeover cleanly
+//! and the terminal is restored in every case.
+
+use crate::client::{Client, ClientSession};
+use crate::ipc::{Request, Response};
+use crate::logging::{LogEntry, LogFollower};
+use crate::tail;
+use crate::terminal::{route_input, terminal_size, InputAction, RawModeGuard};
+use anyhow::{Context, Result};
+use std::io::{Read, Write};
+use std::os::fd::AsRawFd;
+use std::path::Path;
+use tokio::signal::unix::{signal, SignalKind};
+use tokio::sync::mpsc;
+
+/// Take over `session_id` until the user detaches
+pub async fn run(client: &Client, session_id: &str, log_path: &Path) -> Result<()> {
+    let stdin_fd = std::io::stdin().as_raw_fd();
+    let stdout_fd = std::io::stdout().as_raw_fd();
+    let mut requests = client.session().await?;
+
+    // Size the session to this terminal before any output is shown
+    if let Some((rows, cols)) = terminal_size(stdout_fd) {
+        let resize = Request::Resize { session_id: session_id.to_string(), rows, cols };
+        expect_ok(&mut requests, resize).await?;
+    }
+
+    // Only show output produced from now on
+    let log = LogFollower::at_end(log_path);
+
+    let _raw_mode = RawModeGuard::enable(stdin_fd)
+        .context("Takeover needs an interactive terminal")?;
+
+    let output = tokio::spawn(show_output(log));
+
+    let result = pump_input(&mut requests, session_id, stdout_fd).await;
+    output.abort();
+    result
+}
+
+/// Write the session's output to the terminal as it is logged
+async fn show_output(mut log: LogFollower) -> std::io::Result<()> {
+    loop {
+        for entry in log.read_new_entries()?.into_iter().filter_map(LogEntry::into_output) {
+            let mut stdout = std::io::stdout().lock();
+            let _ = stdout.write_all(&entry.data);
+            let _ = stdout.flush();
+        }
+        tokio::time::sleep(tail::POLL_INTERVAL).await;
+    }
+}
+
+/// Forward local input and resizes until detach, stdin EOF or a signal
+async fn pump_input(requests: &mut ClientSession, session_id: &str, stdout_fd: i32) -> Result<()> {
+    // Blocking stdin reads live on a plain thread so they can't hold up
+    // runtime shutdown
+    let (input_tx, mut input_rx) = mpsc::channel::<Vec<u8>>(64);
+    std::thread::spawn(move || {
+        let mut buffer = [0u8; 1024];
+        loop {
+            match std::io::stdin().lock().read(&mut buffer) {
+                Ok(0) | Err(_) => break,
+                Ok(n) => {
+                    if input_tx.blocking_send(buffer[..n].to_vec()).is_err() {
+                        break;
+                    }
+                }
+            }
+        }
+    });
+
+    let mut winch = signal(SignalKind::window_change())?;
+    let mut terminate = signal(SignalKind::terminate())?;
+    let mut hangup = signal(SignalKind::hangup())?;
+
+    loop {
+        tokio::select! {
+            chunk = input_rx.recv() => {
+                let Some(chunk) = chunk else { return Ok(()) };
+                let (data, detach) = match route_input(&chunk) {
+                    InputAction::Forward(data) => (data, false),
+                    InputAction::Detach(data) => (data, true),
+                };
+                if !data.is_empty() {
+                    let send = Request::SendRaw { session_id: session_id.to_string(), data };
+                    expect_ok(requests, send).await?;
+                }
+                if detach {
+                    return Ok(());
+                }
+            }
+            _ = winch.recv() => {
+                if let Some((rows, cols)) = terminal_size(stdout_fd) {
+                    let resize = Request::Resize { session_id: session_id.to_string(), rows, cols };
+                    expect_ok(requests, resize).await?;
+                }
+            }
+            _ = terminate.recv() => return Ok(()),
+            _ = hangup.recv() => return Ok(()),
+        }
+    }
+}
+
+async fn expect_ok(requests: &mut ClientSession, request: Request) -> Result<()> {
+    match requests.send_request(request).await? {
+        Response::Ok => Ok(()),
+        Response::Error { message, .. } => anyhow::bail!(message),
+        _ => anyhow::bail!("Unexpected response from daemon"),
+    }
+}
@@ -0,0 +1,133 @@
+//! Local terminal handling for `attach --takeover`.
+//!
+//! Takeover puts the local terminal into raw mode and passes bytes straight
+//! through in both directions, so restoring the terminal afterwards matters
+//! as much as entering raw mode: `RawModeGuard` restores the saved state on
+//! drop, which also covers early returns and unwinding panics.
+
+use std::io;
+
+/// Byte that detaches from a takeover (Ctrl-], as in telnet)
+pub const DETACH_BYTE: u8 = 0x1d;
+
+/// Puts a terminal into raw mode and restores its previous state on drop
+#[cfg(unix)]
+pub struct RawModeGuard {
+    fd: std::os::fd::RawFd,
+    saved: libc::termios,
+}
+
+#[cfg(unix)]
+impl RawModeGuard {
+    /// Switch `fd` to raw mode, remembering the current settings.
+    ///
+    /// Fails if `fd` isn't a terminal.
+    pub fn enable(fd: std::os::fd::RawFd) -> io::Result<Self> {
+        let mut saved: libc::termios = unsafe { std::mem::zeroed() };
+        if unsafe { libc::tcgetattr(fd, &mut saved) } != 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        let mut raw = saved;
+        unsafe { libc::cfmakeraw(&mut raw) };
+        if unsafe { libc::tcsetattr(fd, libc::TCSANOW, &raw) } != 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        Ok(RawModeGuard { fd, saved })
+    }
+}
+
+#[cfg(unix)]
+impl Drop for RawModeGuard {
+    fn drop(&mut self) {
+        unsafe { libc::tcsetattr(self.fd, libc::TCSANOW, &self.saved) };
+    }
+}
+
+/// Current size of the terminal on `fd` as (rows, cols)
+#[cfg(unix)]
+pub fn terminal_size(fd: std::os::fd::RawFd) -> Option<(u16, u16)> {
+    let mut size: libc::winsize = unsafe { std::mem::zeroed() };
+    if unsafe { libc::ioctl(fd, libc::TIOCGWINSZ, &mut size) } != 0 || size.ws_row == 0 {
+        return None;
+    }
+    Some((size.ws_row, size.ws_col))
+}
+
+/// What to do with a chunk of local input during takeover
+#[derive(Debug, PartialEq, Eq)]
+pub enum InputAction {
+    /// Send these bytes to the session
+    Forward(Vec<u8>),
+    /// Send these bytes (possibly none), then detach
+    Detach(Vec<u8>),
+}
+
+/// Route a chunk of raw local input.
+///
+/// Everything is passed through untouched except `DETACH_BYTE`, which ends
+/// the takeover; bytes typed before it in the same chunk are still sent.
+pub fn route_input(chunk: &[u8]) -> InputAction {
+    match chunk.iter().position(|&b| b == DETACH_BYTE) {
+        Some(at) => InputAction::Detach(chunk[..at].to_vec()),
+        None => InputAction::Forward(chunk.to_vec()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_route_input() {
+        assert_eq!(
+            route_input(b"ls -la\r\x1b[A"),
+            InputAction::Forward(b"ls -la\r\x1b[A".to_vec())
+        );
+        assert_eq!(route_input(b"ab\x1dcd"), InputAction::Detach(b"ab".to_vec()));
+        assert_eq!(route_input(&[DETACH_BYTE]), InputAction::Detach(vec![]));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_raw_mode_guard_restores_state() {
+        use std::os::fd::AsRawFd;
+
+        // The slave side of a fresh pseudo-terminal stands in for the user's terminal
+        let master = unsafe { libc::posix_openpt(libc::O_RDWR | libc::O_NOCTTY) };
+        assert!(master >= 0);
+        assert_eq!(unsafe { libc::grantpt(master) }, 0);
+        assert_eq!(unsafe { libc::unlockpt(master) }, 0);
+        let name = unsafe { std::ffi::CStr::from_ptr(libc::ptsname(master)) };
+        let tty = std::fs::OpenOptions::new()
+            .read(true)
+            .write(true)
+            .open(name.to_str().unwrap())
+            .unwrap();
+        let fd = tty.as_raw_fd();
+
+        let lflag = |fd| {
+            let mut t: libc::termios = unsafe { std::mem::zeroed() };
+            assert_eq!(unsafe { libc::tcgetattr(fd, &mut t) }, 0);
+            t.c_lflag
+        };
+        let before = lflag(fd);
+        assert_ne!(before & libc::ICANON, 0);
+
+        {
+            let _guard = RawModeGuard::enable(fd).unwrap();
+            assert_eq!(lflag(fd) & (libc::ICANON | libc::ECHO), 0);
+        }
+        assert_eq!(lflag(fd), before);
+
+        unsafe { libc::close(master) };
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_raw_mode_requires_tty() {
+        let file = tempfile::tempfile().unwrap();
+        assert!(RawModeGuard::enable(std::os::fd::AsRawFd::as_raw_fd(&file)).is_err());
+    }
+}
@@ -1,7 +1,8 @@
 #[cfg(test)]
+#[allow(clippy::module_inception)]
 mod tests {
-    use crate::manager::SessionManager;
-    use crate::session::Session;
+    use crate::manager::{SessionManager, ShutdownMode, StartOptions};
+    use crate::session::{Session, SessionStatus};
     use std::path::PathBuf;
     use tempfile::TempDir;
 
@@ -40,11 +41,77 @@ mod tests {
     #[tokio::test]
     async fn test_start_session_invalid_dir() {
         let manager = SessionManager::new();
-        let result = manager.start_session(PathBuf::from("/nonexistent/path")).await;
+        let result = manager
+            .start_session(PathBuf::from("/nonexistent/path"), StartOptions::default())
+            .await;
         
         assert!(result.is_err(), "Should fail for non-existent directory");
     }
 
+    #[test]
+    fn test_working_dir_is_canonicalized() {
+        use crate::manager::resolve_working_dir;
+
+        let temp_dir = create_test_dir();
+        let project = temp_dir.path().join("project");
+        std::fs::create_dir(&project).unwrap();
+        let canonical = project.canonicalize().unwrap();
+
+        assert_eq!(resolve_working_dir(&project).unwrap(), canonical);
+        assert_eq!(resolve_working_dir(&project.join("..").join("project").join(".")).unwrap(), canonical);
+        #[cfg(unix)]
+        {
+            let link = temp_dir.path().join("link");
+            std::os::unix::fs::symlink(&project, &link).unwrap();
+            assert_eq!(resolve_working_dir(&link).unwrap(), canonical);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_start_session_rejects_unusable_working_dirs() {
+        use crate::ipc::ErrorCode;
+        use crate::manager::{resolve_working_dir, SessionError, WorkingDirProblem};
+        use crate::persistence::PersistenceManager;
+
+        let temp_dir = create_test_dir();
+        let problem = |path: &std::path::Path| match resolve_working_dir(path) {
+            Err(SessionError::InvalidWorkingDir { problem, .. }) => Some(problem),
+            _ => None,
+        };
+
+        let missing = temp_dir.path().join("missing");
+        assert_eq!(problem(&missing), Some(WorkingDirProblem::NotFound));
+        let file = temp_dir.path().join("file.txt");
+        std::fs::write(&file, b"").unwrap();
+        assert_eq!(problem(&file), Some(WorkingDirProblem::NotADirectory));
+        assert!(matches!(
+            resolve_working_dir(std::path::Path::new("relative/dir")),
+            Err(SessionError::InvalidArgument(_))
+        ));
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let locked = temp_dir.path().join("locked");
+            std::fs::create_dir(&locked).unwrap();
+            std::fs::set_permissions(&locked, std::fs::Permissions::from_mode(0o000)).unwrap();
+            // Root reads it anyway
+            if std::fs::read_dir(&locked).is_err() {
+                assert_eq!(problem(&locked), Some(WorkingDirProblem::PermissionDenied));
+            }
+            std::fs::set_permissions(&locked, std::fs::Permissions::from_mode(0o700)).unwrap();
+        }
+
+        // The daemon reports each as an invalid argument, with its own message
+        let persistence = PersistenceManager::with_state_file(temp_dir.path().join("sessions.json")).unwrap();
+        let manager = SessionManager::with_persistence(persistence);
+        for (path, message) in [(&missing, "does not exist"), (&file, "is not a directory")] {
+            let err = manager.start_session(path.clone(), StartOptions::default()).await.unwrap_err();
+            assert_eq!(ErrorCode::of(&err), ErrorCode::InvalidArgument);
+            assert!(err.to_string().contains(message), "{}", err);
+        }
+        assert!(manager.list_sessions().await.is_empty());
+    }
+
     #[tokio::test]
     async fn test_start_session_valid_dir() {
         let manager = SessionManager::new();
@@ -52,7 +119,9 @@ mod tests {
         
         // Note: This will fail if 'claude' command doesn't exist
         // For testing purposes, we're just checking the directory validation
-        let result = manager.start_session(temp_dir.path().to_path_buf()).await;
+        let result = manager
+            .start_session(temp_dir.path().to_path_buf(), StartOptions::default())
+            .await;
         
         // Expected to fail because 'claude' command likely doesn't exist in test env
         // But should pass directory validation
@@ -86,23 +155,54 @@ mod tests {
         let temp_dir = create_test_dir();
         
         // Start session
-        let session_id = manager.start_session(temp_dir.path().to_path_buf()).await
+        let info = manager
+            .start_session(temp_dir.path().to_path_buf(), StartOptions::default())
+            .await
             .expect("Failed to start session");
+        let session_id = uuid::Uuid::parse_str(&info.id).unwrap();
         
         // Verify it's in the list
         let sessions = manager.list_sessions().await;
         assert_eq!(sessions.len(), 1);
-        assert_eq!(sessions[0].id, session_id.to_string());
+        assert_eq!(sessions[0].id, info.id);
         
         // Stop session
-        manager.stop_session(session_id).await
+        let stopped = manager.stop_session(session_id).await
             .expect("Failed to stop session");
+        assert_eq!(stopped.status, SessionStatus::Stopped);
         
         // Verify it's gone
         let sessions = manager.list_sessions().await;
         assert_eq!(sessions.len(), 0);
     }
 
+    // Integration test - only runs if 'claude' command exists
+    #[tokio::test]
+    #[ignore] // Use `cargo test -- --ignored` to run this
+    async fn test_start_session_returns_info() {
+        let manager = SessionManager::new();
+        let temp_dir = create_test_dir();
+        let log_dir = create_test_dir();
+
+        let options = StartOptions {
+            log_dir: Some(log_dir.path().to_path_buf()),
+            ..StartOptions::default()
+        };
+        let info = manager
+            .start_session(temp_dir.path().to_path_buf(), options)
+            .await
+            .expect("Failed to start session");
+
+        assert!(uuid::Uuid::parse_str(&info.id).is_ok());
+        assert_eq!(info.working_dir, temp_dir.path().canonicalize().unwrap().display().to_string());
+        assert_eq!(info.status, SessionStatus::Running);
+        assert_eq!(info.log_path, log_dir.path().join(format!("{}.jsonl", info.id)).display().to_string());
+        assert!(chrono::DateTime::parse_from_rfc3339(&info.created_at).is_ok());
+        assert!(info.last_error.is_none());
+
+        manager.stop_session(uuid::Uuid::parse_str(&info.id).unwrap()).await.unwrap();
+    }
+
     #[test]
     fn test_session_serialization() {
         let dir = PathBuf::from("/tmp/test");
@@ -122,10 +222,15 @@ mod tests {
         
         let info = SessionInfo {
             id: "test-id".to_string(),
+            label: String::new(),
+            tags: Default::default(),
             working_dir: "/tmp".to_string(),
             created_at: "2024-01-01T00:00:00Z".to_string(),
-            status: "running".to_string(),
+            status: SessionStatus::Running,
             log_path: "/tmp/test.log".to_string(),
+            last_error: None,
+            exit_code: None,
+            last_activity: None,
         };
         
         let json = serde_json::to_string(&info)
@@ -135,4 +240,1144 @@ mod tests {
         assert!(json.contains("running"));
         assert!(json.contains("test.log"));
     }
+
+    #[test]
+    fn test_session_status_keeps_on_disk_strings() {
+        let spellings = [
+            (SessionStatus::Running, "running"),
+            (SessionStatus::Stopped, "stopped"),
+            (SessionStatus::Stale, "stale"),
+            (SessionStatus::Crashed, "crashed"),
+            (SessionStatus::Orphaned, "orphaned"),
+            (SessionStatus::Exited, "exited"),
+            (SessionStatus::CrashLooping, "crash-looping"),
+            (SessionStatus::Imported, "imported"),
+        ];
+        for (status, spelling) in spellings {
+            assert_eq!(serde_json::to_string(&status).unwrap(), format!("\"{}\"", spelling));
+            assert_eq!(serde_json::from_str::<SessionStatus>(&format!("\"{}\"", spelling)).unwrap(), status);
+            assert_eq!(status.to_string(), spelling);
+        }
+        // A newer version's status survives a round trip
+        let newer = serde_json::from_str::<SessionStatus>("\"hibernating\"").unwrap();
+        assert_eq!(newer, SessionStatus::Unknown("hibernating".to_string()));
+        assert_eq!(serde_json::to_string(&newer).unwrap(), "\"hibernating\"");
+
+        // A sessions.json written before the enum still loads
+        let temp_dir = create_test_dir();
+        let state_file = temp_dir.path().join("sessions.json");
+        let id = uuid::Uuid::new_v4();
+        let record = format!(
+            r#"{{"version":1,"sessions":{{"{id}":{{"id":"{id}","working_dir":"/tmp","created_at":"2024-01-01T00:00:00Z","log_path":"/tmp/x.jsonl","pid":null,"status":"orphaned"}}}}}}"#,
+            id = id
+        );
+        std::fs::write(&state_file, record).unwrap();
+        let loaded = crate::persistence::PersistenceManager::with_state_file(state_file)
+            .unwrap()
+            .load_state()
+            .unwrap();
+        assert_eq!(loaded[&id].status, SessionStatus::Orphaned);
+    }
+
+    #[test]
+    fn test_start_raw_prints_only_session_id() {
+        use crate::output::Output;
+
+        let (out, stdout, stderr) = Output::captured(true);
+        let session_id = uuid::Uuid::new_v4().to_string();
+        crate::report_session_started(
+            &out,
+            &session_id,
+            std::path::Path::new("/tmp"),
+            "/tmp/test.jsonl",
+        );
+
+        assert_eq!(stdout.contents(), format!("{}\n", session_id));
+        assert!(stderr.contents().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_flush_persists_state_and_log() {
+        use crate::logging::{Direction, SessionLogger};
+        use crate::persistence::PersistenceManager;
+
+        let temp_dir = create_test_dir();
+        let state_file = temp_dir.path().join("sessions.json");
+        let persistence = PersistenceManager::with_state_file(state_file.clone()).unwrap();
+        let manager = SessionManager::with_persistence(persistence);
+
+        let session = Session::with_log_dir(temp_dir.path().to_path_buf(), Some(temp_dir.path()));
+        let session_id = session.id;
+        let log_path = session.log_path.clone();
+        manager.insert_session(session).await;
+
+        let mut logger = SessionLogger::with_path(session_id, log_path.clone()).unwrap();
+        logger.log(Direction::Output, b"latest output".to_vec()).unwrap();
+
+        let synced = manager.flush(None).await.unwrap();
+        assert_eq!(synced, 1);
+
+        let state = std::fs::read_to_string(&state_file).unwrap();
+        assert!(state.contains(&session_id.to_string()));
+
+        let log = std::fs::read_to_string(&log_path).unwrap();
+        assert_eq!(log.lines().count(), 1);
+        assert!(log.contains(&session_id.to_string()));
+
+        assert!(manager.flush(Some(uuid::Uuid::new_v4())).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_running_session_persists_pid() {
+        use crate::persistence::PersistenceManager;
+        use crate::pty::test_support::cat_process;
+
+        let temp_dir = create_test_dir();
+        let state_file = temp_dir.path().join("sessions.json");
+        let manager = SessionManager::with_persistence(PersistenceManager::with_state_file(state_file.clone()).unwrap());
+
+        let session = Session::with_log_dir(temp_dir.path().to_path_buf(), Some(temp_dir.path()));
+        let session_id = session.id;
+        let process = cat_process(session_id, &session.log_path);
+        let pid = process.pid();
+        assert!(pid.is_some());
+        manager.insert_session(session).await;
+        manager.insert_process(process).await;
+
+        manager.flush(None).await.unwrap();
+        let persisted = PersistenceManager::with_state_file(state_file.clone()).unwrap().load_state().unwrap();
+        assert_eq!(persisted[&session_id].pid, pid);
+
+        // A deliberate shutdown recovers as stale, not crashed
+        manager.shutdown_all(ShutdownMode::Kill).await;
+        let persisted = PersistenceManager::with_state_file(state_file).unwrap().load_state().unwrap();
+        assert_eq!(persisted[&session_id].pid, None);
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_shutdown_syncs_logs_and_saves_state_in_either_mode() {
+        use crate::logging::{read_entries, Direction, EntryFilter};
+        use crate::persistence::{is_process_alive, PersistenceManager};
+        use crate::pty::test_support::cat_process;
+
+        for mode in [ShutdownMode::Leave, ShutdownMode::Kill] {
+            let temp_dir = create_test_dir();
+            let state_file = temp_dir.path().join("sessions.json");
+            let manager =
+                SessionManager::with_persistence(PersistenceManager::with_state_file(state_file.clone()).unwrap());
+
+            let session = Session::with_log_dir(temp_dir.path().to_path_buf(), Some(temp_dir.path()));
+            let session_id = session.id;
+            let log_path = session.log_path.clone();
+            let process = cat_process(session_id, &log_path);
+            let pid = process.pid().unwrap();
+            manager.insert_session(session).await;
+            manager.insert_process(process).await;
+            manager.send_input(session_id, "last words".to_string()).await.unwrap();
+
+            assert_eq!(manager.shutdown_all(mode).await, 1);
+
+            // Everything is on disk by the time shutdown_all returns
+            let input: Vec<Vec<u8>> = read_entries(&log_path, EntryFilter::direction(Direction::Input))
+                .unwrap()
+                .map(|entry| entry.unwrap().data)
+                .collect();
+            assert_eq!(input, vec![b"last words\n".to_vec()]);
+            let persisted = PersistenceManager::with_state_file(state_file).unwrap().load_state().unwrap();
+            match mode {
+                ShutdownMode::Leave => {
+                    assert_eq!(persisted[&session_id].pid, Some(pid));
+                    assert!(is_process_alive(pid), "{:?} leaves the process running", mode);
+                    unsafe { libc::kill(pid as libc::pid_t, libc::SIGKILL) };
+                }
+                ShutdownMode::Kill => {
+                    assert_eq!(persisted[&session_id].pid, None);
+                    assert!(!is_process_alive(pid), "{:?} stops the process", mode);
+                }
+            }
+        }
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_sessions_left_at_shutdown_recover_as_stale() {
+        use crate::persistence::PersistenceManager;
+        use crate::pty::test_support::cat_process;
+
+        let temp_dir = create_test_dir();
+        let state_file = temp_dir.path().join("sessions.json");
+        let manager = SessionManager::with_persistence(PersistenceManager::with_state_file(state_file.clone()).unwrap());
+
+        let session = Session::with_log_dir(temp_dir.path().to_path_buf(), Some(temp_dir.path()));
+        let session_id = session.id;
+        let process = cat_process(session_id, &session.log_path);
+        let pid = process.pid().unwrap();
+        let mut status = process.status_watch();
+        manager.insert_session(session).await;
+        manager.insert_process(process).await;
+
+        // A plain `stop-daemon`: Claude is left running, then dies of the
+        // SIGHUP it gets once the daemon's terminal closes
+        manager.shutdown_all(ShutdownMode::Leave).await;
+        unsafe { libc::kill(pid as libc::pid_t, libc::SIGHUP) };
+        tokio::time::timeout(std::time::Duration::from_secs(5), status.wait_for(|s| s.is_some())).await.unwrap().unwrap();
+
+        let (_, recovered) = SessionManager::recovered_from(PersistenceManager::with_state_file(state_file).unwrap()).await;
+        assert_eq!(recovered[&session_id], SessionStatus::Stale);
+    }
+
+    #[tokio::test]
+    async fn test_send_input_advances_last_activity() {
+        use crate::persistence::PersistenceManager;
+        use crate::pty::test_support::cat_process;
+
+        let temp_dir = create_test_dir();
+        let state_file = temp_dir.path().join("sessions.json");
+        let manager = SessionManager::with_persistence(PersistenceManager::with_state_file(state_file.clone()).unwrap());
+
+        let session = Session::with_log_dir(temp_dir.path().to_path_buf(), Some(temp_dir.path()));
+        let session_id = session.id;
+        let process = cat_process(session_id, &session.log_path);
+        manager.insert_session(session).await;
+        manager.insert_process(process).await;
+
+        let before = chrono::Utc::now();
+        manager.send_input(session_id, "hello".to_string()).await.unwrap();
+        let listed = manager.list_sessions().await;
+        let at = listed[0].last_activity.as_deref().expect("input counts as activity");
+        assert!(chrono::DateTime::parse_from_rfc3339(at).unwrap().with_timezone(&chrono::Utc) >= before);
+
+        // Kept in memory until the periodic save
+        assert!(manager.save_activity().await.unwrap() >= 1);
+        let persisted = PersistenceManager::with_state_file(state_file).unwrap().load_state().unwrap();
+        assert!(persisted[&session_id].last_activity.is_some());
+
+        manager.shutdown_all(ShutdownMode::Kill).await;
+    }
+
+    #[tokio::test]
+    async fn test_silence_after_input_marks_session_unresponsive() {
+        use crate::logging::{read_entries, Direction, EntryFilter};
+        use crate::persistence::PersistenceManager;
+        use crate::pty::SessionProcess;
+        use crate::session::SessionStatus;
+
+        let temp_dir = create_test_dir();
+        let state_file = temp_dir.path().join("sessions.json");
+        let manager = SessionManager::with_persistence(PersistenceManager::with_state_file(state_file).unwrap())
+            .with_hang_threshold(Some(std::time::Duration::from_millis(300)));
+
+        // A process that stops echoing, says it's ready, then never answers
+        let session = Session::with_log_dir(temp_dir.path().to_path_buf(), Some(temp_dir.path()));
+        let session_id = session.id;
+        let log_path = session.log_path.clone();
+        let pair = portable_pty::native_pty_system().openpty(portable_pty::PtySize::default()).unwrap();
+        let mut cmd = portable_pty::CommandBuilder::new("sh");
+        cmd.args(["-c", "stty -echo; echo ready; exec sleep 30"]);
+        let child = pair.slave.spawn_command(cmd).unwrap();
+        let process = SessionProcess::new(session_id, pair, child, log_path.clone()).unwrap();
+        manager.insert_session(session).await;
+        manager.insert_process(process).await;
+
+        let ready = || {
+            read_entries(&log_path, EntryFilter::direction(Direction::Output))
+                .map(|entries| entries.filter_map(|e| e.ok()).any(|e| e.data.windows(5).any(|w| w == b"ready")))
+                .unwrap_or(false)
+        };
+        for _ in 0..50 {
+            if ready() {
+                break;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+        }
+        assert!(ready(), "fake process never became ready");
+        // Output without pending input is not a hang
+        tokio::time::sleep(std::time::Duration::from_millis(400)).await;
+        assert_eq!(manager.list_sessions().await[0].status, SessionStatus::Running);
+
+        manager.send_input(session_id, "hello".to_string()).await.unwrap();
+        assert_eq!(manager.list_sessions().await[0].status, SessionStatus::Running);
+        tokio::time::sleep(std::time::Duration::from_millis(400)).await;
+        assert_eq!(manager.list_sessions().await[0].status, SessionStatus::Unresponsive);
+
+        manager.shutdown_all(ShutdownMode::Kill).await;
+    }
+
+    #[tokio::test]
+    async fn test_exit_code_is_recorded_and_persisted() {
+        use crate::persistence::PersistenceManager;
+        use crate::pty::SessionProcess;
+
+        let temp_dir = create_test_dir();
+        let state_file = temp_dir.path().join("sessions.json");
+        let manager = SessionManager::with_persistence(PersistenceManager::with_state_file(state_file.clone()).unwrap());
+
+        let mut exits = Vec::new();
+        for code in [0, 3] {
+            let session = Session::with_log_dir(temp_dir.path().to_path_buf(), Some(temp_dir.path()));
+            let pair = portable_pty::native_pty_system().openpty(portable_pty::PtySize::default()).unwrap();
+            let mut cmd = portable_pty::CommandBuilder::new("sh");
+            cmd.args(["-c", &format!("sleep 0.2; exit {}", code)]);
+            let child = pair.slave.spawn_command(cmd).unwrap();
+            let process = SessionProcess::new(session.id, pair, child, session.log_path.clone()).unwrap();
+            exits.push((session.id, code, process.status_watch()));
+            manager.insert_session(session).await;
+            manager.insert_process(process).await;
+        }
+        for (session_id, _, status) in &exits {
+            manager.track_exit(*session_id, status.clone());
+        }
+
+        let expected = SessionStatus::from_exit_code;
+        let deadline = std::time::Instant::now() + std::time::Duration::from_secs(5);
+        loop {
+            let sessions = manager.list_sessions().await;
+            if sessions.iter().all(|s| s.status != SessionStatus::Running) {
+                for (session_id, code, _) in &exits {
+                    let info = sessions.iter().find(|s| s.id == session_id.to_string()).unwrap();
+                    assert_eq!(info.status, expected(*code));
+                    assert_eq!(info.exit_code, Some(*code));
+                }
+                break;
+            }
+            assert!(std::time::Instant::now() < deadline, "sessions never exited");
+            tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+        }
+
+        let persisted = PersistenceManager::with_state_file(state_file).unwrap().load_state().unwrap();
+        for (session_id, code, _) in &exits {
+            assert_eq!(persisted[session_id].status, expected(*code));
+            assert_eq!(persisted[session_id].exit_code, Some(*code));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_rename_session_updates_label_and_persists() {
+        use crate::persistence::PersistenceManager;
+
+        let temp_dir = create_test_dir();
+        let state_file = temp_dir.path().join("sessions.json");
+        let manager = SessionManager::with_persistence(PersistenceManager::with_state_file(state_file.clone()).unwrap());
+        let session = Session::with_log_dir(temp_dir.path().join("my-project"), Some(temp_dir.path()));
+        let session_id = session.id;
+        assert_eq!(session.label, "my-project");
+        manager.insert_session(session).await;
+
+        manager.rename_session(session_id, "  backend  ").await.unwrap();
+        assert!(manager.rename_session(session_id, " ").await.is_err());
+        assert_eq!(manager.list_sessions().await[0].label, "backend");
+
+        let persisted = PersistenceManager::with_state_file(state_file).unwrap().load_state().unwrap();
+        assert_eq!(persisted[&session_id].label, "backend");
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_session_limit_counts_only_running_sessions() {
+        use crate::manager::SessionError;
+        use crate::pty::test_support::cat_process;
+
+        let temp_dir = create_test_dir();
+        let manager = SessionManager::new().with_max_sessions(Some(1));
+        // Stale: doesn't count
+        manager
+            .insert_session(Session::with_log_dir(temp_dir.path().to_path_buf(), Some(temp_dir.path())))
+            .await;
+
+        let running = Session::with_log_dir(temp_dir.path().to_path_buf(), Some(temp_dir.path()));
+        let process = cat_process(running.id, &running.log_path);
+        let running_id = running.id;
+        manager.insert_session(running).await;
+        manager.insert_process(process).await;
+
+        let limit_reached = |result: &anyhow::Result<_>| {
+            matches!(
+                result.as_ref().err().and_then(|e| e.downcast_ref::<SessionError>()),
+                Some(SessionError::LimitReached { max: 1 })
+            )
+        };
+        let result = manager.start_session(temp_dir.path().to_path_buf(), StartOptions::default()).await;
+        assert!(limit_reached(&result), "{:?}", result.err());
+
+        // With the slot free, the start gets past the limit (it may still
+        // fail if there's no claude binary here)
+        manager.stop_session(running_id).await.unwrap();
+        let result = manager.start_session(temp_dir.path().to_path_buf(), StartOptions::default()).await;
+        assert!(!limit_reached(&result));
+        if let Ok(info) = result {
+            manager.stop_session(uuid::Uuid::parse_str(&info.id).unwrap()).await.unwrap();
+        }
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_second_session_in_a_directory_needs_allow_duplicate() {
+        use crate::ipc::ErrorCode;
+        use crate::manager::SessionError;
+        use crate::pty::test_support::cat_process;
+
+        let temp_dir = create_test_dir();
+        let working_dir = temp_dir.path().canonicalize().unwrap();
+        let manager = SessionManager::new();
+        let duplicate_of = |result: &anyhow::Result<_>| match result.as_ref().err().and_then(|e| e.downcast_ref::<SessionError>()) {
+            Some(SessionError::DuplicateWorkingDir { session_id, .. }) => Some(*session_id),
+            _ => None,
+        };
+
+        // Stale (no process): doesn't count
+        manager.insert_session(Session::with_log_dir(working_dir.clone(), Some(temp_dir.path()))).await;
+        let result = manager.start_session(temp_dir.path().to_path_buf(), StartOptions::default()).await;
+        assert_eq!(duplicate_of(&result), None);
+        if let Ok(info) = result {
+            manager.stop_session(uuid::Uuid::parse_str(&info.id).unwrap()).await.unwrap();
+        }
+
+        let running = Session::with_log_dir(working_dir.clone(), Some(temp_dir.path()));
+        let process = cat_process(running.id, &running.log_path);
+        let running_id = running.id;
+        manager.insert_session(running).await;
+        manager.insert_process(process).await;
+
+        // Any spelling of the directory is the same one
+        let result = manager.start_session(working_dir.join("."), StartOptions::default()).await;
+        assert_eq!(duplicate_of(&result), Some(running_id), "{:?}", result.err());
+        assert_eq!(ErrorCode::of(result.as_ref().unwrap_err()), ErrorCode::AlreadyExists);
+        assert!(result.unwrap_err().to_string().contains("--allow-duplicate"));
+
+        // Allowed, the start gets past the check (it may still fail if
+        // there's no claude binary here)
+        let options = StartOptions { allow_duplicate: true, ..StartOptions::default() };
+        let result = manager.start_session(working_dir.clone(), options).await;
+        assert_eq!(duplicate_of(&result), None);
+        if let Ok(info) = result {
+            manager.stop_session(uuid::Uuid::parse_str(&info.id).unwrap()).await.unwrap();
+        }
+        manager.stop_session(running_id).await.unwrap();
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_stop_all_spares_orphans_unless_asked() {
+        use crate::persistence::{is_process_alive, process_start_time};
+        use crate::pty::test_support::cat_process;
+
+        let temp_dir = create_test_dir();
+        let manager = SessionManager::new();
+        let new_session = || Session::with_log_dir(temp_dir.path().to_path_buf(), Some(temp_dir.path()));
+
+        let running = new_session();
+        let process = cat_process(running.id, &running.log_path);
+        manager.insert_session(running.clone()).await;
+        manager.insert_process(process).await;
+
+        // A process left behind by a previous daemon (reaped here once killed)
+        let mut leftover = std::process::Command::new("sleep").arg("30").spawn().unwrap();
+        let orphan_pid = leftover.id();
+        std::thread::spawn(move || leftover.wait());
+        let mut orphaned = new_session();
+        orphaned.orphan_pid = Some(orphan_pid);
+        orphaned.process_start_time = process_start_time(orphan_pid);
+        manager.insert_session(orphaned.clone()).await;
+
+        // An orphan whose PID now belongs to a process started later
+        let mut unrelated = std::process::Command::new("sleep").arg("30").spawn().unwrap();
+        let mut reused = new_session();
+        reused.orphan_pid = Some(unrelated.id());
+        reused.process_start_time = process_start_time(unrelated.id()).map(|t| t - 1);
+        manager.insert_session(reused.clone()).await;
+
+        let stale = new_session();
+        manager.insert_session(stale.clone()).await;
+
+        let report = manager.stop_all_sessions(false).await;
+        assert_eq!(report.stopped, vec![running.id]);
+        assert!(report.failed.is_empty());
+        assert!(is_process_alive(orphan_pid));
+
+        let report = manager.stop_all_sessions(true).await;
+        assert_eq!(report.stopped, vec![orphaned.id]);
+        assert!(!is_process_alive(orphan_pid));
+        assert!(is_process_alive(unrelated.id()));
+        unrelated.kill().unwrap();
+        unrelated.wait().unwrap();
+
+        let mut remaining: Vec<String> = manager.list_sessions().await.into_iter().map(|s| s.id).collect();
+        remaining.sort();
+        let mut expected = vec![stale.id.to_string(), reused.id.to_string()];
+        expected.sort();
+        assert_eq!(remaining, expected);
+    }
+
+    #[cfg(unix)]
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_reattach_adopts_only_orphans_in_their_own_terminal() {
+        use crate::logging::{read_entries, Direction, EntryFilter};
+        use crate::persistence::{PersistedSession, Persistence, PersistenceManager};
+        use crate::session::SessionStatus;
+        use std::os::fd::{BorrowedFd, OwnedFd};
+
+        let temp_dir = create_test_dir();
+        let state_file = temp_dir.path().join("sessions.json");
+        let persistence = PersistenceManager::with_state_file(state_file.clone()).unwrap();
+        let new_session = || Session::with_log_dir(temp_dir.path().to_path_buf(), Some(temp_dir.path()));
+
+        // Two sessions left behind by a previous daemon, each still in its
+        // terminal (reaped here once killed)
+        let mut left = Vec::new();
+        for _ in 0..2 {
+            let pair = portable_pty::native_pty_system().openpty(portable_pty::PtySize::default()).unwrap();
+            let mut child = pair.slave.spawn_command(portable_pty::CommandBuilder::new("cat")).unwrap();
+            let pid = child.process_id().unwrap();
+            std::thread::spawn(move || child.wait());
+            let master_fd = pair.master.as_raw_fd().unwrap();
+            let master: OwnedFd = unsafe { BorrowedFd::borrow_raw(master_fd) }.try_clone_to_owned().unwrap();
+            drop(pair);
+
+            let mut session = new_session();
+            session.orphan_pid = Some(pid);
+            persistence.upsert(&PersistedSession::from_session(&session, Some(pid))).unwrap();
+            left.push((session, pid, master));
+        }
+        let (mut not_orphaned, other_pid, other_master) = left.pop().unwrap();
+        let (orphaned, pid, master) = left.pop().unwrap();
+
+        let manager = SessionManager::with_persistence(PersistenceManager::with_state_file(state_file.clone()).unwrap());
+        manager.insert_session(orphaned.clone()).await;
+        not_orphaned.orphan_pid = None;
+        manager.insert_session(not_orphaned.clone()).await;
+
+        // Saving keeps the orphan's PID for the next daemon
+        manager.flush(None).await.unwrap();
+        let persisted = PersistenceManager::with_state_file(state_file.clone()).unwrap().load_state().unwrap();
+        assert_eq!(persisted[&orphaned.id].pid, Some(pid));
+
+        // The second terminal is offered under the wrong session, and the
+        // first session's terminal is offered for a session that isn't orphaned
+        let reattached = manager
+            .reattach(vec![(not_orphaned.id, other_master), (orphaned.id, master.try_clone().unwrap())])
+            .await;
+        assert_eq!(reattached, 1);
+
+        let listed = manager.list_sessions().await;
+        let status = |id: uuid::Uuid| listed.iter().find(|s| s.id == id.to_string()).unwrap().status.clone();
+        assert_eq!(status(orphaned.id), SessionStatus::Running);
+        assert_ne!(status(not_orphaned.id), SessionStatus::Running);
+        let persisted = PersistenceManager::with_state_file(state_file.clone()).unwrap().load_state().unwrap();
+        assert_eq!(persisted[&orphaned.id].pid, Some(pid));
+
+        // Input reaches the adopted process and its echo is logged again
+        manager.send_input(orphaned.id, "still here".to_string()).await.unwrap();
+        let deadline = std::time::Instant::now() + std::time::Duration::from_secs(5);
+        loop {
+            let output: Vec<u8> = read_entries(&orphaned.log_path, EntryFilter::direction(Direction::Output))
+                .map(|entries| entries.flatten().flat_map(|entry| entry.data).collect())
+                .unwrap_or_default();
+            if String::from_utf8_lossy(&output).contains("still here") {
+                break;
+            }
+            assert!(std::time::Instant::now() < deadline, "echo never logged");
+            tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+        }
+
+        // A terminal whose session leader isn't the recorded process is refused
+        let mut wrong = new_session();
+        wrong.orphan_pid = Some(other_pid);
+        persistence.upsert(&PersistedSession::from_session(&wrong, Some(other_pid))).unwrap();
+        manager.insert_session(wrong.clone()).await;
+        assert_eq!(manager.reattach(vec![(wrong.id, master)]).await, 0);
+
+        manager.shutdown_all(ShutdownMode::Kill).await;
+        unsafe { libc::kill(other_pid as libc::pid_t, libc::SIGKILL) };
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_stop_session_terminates_the_child() {
+        use crate::persistence::is_process_alive;
+        use crate::pty::SessionProcess;
+        use std::time::{Duration, Instant};
+
+        let temp_dir = create_test_dir();
+        let manager = SessionManager::new();
+
+        // One dummy exits on SIGTERM, the other ignores it and needs SIGKILL
+        let mut stops = Vec::new();
+        for script in ["exec sleep 30", "trap '' TERM; echo ready; exec sleep 30"] {
+            let session = Session::with_log_dir(temp_dir.path().to_path_buf(), Some(temp_dir.path()));
+            let pair = portable_pty::native_pty_system().openpty(portable_pty::PtySize::default()).unwrap();
+            let mut cmd = portable_pty::CommandBuilder::new("sh");
+            cmd.args(["-c", script]);
+            let child = pair.slave.spawn_command(cmd).unwrap();
+            let process = SessionProcess::new(session.id, pair, child, session.log_path.clone()).unwrap();
+            stops.push((session.id, session.log_path.clone(), process.pid().unwrap()));
+            manager.insert_session(session).await;
+            manager.insert_process(process).await;
+        }
+
+        // Don't signal before the trap is in place
+        let (_, trap_log, _) = &stops[1];
+        let output = || -> Vec<u8> {
+            crate::logging::read_entries(trap_log, Default::default())
+                .map(|entries| entries.flatten().flat_map(|entry| entry.data).collect())
+                .unwrap_or_default()
+        };
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while !String::from_utf8_lossy(&output()).contains("ready") {
+            assert!(Instant::now() < deadline, "dummy process never got ready");
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+
+        let started = Instant::now();
+        for (session_id, _, pid) in &stops {
+            let stopped = manager.stop_session_with(*session_id, Some(Duration::from_millis(200))).await.unwrap();
+            assert_eq!(stopped.status, SessionStatus::Stopped);
+            assert!(!is_process_alive(*pid), "process {} outlived stop", pid);
+        }
+        assert!(started.elapsed() < Duration::from_secs(5));
+        assert_eq!(manager.running_count().await, 0);
+    }
+
+    #[test]
+    fn test_sort_sessions_by_created() {
+        use crate::ipc::SessionInfo;
+        use crate::{sort_sessions, SortKey};
+
+        let info = |id: &str, created_at: &str, status: SessionStatus| SessionInfo {
+            id: id.to_string(),
+            working_dir: format!("/tmp/{}", id),
+            created_at: created_at.to_string(),
+            status,
+            log_path: format!("/tmp/{}.jsonl", id),
+            last_error: None,
+            exit_code: None,
+            last_activity: None,
+            idle_seconds: None,
+            label: String::new(),
+            tags: Default::default(),
+            healthy: None,
+        };
+        let seeded = vec![
+            info("b", "2024-01-02T00:00:00+00:00", SessionStatus::Stale),
+            info("c", "2024-01-03T00:00:00+00:00", SessionStatus::Running),
+            info("a", "2024-01-02T00:00:00+00:00", SessionStatus::Running),
+            info("d", "2024-01-01T00:00:00+00:00", SessionStatus::Crashed),
+        ];
+        let ids = |sessions: &[SessionInfo]| {
+            sessions.iter().map(|s| s.id.clone()).collect::<Vec<_>>()
+        };
+
+        // Newest first, ties broken by id; repeated sorts agree
+        let mut sessions = seeded.clone();
+        sort_sessions(&mut sessions, SortKey::Created, false);
+        assert_eq!(ids(&sessions), vec!["c", "a", "b", "d"]);
+
+        let mut shuffled = seeded.into_iter().rev().collect::<Vec<_>>();
+        sort_sessions(&mut shuffled, SortKey::Created, false);
+        assert_eq!(ids(&shuffled), ids(&sessions));
+
+        sort_sessions(&mut sessions, SortKey::Created, true);
+        assert_eq!(ids(&sessions), vec!["d", "b", "a", "c"]);
+
+        sort_sessions(&mut sessions, SortKey::Status, false);
+        assert_eq!(ids(&sessions), vec!["d", "a", "c", "b"]);
+    }
+
+    #[test]
+    fn test_sort_sessions_by_name_and_idle() {
+        use crate::ipc::SessionInfo;
+        use crate::{sort_sessions, SortKey};
+
+        let info = |id: &str, label: &str, idle_seconds: Option<u64>| SessionInfo {
+            id: id.to_string(),
+            working_dir: format!("/tmp/{}", id),
+            created_at: "2024-01-01T00:00:00+00:00".to_string(),
+            status: SessionStatus::Running,
+            log_path: format!("/tmp/{}.jsonl", id),
+            last_error: None,
+            exit_code: None,
+            last_activity: None,
+            idle_seconds,
+            label: label.to_string(),
+            tags: Default::default(),
+            healthy: None,
+        };
+        let mut sessions = vec![
+            info("a", "zeta", None),
+            info("b", "", Some(30)),
+            info("c", "alpha", Some(5)),
+            info("d", "", None),
+            info("e", "alpha", Some(600)),
+        ];
+        let ids = |sessions: &[SessionInfo]| {
+            sessions.iter().map(|s| s.id.clone()).collect::<Vec<_>>()
+        };
+
+        // Unlabelled sessions sort by their ID; equal labels by ID
+        sort_sessions(&mut sessions, SortKey::Name, false);
+        assert_eq!(ids(&sessions), vec!["c", "e", "b", "d", "a"]);
+
+        // Least idle first, sessions without an idle time last
+        sort_sessions(&mut sessions, SortKey::Idle, false);
+        assert_eq!(ids(&sessions), vec!["c", "b", "e", "a", "d"]);
+
+        sort_sessions(&mut sessions, SortKey::Idle, true);
+        assert_eq!(ids(&sessions), vec!["d", "a", "e", "b", "c"]);
+    }
+
+    #[test]
+    fn test_dir_scoped_search() {
+        use crate::ipc::SessionInfo;
+        use crate::logging::{search_logs, Direction, SearchLimits, SessionLogger};
+
+        let root = create_test_dir();
+        let mut sessions = Vec::new();
+        for dir in ["a", "a/sub", "ab", "b"] {
+            let working_dir = root.path().join(dir);
+            std::fs::create_dir_all(&working_dir).unwrap();
+
+            let session = Session::with_log_dir(working_dir.clone(), Some(root.path()));
+            let mut logger = SessionLogger::with_path(session.id, session.log_path.clone()).unwrap();
+            logger.log(Direction::Output, b"found the needle\r\n".to_vec()).unwrap();
+
+            sessions.push(SessionInfo {
+                id: session.id.to_string(),
+                working_dir: working_dir.display().to_string(),
+                created_at: session.created_at.clone(),
+                status: SessionStatus::Running,
+                log_path: session.log_path.display().to_string(),
+                last_error: None,
+                exit_code: None,
+                last_activity: None,
+                idle_seconds: None,
+                label: String::new(),
+                tags: Default::default(),
+                healthy: None,
+            });
+        }
+        let expected: Vec<String> = sessions[..2].iter().map(|s| s.id.clone()).collect();
+
+        let scoped = crate::sessions_under_dir(sessions, &root.path().join("a"));
+        let paths: Vec<PathBuf> = scoped.iter().map(|s| PathBuf::from(&s.log_path)).collect();
+        let mut hits = Vec::new();
+        for (index, m) in search_logs(&paths, "needle", SearchLimits::default()).unwrap().matches {
+            assert_eq!(m.line, "found the needle");
+            hits.push(scoped[index].id.clone());
+        }
+
+        assert_eq!(hits, expected);
+    }
+
+    #[test]
+    fn test_which_prints_only_log_path() {
+        use crate::output::Output;
+
+        let session = Session::new(PathBuf::from("/tmp/project"));
+        let info = crate::ipc::SessionInfo::from(session.clone());
+        let sessions = vec![info];
+
+        let (out, stdout, stderr) = Output::captured(false);
+        crate::print_which(&out, &sessions, &session.id.to_string()).unwrap();
+
+        assert_eq!(stdout.contents(), format!("{}\n", session.log_path.display()));
+        assert_eq!(stderr.contents(), "");
+        assert!(crate::print_which(&out, &sessions, "ffffffff").is_err());
+    }
+
+    #[tokio::test]
+    async fn test_start_follow_attaches_read_only() {
+        use crate::client::Client;
+        use crate::ipc::{Request, Response};
+        use crate::output::Output;
+        use base64::{engine::general_purpose, Engine as _};
+        use std::sync::{Arc, Mutex};
+        use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+        use tokio::net::UnixListener;
+
+        let temp_dir = create_test_dir();
+        let socket_path = temp_dir.path().join("daemon.sock");
+        let listener = UnixListener::bind(&socket_path).unwrap();
+
+        // Fake daemon: record every request, stream one chunk after attach
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        let record = Arc::clone(&seen);
+        tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                let record = Arc::clone(&record);
+                tokio::spawn(async move {
+                    let (reader, mut writer) = stream.into_split();
+                    let mut lines = BufReader::new(reader).lines();
+                    while let Ok(Some(line)) = lines.next_line().await {
+                        let request: Request = serde_json::from_str(&line).unwrap();
+                        let mut responses = match &request {
+                            Request::Hello { protocol_version, .. } => {
+                                let hello = Response::Hello {
+                                    protocol_version: *protocol_version,
+                                    version: "test".to_string(),
+                                };
+                                let json = serde_json::to_string(&hello).unwrap();
+                                writer.write_all(format!("{}\n", json).as_bytes()).await.unwrap();
+                                continue;
+                            }
+                            Request::StartSession { .. } => vec![Response::SessionStarted {
+                                session_id: "s1".to_string(),
+                                log_path: "/logs/s1.jsonl".to_string(),
+                            }],
+                            Request::AttachSession { session_id, .. } => vec![
+                                Response::AttachStarted {
+                                    session_id: session_id.clone(),
+                                    pty_rows: 24,
+                                    pty_cols: 80,
+                                    scrollback_available: false,
+                                },
+                                Response::LogChunk {
+                                    session_id: session_id.clone(),
+                                    seq: 0,
+                                    data: general_purpose::STANDARD.encode("hello"),
+                                    timestamp: None,
+                                },
+                            ],
+                            _ => vec![Response::Ok],
+                        };
+                        record.lock().unwrap().push(request);
+                        for response in responses.drain(..) {
+                            let json = serde_json::to_string(&response).unwrap();
+                            writer.write_all(json.as_bytes()).await.unwrap();
+                            writer.write_all(b"\n").await.unwrap();
+                        }
+                    }
+                });
+            }
+        });
+
+        let client = Client::with_socket_path(socket_path);
+        let (out, stdout, _) = Output::captured(true);
+        let request = Request::StartSession {
+            working_dir: temp_dir.path().to_path_buf(),
+            restart_on_crash: false,
+            log_dir: None,
+            descriptive_log_name: false,
+            env: vec![],
+            claude_args: vec![],
+            name: None,
+            tags: Default::default(),
+            allow_duplicate: false,
+        };
+        // Following never ends on its own; stop once output has arrived
+        let _ = tokio::time::timeout(
+            std::time::Duration::from_millis(500),
+            crate::start_session(&out, &client, request, true),
+        )
+        .await;
+
+        let seen = seen.lock().unwrap();
+        assert!(matches!(seen[0], Request::StartSession { .. }));
+        assert!(matches!(&seen[1], Request::AttachSession { session_id, since_seq: None } if session_id == "s1"));
+        assert_eq!(seen.len(), 2, "follow must not send input");
+        assert!(stdout.contents().ends_with("hello"));
+    }
+
+    #[tokio::test]
+    async fn test_send_to_dir_reaches_only_matching_sessions() {
+        use crate::client::Client;
+        use crate::ipc::{ErrorCode, Request, Response, SessionInfo};
+        use std::path::Path;
+        use std::sync::{Arc, Mutex};
+        use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+        use tokio::net::UnixListener;
+
+        let info = |id: &str, working_dir: &str, status: SessionStatus| SessionInfo {
+            id: id.to_string(),
+            working_dir: working_dir.to_string(),
+            created_at: "2024-01-01T00:00:00Z".to_string(),
+            status,
+            log_path: format!("/tmp/{}.jsonl", id),
+            last_error: None,
+            exit_code: None,
+            last_activity: None,
+            idle_seconds: None,
+            label: String::new(),
+            tags: Default::default(),
+            healthy: None,
+        };
+        let sessions = vec![
+            info("api-one", "/repos/api", SessionStatus::Running),
+            info("api-two", "/repos/api/worker", SessionStatus::Running),
+            info("api-old", "/repos/api", SessionStatus::Stopped),
+            info("web-one", "/repos/web", SessionStatus::Running),
+        ];
+
+        let targets = crate::send_targets(&sessions, &[], Some(Path::new("/repos/api")), &[]).unwrap();
+        let ids: Vec<&str> = targets.iter().map(|s| s.id.as_str()).collect();
+        assert_eq!(ids, vec!["api-one", "api-two"]);
+
+        let temp_dir = create_test_dir();
+        let socket_path = temp_dir.path().join("daemon.sock");
+        let listener = UnixListener::bind(&socket_path).unwrap();
+
+        // Fake daemon: record inputs, fail the second session
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        let record = Arc::clone(&seen);
+        tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            let (reader, mut writer) = stream.into_split();
+            let mut lines = BufReader::new(reader).lines();
+            while let Ok(Some(line)) = lines.next_line().await {
+                let response = match serde_json::from_str::<Request>(&line).unwrap() {
+                    Request::SendInput { session_id, text } => {
+                        let failed = session_id == "api-two";
+                        record.lock().unwrap().push((session_id, text));
+                        if failed {
+                            Response::Error { message: "no PTY".to_string(), code: ErrorCode::Internal }
+                        } else {
+                            Response::Ok
+                        }
+                    }
+                    Request::Hello { protocol_version, .. } => {
+                        Response::Hello { protocol_version, version: "test".to_string() }
+                    }
+                    _ => Response::Error { message: "unexpected".to_string(), code: ErrorCode::Internal },
+                };
+                let json = serde_json::to_string(&response).unwrap();
+                writer.write_all(json.as_bytes()).await.unwrap();
+                writer.write_all(b"\n").await.unwrap();
+            }
+        });
+
+        let client = Client::with_socket_path(socket_path);
+        let results = crate::broadcast_input(&client, &targets, "run the tests").await;
+        assert_eq!(results, vec![Ok(()), Err("no PTY".to_string())]);
+
+        let seen = seen.lock().unwrap();
+        assert_eq!(
+            *seen,
+            vec![
+                ("api-one".to_string(), "run the tests".to_string()),
+                ("api-two".to_string(), "run the tests".to_string()),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_send_to_tag_reaches_only_matching_sessions() {
+        use crate::client::Client;
+        use crate::ipc::{ErrorCode, Request, Response, SessionInfo};
+        use std::sync::{Arc, Mutex};
+        use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+        use tokio::net::UnixListener;
+
+        let info = |id: &str, tags: &[(&str, &str)], status: SessionStatus| SessionInfo {
+            id: id.to_string(),
+            working_dir: format!("/repos/{}", id),
+            created_at: "2024-01-01T00:00:00Z".to_string(),
+            status,
+            log_path: format!("/tmp/{}.jsonl", id),
+            last_error: None,
+            exit_code: None,
+            last_activity: None,
+            idle_seconds: None,
+            label: String::new(),
+            tags: tags.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect(),
+            healthy: None,
+        };
+        let sessions = vec![
+            info("api-one", &[("project", "api")], SessionStatus::Running),
+            info("web-one", &[("project", "web")], SessionStatus::Running),
+            info("api-two", &[("project", "api"), ("team", "core")], SessionStatus::Running),
+            info("api-old", &[("project", "api")], SessionStatus::Stopped),
+            info("untagged", &[], SessionStatus::Running),
+            info("api-three", &[("project", "api")], SessionStatus::Running),
+        ];
+
+        let tag = |k: &str, v: &str| (k.to_string(), v.to_string());
+        let targets = crate::send_targets(&sessions, &[], None, &[tag("project", "api")]).unwrap();
+        let ids: Vec<&str> = targets.iter().map(|s| s.id.as_str()).collect();
+        assert_eq!(ids, vec!["api-one", "api-two", "api-three"]);
+
+        // Every tag has to match
+        let both = crate::send_targets(&sessions, &[], None, &[tag("project", "api"), tag("team", "core")]).unwrap();
+        assert_eq!(both.iter().map(|s| s.id.as_str()).collect::<Vec<_>>(), vec!["api-two"]);
+
+        let temp_dir = create_test_dir();
+        let socket_path = temp_dir.path().join("daemon.sock");
+        let listener = UnixListener::bind(&socket_path).unwrap();
+
+        // Fake daemon: record inputs, hang up on the second session
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        let record = Arc::clone(&seen);
+        tokio::spawn(async move {
+            loop {
+                let (stream, _) = listener.accept().await.unwrap();
+                let (reader, mut writer) = stream.into_split();
+                let mut lines = BufReader::new(reader).lines();
+                while let Ok(Some(line)) = lines.next_line().await {
+                    let response = match serde_json::from_str::<Request>(&line).unwrap() {
+                        Request::SendInput { session_id, text } => {
+                            let hang_up = session_id == "api-two";
+                            record.lock().unwrap().push((session_id, text));
+                            if hang_up {
+                                break;
+                            }
+                            Response::Ok
+                        }
+                        Request::Hello { protocol_version, .. } => {
+                            Response::Hello { protocol_version, version: "test".to_string() }
+                        }
+                        _ => Response::Error { message: "unexpected".to_string(), code: ErrorCode::Internal },
+                    };
+                    let json = serde_json::to_string(&response).unwrap();
+                    writer.write_all(json.as_bytes()).await.unwrap();
+                    writer.write_all(b"\n").await.unwrap();
+                }
+            }
+        });
+
+        // The dropped connection fails its own send; the rest still go out
+        let client = Client::with_socket_path(socket_path);
+        let results = crate::broadcast_input(&client, &targets, "check the build").await;
+        assert_eq!(results.len(), 3);
+        assert_eq!(results[0], Ok(()));
+        assert!(results[1].is_err(), "{:?}", results[1]);
+        assert_eq!(results[2], Ok(()));
+
+        let seen = seen.lock().unwrap();
+        let delivered: Vec<&str> = seen.iter().map(|(id, _)| id.as_str()).collect();
+        assert_eq!(delivered, vec!["api-one", "api-two", "api-three"]);
+        assert!(seen.iter().all(|(_, text)| text == "check the build"));
+    }
+
+    #[tokio::test]
+    async fn test_prune_confirms_before_removing() {
+        use crate::client::Client;
+        use crate::ipc::{ErrorCode, Request, Response};
+        use crate::output::Output;
+        use std::sync::{Arc, Mutex};
+        use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+        use tokio::net::UnixListener;
+
+        let temp_dir = create_test_dir();
+        let socket_path = temp_dir.path().join("daemon.sock");
+        let listener = UnixListener::bind(&socket_path).unwrap();
+
+        // Fake daemon with one session to prune; records each request's dry_run
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        let record = Arc::clone(&seen);
+        tokio::spawn(async move {
+            loop {
+                let (stream, _) = listener.accept().await.unwrap();
+                let (reader, mut writer) = stream.into_split();
+                let mut lines = BufReader::new(reader).lines();
+                while let Ok(Some(line)) = lines.next_line().await {
+                    let response = match serde_json::from_str::<Request>(&line).unwrap() {
+                        Request::PruneSessions { dry_run, .. } => {
+                            record.lock().unwrap().push(dry_run);
+                            Response::Pruned {
+                                session_ids: vec!["old-session".to_string()],
+                                logs_removed: vec!["/tmp/old-session.jsonl".to_string()],
+                                dry_run,
+                            }
+                        }
+                        Request::Hello { protocol_version, .. } => {
+                            Response::Hello { protocol_version, version: "test".to_string() }
+                        }
+                        _ => Response::Error { message: "unexpected".to_string(), code: ErrorCode::Internal },
+                    };
+                    let json = serde_json::to_string(&response).unwrap();
+                    writer.write_all(json.as_bytes()).await.unwrap();
+                    writer.write_all(b"\n").await.unwrap();
+                }
+            }
+        });
+        let client = Client::with_socket_path(socket_path);
+        let day = 24 * 60 * 60;
+
+        // Without --yes and no TTY to ask on, nothing is removed
+        let (out, stdout, stderr) = Output::captured(true);
+        assert!(!crate::prune_sessions(&out, &client, day, false, false).await.unwrap());
+        assert_eq!(*seen.lock().unwrap(), vec![true]);
+        assert!(stdout.contents().is_empty());
+        assert!(stderr.contents().contains("--yes"), "{}", stderr.contents());
+
+        // A dry run needs no confirmation
+        seen.lock().unwrap().clear();
+        let (out, stdout, _) = Output::captured(true);
+        assert!(crate::prune_sessions(&out, &client, day, true, false).await.unwrap());
+        assert_eq!(*seen.lock().unwrap(), vec![true]);
+        assert_eq!(stdout.contents(), "session\told-session\nlog\t/tmp/old-session.jsonl\n");
+
+        // --yes confirms, then prunes for real
+        seen.lock().unwrap().clear();
+        let (out, stdout, _) = Output::captured(true);
+        let out = out.assume_yes(true);
+        assert!(crate::prune_sessions(&out, &client, day, false, false).await.unwrap());
+        assert_eq!(*seen.lock().unwrap(), vec![true, false]);
+        assert_eq!(stdout.contents(), "session\told-session\nlog\t/tmp/old-session.jsonl\n");
+    }
+
+    #[tokio::test]
+    async fn test_descriptive_log_name_resolves_by_id() {
+        use crate::persistence::PersistenceManager;
+        use crate::session::LogNaming;
+
+        let temp_dir = create_test_dir();
+        let working_dir = temp_dir.path().join("my api");
+        let session = Session::with_log_options(working_dir, Some(temp_dir.path()), LogNaming::Descriptive);
+
+        let file_name = session.log_path.file_name().unwrap().to_string_lossy().into_owned();
+        let short_id = &session.id.to_string()[..8];
+        assert!(file_name.ends_with(&format!("-my-api-{}.jsonl", short_id)), "{}", file_name);
+        assert!(file_name.starts_with(&session.created_at[..4]));
+
+        // Lookups by ID use the stored path, not a name derived from the ID
+        let persistence = PersistenceManager::with_state_file(temp_dir.path().join("sessions.json")).unwrap();
+        let manager = SessionManager::with_persistence(persistence);
+        let (id, log_path) = (session.id, session.log_path.clone());
+        manager.insert_session(session).await;
+        assert_eq!(manager.log_path(id).await.unwrap(), log_path);
+    }
+
+    #[test]
+    fn test_start_env_flags_are_repeatable() {
+        use clap::Parser;
+
+        let cli = crate::Cli::try_parse_from([
+            "claude-sessions", "start", "/tmp", "--env", "ANTHROPIC_MODEL=opus", "--env", "OPTS=a=b",
+        ])
+        .unwrap();
+        match cli.command {
+            crate::Commands::Start { env, .. } => assert_eq!(
+                env,
+                vec![
+                    ("ANTHROPIC_MODEL".to_string(), "opus".to_string()),
+                    ("OPTS".to_string(), "a=b".to_string()),
+                ]
+            ),
+            _ => panic!("Expected start"),
+        }
+
+        assert!(crate::Cli::try_parse_from(["claude-sessions", "start", "/tmp", "--env", "=x"]).is_err());
+    }
+
+    // Linux file systems accept arbitrary bytes in names; macOS ones don't
+    #[cfg(target_os = "linux")]
+    #[tokio::test]
+    async fn test_start_rejects_non_utf8_working_dir() {
+        use crate::persistence::PersistenceManager;
+        use std::ffi::OsStr;
+        use std::os::unix::ffi::OsStrExt;
+
+        let temp_dir = create_test_dir();
+        let working_dir = temp_dir.path().join(OsStr::from_bytes(b"caf\xe9"));
+        std::fs::create_dir(&working_dir).unwrap();
+
+        let persistence = PersistenceManager::with_state_file(temp_dir.path().join("sessions.json")).unwrap();
+        let manager = SessionManager::with_persistence(persistence);
+        let err = manager
+            .start_session(working_dir, StartOptions::default())
+            .await
+            .unwrap_err();
+
+        assert!(err.to_string().contains("not valid UTF-8"), "{}", err);
+        assert!(manager.list_sessions().await.is_empty());
+    }
 }
@@ -1,9 +1,13 @@
 use anyhow::{Context, Result};
 use serde::{Deserialize, Serialize};
 use std::path::PathBuf;
-use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::io::{AsyncBufRead, AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
 use tokio::net::UnixStream;
 
+/// Longest response line read from the daemon; a longer one is an error
+/// rather than an ever-growing buffer
+const MAX_RESPONSE_BYTES: u64 = 16 * 1024 * 1024;
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct SessionInfo {
     pub id: String,
@@ -15,6 +19,9 @@ pub struct SessionInfo {
 
 pub struct DaemonClient {
     socket_path: PathBuf,
+    /// Presented first on every connection, for daemons started with
+    /// `--require-token`
+    token: Option<String>,
 }
 
 impl DaemonClient {
@@ -22,10 +29,16 @@ impl DaemonClient {
         let home = std::env::var("HOME")
             .or_else(|_| std::env::var("USERPROFILE"))
             .context("Cannot determine home directory")?;
-        let socket_path = PathBuf::from(home)
-            .join(".claude-sessions")
-            .join("daemon.sock");
-        Ok(DaemonClient { socket_path })
+        let state_dir = PathBuf::from(home).join(".claude-sessions");
+        let token = std::env::var("CLAUDE_SESSIONS_TOKEN")
+            .ok()
+            .or_else(|| std::fs::read_to_string(state_dir.join("token")).ok())
+            .map(|token| token.trim().to_string())
+            .filter(|token| !token.is_empty());
+        Ok(DaemonClient {
+            socket_path: state_dir.join("daemon.sock"),
+            token,
+        })
     }
 
     pub async fn list_sessions(&self) -> Result<Vec<SessionInfo>> {
@@ -128,6 +141,28 @@ impl DaemonClient {
             _ => anyhow::bail!("Unexpected response type"),
         }
     }
+
+    pub async fn resize_session(&self, session_id: String, rows: u16, cols: u16) -> Result<()> {
+        let request = serde_json::json!({
+            "type": "resize",
+            "session_id": session_id,
+            "rows": rows,
+            "cols": cols
+        });
+        let response = self.send_request(&request).await?;
+
+        match response.get("type").and_then(|v| v.as_str()) {
+            Some("ok") => Ok(()),
+            Some("error") => {
+                let msg = response
+                    .get("message")
+                    .and_then(|v| v.as_str())
+                    .unwrap_or("Unknown error");
+                anyhow::bail!("Daemon error: {}", msg)
+            }
+            _ => anyhow::bail!("Unexpected response type"),
+        }
+    }
 }
 
 #[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
@@ -144,6 +179,17 @@ pub struct SessionCreatedResponse {
         let (reader, mut writer) = stream.into_split();
         let mut reader = BufReader::new(reader);
 
+        if let Some(token) = &self.token {
+            let auth = serde_json::json!({"type": "authenticate", "token": token});
+            writer.write_all(format!("{}\n", auth).as_bytes()).await?;
+            writer.flush().await?;
+
+            let response = read_response(&mut reader).await?;
+            if response.get("code").and_then(|v| v.as_str()) == Some("unauthenticated") {
+                anyhow::bail!("Daemon rejected the auth token");
+            }
+        }
+
         // Send request
         let request_json = serde_json::to_string(request)?;
         writer.write_all(request_json.as_bytes()).await?;
@@ -151,12 +197,19 @@ pub struct SessionCreatedResponse {
         writer.flush().await?;
 
         // Read response
-        let mut line = String::new();
-        reader.read_line(&mut line).await?;
-
-        let response: serde_json::Value =
-            serde_json::from_str(&line).context("Failed to parse daemon response")?;
+        read_response(&mut reader).await
+    }
+}
 
-        Ok(response)
+/// Read one newline-terminated JSON response of at most `MAX_RESPONSE_BYTES`
+async fn read_response<R: AsyncBufRead + Unpin>(reader: &mut R) -> Result<serde_json::Value> {
+    let mut line = Vec::new();
+    (&mut *reader)
+        .take(MAX_RESPONSE_BYTES + 1)
+        .read_until(b'\n', &mut line)
+        .await?;
+    if line.len() as u64 > MAX_RESPONSE_BYTES {
+        anyhow::bail!("Daemon response longer than {} bytes", MAX_RESPONSE_BYTES);
     }
+    serde_json::from_slice(&line).context("Failed to parse daemon response")
 }
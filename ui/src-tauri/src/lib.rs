@@ -1,5 +1,6 @@
 mod daemon_client;
 
+use base64::Engine;
 use daemon_client::{DaemonClient, SessionInfo};
 use std::fs::File;
 use std::io::{BufRead, BufReader};
@@ -32,19 +33,128 @@ async fn delete_session(session_id: String) -> Result<(), String> {
         .map_err(|e| format!("Failed to delete session: {}", e))
 }
 
+/// One decoded session log entry
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct LogLine {
+    pub timestamp: String,
+    /// "input", "output" or "marker"
+    pub direction: String,
+    pub text: String,
+}
+
+/// Entries read by `read_session_logs`
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct LogChunk {
+    pub entries: Vec<LogLine>,
+    /// Line offset to pass to the next call
+    pub next_offset: usize,
+}
+
+/// The fields of a daemon `LogEntry` the UI shows
+#[derive(serde::Deserialize)]
+struct RawLogEntry {
+    timestamp: String,
+    direction: String,
+    data: String,
+}
+
+/// Removes ANSI escape sequences (CSI, OSC and two-byte escapes) from
+/// output text, keeping printable text and newlines.
+///
+/// Stateful, so a sequence split across log entries is still removed;
+/// carriage returns are dropped so `\r\n` reads as one line break.
+#[derive(Default)]
+struct AnsiStripper {
+    state: AnsiState,
+}
+
+#[derive(Default, Clone, Copy)]
+enum AnsiState {
+    #[default]
+    Ground,
+    Escape,
+    Csi,
+    Osc,
+    OscEscape,
+}
+
+impl AnsiStripper {
+    fn strip(&mut self, text: &str) -> String {
+        let mut out = String::with_capacity(text.len());
+        for c in text.chars() {
+            self.state = match (self.state, c) {
+                (AnsiState::Ground, '\x1b') => AnsiState::Escape,
+                (AnsiState::Ground, '\r' | '\x07') => AnsiState::Ground,
+                (AnsiState::Ground, c) => {
+                    out.push(c);
+                    AnsiState::Ground
+                }
+                (AnsiState::Escape, '[') => AnsiState::Csi,
+                (AnsiState::Escape, ']') => AnsiState::Osc,
+                (AnsiState::Escape, _) => AnsiState::Ground,
+                // Cursor down / next line still start a new line
+                (AnsiState::Csi, 'B' | 'E') => {
+                    out.push('\n');
+                    AnsiState::Ground
+                }
+                (AnsiState::Csi, '\x40'..='\x7e') => AnsiState::Ground,
+                (AnsiState::Csi, _) => AnsiState::Csi,
+                (AnsiState::Osc, '\x07') => AnsiState::Ground,
+                (AnsiState::Osc, '\x1b') => AnsiState::OscEscape,
+                (AnsiState::Osc, _) => AnsiState::Osc,
+                (AnsiState::OscEscape, _) => AnsiState::Ground,
+            };
+        }
+        out
+    }
+}
+
+/// Decode the log lines after line `offset`, with ANSI escape sequences
+/// removed from output entries if `strip_ansi` is set.
+///
+/// Malformed lines are skipped (but still counted, so they aren't read
+/// again). A final line without its newline may still be being written;
+/// it's left for the next call.
+fn decode_log_lines<R: BufRead>(mut reader: R, offset: usize, strip_ansi: bool) -> std::io::Result<LogChunk> {
+    let engine = base64::engine::general_purpose::STANDARD;
+    let mut stripper = AnsiStripper::default();
+    let mut entries = Vec::new();
+    let mut line_number = 0;
+    let mut line = String::new();
+    loop {
+        line.clear();
+        if reader.read_line(&mut line)? == 0 || !line.ends_with('\n') {
+            break;
+        }
+        line_number += 1;
+        if line_number <= offset {
+            continue;
+        }
+        let Ok(entry) = serde_json::from_str::<RawLogEntry>(&line) else {
+            continue;
+        };
+        let Ok(data) = engine.decode(&entry.data) else {
+            continue;
+        };
+        let mut text = String::from_utf8_lossy(&data).into_owned();
+        if strip_ansi && entry.direction == "output" {
+            text = stripper.strip(&text);
+        }
+        entries.push(LogLine {
+            timestamp: entry.timestamp,
+            direction: entry.direction,
+            text,
+        });
+    }
+    Ok(LogChunk { entries, next_offset: line_number.max(offset) })
+}
+
 #[tauri::command]
-async fn read_session_logs(log_path: String, offset: usize) -> Result<Vec<String>, String> {
+async fn read_session_logs(log_path: String, offset: usize, strip_ansi: Option<bool>) -> Result<LogChunk, String> {
     let file = File::open(&log_path)
         .map_err(|e| format!("Failed to open log file {}: {}", log_path, e))?;
-    let reader = BufReader::new(file);
-
-    let lines: Vec<String> = reader
-        .lines()
-        .skip(offset)
-        .filter_map(|line| line.ok())
-        .collect();
-
-    Ok(lines)
+    decode_log_lines(BufReader::new(file), offset, strip_ansi.unwrap_or(false))
+        .map_err(|e| format!("Failed to read log file {}: {}", log_path, e))
 }
 
 #[tauri::command]
@@ -56,6 +166,15 @@ async fn send_input(session_id: String, text: String) -> Result<(), String> {
         .map_err(|e| format!("Failed to send input: {}", e))
 }
 
+#[tauri::command]
+async fn resize_session(session_id: String, rows: u16, cols: u16) -> Result<(), String> {
+    let client = DaemonClient::new().map_err(|e| e.to_string())?;
+    client
+        .resize_session(session_id, rows, cols)
+        .await
+        .map_err(|e| format!("Failed to resize session: {}", e))
+}
+
 #[tauri::command]
 fn pick_directory() -> Result<Option<String>, String> {
     let dialog = FileDialogBuilder::new()
@@ -81,8 +200,33 @@ pub fn run() {
             delete_session,
             read_session_logs,
             send_input,
+            resize_session,
             pick_directory
         ])
         .run(tauri::generate_context!())
         .expect("error while running tauri application");
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_stripper_keeps_only_text() {
+        // Colors, cursor moves, clear-screen and a window title
+        let input = "\x1b[2J\x1b[H\x1b[1;32mok\x1b[0m done\r\n\x1b[3;5Hnext\x1b[1Bline\x1b]0;claude\x07!";
+
+        assert_eq!(AnsiStripper::default().strip(input), "ok done\nnext\nline!");
+    }
+
+    #[test]
+    fn test_stripper_handles_sequences_split_across_entries() {
+        let mut stripper = AnsiStripper::default();
+        let mut text = stripper.strip("a\x1b[3");
+        text.push_str(&stripper.strip("1mb\x1b]0;ti"));
+        text.push_str(&stripper.strip("tle\x1b\\c\x1b"));
+        text.push_str(&stripper.strip("[0m\n"));
+
+        assert_eq!(text, "abc\n");
+    }
+}
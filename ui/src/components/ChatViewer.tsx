@@ -19,6 +19,17 @@ interface Message {
   raw?: string;
 }
 
+interface LogLine {
+  timestamp: string;
+  direction: string;
+  text: string;
+}
+
+interface LogChunk {
+  entries: LogLine[];
+  next_offset: number;
+}
+
 export function ChatViewer({ session }: ChatViewerProps) {
   const [messages, setMessages] = useState<Message[]>([]);
   const [input, setInput] = useState('');
@@ -49,16 +60,17 @@ export function ChatViewer({ session }: ChatViewerProps) {
     isLoadingRef.current = true;
 
     try {
-      const lines = await invoke<string[]>('read_session_logs', {
+      const chunk = await invoke<LogChunk>('read_session_logs', {
         logPath: session.log_path,
         offset,
+        stripAnsi: true,
       });
 
-      if (lines.length > 0) {
-        const newMessages = parseLogLines(lines);
+      if (chunk.entries.length > 0) {
+        const newMessages = parseLogLines(chunk.entries);
         setMessages((prev) => [...prev, ...newMessages]);
-        setOffset((prev) => prev + lines.length);
       }
+      setOffset(chunk.next_offset);
     } catch (err) {
       console.error('Failed to read logs:', err);
     } finally {
@@ -66,41 +78,35 @@ export function ChatViewer({ session }: ChatViewerProps) {
     }
   }
 
-  function parseLogLines(lines: string[]): Message[] {
+  function parseLogLines(entries: LogLine[]): Message[] {
     const messages: Message[] = [];
     let currentGroup: { type: 'input' | 'output'; lines: string[]; timestamp: string } | null =
       null;
 
-    for (const line of lines) {
-      try {
-        const entry = JSON.parse(line);
-        const data = atob(entry.data);
-        const timestamp = entry.timestamp;
+    for (const entry of entries) {
+      const data = entry.text;
+      const timestamp = entry.timestamp;
 
-        if (entry.direction === 'input') {
-          // User input
+      if (entry.direction === 'input') {
+        // User input
+        if (currentGroup) {
+          messages.push(...groupToMessages(currentGroup));
+          currentGroup = null;
+        }
+        messages.push({
+          type: 'user',
+          content: data.trim(),
+          timestamp,
+        });
+      } else if (entry.direction === 'output') {
+        // Group consecutive output
+        if (!currentGroup || currentGroup.type !== 'output') {
           if (currentGroup) {
             messages.push(...groupToMessages(currentGroup));
-            currentGroup = null;
-          }
-          messages.push({
-            type: 'user',
-            content: data.trim(),
-            timestamp,
-            raw: line,
-          });
-        } else if (entry.direction === 'output') {
-          // Group consecutive output
-          if (!currentGroup || currentGroup.type !== 'output') {
-            if (currentGroup) {
-              messages.push(...groupToMessages(currentGroup));
-            }
-            currentGroup = { type: 'output', lines: [], timestamp };
           }
-          currentGroup.lines.push(data);
+          currentGroup = { type: 'output', lines: [], timestamp };
         }
-      } catch (e) {
-        console.error('Failed to parse log line:', e);
+        currentGroup.lines.push(data);
       }
     }
 
@@ -4,6 +4,11 @@ import { FitAddon } from '@xterm/addon-fit';
 import { invoke } from '@tauri-apps/api/core';
 import '@xterm/xterm/css/xterm.css';
 
+interface LogChunk {
+  entries: { timestamp: string; direction: string; text: string }[];
+  next_offset: number;
+}
+
 interface Session {
   id: string;
   working_dir: string;
@@ -77,26 +82,17 @@ export function TerminalViewer({ session }: TerminalViewerProps) {
     isLoadingRef.current = true;
 
     try {
-      const lines = await invoke<string[]>('read_session_logs', {
+      const chunk = await invoke<LogChunk>('read_session_logs', {
         logPath: session.log_path,
         offset,
       });
 
-      if (lines.length > 0) {
-        for (const line of lines) {
-          try {
-            const entry = JSON.parse(line);
-            if (entry.direction === 'output' && entry.data) {
-              // Decode base64 data
-              const data = atob(entry.data);
-              terminalInstance.current?.write(data);
-            }
-          } catch (e) {
-            console.error('Failed to parse log entry:', e, line);
-          }
+      for (const entry of chunk.entries) {
+        if (entry.direction === 'output') {
+          terminalInstance.current?.write(entry.text);
         }
-        setOffset((prev) => prev + lines.length);
       }
+      setOffset(chunk.next_offset);
     } catch (err) {
       console.error('Failed to read logs:', err);
     } finally {